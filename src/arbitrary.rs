@@ -0,0 +1,527 @@
+/// proptest `Arbitrary` implementations for the fel4 manifest types, enabled
+/// by the `proptest` feature for property testing and fuzzing
+use proptest::arbitrary::{any, Arbitrary};
+use proptest::collection::{self, btree_map, btree_set, hash_map};
+use proptest::num;
+use proptest::option;
+use proptest::sample::select;
+use proptest::strategy::{BoxedStrategy, Just, Strategy, ValueTree};
+use std::collections::BTreeMap;
+use toml;
+
+use super::{is_supported_target_platform_pair, ALL_PROPERTIES_WHITELIST};
+use manifest::*;
+use conditional::ConditionalValue;
+use formula::Formula;
+use types::*;
+use variant::ManifestVariant;
+
+impl Arbitrary for SupportedTarget {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    type ValueTree = Box<dyn ValueTree<Value = Self>>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        select(SupportedTarget::targets()).boxed()
+    }
+}
+
+impl Arbitrary for SupportedPlatform {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    type ValueTree = Box<dyn ValueTree<Value = Self>>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        select(SupportedPlatform::platforms()).boxed()
+    }
+}
+
+impl Arbitrary for BuildProfile {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    type ValueTree = Box<dyn ValueTree<Value = Self>>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        select(BuildProfile::build_profiles()).boxed()
+    }
+}
+
+/// Only finite floats are generated, since NaN and the infinities can be
+/// neither compared for equality nor written as toml by this version of the
+/// toml crate.
+impl Arbitrary for FlatTomlValue {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    type ValueTree = Box<dyn ValueTree<Value = Self>>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            "[ -~]{0,16}".prop_map(FlatTomlValue::String),
+            any::<i64>().prop_map(FlatTomlValue::Integer),
+            (num::f64::NORMAL | num::f64::ZERO).prop_map(FlatTomlValue::Float),
+            any::<bool>().prop_map(FlatTomlValue::Boolean),
+            arb_datetime().prop_map(FlatTomlValue::Datetime),
+        ]
+        .boxed()
+    }
+}
+
+/// Manifests always select a supported target and platform pair, but the
+/// selected target is not necessarily among the generated target tables, so
+/// resolution failures are exercised as well as successes.
+impl Arbitrary for FullFel4Manifest {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    type ValueTree = Box<dyn ValueTree<Value = Self>>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        let selection = (any::<SupportedTarget>(), any::<SupportedPlatform>())
+            .prop_filter("unsupported target and platform pair", |&(t, p)| {
+                is_supported_target_platform_pair(t, p)
+            });
+        let targets = hash_map(any::<SupportedTarget>(), arb_target_content(), 0..4);
+        let toolchains = hash_map(any::<SupportedTarget>(), arb_toolchain(), 0..2);
+        let kernel = (
+            option::of(arb_kernel_source()),
+            option::of("[0-9]{1,2}\\.[0-9]\\.[0-9]"),
+            option::of("[0-9a-f]{7,40}"),
+            option::of(prop_oneof![
+                (0..i64::MAX).prop_map(SourceDateEpoch::Fixed),
+                Just(SourceDateEpoch::KernelCommit),
+            ]),
+        );
+        let image_naming = (
+            option::of("[a-zA-Z0-9_]{1,8}"),
+            option::of(select(vec!["{{name}}", "{{name}}-{{profile}}-{{hash}}"])),
+        );
+        let cmake_build_types = hash_map(
+            any::<BuildProfile>(),
+            select(vec!["Debug", "Release", "RelWithDebInfo", "MinSizeRel"]),
+            0..2,
+        );
+        let property_scopes = hash_map(
+            select(ALL_PROPERTIES_WHITELIST.to_vec()),
+            select(PropertyScope::scopes()),
+            0..3,
+        );
+        let locked_properties = btree_set(select(ALL_PROPERTIES_WHITELIST.to_vec()), 0..3);
+        (
+            (arb_path(), select(ArtifactLayout::layouts())),
+            arb_path(),
+            (selection, option::of(select(Deployment::deployments()))),
+            targets,
+            (toolchains, kernel, cmake_build_types, property_scopes, locked_properties),
+            image_naming,
+            arb_presets_and_variants(),
+        )
+            .prop_map(
+                |(
+                    (artifact_path, artifact_layout),
+                    target_specs_path,
+                    ((t, p), deployment),
+                    targets,
+                    (
+                        toolchains,
+                        (kernel_source, kernel_version, kernel_commit, source_date_epoch),
+                        cmake_build_types,
+                        property_scopes,
+                        locked_properties,
+                    ),
+                    (image_name, image_name_pattern),
+                    (presets, variants),
+                )| {
+                    FullFel4Manifest {
+                        artifact_path,
+                        artifact_layout,
+                        target_specs_path,
+                        selected_target: t,
+                        selected_platform: p,
+                        deployment,
+                        targets: targets
+                            .into_iter()
+                            .map(|(identity, content)| (identity, content.into_target(identity)))
+                            .collect(),
+                        toolchains,
+                        kernel_source,
+                        kernel_version,
+                        kernel_commit,
+                        image_name,
+                        image_name_pattern: image_name_pattern.map(str::to_string),
+                        source_date_epoch,
+                        cmake_build_types: cmake_build_types
+                            .into_iter()
+                            .map(|(profile, build_type)| (profile, build_type.to_string()))
+                            .collect(),
+                        property_scopes: property_scopes
+                            .into_iter()
+                            .map(|(name, scope)| (name.to_string(), scope))
+                            .collect(),
+                        locked_properties: locked_properties
+                            .into_iter()
+                            .map(str::to_string)
+                            .collect(),
+                        presets,
+                        variants,
+                    }
+                },
+            )
+            .boxed()
+    }
+}
+
+/// The portions of a `FullFel4Target` that don't depend on its identity
+#[derive(Clone, Debug)]
+struct TargetContent {
+    direct: PropertySet,
+    profiles: Vec<(BuildProfile, PropertySet)>,
+    platforms: Vec<(SupportedPlatform, PropertySet)>,
+    conditionals: BTreeMap<String, ConditionalValue>,
+    derived: BTreeMap<String, Formula>,
+    rootserver: RootserverSettings,
+    test: PropertySet,
+}
+
+impl TargetContent {
+    fn into_target(self, identity: SupportedTarget) -> FullFel4Target {
+        FullFel4Target {
+            identity,
+            direct_properties: self.direct,
+            build_profile_properties: self.profiles.into_iter().collect(),
+            platform_properties: self.platforms.into_iter().collect(),
+            conditional_properties: self.conditionals,
+            derived_properties: self.derived,
+            rootserver: self.rootserver,
+            test_properties: self.test,
+        }
+    }
+}
+
+fn arb_target_content() -> BoxedStrategy<TargetContent> {
+    let profiles = (option::of(arb_properties()), option::of(arb_properties()));
+    let platforms = (
+        option::of(arb_properties()),
+        option::of(arb_properties()),
+        option::of(arb_properties()),
+        option::of(arb_properties()),
+    );
+    let conditionals = btree_map(
+        select(ALL_PROPERTIES_WHITELIST.to_vec()),
+        arb_conditional_value(),
+        0..3,
+    );
+    let formulas = vec![
+        "KernelMaxNumNodes * 2",
+        "'X-Base' + log2(KernelRetypeFanOutLimit) - 1",
+        "min(0x10, 1 << KernelNumDomains)",
+    ];
+    let derived = btree_map(
+        select(ALL_PROPERTIES_WHITELIST.to_vec()),
+        select(formulas),
+        0..2,
+    );
+    (
+        arb_properties(),
+        profiles,
+        platforms,
+        (conditionals, derived),
+        (arb_rootserver(), arb_properties()),
+    )
+        .prop_map(
+            |(direct, (debug, release), (pc99, sabre, tx1, tx2), expressions, settings)| {
+                let (rootserver, test) = settings;
+                let (conditionals, derived) = expressions;
+                let conditionals: BTreeMap<String, ConditionalValue> = conditionals
+                    .into_iter()
+                    .filter(|&(name, _)| !direct.contains(name))
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect();
+                let derived = derived
+                    .into_iter()
+                    .filter(|&(name, _)| !direct.contains(name) && !conditionals.contains_key(name))
+                    .map(|(name, formula)| {
+                        (name.to_string(), formula.parse().expect("The formulas parse"))
+                    })
+                    .collect();
+                TargetContent {
+                    direct,
+                    profiles: BuildProfile::build_profiles()
+                        .into_iter()
+                        .zip(vec![debug, release])
+                        .filter_map(|(profile, properties)| properties.map(|p| (profile, p)))
+                        .collect(),
+                    platforms: SupportedPlatform::platforms()
+                        .into_iter()
+                        .zip(vec![pc99, sabre, tx1, tx2])
+                        .filter_map(|(platform, properties)| properties.map(|p| (platform, p)))
+                        .collect(),
+                    conditionals,
+                    derived,
+                    rootserver,
+                    test,
+                }
+            },
+        )
+        .boxed()
+}
+
+/// Conditional values of each of the three forms the parser accepts
+fn arb_conditional_value() -> BoxedStrategy<ConditionalValue> {
+    let by_name = |names: Vec<String>| {
+        (
+            option::of(any::<FlatTomlValue>()),
+            btree_map(select(names), any::<FlatTomlValue>(), 1..3),
+        )
+            .prop_map(|(default, by_name)| ConditionalValue {
+                by_name,
+                when: None,
+                default,
+            })
+    };
+    let conditions = vec![
+        "platform == 'sabre'",
+        "profile != 'debug' && target == 'x86_64-sel4-fel4'",
+        "!(platform == 'pc99' || profile == 'release')",
+    ];
+    let when = (
+        option::of(any::<FlatTomlValue>()),
+        select(conditions),
+        any::<FlatTomlValue>(),
+    )
+        .prop_map(|(default, condition, value)| ConditionalValue {
+            by_name: BTreeMap::new(),
+            when: Some((condition.parse().expect("The conditions parse"), value)),
+            default,
+        });
+    prop_oneof![
+        by_name(BuildProfile::build_profile_names()),
+        by_name(SupportedPlatform::platform_names()),
+        when,
+    ]
+    .boxed()
+}
+
+/// Properties named from the whitelist, in the name-sorted order the parser
+/// produces them
+fn arb_properties() -> BoxedStrategy<PropertySet> {
+    btree_map(
+        select(ALL_PROPERTIES_WHITELIST.to_vec()),
+        any::<FlatTomlValue>(),
+        0..6,
+    )
+    .prop_map(|m: BTreeMap<&str, FlatTomlValue>| {
+        PropertySet::from_properties(
+            m.into_iter()
+                .map(|(name, value)| FlatTomlProperty::new(name.to_string(), value)),
+        )
+        .expect("Map keys are unique")
+    })
+    .boxed()
+}
+
+/// Presets, and variants that each apply either none or all of them
+fn arb_presets_and_variants(
+) -> BoxedStrategy<(BTreeMap<String, PropertySet>, BTreeMap<String, ManifestVariant>)> {
+    let selection = (any::<SupportedTarget>(), any::<SupportedPlatform>())
+        .prop_filter("unsupported target and platform pair", |&(t, p)| {
+            is_supported_target_platform_pair(t, p)
+        });
+    (
+        btree_map("[a-z]{1,6}", arb_properties(), 0..3),
+        btree_map(
+            "[a-z][a-z-]{0,7}",
+            (selection, any::<BuildProfile>(), any::<bool>()),
+            0..3,
+        ),
+    )
+        .prop_map(|(presets, variants)| {
+            let variants = variants
+                .into_iter()
+                .map(|(name, ((target, platform), build_profile, with_presets))| {
+                    let variant = ManifestVariant {
+                        target,
+                        platform,
+                        build_profile,
+                        presets: if with_presets {
+                            presets.keys().cloned().collect()
+                        } else {
+                            Vec::new()
+                        },
+                    };
+                    (name, variant)
+                })
+                .collect();
+            (presets, variants)
+        })
+        .boxed()
+}
+
+fn arb_rootserver() -> BoxedStrategy<RootserverSettings> {
+    (
+        option::of(1..(1i64 << 32)),
+        option::of(1..(1i64 << 32)),
+        option::of(1..64i64),
+    )
+        .prop_map(|(stack_size, heap_size, cnode_size_bits)| RootserverSettings {
+            stack_size,
+            heap_size,
+            cnode_size_bits,
+        })
+        .boxed()
+}
+
+fn arb_toolchain() -> BoxedStrategy<ToolchainSettings> {
+    (
+        option::of("[a-z0-9_]{1,8}-"),
+        hash_map(any::<SupportedPlatform>(), "[a-z0-9_]{1,8}-", 0..2),
+        option::of(select(FloatAbi::abis())),
+        (option::of(arb_path()), option::of(arb_path()), option::of(arb_path())),
+        arb_properties(),
+        collection::vec("[a-z0-9_.]{1,12}", 0..3),
+        option::of(collection::vec("[A-Z_]{1,12}", 0..3)),
+    )
+        .prop_map(
+            |(prefix, platform_prefixes, float_abi, tools, variables, targets, cleared)| {
+                let (cmake, ninja, compiler_launcher) = tools;
+                ToolchainSettings {
+                    compiler_prefix: prefix,
+                    platform_compiler_prefixes: platform_prefixes,
+                    float_abi,
+                    cmake,
+                    ninja,
+                    compiler_launcher,
+                    variables: variables.into_iter().collect(),
+                    build_targets: targets,
+                    cleared_env: cleared,
+                }
+            },
+        )
+        .boxed()
+}
+
+fn arb_kernel_source() -> BoxedStrategy<KernelSource> {
+    prop_oneof![
+        (arb_path(), option::of("[0-9a-f]{7,40}"))
+            .prop_map(|(url, rev)| KernelSource::Git { url, rev }),
+        (arb_path(), "[0-9a-f]{64}")
+            .prop_map(|(url, sha256)| KernelSource::Tarball { url, sha256 }),
+    ]
+    .boxed()
+}
+
+fn arb_path() -> BoxedStrategy<String> {
+    "[a-z_]{1,8}(/[a-z_]{1,8}){0,2}".boxed()
+}
+
+fn arb_datetime() -> BoxedStrategy<toml::value::Datetime> {
+    (
+        1000u32..10000,
+        1u32..13,
+        1u32..29,
+        0u32..24,
+        0u32..60,
+        0u32..60,
+    )
+        .prop_flat_map(|(year, month, day, hour, minute, second)| {
+            let date = format!("{:04}-{:02}-{:02}", year, month, day);
+            let date_time = format!("{}T{:02}:{:02}:{:02}Z", date, hour, minute, second);
+            select(vec![date, date_time])
+        })
+        .prop_map(|s| s.parse().expect("Generated datetimes should be valid"))
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::resolve_fel4_config;
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn full_manifest_round_trips_through_toml(ref full in any::<FullFel4Manifest>()) {
+            let rendered = toml::to_string(&full_manifest_to_toml(full))
+                .expect("Generated manifests should serialize");
+            prop_assert_eq!(Ok(full.clone()), parse_full_manifest(rendered));
+            prop_assert_eq!(Ok(full.clone()), parse_full_manifest(full_manifest_to_toml_string(full)));
+        }
+
+        #[test]
+        fn resolution_only_produces_properties_from_the_selected_layers(
+            ref full in any::<FullFel4Manifest>(),
+            ref profile in any::<BuildProfile>()
+        ) {
+            if let Ok(config) = resolve_fel4_config(full, profile) {
+                let target = &full.targets[&config.target];
+                let layers = target.direct_properties.iter()
+                    .chain(&target.build_profile_properties[profile])
+                    .chain(&target.platform_properties[&config.platform]);
+                for p in layers {
+                    prop_assert_eq!(Some(&p.value), config.properties.get(&p.name));
+                }
+            }
+        }
+
+        #[test]
+        fn flat_toml_values_survive_toml_conversion(ref value in any::<FlatTomlValue>()) {
+            let mut table = BTreeMap::new();
+            table.insert("KernelArch".to_string(), toml::Value::from(value));
+            let rendered = toml::to_string(&toml::Value::Table(table))
+                .expect("Generated values should serialize");
+            let parsed: toml::Value = rendered.parse().expect("Rendered values should parse");
+            prop_assert_eq!(Some(&toml::Value::from(value)), parsed.get("KernelArch"));
+        }
+
+        #[test]
+        fn flat_toml_values_round_trip_through_literals(ref value in any::<FlatTomlValue>()) {
+            let literal = value.to_string();
+            prop_assert_eq!(Ok(value.clone()), literal.parse::<FlatTomlValue>());
+            prop_assert_eq!(
+                Ok(value.clone()),
+                FlatTomlValue::parse_as(&literal, value.value_type())
+            );
+        }
+
+        #[test]
+        fn flat_toml_strings_with_escapes_round_trip(
+            ref s in "[\\PC\\t\\r\\n\\x00-\\x1f\\x7f\"\\\\]{0,16}"
+        ) {
+            let value = FlatTomlValue::String(s.clone());
+            prop_assert_eq!(Ok(value.clone()), value.to_string().parse::<FlatTomlValue>());
+        }
+    }
+
+    #[test]
+    fn flat_toml_values_parse_with_type_hints() {
+        assert_eq!(
+            Ok(FlatTomlValue::Float(2.0)),
+            FlatTomlValue::parse_as("2", FlatTomlType::Float)
+        );
+        assert_eq!(
+            Ok(FlatTomlValue::String("-O2".into())),
+            FlatTomlValue::parse_as("-O2", FlatTomlType::String)
+        );
+        assert_eq!(
+            Ok(FlatTomlValue::String("sabre".into())),
+            FlatTomlValue::parse_as("\"sabre\"", FlatTomlType::String)
+        );
+        assert_eq!(
+            Ok(FlatTomlValue::String("true".into())),
+            FlatTomlValue::parse_as("true", FlatTomlType::String)
+        );
+        assert_eq!(
+            Err("yes is not a toml boolean".to_string()),
+            FlatTomlValue::parse_as("yes", FlatTomlType::Boolean)
+        );
+        assert_eq!(
+            Err("1.5 is not a toml integer".to_string()),
+            FlatTomlValue::parse_as("1.5", FlatTomlType::Integer)
+        );
+        assert!("x = 1".parse::<FlatTomlValue>().is_err());
+        assert!("1\nx = 2".parse::<FlatTomlValue>().is_err());
+        assert_eq!("1.0", FlatTomlValue::Float(1.0).to_string());
+        assert_eq!("-inf", FlatTomlValue::Float(-f64::INFINITY).to_string());
+        assert_eq!(
+            "\"a\\\"b\\u0007\"",
+            FlatTomlValue::String("a\"b\u{7}".into()).to_string()
+        );
+    }
+}
@@ -0,0 +1,308 @@
+/// Lint profiles that audit a resolved configuration for options a
+/// particular kind of build shouldn't ship with
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use diagnostics::Severity;
+use json::Json;
+use types::*;
+
+/// A named set of lints for `audit_config`
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum LintProfile {
+    /// Flags debugging, tracing, and hardware access options that weaken
+    /// the isolation a release kernel is relied on for
+    SecureRelease,
+}
+const LINT_PROFILE_SECURE_RELEASE: &str = "secure-release";
+impl LintProfile {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            LintProfile::SecureRelease => LINT_PROFILE_SECURE_RELEASE,
+        }
+    }
+
+    pub fn profiles() -> Vec<LintProfile> {
+        vec![LintProfile::SecureRelease]
+    }
+
+    pub fn profile_names() -> Vec<String> {
+        LintProfile::profiles()
+            .iter()
+            .map(|p| p.full_name().into())
+            .collect()
+    }
+
+    fn lints(&self) -> &'static [Lint] {
+        match *self {
+            LintProfile::SecureRelease => SECURE_RELEASE_LINTS,
+        }
+    }
+}
+
+impl Display for LintProfile {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for LintProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            LINT_PROFILE_SECURE_RELEASE => Ok(LintProfile::SecureRelease),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// A property value a lint profile flags in a configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditFinding {
+    /// `Severity::Error` for options that must not ship, and
+    /// `Severity::Warning` for those that need a justification
+    pub severity: Severity,
+    pub property: String,
+    pub value: FlatTomlValue,
+    /// The manifest table the value was resolved from, as in
+    /// `Fel4Config::render_report`
+    pub source: String,
+    pub reason: &'static str,
+}
+
+/// The findings of auditing one configuration against a lint profile
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditReport {
+    pub profile: LintProfile,
+    pub target: SupportedTarget,
+    pub platform: SupportedPlatform,
+    pub build_profile: BuildProfile,
+    /// In the order of the profile's lints
+    pub findings: Vec<AuditFinding>,
+}
+
+impl AuditReport {
+    /// Whether the configuration has no `Severity::Error` findings
+    pub fn passed(&self) -> bool {
+        self.findings.iter().all(|f| f.severity != Severity::Error)
+    }
+
+    /// Render the findings as an aligned table ending in the overall result,
+    /// for attaching to a release sign-off
+    pub fn render(&self) -> String {
+        let name_width = self
+            .findings
+            .iter()
+            .map(|f| f.property.len() + f.value.to_string().len() + 3)
+            .max()
+            .unwrap_or(0);
+        let mut report = format!(
+            "{} audit of {} {} {}\n",
+            self.profile, self.target, self.platform, self.build_profile
+        );
+        for finding in &self.findings {
+            report.push_str(&format!(
+                "  {:7}  {:name_width$}  [{}] {}\n",
+                finding.severity.full_name(),
+                format!("{} = {}", finding.property, finding.value),
+                finding.source,
+                finding.reason,
+                name_width = name_width
+            ));
+        }
+        report.push_str(&format!(
+            "result: {}\n",
+            if self.passed() { "pass" } else { "fail" }
+        ));
+        report
+    }
+
+    /// The report as a JSON document, for archiving with release records
+    pub fn to_json_string(&self) -> String {
+        let findings = self
+            .findings
+            .iter()
+            .map(|f| {
+                Json::object(vec![
+                    ("severity", Json::string(f.severity.full_name())),
+                    ("property", Json::string(f.property.as_str())),
+                    ("value", Json::from(&f.value)),
+                    ("source", Json::string(f.source.as_str())),
+                    ("reason", Json::string(f.reason)),
+                ])
+            })
+            .collect();
+        let document = Json::object(vec![
+            ("profile", Json::string(self.profile.full_name())),
+            ("target", Json::string(self.target.full_name())),
+            ("platform", Json::string(self.platform.full_name())),
+            ("build-profile", Json::string(self.build_profile.full_name())),
+            ("passed", Json::Bool(self.passed())),
+            ("findings", Json::Array(findings)),
+        ]);
+        format!("{}\n", document)
+    }
+}
+
+/// A property value one lint flags
+struct Lint {
+    property: &'static str,
+    severity: Severity,
+    flags: fn(&FlatTomlValue) -> bool,
+    reason: &'static str,
+}
+
+fn enabled(value: &FlatTomlValue) -> bool {
+    *value == FlatTomlValue::Boolean(true)
+}
+
+fn not_none(value: &FlatTomlValue) -> bool {
+    *value != FlatTomlValue::String("none".into())
+}
+
+const SECURE_RELEASE_LINTS: &[Lint] = &[
+    Lint {
+        property: "KernelDebugBuild",
+        severity: Severity::Error,
+        flags: enabled,
+        reason: "debug kernels expose debugging system calls to every thread",
+    },
+    Lint {
+        property: "KernelPrinting",
+        severity: Severity::Error,
+        flags: enabled,
+        reason: "kernel printing lets any thread write to the console",
+    },
+    Lint {
+        property: "HardwareDebugAPI",
+        severity: Severity::Error,
+        flags: enabled,
+        reason: "hardware breakpoints and watchpoints are available to userland",
+    },
+    Lint {
+        property: "KernelX86DangerousMSR",
+        severity: Severity::Error,
+        flags: enabled,
+        reason: "userland may read and write arbitrary model-specific registers",
+    },
+    Lint {
+        property: "KernelBenchmarks",
+        severity: Severity::Error,
+        flags: not_none,
+        reason: "benchmarking adds system calls and exports kernel timing",
+    },
+    Lint {
+        property: "KernelExportPMCUser",
+        severity: Severity::Warning,
+        flags: enabled,
+        reason: "userland performance counters are a timing side channel",
+    },
+    Lint {
+        property: "KernelArmExportPMUUser",
+        severity: Severity::Warning,
+        flags: enabled,
+        reason: "userland performance counters are a timing side channel",
+    },
+    Lint {
+        property: "KernelArmExportPCNTUser",
+        severity: Severity::Warning,
+        flags: enabled,
+        reason: "the userland physical counter is a timing side channel",
+    },
+    Lint {
+        property: "KernelArmExportVCNTUser",
+        severity: Severity::Warning,
+        flags: enabled,
+        reason: "the userland virtual counter is a timing side channel",
+    },
+    Lint {
+        property: "BuildWithCommonSimulationSettings",
+        severity: Severity::Warning,
+        flags: enabled,
+        reason: "simulation settings are not intended for deployed images",
+    },
+];
+
+/// Check a resolved configuration against a lint profile, reporting every
+/// property value the profile flags
+pub fn audit_config(fel4_config: &Fel4Config, profile: LintProfile) -> AuditReport {
+    let findings = profile
+        .lints()
+        .iter()
+        .filter_map(|lint| {
+            let value = fel4_config.properties.get(lint.property)?;
+            if !(lint.flags)(value) {
+                return None;
+            }
+            let source = fel4_config
+                .provenance
+                .get(lint.property)
+                .map(|layer| layer.location(fel4_config))
+                .unwrap_or_else(|| PropertyOrigin::Overridden.to_string());
+            Some(AuditFinding {
+                severity: lint.severity,
+                property: lint.property.to_string(),
+                value: value.clone(),
+                source,
+                reason: lint.reason,
+            })
+        })
+        .collect();
+    fel4_trace!("audit", "audited with the {} lints", profile);
+    AuditReport {
+        profile,
+        target: fel4_config.target,
+        platform: fel4_config.platform,
+        build_profile: fel4_config.build_profile,
+        findings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn secure_release_flags_debugging_options() {
+        let debug = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        let report = audit_config(&debug, LintProfile::SecureRelease);
+        assert!(!report.passed());
+        let flagged: Vec<(Severity, &str)> = report
+            .findings
+            .iter()
+            .map(|f| (f.severity, f.property.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Severity::Error, "KernelDebugBuild"),
+                (Severity::Error, "KernelPrinting"),
+                (Severity::Warning, "BuildWithCommonSimulationSettings"),
+            ],
+            flagged
+        );
+        let rendered = report.render();
+        assert!(rendered.starts_with("secure-release audit of x86_64-sel4-fel4 pc99 debug\n"));
+        assert!(rendered.contains("KernelPrinting = true"));
+        assert!(rendered.contains("[x86_64-sel4-fel4.debug]"));
+        assert!(rendered.ends_with("result: fail\n"));
+        assert!(report.to_json_string().contains("\"passed\": false"));
+
+        let release = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Release,
+        );
+        let report = audit_config(&release, LintProfile::SecureRelease);
+        assert!(report.passed());
+        assert_eq!(1, report.findings.len());
+        assert!(report.render().ends_with("result: pass\n"));
+        assert_eq!(Ok(LintProfile::SecureRelease), "secure-release".parse());
+    }
+}
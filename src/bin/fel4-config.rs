@@ -0,0 +1,88 @@
+//! The `fel4-config` command line tool, built with the `cli` feature
+extern crate fel4_config;
+
+use std::env;
+use std::io;
+use std::process;
+
+use fel4_config::{check_manifest, run_wizard, write_wizard_manifest};
+
+const USAGE: &str = "Usage: fel4-config wizard [--force] [MANIFEST_PATH]
+       fel4-config check [--quiet] [--format human|json] [MANIFEST_PATH]
+
+Commands:
+    wizard    Answer a few questions to write a new fel4.toml, at
+              MANIFEST_PATH or ./fel4.toml. An existing manifest is only
+              replaced with --force, and is then kept as a .bak file.
+    check     Validate and lint MANIFEST_PATH or ./fel4.toml and check that
+              the files it refers to exist, without running CMake. Exits
+              non-zero if there are errors, so it suits git hooks. --quiet
+              prints only errors.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("wizard") => wizard(&args[1..]),
+        Some("check") => check(&args[1..]),
+        Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2)
+        }
+    };
+    if let Err(message) = result {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
+}
+
+fn wizard(args: &[String]) -> Result<(), String> {
+    let force = args.iter().any(|a| a == "--force");
+    let path = args
+        .iter()
+        .find(|a| !a.starts_with("--"))
+        .map_or("fel4.toml", String::as_str);
+    let stdin = io::stdin();
+    let manifest = run_wizard(stdin.lock(), io::stdout()).map_err(|e| e.to_string())?;
+    match write_wizard_manifest(path, &manifest, force).map_err(|e| e.to_string())? {
+        Some(backup) => println!(
+            "Wrote {}, keeping the previous manifest as {}",
+            path,
+            backup.display()
+        ),
+        None => println!("Wrote {}", path),
+    }
+    Ok(())
+}
+
+fn check(args: &[String]) -> Result<(), String> {
+    let mut quiet = false;
+    let mut json = false;
+    let mut path = "fel4.toml";
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--quiet" | "-q" => quiet = true,
+            "--format" => match args.next().map(String::as_str) {
+                Some("json") => json = true,
+                Some("human") => json = false,
+                other => return Err(format!("unknown --format {}", other.unwrap_or(""))),
+            },
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            manifest => path = manifest,
+        }
+    }
+    let report = check_manifest(path);
+    if json {
+        print!("{}", report.to_json_string());
+    } else {
+        print!("{}", report.summary(quiet));
+    }
+    if !report.passed() {
+        process::exit(1);
+    }
+    Ok(())
+}
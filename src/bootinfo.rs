@@ -0,0 +1,184 @@
+/// What the root task should find in the kernel's bootinfo, so it can check
+/// at boot that the kernel it runs on was built from the same configuration
+use std::path::Path;
+
+use generated_source::{optional_usize_constants, write_generated_file};
+use types::*;
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum BootinfoError {
+    #[fail(
+        display = "The {} property is {}, which doesn't fit the {}-bit kernel's bootinfo",
+        _0, _1, _2
+    )]
+    OutOfRange(&'static str, i64, u32),
+    #[fail(display = "Unable to write the bootinfo expectations to {}", _0)]
+    WriteFailure(String),
+}
+
+/// The bootinfo limits a resolved configuration implies, each `None` where
+/// the manifest leaves the kernel option to the kernel's default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BootinfoExpectations {
+    /// `KernelRootCNodeSizeBits`, the size of the root task's CNode
+    pub root_cnode_size_bits: Option<u64>,
+    /// `KernelMaxNumBootinfoUntypedCaps`, the most untyped capabilities
+    /// bootinfo can describe
+    pub max_untyped_caps: Option<u64>,
+    /// The slots in the root task's CNode, which every capability bootinfo
+    /// describes must fit within
+    pub max_bootinfo_caps: Option<u64>,
+}
+
+/// The bootinfo limits the configuration's kernel options imply.
+///
+/// Each limit must fit the kernel's word, so negative values, values too
+/// large for the word, and CNode sizes whose slot count is too large for it
+/// are an `OutOfRange` error.
+pub fn bootinfo_expectations(
+    fel4_config: &Fel4Config,
+) -> Result<BootinfoExpectations, BootinfoError> {
+    let word_size = fel4_config.word_size();
+    let integer = |name: &'static str| match fel4_config.properties.get(name) {
+        Some(&FlatTomlValue::Integer(i)) if i >= 0 && (word_size >= 64 || i < 1 << word_size) => {
+            Ok(Some(i as u64))
+        }
+        Some(&FlatTomlValue::Integer(i)) => Err(BootinfoError::OutOfRange(name, i, word_size)),
+        _ => Ok(None),
+    };
+    let root_cnode_size_bits = integer("KernelRootCNodeSizeBits")?;
+    // The CNode has 1 << bits slots, which must fit the word too
+    if let Some(bits) = root_cnode_size_bits.filter(|&bits| bits >= u64::from(word_size)) {
+        return Err(BootinfoError::OutOfRange(
+            "KernelRootCNodeSizeBits",
+            bits as i64,
+            word_size,
+        ));
+    }
+    Ok(BootinfoExpectations {
+        root_cnode_size_bits,
+        max_untyped_caps: integer("KernelMaxNumBootinfoUntypedCaps")?,
+        max_bootinfo_caps: root_cnode_size_bits.map(|bits| 1 << bits),
+    })
+}
+
+/// Render Rust source declaring the `bootinfo_expectations` as constants,
+/// for a build script to write to `OUT_DIR` and the root task to `include!`.
+///
+/// `EXPECTED_ROOT_CNODE_SIZE_BITS`, `EXPECTED_MAX_UNTYPED_CAPS`, and
+/// `EXPECTED_MAX_BOOTINFO_CAPS` are each an `Option<usize>`. A root task can
+/// assert that bootinfo's untyped range is no longer than the untyped limit
+/// and that its empty slot range ends within the CNode, rather than failing
+/// later with a capability fault.
+pub fn bootinfo_expectations_rust_source(
+    fel4_config: &Fel4Config,
+) -> Result<String, BootinfoError> {
+    let expectations = bootinfo_expectations(fel4_config)?;
+    Ok(optional_usize_constants(&[
+        (
+            "EXPECTED_ROOT_CNODE_SIZE_BITS",
+            expectations.root_cnode_size_bits,
+        ),
+        ("EXPECTED_MAX_UNTYPED_CAPS", expectations.max_untyped_caps),
+        ("EXPECTED_MAX_BOOTINFO_CAPS", expectations.max_bootinfo_caps),
+    ]))
+}
+
+/// Write the `bootinfo_expectations_rust_source` for the configuration to a
+/// file, creating any missing parent directories
+pub fn write_bootinfo_expectations_rust_source<P: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    path: P,
+) -> Result<(), BootinfoError> {
+    let source = bootinfo_expectations_rust_source(fel4_config)?;
+    write_generated_file(&path, &source)
+        .map_err(|_| BootinfoError::WriteFailure(path.as_ref().display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn bootinfo_expectations_follow_the_kernel_options() {
+        let config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        assert_eq!(
+            BootinfoExpectations {
+                root_cnode_size_bits: Some(19),
+                max_untyped_caps: Some(230),
+                max_bootinfo_caps: Some(1 << 19),
+            },
+            bootinfo_expectations(&config).unwrap()
+        );
+        let source = bootinfo_expectations_rust_source(&config).unwrap();
+        assert!(source.starts_with("// Generated from fel4.toml by fel4-config."));
+        assert!(
+            source.contains("pub const EXPECTED_MAX_UNTYPED_CAPS: Option<usize> = Some(230);\n")
+        );
+        assert!(source
+            .contains("pub const EXPECTED_MAX_BOOTINFO_CAPS: Option<usize> = Some(524288);\n"));
+
+        let mut unset = config.clone();
+        unset.properties.remove("KernelRootCNodeSizeBits");
+        assert_eq!(
+            None,
+            bootinfo_expectations(&unset).unwrap().max_bootinfo_caps
+        );
+        assert!(bootinfo_expectations_rust_source(&unset)
+            .unwrap()
+            .contains("pub const EXPECTED_ROOT_CNODE_SIZE_BITS: Option<usize> = None;\n"));
+    }
+
+    #[test]
+    fn bootinfo_values_must_fit_the_kernel_word() {
+        let with = |target, platform, name: &str, value| {
+            let mut config = test_helpers::fixture_config(target, platform, BuildProfile::Debug);
+            let _ = config
+                .properties
+                .insert(name.to_string(), FlatTomlValue::Integer(value));
+            bootinfo_expectations(&config)
+        };
+        let (armv7, sabre) = (SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre);
+        let (x86_64, pc99) = (SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99);
+        assert_eq!(
+            Err(BootinfoError::OutOfRange("KernelRootCNodeSizeBits", 40, 32)),
+            with(armv7, sabre, "KernelRootCNodeSizeBits", 40)
+        );
+        assert_eq!(
+            Some(1 << 40),
+            with(x86_64, pc99, "KernelRootCNodeSizeBits", 40)
+                .unwrap()
+                .max_bootinfo_caps
+        );
+        assert_eq!(
+            Err(BootinfoError::OutOfRange(
+                "KernelMaxNumBootinfoUntypedCaps",
+                -1,
+                64
+            )),
+            with(x86_64, pc99, "KernelMaxNumBootinfoUntypedCaps", -1)
+        );
+        assert_eq!(
+            Err(BootinfoError::OutOfRange(
+                "KernelMaxNumBootinfoUntypedCaps",
+                1 << 32,
+                32
+            )),
+            with(armv7, sabre, "KernelMaxNumBootinfoUntypedCaps", 1 << 32)
+        );
+        let dir = ::tempfile::tempdir().unwrap();
+        let mut config = test_helpers::fixture_config(armv7, sabre, BuildProfile::Debug);
+        let _ = config
+            .properties
+            .insert("KernelRootCNodeSizeBits".into(), FlatTomlValue::Integer(-3));
+        let path = dir.path().join("bootinfo.rs");
+        assert!(write_bootinfo_expectations_rust_source(&config, &path).is_err());
+        assert!(!path.exists());
+    }
+}
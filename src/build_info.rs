@@ -0,0 +1,281 @@
+/// A small record of the configuration an image was built with, written
+/// alongside the build artifacts and readable from deployed images' outputs
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml;
+
+use cache::config_to_cached_manifest;
+use digest::sha256_hex;
+use json::Json;
+use manifest::full_manifest_to_toml;
+use trace;
+use types::*;
+
+/// The file name `write_build_info` uses within the output directory
+pub const BUILD_INFO_FILE_NAME: &str = "fel4-build-info.toml";
+
+/// What a build was configured with
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildInfo {
+    /// The `fel4_config_hash` of the resolved configuration
+    pub config_hash: String,
+    pub target: SupportedTarget,
+    pub platform: SupportedPlatform,
+    pub build_profile: BuildProfile,
+    pub kernel_version: Option<String>,
+    pub kernel_commit: Option<String>,
+    /// Seconds since the Unix epoch at which the record was made, or the
+    /// pinned `source-date-epoch`, see `BuildInfo::new`
+    pub timestamp: u64,
+    /// The version of fel4-config that resolved the configuration
+    pub fel4_config_version: String,
+}
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum BuildInfoError {
+    #[fail(display = "Unable to read the build info file {}", _0)]
+    ReadFailure(String),
+    #[fail(display = "Unable to write the build info file {}", _0)]
+    WriteFailure(String),
+    #[fail(display = "The build info is missing or has an invalid {} field", _0)]
+    InvalidField(&'static str),
+}
+
+/// A digest identifying a resolved configuration's content.
+///
+/// Two configurations have the same hash exactly when they select the same
+/// target, platform, and build profile, resolve the same properties, and
+/// agree on paths, toolchain settings, and kernel expectations.
+pub fn fel4_config_hash(fel4_config: &Fel4Config) -> String {
+    let canonical = toml::to_string(&full_manifest_to_toml(&config_to_cached_manifest(
+        fel4_config,
+    )))
+    .expect("Resolved configurations should always serialize");
+    sha256_hex(format!("{}\n{}", fel4_config.build_profile, canonical).as_bytes())
+}
+
+impl BuildInfo {
+    /// The build info for a configuration, timestamped with its pinned
+    /// `source-date-epoch` so reproducible builds record the same info.
+    ///
+    /// A `kernel-commit` epoch needs the kernel checkout, so the
+    /// `SOURCE_DATE_EPOCH` environment variable is used in its place, as it
+    /// is for configurations without an epoch. Failing both, the record is
+    /// timestamped now.
+    pub fn new(fel4_config: &Fel4Config) -> Self {
+        let pinned = match fel4_config.source_date_epoch {
+            Some(SourceDateEpoch::Fixed(seconds)) if seconds >= 0 => Some(seconds as u64),
+            _ => trace::var("SOURCE_DATE_EPOCH")
+                .ok()
+                .and_then(|seconds| seconds.trim().parse().ok()),
+        };
+        let timestamp = pinned.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+        BuildInfo {
+            config_hash: fel4_config_hash(fel4_config),
+            target: fel4_config.target,
+            platform: fel4_config.platform,
+            build_profile: fel4_config.build_profile,
+            kernel_version: fel4_config.kernel_version.clone(),
+            kernel_commit: fel4_config.kernel_commit.clone(),
+            timestamp,
+            fel4_config_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    fn fields(&self) -> Vec<(&'static str, Option<Json>)> {
+        vec![
+            ("config-hash", Some(Json::string(self.config_hash.as_str()))),
+            ("target", Some(Json::string(self.target.full_name()))),
+            ("platform", Some(Json::string(self.platform.full_name()))),
+            (
+                "build-profile",
+                Some(Json::string(self.build_profile.full_name())),
+            ),
+            (
+                "kernel-version",
+                self.kernel_version
+                    .as_ref()
+                    .map(|v| Json::string(v.as_str())),
+            ),
+            (
+                "kernel-commit",
+                self.kernel_commit
+                    .as_ref()
+                    .map(|v| Json::string(v.as_str())),
+            ),
+            ("timestamp", Some(Json::Integer(self.timestamp as i64))),
+            (
+                "fel4-config-version",
+                Some(Json::string(self.fel4_config_version.as_str())),
+            ),
+        ]
+    }
+
+    /// The build info as a toml document, as `write_build_info` writes it
+    pub fn to_toml_string(&self) -> String {
+        let table: BTreeMap<String, toml::Value> = self
+            .fields()
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let value = match value? {
+                    Json::String(s) => toml::Value::String(s),
+                    Json::Integer(i) => toml::Value::Integer(i),
+                    _ => unreachable!("Build info fields are strings and integers"),
+                };
+                Some((name.to_string(), value))
+            })
+            .collect();
+        toml::to_string(&toml::Value::Table(table)).expect("Build info should always serialize")
+    }
+
+    /// The build info as a JSON object, for tooling outside of Rust
+    pub fn to_json_string(&self) -> String {
+        let members = self
+            .fields()
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|v| (name, v)))
+            .collect();
+        format!("{}\n", Json::object(members))
+    }
+
+    /// Parse build info from the toml produced by `to_toml_string`
+    pub fn from_toml_str(text: &str) -> Result<Self, BuildInfoError> {
+        let raw = text
+            .parse::<toml::Value>()
+            .map_err(|_| BuildInfoError::InvalidField("toml"))?;
+        let string = |name: &'static str| -> Result<String, BuildInfoError> {
+            raw.get(name)
+                .and_then(toml::Value::as_str)
+                .map(str::to_string)
+                .ok_or(BuildInfoError::InvalidField(name))
+        };
+        let optional = |name: &'static str| -> Result<Option<String>, BuildInfoError> {
+            match raw.get(name) {
+                None => Ok(None),
+                Some(_) => string(name).map(Some),
+            }
+        };
+        Ok(BuildInfo {
+            config_hash: string("config-hash")?,
+            target: string("target")?
+                .parse()
+                .map_err(|_| BuildInfoError::InvalidField("target"))?,
+            platform: string("platform")?
+                .parse()
+                .map_err(|_| BuildInfoError::InvalidField("platform"))?,
+            build_profile: string("build-profile")?
+                .parse()
+                .map_err(|_| BuildInfoError::InvalidField("build-profile"))?,
+            kernel_version: optional("kernel-version")?,
+            kernel_commit: optional("kernel-commit")?,
+            timestamp: raw
+                .get("timestamp")
+                .and_then(toml::Value::as_integer)
+                .filter(|t| *t >= 0)
+                .ok_or(BuildInfoError::InvalidField("timestamp"))? as u64,
+            fel4_config_version: string("fel4-config-version")?,
+        })
+    }
+
+    /// True if the build info describes the given configuration
+    pub fn matches(&self, fel4_config: &Fel4Config) -> bool {
+        self.config_hash == fel4_config_hash(fel4_config)
+    }
+}
+
+/// Write the `BuildInfo` for a configuration to `BUILD_INFO_FILE_NAME` within
+/// the output directory, relative to `project_dir`, returning the written path
+pub fn write_build_info<P: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    project_dir: P,
+) -> Result<PathBuf, BuildInfoError> {
+    let artifact_dir = project_dir.as_ref().join(fel4_config.output_dir());
+    let path = artifact_dir.join(BUILD_INFO_FILE_NAME);
+    fs::create_dir_all(&artifact_dir)
+        .and_then(|_| File::create(&path))
+        .and_then(|mut f| f.write_all(BuildInfo::new(fel4_config).to_toml_string().as_bytes()))
+        .map_err(|_| BuildInfoError::WriteFailure(path.display().to_string()))?;
+    Ok(path)
+}
+
+/// Read a build info file written by `write_build_info`
+pub fn read_build_info<P: AsRef<Path>>(path: P) -> Result<BuildInfo, BuildInfoError> {
+    let mut text = String::new();
+    File::open(path.as_ref())
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|_| BuildInfoError::ReadFailure(path.as_ref().display().to_string()))?;
+    BuildInfo::from_toml_str(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    fn config() -> Fel4Config {
+        test_helpers::fixture_config(
+            SupportedTarget::Aarch64Sel4Fel4,
+            SupportedPlatform::Tx1,
+            BuildProfile::Release,
+        )
+    }
+
+    #[test]
+    fn config_hash_follows_properties_and_profile() {
+        let config = config();
+        let hash = fel4_config_hash(&config);
+        assert_eq!(hash, fel4_config_hash(&config.clone()));
+        let mut changed = config.clone();
+        changed
+            .properties
+            .insert("KernelPrinting".into(), FlatTomlValue::Boolean(true));
+        assert_ne!(hash, fel4_config_hash(&changed));
+        let mut relabelled = config.clone();
+        relabelled.build_profile = BuildProfile::Debug;
+        assert_ne!(hash, fel4_config_hash(&relabelled));
+    }
+
+    #[test]
+    fn build_info_is_written_and_read_back() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let mut config = config();
+        config.kernel_version = Some("10.0.0".into());
+        let path = write_build_info(&config, dir.path()).expect("Should write the build info");
+        assert_eq!(
+            dir.path()
+                .join(config.output_dir())
+                .join(BUILD_INFO_FILE_NAME),
+            path
+        );
+        let info = read_build_info(&path).expect("Should read the build info back");
+        assert!(info.matches(&config));
+        assert_eq!(SupportedPlatform::Tx1, info.platform);
+        assert_eq!(Some("10.0.0".to_string()), info.kernel_version);
+        assert_eq!(None, info.kernel_commit);
+        assert!(info
+            .to_json_string()
+            .contains("\"kernel-version\": \"10.0.0\""));
+        assert_eq!(
+            Err(BuildInfoError::InvalidField("config-hash")),
+            BuildInfo::from_toml_str("target = \"aarch64-sel4-fel4\"\n")
+        );
+    }
+
+    #[test]
+    fn pinned_source_date_epochs_timestamp_the_build_info() {
+        let mut config = config();
+        config.source_date_epoch = Some(SourceDateEpoch::Fixed(1_500_000_000));
+        let info = BuildInfo::new(&config);
+        assert_eq!(1_500_000_000, info.timestamp);
+        assert_eq!(info, BuildInfo::new(&config));
+    }
+}
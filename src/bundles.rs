@@ -0,0 +1,775 @@
+/// Groups of related kernel options that are only meaningful together, and
+/// are validated and completed as a unit during resolution
+use std::collections::HashMap;
+
+use super::ConfigError;
+use types::*;
+
+/// The kernel's benchmarking support, from `KernelBenchmarks`
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum BenchmarkMode {
+    /// Generic trace points, recorded in a buffer of `KernelMaxNumTracePoints`
+    Generic,
+    /// Every kernel entry is logged
+    TrackKernelEntries,
+    /// Per-thread CPU utilisation is tracked
+    TrackUtilisation,
+}
+const BENCHMARK_MODE_GENERIC: &str = "generic";
+const BENCHMARK_MODE_TRACK_KERNEL_ENTRIES: &str = "track_kernel_entries";
+const BENCHMARK_MODE_TRACK_UTILISATION: &str = "track_utilisation";
+impl BenchmarkMode {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            BenchmarkMode::Generic => BENCHMARK_MODE_GENERIC,
+            BenchmarkMode::TrackKernelEntries => BENCHMARK_MODE_TRACK_KERNEL_ENTRIES,
+            BenchmarkMode::TrackUtilisation => BENCHMARK_MODE_TRACK_UTILISATION,
+        }
+    }
+
+    pub fn modes() -> Vec<BenchmarkMode> {
+        vec![
+            BenchmarkMode::Generic,
+            BenchmarkMode::TrackKernelEntries,
+            BenchmarkMode::TrackUtilisation,
+        ]
+    }
+}
+
+/// A typed view of the benchmarks bundle of a resolved configuration
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BenchmarkConfig {
+    pub mode: BenchmarkMode,
+    /// The capacity of the trace point buffer, only nonzero in
+    /// `BenchmarkMode::Generic`
+    pub max_trace_points: i64,
+    /// Whether userland may read the performance counters, which is always
+    /// the case for a valid benchmarks bundle
+    pub export_performance_counters: bool,
+}
+
+impl Fel4Config {
+    /// The benchmarks bundle, or None if `KernelBenchmarks` is absent or
+    /// `"none"`
+    pub fn benchmarks(&self) -> Option<BenchmarkConfig> {
+        let mode = benchmark_mode(&self.properties).ok()??;
+        Some(BenchmarkConfig {
+            mode,
+            max_trace_points: match self.properties.get(MAX_TRACE_POINTS) {
+                Some(FlatTomlValue::Integer(i)) => *i,
+                _ => 0,
+            },
+            export_performance_counters: self
+                .properties
+                .get(performance_counter_export_property(self.target))
+                == Some(&FlatTomlValue::Boolean(true)),
+        })
+    }
+}
+
+const BENCHMARKS: &str = "KernelBenchmarks";
+const MAX_TRACE_POINTS: &str = "KernelMaxNumTracePoints";
+
+/// The property that exports the performance counters to userland, which
+/// differs between architectures
+pub(crate) fn performance_counter_export_property(target: SupportedTarget) -> &'static str {
+    match target {
+        SupportedTarget::X8664Sel4Fel4 => "KernelExportPMCUser",
+        SupportedTarget::Armv7Sel4Fel4 | SupportedTarget::Aarch64Sel4Fel4 => {
+            "KernelArmExportPMUUser"
+        }
+    }
+}
+
+fn benchmark_mode(
+    properties: &HashMap<String, FlatTomlValue>,
+) -> Result<Option<BenchmarkMode>, ConfigError> {
+    let invalid = || {
+        let mut names = vec!["none".to_string()];
+        names.extend(BenchmarkMode::modes().iter().map(|m| m.full_name().into()));
+        ConfigError::InvalidValueOption(
+            BENCHMARKS,
+            names,
+            properties[BENCHMARKS].to_string(),
+        )
+    };
+    match properties.get(BENCHMARKS) {
+        None => Ok(None),
+        Some(FlatTomlValue::String(s)) if s == "none" => Ok(None),
+        Some(FlatTomlValue::String(s)) => BenchmarkMode::modes()
+            .into_iter()
+            .find(|m| m.full_name() == s)
+            .map(Some)
+            .ok_or_else(invalid),
+        Some(_) => Err(invalid()),
+    }
+}
+
+/// Validate and complete the benchmarks bundle.
+///
+/// When benchmarking is enabled, the performance counters are exported to
+/// userland, filling in the architecture's export property if absent, and
+/// a trace point buffer may only be sized for `BenchmarkMode::Generic`.
+fn apply_benchmark_bundle(
+    properties: &mut HashMap<String, FlatTomlValue>,
+    target: SupportedTarget,
+) -> Result<(), ConfigError> {
+    let mode = match benchmark_mode(properties)? {
+        None => return Ok(()),
+        Some(mode) => mode,
+    };
+    let export = performance_counter_export_property(target);
+    match properties.get(export) {
+        None => {
+            fel4_trace!("rules", "implied {} = true", export);
+            properties.insert(export.to_string(), FlatTomlValue::Boolean(true));
+        }
+        Some(FlatTomlValue::Boolean(true)) => {}
+        Some(_) => {
+            return Err(ConfigError::InvalidBundle(
+                "benchmarks",
+                format!("{} must be true when {} is enabled", export, BENCHMARKS),
+            ))
+        }
+    }
+    match (mode, properties.get(MAX_TRACE_POINTS)) {
+        (_, None) => Ok(()),
+        (_, Some(FlatTomlValue::Integer(0))) => Ok(()),
+        (BenchmarkMode::Generic, Some(FlatTomlValue::Integer(i))) if *i > 0 => Ok(()),
+        (BenchmarkMode::Generic, Some(_)) => Err(ConfigError::InvalidBundle(
+            "benchmarks",
+            format!("{} must be a non-negative integer", MAX_TRACE_POINTS),
+        )),
+        (mode, Some(_)) => Err(ConfigError::InvalidBundle(
+            "benchmarks",
+            format!(
+                "{} only applies to generic benchmarks, not {}",
+                MAX_TRACE_POINTS,
+                mode.full_name()
+            ),
+        )),
+    }
+}
+
+/// How the kernel exposes hardware breakpoints and watchpoints to userland
+/// debuggers, from `HardwareDebugAPI` and the target architecture
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum HardwareDebug {
+    Disabled,
+    /// The x86 debug registers, DR0 through DR7
+    X86DebugRegisters,
+    /// The ARMv7 CP14 debug coprocessor
+    ArmDebugCoprocessor,
+}
+
+impl Fel4Config {
+    /// The hardware debug support of the configuration
+    pub fn hardware_debug(&self) -> HardwareDebug {
+        if self.properties.get(HARDWARE_DEBUG_API) != Some(&FlatTomlValue::Boolean(true)) {
+            return HardwareDebug::Disabled;
+        }
+        hardware_debug_mechanism(self.target).unwrap_or(HardwareDebug::Disabled)
+    }
+}
+
+const HARDWARE_DEBUG_API: &str = "HardwareDebugAPI";
+
+/// The kernel releases fel4 builds only implement the hardware debug API for
+/// x86 and ARMv7
+fn hardware_debug_mechanism(target: SupportedTarget) -> Option<HardwareDebug> {
+    match target {
+        SupportedTarget::X8664Sel4Fel4 => Some(HardwareDebug::X86DebugRegisters),
+        SupportedTarget::Armv7Sel4Fel4 => Some(HardwareDebug::ArmDebugCoprocessor),
+        SupportedTarget::Aarch64Sel4Fel4 => None,
+    }
+}
+
+/// Validate and complete the hardware debug bundle.
+///
+/// The hardware debug API must be supported by the target architecture, is
+/// only built into debug kernels, filling in `KernelDebugBuild` if absent,
+/// and cannot be combined with `KernelVerificationBuild`.
+fn apply_hardware_debug_bundle(
+    properties: &mut HashMap<String, FlatTomlValue>,
+    target: SupportedTarget,
+) -> Result<(), ConfigError> {
+    let invalid = |reason: String| Err(ConfigError::InvalidBundle("hardware debug", reason));
+    match properties.get(HARDWARE_DEBUG_API) {
+        None | Some(FlatTomlValue::Boolean(false)) => return Ok(()),
+        Some(FlatTomlValue::Boolean(true)) => {}
+        Some(_) => return invalid(format!("{} must be a boolean", HARDWARE_DEBUG_API)),
+    }
+    if hardware_debug_mechanism(target).is_none() {
+        return invalid(format!(
+            "{} is not supported for the {} target",
+            HARDWARE_DEBUG_API, target
+        ));
+    }
+    match properties.get("KernelDebugBuild") {
+        None => {
+            fel4_trace!("rules", "implied KernelDebugBuild = true");
+            properties.insert("KernelDebugBuild".to_string(), FlatTomlValue::Boolean(true));
+        }
+        Some(FlatTomlValue::Boolean(true)) => {}
+        Some(_) => {
+            return invalid(format!(
+                "{} requires KernelDebugBuild to be true",
+                HARDWARE_DEBUG_API
+            ))
+        }
+    }
+    if properties.get("KernelVerificationBuild") == Some(&FlatTomlValue::Boolean(true)) {
+        return invalid(format!(
+            "{} cannot be enabled in a KernelVerificationBuild",
+            HARDWARE_DEBUG_API
+        ));
+    }
+    Ok(())
+}
+
+const FPU_CONTEXT_SWITCH: &str = "KernelAArch32FPUEnableContextSwitch";
+
+/// Validate and complete the float ABI bundle.
+///
+/// A `float-abi` may only be given for ARM targets, and aarch64 kernels are
+/// always hard float. On armv7 the kernel preserves FPU state across threads
+/// for hard float only, filling in `KernelAArch32FPUEnableContextSwitch` if
+/// absent. A `compiler-prefix` naming a Linux gnueabi toolchain must be the
+/// hard or soft float one to match.
+fn apply_float_abi_bundle(
+    properties: &mut HashMap<String, FlatTomlValue>,
+    target: SupportedTarget,
+    toolchain: &ToolchainSettings,
+) -> Result<(), ConfigError> {
+    let invalid = |reason: String| Err(ConfigError::InvalidBundle("float ABI", reason));
+    let abi = match toolchain.float_abi {
+        None => return Ok(()),
+        Some(abi) => abi,
+    };
+    match (target, abi) {
+        (SupportedTarget::X8664Sel4Fel4, _) => {
+            return invalid(format!("float-abi does not apply to the {} target", target))
+        }
+        (SupportedTarget::Aarch64Sel4Fel4, FloatAbi::Soft) => {
+            return invalid(format!(
+                "the {} target only supports the {} float ABI",
+                target,
+                FloatAbi::Hard
+            ))
+        }
+        (SupportedTarget::Armv7Sel4Fel4, _) => {
+            let context_switch = abi == FloatAbi::Hard;
+            match properties.get(FPU_CONTEXT_SWITCH) {
+                None => {
+                    fel4_trace!("rules", "implied {} = {}", FPU_CONTEXT_SWITCH, context_switch);
+                    properties.insert(
+                        FPU_CONTEXT_SWITCH.to_string(),
+                        FlatTomlValue::Boolean(context_switch),
+                    );
+                }
+                Some(FlatTomlValue::Boolean(b)) if *b == context_switch => {}
+                Some(_) => {
+                    return invalid(format!(
+                        "{} must be {} for the {} float ABI",
+                        FPU_CONTEXT_SWITCH, context_switch, abi
+                    ))
+                }
+            }
+        }
+        _ => {}
+    }
+    let prefix = match toolchain.compiler_prefix {
+        None => return Ok(()),
+        Some(ref prefix) => prefix,
+    };
+    let prefix_abi = if prefix.contains("gnueabihf") {
+        FloatAbi::Hard
+    } else if prefix.contains("gnueabi") {
+        FloatAbi::Soft
+    } else {
+        return Ok(());
+    };
+    if prefix_abi != abi {
+        return invalid(format!(
+            "the {} compiler prefix is for the {} float ABI, not {}",
+            prefix, prefix_abi, abi
+        ));
+    }
+    Ok(())
+}
+
+const ARM_PLATFORM: &str = "KernelARMPlatform";
+const SMMU: &str = "KernelArmSMMU";
+const GIC_V3: &str = "KernelArmGicV3";
+const ARM_CONSOLE_DEVICE: &str = "LibPlatSupportArmConsoleDevice";
+
+/// The peripherals of a Jetson-class board that the kernel and platform
+/// support options must agree with
+struct JetsonBoard {
+    platform: SupportedPlatform,
+    /// Whether the interrupt controller is a GICv3 rather than a GICv2
+    gic_v3: bool,
+    /// Whether the kernel has a driver for the board's SMMU
+    smmu: bool,
+    /// The serial ports the platform support library can use as a console
+    consoles: &'static [&'static str],
+}
+
+const TEGRA_UARTS: &[&str] = &["uarta", "uartb", "uartc", "uartd"];
+
+const JETSON_BOARDS: &[JetsonBoard] = &[
+    JetsonBoard {
+        platform: SupportedPlatform::Tx1,
+        gic_v3: false,
+        smmu: false,
+        consoles: TEGRA_UARTS,
+    },
+    JetsonBoard {
+        platform: SupportedPlatform::Tx2,
+        gic_v3: false,
+        smmu: true,
+        consoles: TEGRA_UARTS,
+    },
+];
+
+/// Validate the Jetson board bundle.
+///
+/// `KernelArmSMMU` is only accepted on a board the kernel has an SMMU
+/// driver for. On a Jetson-class board, `KernelARMPlatform` is a
+/// prerequisite that must be set to name the board, since the kernel would
+/// otherwise build for its default platform. `KernelArmGicV3` must match
+/// the board's interrupt controller and `LibPlatSupportArmConsoleDevice`
+/// must be one of its serial ports, but either may be left unset, as the
+/// kernel and platform support library default them from the platform.
+fn apply_jetson_bundle(
+    properties: &HashMap<String, FlatTomlValue>,
+    platform: SupportedPlatform,
+) -> Result<(), ConfigError> {
+    let invalid = |reason: String| Err(ConfigError::InvalidBundle("jetson", reason));
+    let board = JETSON_BOARDS.iter().find(|b| b.platform == platform);
+    match properties.get(SMMU) {
+        None | Some(FlatTomlValue::Boolean(false)) => {}
+        Some(FlatTomlValue::Boolean(true)) if board.is_some_and(|b| b.smmu) => {}
+        Some(FlatTomlValue::Boolean(true)) => {
+            return invalid(format!(
+                "{} is not supported on the {} platform, which has no SMMU driver",
+                SMMU, platform
+            ))
+        }
+        Some(_) => return invalid(format!("{} must be a boolean", SMMU)),
+    }
+    let board = match board {
+        None => return Ok(()),
+        Some(board) => board,
+    };
+    match properties.get(ARM_PLATFORM) {
+        None => {
+            return invalid(format!(
+                "{} must be set to {} for the {} platform",
+                ARM_PLATFORM, platform, platform
+            ))
+        }
+        Some(FlatTomlValue::String(s)) if s != platform.full_name() => {
+            return invalid(format!(
+                "{} must be {} for the {} platform, not {}",
+                ARM_PLATFORM, platform, platform, s
+            ))
+        }
+        _ => {}
+    }
+    match properties.get(GIC_V3) {
+        Some(FlatTomlValue::Boolean(b)) if *b != board.gic_v3 => {
+            return invalid(format!(
+                "{} must be {}, since the {} interrupt controller is a GICv{}",
+                GIC_V3,
+                board.gic_v3,
+                platform,
+                if board.gic_v3 { 3 } else { 2 }
+            ))
+        }
+        _ => {}
+    }
+    match properties.get(ARM_CONSOLE_DEVICE) {
+        Some(FlatTomlValue::String(s)) if !board.consoles.contains(&s.as_str()) => invalid(
+            format!(
+                "{} must be one of {}, the {} serial ports, not {}",
+                ARM_CONSOLE_DEVICE,
+                board.consoles.join(", "),
+                platform,
+                s
+            ),
+        ),
+        _ => Ok(()),
+    }
+}
+
+const VERIFICATION_BUILD: &str = "KernelVerificationBuild";
+
+/// The platforms the seL4 proofs cover for each target
+fn verified_platform(target: SupportedTarget) -> Option<SupportedPlatform> {
+    match target {
+        SupportedTarget::X8664Sel4Fel4 => Some(SupportedPlatform::PC99),
+        SupportedTarget::Armv7Sel4Fel4 => Some(SupportedPlatform::Sabre),
+        SupportedTarget::Aarch64Sel4Fel4 => None,
+    }
+}
+
+/// Options a verification build must leave at the value the proofs assume,
+/// where the configuration sets them, with what the proofs don't cover
+const VERIFIED_OPTIONS: &[(&str, FlatTomlValue, &str)] = &[
+    ("KernelMaxNumNodes", FlatTomlValue::Integer(1), "multicore kernels"),
+    ("KernelFastpath", FlatTomlValue::Boolean(true), "kernels without the fastpath"),
+    ("KernelDebugBuild", FlatTomlValue::Boolean(false), "debug kernels"),
+    ("KernelPrinting", FlatTomlValue::Boolean(false), "kernel printing"),
+    ("KernelArmHypervisorSupport", FlatTomlValue::Boolean(false), "the hypervisor extensions"),
+    ("KernelVTX", FlatTomlValue::Boolean(false), "VT-x virtualization"),
+    ("KernelIOMMU", FlatTomlValue::Boolean(false), "the IOMMU"),
+];
+
+/// The most domains the proofs' domain scheduler is instantiated for
+const MAX_VERIFIED_DOMAINS: i64 = 256;
+
+/// Validate the verification bundle.
+///
+/// A `KernelVerificationBuild` is only accepted for the target and platform
+/// pairs the seL4 proofs cover, on a single core, with the fastpath, without
+/// debugging or benchmarking support, without virtualization, and with a
+/// domain count the proofs cover.
+fn apply_verification_bundle(
+    properties: &HashMap<String, FlatTomlValue>,
+    target: SupportedTarget,
+    platform: SupportedPlatform,
+) -> Result<(), ConfigError> {
+    let invalid = |reason: String| Err(ConfigError::InvalidBundle("verification", reason));
+    match properties.get(VERIFICATION_BUILD) {
+        None | Some(FlatTomlValue::Boolean(false)) => return Ok(()),
+        Some(FlatTomlValue::Boolean(true)) => {}
+        Some(_) => return invalid(format!("{} must be a boolean", VERIFICATION_BUILD)),
+    }
+    if verified_platform(target) != Some(platform) {
+        return invalid(format!(
+            "the seL4 proofs don't cover the {} target on the {} platform",
+            target, platform
+        ));
+    }
+    for &(name, ref verified, unverified) in VERIFIED_OPTIONS {
+        match properties.get(name) {
+            Some(value) if value != verified => {
+                return invalid(format!(
+                    "{} must be {}, since the seL4 proofs don't cover {}",
+                    name, verified, unverified
+                ))
+            }
+            _ => {}
+        }
+    }
+    if benchmark_mode(properties)?.is_some() {
+        return invalid(format!(
+            "{} must be none, since the seL4 proofs don't cover benchmarking",
+            BENCHMARKS
+        ));
+    }
+    match properties.get("KernelNumDomains") {
+        None => Ok(()),
+        Some(FlatTomlValue::Integer(n)) if *n >= 1 && *n <= MAX_VERIFIED_DOMAINS => Ok(()),
+        Some(value) => invalid(format!(
+            "KernelNumDomains must be from 1 to {}, not {}, for the seL4 proofs to cover it",
+            MAX_VERIFIED_DOMAINS, value
+        )),
+    }
+}
+
+/// Validate and complete every bundle within a resolved property set, after
+/// the `PropertyRule`s have been applied
+pub(crate) fn apply_property_bundles(
+    properties: &mut HashMap<String, FlatTomlValue>,
+    target: SupportedTarget,
+    platform: SupportedPlatform,
+    toolchain: &ToolchainSettings,
+) -> Result<(), ConfigError> {
+    apply_benchmark_bundle(properties, target)?;
+    apply_hardware_debug_bundle(properties, target)?;
+    apply_float_abi_bundle(properties, target, toolchain)?;
+    apply_jetson_bundle(properties, platform)?;
+    apply_verification_bundle(properties, target, platform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    fn with_benchmarks(mode: &str) -> test_helpers::ManifestFixture {
+        test_helpers::ManifestFixture::new()
+            .with_property("x86_64-sel4-fel4", "KernelBenchmarks", mode)
+            .without_property("x86_64-sel4-fel4", "KernelExportPMCUser")
+    }
+
+    #[test]
+    fn benchmarks_imply_exported_performance_counters() {
+        let config = with_benchmarks("generic")
+            .with_property("x86_64-sel4-fel4", MAX_TRACE_POINTS, 64)
+            .resolve(&BuildProfile::Debug)
+            .expect("Should resolve the benchmarks bundle");
+        assert_eq!(
+            Some(BenchmarkConfig {
+                mode: BenchmarkMode::Generic,
+                max_trace_points: 64,
+                export_performance_counters: true,
+            }),
+            config.benchmarks()
+        );
+        assert_eq!(
+            Some(&PropertyLayer::Rule),
+            config.provenance.get("KernelExportPMCUser")
+        );
+        assert_eq!(
+            None,
+            test_helpers::fixture_config(
+                SupportedTarget::X8664Sel4Fel4,
+                SupportedPlatform::PC99,
+                BuildProfile::Debug
+            )
+            .benchmarks()
+        );
+    }
+
+    #[test]
+    fn inconsistent_benchmarks_bundles_are_rejected() {
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "benchmarks",
+                "KernelExportPMCUser must be true when KernelBenchmarks is enabled".into()
+            )),
+            with_benchmarks("track_utilisation")
+                .with_property("x86_64-sel4-fel4", "KernelExportPMCUser", false)
+                .resolve(&BuildProfile::Debug)
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "benchmarks",
+                "KernelMaxNumTracePoints only applies to generic benchmarks, not track_kernel_entries"
+                    .into()
+            )),
+            with_benchmarks("track_kernel_entries")
+                .with_property("x86_64-sel4-fel4", MAX_TRACE_POINTS, 16)
+                .resolve(&BuildProfile::Debug)
+        );
+        assert!(matches!(
+            with_benchmarks("everything").resolve(&BuildProfile::Debug),
+            Err(ConfigError::InvalidValueOption(BENCHMARKS, _, _))
+        ));
+    }
+
+    #[test]
+    fn hardware_debug_is_validated_as_a_unit() {
+        let with_debug = |target: &str| {
+            test_helpers::ManifestFixture::new().with_property(target, HARDWARE_DEBUG_API, true)
+        };
+        assert_eq!(
+            HardwareDebug::X86DebugRegisters,
+            with_debug("x86_64-sel4-fel4")
+                .resolve(&BuildProfile::Debug)
+                .expect("Should resolve the hardware debug bundle")
+                .hardware_debug()
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "hardware debug",
+                "HardwareDebugAPI requires KernelDebugBuild to be true".into()
+            )),
+            with_debug("x86_64-sel4-fel4").resolve(&BuildProfile::Release)
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "hardware debug",
+                "HardwareDebugAPI cannot be enabled in a KernelVerificationBuild".into()
+            )),
+            with_debug("x86_64-sel4-fel4")
+                .with_property("x86_64-sel4-fel4", "KernelVerificationBuild", true)
+                .resolve(&BuildProfile::Debug)
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "hardware debug",
+                "HardwareDebugAPI is not supported for the aarch64-sel4-fel4 target".into()
+            )),
+            with_debug("aarch64-sel4-fel4")
+                .with_property("fel4", "target", "aarch64-sel4-fel4")
+                .with_property("fel4", "platform", "tx1")
+                .resolve(&BuildProfile::Debug)
+        );
+    }
+
+    #[test]
+    fn float_abi_agrees_with_the_fpu_options_and_compiler_prefix() {
+        let with_abi = |abi: &str| {
+            test_helpers::ManifestFixture::for_selection(
+                SupportedTarget::Armv7Sel4Fel4,
+                SupportedPlatform::Sabre,
+            ).with_property("toolchain.armv7-sel4-fel4", "float-abi", abi)
+                .without_property("armv7-sel4-fel4", FPU_CONTEXT_SWITCH)
+        };
+        let soft = with_abi("sf")
+            .resolve(&BuildProfile::Debug)
+            .expect("Should resolve the float ABI bundle");
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(false)),
+            soft.properties.get(FPU_CONTEXT_SWITCH)
+        );
+        assert_eq!(Some(&PropertyLayer::Rule), soft.provenance.get(FPU_CONTEXT_SWITCH));
+        assert_eq!(Some(FloatAbi::Soft), soft.float_abi());
+        assert_eq!(
+            Some("arm-linux-gnueabi-"),
+            configured_compiler_prefix(&soft, HostOs::Linux)
+        );
+
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "float ABI",
+                "KernelAArch32FPUEnableContextSwitch must be true for the hf float ABI".into()
+            )),
+            with_abi("hf")
+                .with_property("armv7-sel4-fel4", FPU_CONTEXT_SWITCH, false)
+                .resolve(&BuildProfile::Debug)
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "float ABI",
+                "the arm-linux-gnueabihf- compiler prefix is for the hf float ABI, not sf".into()
+            )),
+            with_abi("sf")
+                .with_property(
+                    "toolchain.armv7-sel4-fel4",
+                    "compiler-prefix",
+                    "arm-linux-gnueabihf-"
+                )
+                .resolve(&BuildProfile::Debug)
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "float ABI",
+                "the aarch64-sel4-fel4 target only supports the hf float ABI".into()
+            )),
+            test_helpers::ManifestFixture::for_selection(
+                SupportedTarget::Aarch64Sel4Fel4,
+                SupportedPlatform::Tx1,
+            ).with_property("toolchain.aarch64-sel4-fel4", "float-abi", "sf")
+                .resolve(&BuildProfile::Debug)
+        );
+    }
+
+    #[test]
+    fn jetson_options_must_match_the_board() {
+        let tx2 = test_helpers::ManifestFixture::for_selection(
+            SupportedTarget::Aarch64Sel4Fel4,
+            SupportedPlatform::Tx2,
+        );
+        let config = tx2
+            .resolve(&BuildProfile::Release)
+            .expect("Should resolve the tx2 exemplar");
+        assert_eq!(Some(&FlatTomlValue::Boolean(true)), config.properties.get(SMMU));
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "jetson",
+                "KernelArmSMMU is not supported on the tx1 platform, which has no SMMU driver"
+                    .into()
+            )),
+            test_helpers::ManifestFixture::for_selection(
+                SupportedTarget::Aarch64Sel4Fel4,
+                SupportedPlatform::Tx1,
+            ).with_property("aarch64-sel4-fel4.tx1", SMMU, true)
+                .resolve(&BuildProfile::Release)
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "jetson",
+                "KernelArmGicV3 must be false, since the tx2 interrupt controller is a GICv2"
+                    .into()
+            )),
+            tx2.clone()
+                .with_property("aarch64-sel4-fel4.tx2", GIC_V3, true)
+                .resolve(&BuildProfile::Release)
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "jetson",
+                "KernelARMPlatform must be set to tx2 for the tx2 platform".into()
+            )),
+            tx2.clone()
+                .without_property("aarch64-sel4-fel4.tx2", ARM_PLATFORM)
+                .resolve(&BuildProfile::Release)
+        );
+        assert!(tx2
+            .clone()
+            .without_property("aarch64-sel4-fel4.tx2", GIC_V3)
+            .without_property("aarch64-sel4-fel4.tx2", ARM_CONSOLE_DEVICE)
+            .resolve(&BuildProfile::Release)
+            .is_ok());
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "jetson",
+                "KernelARMPlatform must be tx2 for the tx2 platform, not tx1".into()
+            )),
+            tx2.clone()
+                .with_property("aarch64-sel4-fel4.tx2", ARM_PLATFORM, "tx1")
+                .resolve(&BuildProfile::Release)
+        );
+        assert!(matches!(
+            tx2.with_property("aarch64-sel4-fel4.tx2", ARM_CONSOLE_DEVICE, "ttyS0")
+                .resolve(&BuildProfile::Release),
+            Err(ConfigError::InvalidBundle("jetson", ref reason))
+                if reason.ends_with("uartd, the tx2 serial ports, not ttyS0")
+        ));
+    }
+
+    #[test]
+    fn verification_builds_are_limited_to_what_the_proofs_cover() {
+        let verified = |target: SupportedTarget, platform: SupportedPlatform| {
+            test_helpers::ManifestFixture::for_selection(target, platform)
+                .with_property(target.full_name(), VERIFICATION_BUILD, true)
+        };
+        let pc99 = verified(SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99);
+        assert!(pc99.resolve(&BuildProfile::Release).is_ok());
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "verification",
+                "KernelDebugBuild must be false, since the seL4 proofs don't cover debug kernels"
+                    .into()
+            )),
+            pc99.resolve(&BuildProfile::Debug)
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "verification",
+                "KernelMaxNumNodes must be 1, since the seL4 proofs don't cover multicore kernels"
+                    .into()
+            )),
+            pc99.clone()
+                .with_property("x86_64-sel4-fel4", "KernelMaxNumNodes", 4)
+                .resolve(&BuildProfile::Release)
+        );
+        assert!(matches!(
+            pc99.with_property("x86_64-sel4-fel4", "KernelNumDomains", 0)
+                .resolve(&BuildProfile::Release),
+            Err(ConfigError::InvalidBundle("verification", ref reason))
+                if reason.starts_with("KernelNumDomains must be from 1 to 256")
+        ));
+        assert!(
+            verified(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
+                .resolve(&BuildProfile::Release)
+                .is_ok()
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidBundle(
+                "verification",
+                "the seL4 proofs don't cover the aarch64-sel4-fel4 target on the tx1 platform"
+                    .into()
+            )),
+            verified(SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1)
+                .resolve(&BuildProfile::Release)
+        );
+    }
+}
@@ -0,0 +1,224 @@
+/// Opt-in caching of resolved fel4 configurations between build script runs
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use super::{resolve_fel4_config_with_options, ConfigError, ResolutionOptions};
+use digest::sha256_hex;
+use freeze::{freeze_fel4_config, thaw_fel4_config};
+use manifest::{decode_manifest, parse_full_manifest, FullFel4Manifest, FullFel4Target};
+use policy::policy_alongside;
+use types::*;
+
+/// The cache key for a manifest's contents resolved for a build profile.
+///
+/// The key also covers the version of this crate, so that upgrading
+/// fel4-config never returns a configuration resolved by older rules.
+pub fn fel4_config_cache_key(manifest_contents: &[u8], build_profile: &BuildProfile) -> String {
+    let mut keyed = Vec::with_capacity(manifest_contents.len() + 32);
+    keyed.extend_from_slice(env!("CARGO_PKG_VERSION").as_bytes());
+    keyed.push(0);
+    keyed.extend_from_slice(build_profile.full_name().as_bytes());
+    keyed.push(0);
+    keyed.extend_from_slice(manifest_contents);
+    sha256_hex(&keyed)
+}
+
+/// Load, parse, and resolve a Fel4Config as `get_fel4_config` does, reusing
+/// a previously resolved configuration stored in `cache_dir` when the
+/// manifest is unchanged.
+///
+/// The cache is best-effort: a missing, unreadable, or corrupt entry is
+/// resolved afresh, and failing to store a new entry is not an error.
+pub fn get_fel4_config_cached<P: AsRef<Path>, Q: AsRef<Path>>(
+    fel4_manifest_path: P,
+    build_profile: &BuildProfile,
+    cache_dir: Q,
+) -> Result<Fel4Config, ConfigError> {
+    let manifest_path = fel4_manifest_path.as_ref().to_path_buf();
+    let mut contents = Vec::new();
+    File::open(&manifest_path)
+        .and_then(|mut f| f.read_to_end(&mut contents))
+        .map_err(|_| ConfigError::FileReadFailure)?;
+    let entry = cache_entry_path(
+        cache_dir.as_ref(),
+        &fel4_config_cache_key(&contents, build_profile),
+    );
+    let with_path = |config| Fel4Config {
+        manifest_path: Some(manifest_path.clone()),
+        ..config
+    };
+    let policy = policy_alongside(&manifest_path)?;
+    if let Some(config) = read_cache_entry(&entry, build_profile) {
+        if let Some(ref policy) = policy {
+            policy.check(&config)?;
+        }
+        return Ok(with_path(config));
+    }
+
+    let text = decode_manifest(&contents)?;
+    let options = ResolutionOptions::new().policy(policy);
+    let (config, _warnings) =
+        resolve_fel4_config_with_options(parse_full_manifest(text)?, build_profile, &options)?;
+    let _ = write_cache_entry(&entry, &config);
+    Ok(with_path(config))
+}
+
+fn cache_entry_path(cache_dir: &Path, key: &str) -> PathBuf {
+    cache_dir.join(format!("fel4-config-{}.bin", key))
+}
+
+/// Entries are frozen configurations, see `freeze_fel4_config`
+fn read_cache_entry(entry: &Path, build_profile: &BuildProfile) -> Option<Fel4Config> {
+    let frozen = fs::read(entry).ok()?;
+    thaw_fel4_config(&frozen)
+        .ok()
+        .filter(|config| config.build_profile == *build_profile)
+}
+
+/// Written to a temporary file and renamed into place, so concurrent build
+/// scripts never observe a partial entry
+fn write_cache_entry(entry: &Path, config: &Fel4Config) -> Result<(), ::std::io::Error> {
+    if let Some(parent) = entry.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let staging = entry.with_extension(format!("bin.{}.partial", ::std::process::id()));
+    File::create(&staging).and_then(|mut f| f.write_all(&freeze_fel4_config(config)))?;
+    fs::rename(&staging, entry)
+}
+
+/// A resolved configuration as a manifest whose selected target table
+/// directly holds every resolved property, in `ordered_properties` order,
+/// alongside empty tables for the other declared targets
+pub(crate) fn config_to_cached_manifest(config: &Fel4Config) -> FullFel4Manifest {
+    let direct_properties = PropertySet::from_properties(
+        config
+            .ordered_properties()
+            .map(|(name, value)| FlatTomlProperty::new(name.to_string(), value.clone())),
+    ).expect("Resolved property names are unique");
+    let mut targets: HashMap<SupportedTarget, FullFel4Target> = config
+        .declared_targets
+        .iter()
+        .map(|&identity| {
+            (
+                identity,
+                FullFel4Target {
+                    identity,
+                    direct_properties: PropertySet::new(),
+                    build_profile_properties: HashMap::new(),
+                    platform_properties: HashMap::new(),
+                    conditional_properties: BTreeMap::new(),
+                    derived_properties: BTreeMap::new(),
+                    rootserver: RootserverSettings::default(),
+                    test_properties: PropertySet::new(),
+                },
+            )
+        })
+        .collect();
+    targets.insert(
+        config.target,
+        FullFel4Target {
+            identity: config.target,
+            direct_properties,
+            build_profile_properties: HashMap::new(),
+            platform_properties: HashMap::new(),
+            conditional_properties: BTreeMap::new(),
+            derived_properties: BTreeMap::new(),
+            rootserver: config.rootserver.clone(),
+            test_properties: PropertySet::new(),
+        },
+    );
+    let mut cmake_build_types = HashMap::new();
+    if config.cmake_build_type != config.build_profile.default_cmake_build_type() {
+        cmake_build_types.insert(config.build_profile, config.cmake_build_type.clone());
+    }
+    let mut toolchains = HashMap::new();
+    if config.toolchain != ToolchainSettings::default() {
+        toolchains.insert(config.target, config.toolchain.clone());
+    }
+    FullFel4Manifest {
+        artifact_path: config.artifact_path.clone(),
+        artifact_layout: config.artifact_layout,
+        target_specs_path: config.target_specs_path.clone(),
+        selected_target: config.target,
+        selected_platform: config.platform,
+        deployment: None,
+        targets,
+        toolchains,
+        kernel_source: config.kernel_source.clone(),
+        kernel_version: config.kernel_version.clone(),
+        kernel_commit: config.kernel_commit.clone(),
+        image_name: config.image_name.clone(),
+        image_name_pattern: config.image_name_pattern.clone(),
+        source_date_epoch: config.source_date_epoch,
+        cmake_build_types,
+        property_scopes: config.property_scopes.clone(),
+        locked_properties: config.locked_properties.clone(),
+        presets: BTreeMap::new(),
+        variants: BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn cached_config_matches_uncached_resolution() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("fel4.toml");
+        let cache_dir = dir.path().join("cache");
+        fs::write(&manifest_path, get_exemplar_default_toml()).unwrap();
+        let expected = get_fel4_config(&manifest_path, &BuildProfile::Release).unwrap();
+
+        let first = get_fel4_config_cached(&manifest_path, &BuildProfile::Release, &cache_dir)
+            .expect("Should resolve and store the config");
+        assert_eq!(expected, first);
+        let key = fel4_config_cache_key(
+            get_exemplar_default_toml().as_bytes(),
+            &BuildProfile::Release,
+        );
+        let entry = cache_entry_path(&cache_dir, &key);
+        assert!(entry.is_file());
+
+        let second = get_fel4_config_cached(&manifest_path, &BuildProfile::Release, &cache_dir)
+            .expect("Should load the cached config");
+        assert_eq!(expected, second);
+
+        let mut stored = thaw_fel4_config(&fs::read(&entry).unwrap()).unwrap();
+        stored
+            .properties
+            .insert("KernelPrinting".into(), FlatTomlValue::Boolean(true));
+        fs::write(&entry, freeze_fel4_config(&stored)).unwrap();
+        let from_cache =
+            get_fel4_config_cached(&manifest_path, &BuildProfile::Release, &cache_dir).unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(true)),
+            from_cache.properties.get("KernelPrinting")
+        );
+
+        fs::write(&entry, "not a manifest").unwrap();
+        assert_eq!(
+            Ok(expected),
+            get_fel4_config_cached(&manifest_path, &BuildProfile::Release, &cache_dir)
+        );
+    }
+
+    #[test]
+    fn cache_key_follows_manifest_and_profile() {
+        let manifest = get_exemplar_default_toml().as_bytes();
+        let debug = fel4_config_cache_key(manifest, &BuildProfile::Debug);
+        assert_eq!(64, debug.len());
+        assert_eq!(debug, fel4_config_cache_key(manifest, &BuildProfile::Debug));
+        assert_ne!(
+            debug,
+            fel4_config_cache_key(manifest, &BuildProfile::Release)
+        );
+        assert_ne!(
+            debug,
+            fel4_config_cache_key(b"[fel4]\n", &BuildProfile::Debug)
+        );
+    }
+}
@@ -0,0 +1,312 @@
+/// Utilities for keeping a project's cargo configuration consistent with the
+/// fel4 configuration data
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use toml;
+
+use generated_config::upper_snake_case;
+use generated_source::{generated_file_header, write_generated_file};
+use types::*;
+
+/// Render a `.cargo/config` fragment that builds for the configured target,
+/// with matching rustflags, and finds the target specifications under the
+/// configured target-specs-path.
+///
+/// The target-specs-path is given relative to the directory containing the
+/// `.cargo` directory, which is expected to be the project root alongside
+/// fel4.toml.
+pub fn cargo_config_fragment(fel4_config: &Fel4Config) -> String {
+    let target = fel4_config.target.full_name();
+    let rustflags = toml::Value::Array(
+        derive_rustflags(fel4_config)
+            .into_iter()
+            .map(toml::Value::String)
+            .collect(),
+    );
+    format!(
+        "{header}\
+         [build]\n\
+         target = {target}\n\
+         \n\
+         [target.{name}]\n\
+         rustflags = {rustflags}\n\
+         \n\
+         [env]\n\
+         RUST_TARGET_PATH = {{ value = {specs}, relative = true }}\n",
+        header = generated_file_header("#"),
+        target = toml::Value::String(target.to_string()),
+        name = target,
+        rustflags = rustflags,
+        specs = toml::Value::String(fel4_config.target_specs_path.clone()),
+    )
+}
+
+/// Write the `cargo_config_fragment` for the configuration to a file,
+/// creating any missing parent directories
+pub fn write_cargo_config_fragment<P: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    path: P,
+) -> Result<(), io::Error> {
+    write_generated_file(path, &cargo_config_fragment(fel4_config))
+}
+
+/// Derive the rustc and linker flags the userland build needs in order to
+/// agree with the kernel configuration.
+///
+/// * `LinkPageSize` sets the linker's maximum and common page sizes
+/// * `UserLinkerGCSections = true` enables `--gc-sections`
+/// * `KernelFPU` on x86 enables the target feature for the kernel's chosen
+///   FPU state save mechanism
+/// * `KernelAArch32FPUEnableContextSwitch = false` on armv7 selects soft
+///   float, since the kernel won't preserve FPU state across threads
+///
+/// Each flag is a single token, suitable for joining into `RUSTFLAGS`.
+pub fn derive_rustflags(fel4_config: &Fel4Config) -> Vec<String> {
+    let properties = &fel4_config.properties;
+    let mut flags = Vec::new();
+    if let Some(FlatTomlValue::Integer(page_size)) = properties.get("LinkPageSize") {
+        flags.push(format!("-Clink-arg=-zmax-page-size={}", page_size));
+        flags.push(format!("-Clink-arg=-zcommon-page-size={}", page_size));
+    }
+    if let Some(FlatTomlValue::Boolean(true)) = properties.get("UserLinkerGCSections") {
+        flags.push("-Clink-arg=--gc-sections".to_string());
+    }
+    let target_features = match (fel4_config.target, properties.get("KernelFPU")) {
+        (SupportedTarget::X8664Sel4Fel4, Some(FlatTomlValue::String(fpu))) => match fpu.as_str() {
+            "FXSAVE" => Some("+fxsr"),
+            "XSAVE" => Some("+xsave"),
+            "XSAVEOPT" => Some("+xsave,+xsaveopt"),
+            "XSAVEC" => Some("+xsave,+xsavec"),
+            "XSAVES" => Some("+xsave,+xsaves"),
+            _ => None,
+        },
+        (SupportedTarget::Armv7Sel4Fel4, _) if fel4_config.float_abi() == Some(FloatAbi::Soft) => {
+            Some("+soft-float")
+        }
+        _ => None,
+    };
+    if let Some(features) = target_features {
+        flags.push(format!("-Ctarget-feature={}", features));
+    }
+    flags
+}
+
+/// The boolean kernel options of the configuration as `(feature_name,
+/// enabled)` pairs, sorted by feature name, for a build script to turn into
+/// cfgs or to check its crate's features against.
+///
+/// Feature names are the property names in snake case, so `KernelPrinting`
+/// becomes `kernel_printing`. Only properties in `PropertyGroup::Kernel` are
+/// included.
+pub fn config_feature_flags(fel4_config: &Fel4Config) -> Vec<(String, bool)> {
+    let mut flags: Vec<(String, bool)> = fel4_config
+        .properties_in_group(PropertyGroup::Kernel)
+        .filter_map(|(name, value)| match *value {
+            FlatTomlValue::Boolean(enabled) => {
+                Some((upper_snake_case(name).to_ascii_lowercase(), enabled))
+            }
+            _ => None,
+        })
+        .collect();
+    flags.sort();
+    flags
+}
+
+/// The environment for cross-compiling the Rust sysroot and userland with
+/// xargo or cargo-xbuild against the configured target specification
+#[derive(Clone, Debug, PartialEq)]
+pub struct SysrootBuildEnv {
+    /// The target triple to pass as `--target`
+    pub target: String,
+    /// Environment variables to set for the build, in a stable order
+    pub vars: Vec<(String, OsString)>,
+}
+
+impl SysrootBuildEnv {
+    /// Set every variable on a command that runs xargo or cargo-xbuild
+    pub fn apply(&self, command: &mut Command) {
+        command.envs(self.vars.iter().map(|(k, v)| (k, v)));
+    }
+
+    /// The value of a variable, if it is set
+    pub fn get(&self, name: &str) -> Option<&OsString> {
+        self.vars.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+}
+
+/// Derive the sysroot build environment for a project rooted at
+/// `project_dir`.
+///
+/// * `RUST_TARGET_PATH` points at the target-specs-path, so the custom target
+///   JSON can be found by name
+/// * `XARGO_HOME` and `XBUILD_SYSROOT_PATH` keep the built sysroot under the
+///   output directory (see `Fel4Config::output_dir`), apart from any other
+///   project's or build profile's
+/// * `RUSTFLAGS` carries the `derive_rustflags` flags, when there are any, so
+///   the sysroot is compiled with the same settings as the userland
+pub fn sysroot_build_env<P: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    project_dir: P,
+) -> SysrootBuildEnv {
+    let project_dir = project_dir.as_ref();
+    let artifacts = project_dir.join(fel4_config.output_dir());
+    let mut vars = vec![
+        (
+            "RUST_TARGET_PATH".to_string(),
+            project_dir
+                .join(&fel4_config.target_specs_path)
+                .into_os_string(),
+        ),
+        (
+            "XARGO_HOME".to_string(),
+            artifacts.join("xargo").into_os_string(),
+        ),
+        (
+            "XBUILD_SYSROOT_PATH".to_string(),
+            artifacts.join("sysroot").into_os_string(),
+        ),
+    ];
+    let rustflags = derive_rustflags(fel4_config);
+    if !rustflags.is_empty() {
+        vars.push(("RUSTFLAGS".to_string(), rustflags.join(" ").into()));
+    }
+    SysrootBuildEnv {
+        target: fel4_config.target.full_name().to_string(),
+        vars,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn cargo_config_fragment_matches_the_resolved_config() {
+        let config = test_helpers::fixture_config(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Debug,
+        );
+        let fragment = cargo_config_fragment(&config);
+        let parsed: toml::Value = fragment.parse().expect("The fragment should be valid toml");
+        assert_eq!(Some("armv7-sel4-fel4"), parsed["build"]["target"].as_str());
+        assert_eq!(
+            Some("-Clink-arg=-zmax-page-size=4096"),
+            parsed["target"]["armv7-sel4-fel4"]["rustflags"][0].as_str()
+        );
+        assert_eq!(
+            Some(config.target_specs_path.as_str()),
+            parsed["env"]["RUST_TARGET_PATH"]["value"].as_str()
+        );
+        assert_eq!(
+            Some(true),
+            parsed["env"]["RUST_TARGET_PATH"]["relative"].as_bool()
+        );
+    }
+
+    #[test]
+    fn rustflags_follow_linker_and_fpu_properties() {
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        assert_eq!(
+            vec![
+                "-Clink-arg=-zmax-page-size=4096",
+                "-Clink-arg=-zcommon-page-size=4096",
+                "-Ctarget-feature=+fxsr",
+            ],
+            derive_rustflags(&config)
+        );
+        config
+            .properties
+            .insert("UserLinkerGCSections".into(), FlatTomlValue::Boolean(true));
+        config.properties.remove("KernelFPU");
+        assert_eq!(
+            Some(&"-Clink-arg=--gc-sections".to_string()),
+            derive_rustflags(&config).last()
+        );
+
+        let mut arm = test_helpers::fixture_config(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Debug,
+        );
+        arm.properties.insert(
+            "KernelAArch32FPUEnableContextSwitch".into(),
+            FlatTomlValue::Boolean(false),
+        );
+        assert!(derive_rustflags(&arm).contains(&"-Ctarget-feature=+soft-float".to_string()));
+    }
+
+    #[test]
+    fn feature_flags_follow_boolean_kernel_options() {
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        config
+            .properties
+            .insert("UserLinkerGCSections".into(), FlatTomlValue::Boolean(true));
+        let flags = config_feature_flags(&config);
+        assert!(flags.contains(&("kernel_printing".to_string(), true)));
+        assert!(flags.iter().all(|f| f.0 != "user_linker_gc_sections"));
+        assert!(flags.iter().all(|f| f.0 != "kernel_fpu"));
+        assert!(flags.windows(2).all(|w| w[0].0 < w[1].0));
+
+        config
+            .properties
+            .insert("KernelPrinting".into(), FlatTomlValue::Boolean(false));
+        assert!(config_feature_flags(&config).contains(&("kernel_printing".to_string(), false)));
+    }
+
+    #[test]
+    fn cargo_config_fragment_is_written_with_parent_directories() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let path = dir.path().join(".cargo").join("config");
+        let config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Release,
+        );
+        write_cargo_config_fragment(&config, &path).expect("Should write the fragment");
+        assert_eq!(
+            cargo_config_fragment(&config),
+            ::std::fs::read_to_string(&path).unwrap()
+        );
+    }
+
+    #[test]
+    fn sysroot_build_env_points_at_specs_and_artifacts() {
+        let config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        let project = Path::new("/work/project");
+        let env = sysroot_build_env(&config, project);
+        assert_eq!("x86_64-sel4-fel4", env.target);
+        assert_eq!(
+            Some(&project.join(&config.target_specs_path).into_os_string()),
+            env.get("RUST_TARGET_PATH")
+        );
+        assert_eq!(
+            Some(
+                &project
+                    .join(config.output_dir())
+                    .join("sysroot")
+                    .into_os_string()
+            ),
+            env.get("XBUILD_SYSROOT_PATH")
+        );
+        assert_eq!(
+            Some(&OsString::from(derive_rustflags(&config).join(" "))),
+            env.get("RUSTFLAGS")
+        );
+    }
+}
@@ -0,0 +1,298 @@
+/// Quick whole-manifest checks for pre-commit hooks and CI, covering
+/// validation, lints, and the files the manifest refers to without running
+/// CMake
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{resolve_fel4_config_with_options, ConfigError, ResolutionOptions};
+use audit::{audit_config, LintProfile};
+use cmake_integration::kernel_path_for;
+use diagnostics::{locate_in_manifest, validate_manifest_source, Diagnostic, Severity};
+use json::Json;
+use manifest::{decode_manifest, parse_full_manifest};
+use policy::policy_alongside;
+use redact::is_path;
+use types::*;
+
+/// The findings of `check_manifest`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckReport {
+    pub manifest_path: PathBuf,
+    /// Validation findings, then lint findings, then missing files
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckReport {
+    /// Whether the manifest has no `Severity::Error` findings
+    pub fn passed(&self) -> bool {
+        self.count(Severity::Error) == 0
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+
+    /// One line per finding, then a line with the counts, e.g.
+    /// `fel4.toml: 1 error(s), 0 warning(s)`. With `errors_only`, warnings
+    /// and the counts of a passing manifest are left out.
+    pub fn summary(&self, errors_only: bool) -> String {
+        let name = self.manifest_path.display();
+        let mut summary = String::new();
+        for d in &self.diagnostics {
+            if !errors_only || d.severity == Severity::Error {
+                summary.push_str(&format!("{}: {}\n", name, d));
+            }
+        }
+        if !errors_only || !self.passed() {
+            summary.push_str(&format!(
+                "{}: {} error(s), {} warning(s)\n",
+                name,
+                self.count(Severity::Error),
+                self.count(Severity::Warning)
+            ));
+        }
+        summary
+    }
+
+    /// The report as a JSON document, for hooks that post-process findings
+    pub fn to_json_string(&self) -> String {
+        let diagnostics = self
+            .diagnostics
+            .iter()
+            .map(|d| {
+                let mut members = vec![
+                    ("severity", Json::string(d.severity.full_name())),
+                    ("code", Json::string(d.code)),
+                    ("message", Json::string(d.message.as_str())),
+                ];
+                if let Some(span) = d.span {
+                    members.push(("line", Json::Integer(span.line as i64)));
+                    members.push(("column", Json::Integer(span.column as i64)));
+                }
+                Json::object(members)
+            })
+            .collect();
+        let document = Json::object(vec![
+            (
+                "manifest",
+                Json::string(self.manifest_path.display().to_string()),
+            ),
+            ("passed", Json::Bool(self.passed())),
+            ("errors", Json::Integer(self.count(Severity::Error) as i64)),
+            ("warnings", Json::Integer(self.count(Severity::Warning) as i64)),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]);
+        format!("{}\n", document)
+    }
+}
+
+/// Check a manifest file as a pre-commit hook would.
+///
+/// The manifest is validated as `validate_manifest_source` does. If it is
+/// valid, it is resolved for every build profile under the
+/// `POLICY_FILE_NAME` policy beside it, if there is one, and the release
+/// configuration of its selected target and platform is audited with
+/// `LintProfile::SecureRelease`. The files it refers to, relative to the
+/// manifest's directory, are then checked for: the
+/// `target-specs-path` directory and any `UserLinkerScript` are errors when
+/// missing, while a missing seL4_kernel checkout or toolchain program is a
+/// warning, since those are commonly provided per machine.
+pub fn check_manifest<P: AsRef<Path>>(fel4_manifest_path: P) -> CheckReport {
+    let manifest_path = fel4_manifest_path.as_ref().to_path_buf();
+    let diagnostics = match fs::read(&manifest_path)
+        .map_err(|_| ConfigError::FileReadFailure)
+        .and_then(|contents| decode_manifest(&contents))
+    {
+        Ok(text) => check_manifest_text(&text, &manifest_path),
+        Err(e) => vec![Diagnostic::from_config_error(&e, "")],
+    };
+    CheckReport {
+        manifest_path,
+        diagnostics,
+    }
+}
+
+fn check_manifest_text(text: &str, manifest_path: &Path) -> Vec<Diagnostic> {
+    let mut diagnostics = validate_manifest_source(text);
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return diagnostics;
+    }
+    let full = match parse_full_manifest(text) {
+        Ok(full) => full,
+        Err(e) => {
+            diagnostics.push(Diagnostic::from_config_error(&e, text));
+            return diagnostics;
+        }
+    };
+    let options = match policy_alongside(manifest_path) {
+        Ok(policy) => ResolutionOptions::new().policy(policy),
+        Err(e) => {
+            diagnostics.push(Diagnostic::from_config_error(&e, ""));
+            return diagnostics;
+        }
+    };
+    let mut configs = Vec::new();
+    for profile in BuildProfile::build_profiles() {
+        match resolve_fel4_config_with_options(&full, &profile, &options) {
+            Ok((config, _warnings)) => configs.push(config),
+            Err(e) => {
+                diagnostics.push(Diagnostic::from_config_error(&e, text));
+                return diagnostics;
+            }
+        }
+    }
+
+    for config in configs.iter().filter(|c| c.build_profile == BuildProfile::Release) {
+        for finding in audit_config(config, LintProfile::SecureRelease).findings {
+            let property = finding.property.as_str();
+            diagnostics.push(Diagnostic {
+                severity: finding.severity,
+                code: "secure-release-lint",
+                message: format!(
+                    "{} = {} in the release configuration: {}",
+                    property, finding.value, finding.reason
+                ),
+                span: locate_in_manifest(text, Some(&finding.source), Some(property))
+                    .or_else(|| locate_in_manifest(text, None, Some(property))),
+            });
+        }
+    }
+
+    let project_dir = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut checked: Vec<PathBuf> = Vec::new();
+    let mut check_exists = |severity, code, what: &str, path: PathBuf, key: &str| {
+        if checked.contains(&path) {
+            return;
+        }
+        if !path.exists() {
+            diagnostics.push(Diagnostic {
+                severity,
+                code,
+                message: format!("The {} {} does not exist", what, path.display()),
+                span: locate_in_manifest(text, None, Some(key)),
+            });
+        }
+        checked.push(path);
+    };
+    check_exists(
+        Severity::Error,
+        "missing-target-specs-path",
+        "target specification directory",
+        project_dir.join(&full.target_specs_path),
+        "target-specs-path",
+    );
+    if full.kernel_source.is_none() {
+        check_exists(
+            Severity::Warning,
+            "missing-kernel-source",
+            "seL4_kernel checkout",
+            kernel_path_for(project_dir),
+            "kernel-source",
+        );
+    }
+    for config in &configs {
+        if let Some(FlatTomlValue::String(script)) = config.properties.get("UserLinkerScript") {
+            check_exists(
+                Severity::Error,
+                "missing-linker-script",
+                "linker script template",
+                project_dir.join(script),
+                "UserLinkerScript",
+            );
+        }
+        let toolchain = &config.toolchain;
+        let programs = [
+            ("cmake", &toolchain.cmake),
+            ("ninja", &toolchain.ninja),
+            ("compiler-launcher", &toolchain.compiler_launcher),
+        ];
+        for &(key, program) in &programs {
+            match *program {
+                Some(ref p) if is_path(p) => check_exists(
+                    Severity::Warning,
+                    "missing-toolchain-program",
+                    "toolchain program",
+                    project_dir.join(p),
+                    key,
+                ),
+                _ => {}
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn checks_report_missing_files_and_lints() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("fel4.toml");
+        assert_eq!(
+            vec!["file-read-failure"],
+            check_manifest(&manifest_path)
+                .diagnostics
+                .iter()
+                .map(|d| d.code)
+                .collect::<Vec<_>>()
+        );
+
+        fs::write(&manifest_path, get_exemplar_default_toml()).unwrap();
+        let report = check_manifest(&manifest_path);
+        assert!(!report.passed());
+        let codes: Vec<&str> = report.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(
+            vec!["secure-release-lint", "missing-target-specs-path", "missing-kernel-source"],
+            codes
+        );
+        let first_error = format!("{}: error[missing-target-specs-path]", manifest_path.display());
+        assert!(report.summary(true).starts_with(&first_error));
+
+        fs::create_dir_all(dir.path().join("target_specs")).unwrap();
+        fs::create_dir_all(dir.path().join("deps").join("seL4_kernel")).unwrap();
+        let report = check_manifest(&manifest_path);
+        assert!(report.passed());
+        assert_eq!(1, report.count(Severity::Warning));
+        assert_eq!("", report.summary(true));
+        assert!(report.to_json_string().contains("\"passed\": true"));
+    }
+
+    #[test]
+    fn checks_enforce_the_policy_and_keep_validation_warnings() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("fel4.toml");
+        fs::create_dir_all(dir.path().join("target_specs")).unwrap();
+        fs::create_dir_all(dir.path().join("deps").join("seL4_kernel")).unwrap();
+        let legacy = "[fel4]\n\
+                      target = \"armv7-sel4-fel4\"\n\
+                      platform = \"sabre\"\n\
+                      artifact-path = \"artifacts\"\n\
+                      target-specs-path = \"target_specs\"\n\
+                      KernelARMPlatform = \"sabre\"\n\
+                      KernelArmSel4Arch = \"aarch32\"\n\
+                      [fel4.debug]\n\
+                      KernelPrinting = true\n";
+        fs::write(&manifest_path, legacy).unwrap();
+        let report = check_manifest(&manifest_path);
+        assert!(report.passed());
+        let warnings: Vec<&str> = report.diagnostics.iter().map(|d| d.code).collect();
+        assert!(warnings.contains(&"legacy-manifest-layout"));
+
+        fs::write(
+            dir.path().join(POLICY_FILE_NAME),
+            "forbidden = [\"KernelPrinting\"]\n",
+        )
+        .unwrap();
+        let report = check_manifest(&manifest_path);
+        assert!(!report.passed());
+        let codes: Vec<&str> = report.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(Some(&"policy-forbidden-property"), codes.last());
+        assert!(codes.contains(&"legacy-manifest-layout"));
+    }
+}
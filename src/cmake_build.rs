@@ -0,0 +1,380 @@
+/// Running the seL4_kernel CMake build directly, for callers that need
+/// control over how much it prints and a record of what it printed, and
+/// reading back which definitions CMake ignored
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use cmake_integration::{
+    check_kernel_paths, configure_cmake_build, kernel_path_for, CmakeConfigurationError,
+    RecordedDefinitions,
+};
+use diagnostics::{locate_in_manifest, Diagnostic, Severity};
+use types::*;
+
+/// The file `run_cmake_build` captures CMake's output to, within the output
+/// directory
+pub const CMAKE_BUILD_LOG_FILE_NAME: &str = "cmake-build.log";
+
+/// How much CMake and Ninja print while configuring and building
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CmakeVerbosity {
+    /// Print nothing, leaving the output to the log if one is captured
+    Quiet,
+    /// Print the usual CMake and Ninja output
+    #[default]
+    Normal,
+    /// Also print every compiler command line
+    Verbose,
+}
+
+/// Output handling for `run_cmake_build`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CmakeLogging {
+    pub verbosity: CmakeVerbosity,
+    /// Write the complete configure and build output to
+    /// `CMAKE_BUILD_LOG_FILE_NAME` in the output directory, whatever the
+    /// verbosity
+    pub capture_log: bool,
+}
+
+/// What `run_cmake_build` produced
+#[derive(Clone, Debug, PartialEq)]
+pub struct CmakeBuildOutput {
+    pub build_dir: PathBuf,
+    /// The captured log, when `CmakeLogging::capture_log` was requested
+    pub log_path: Option<PathBuf>,
+    /// The definitions the configure step reported as not used by the
+    /// project, see `unused_variable_diagnostics`
+    pub unused_variables: Vec<String>,
+}
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum CmakeBuildError {
+    #[fail(display = "{}", _0)]
+    Configuration(CmakeConfigurationError),
+    #[fail(display = "Unable to run {}", _0)]
+    LaunchFailure(String),
+    #[fail(display = "The CMake {} step failed with {}", _0, _1)]
+    StepFailure(String, String),
+    #[fail(display = "Unable to write the CMake build log to {}", _0)]
+    LogWriteFailure(String),
+}
+
+/// Configure and build the seL4_kernel in `build_dir` by running cmake
+/// directly, with the definitions `configure_cmake_build` derives for the
+/// configuration.
+///
+/// Unlike building a `cmake::Config`, the output of each step can be
+/// silenced or made verbose, and captured to a log in the output directory
+/// (relative to `cargo_manifest_dir`), which is still written when a step
+/// fails.
+pub fn run_cmake_build<P: AsRef<Path>, Q: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    cargo_manifest_dir: P,
+    build_dir: Q,
+    logging: &CmakeLogging,
+) -> Result<CmakeBuildOutput, CmakeBuildError> {
+    let cargo_manifest_dir = cargo_manifest_dir.as_ref();
+    let build_dir = build_dir.as_ref();
+    let mut recorded = RecordedDefinitions::default();
+    configure_cmake_build(
+        &mut recorded,
+        fel4_config,
+        cargo_manifest_dir,
+        fel4_config.target.full_name(),
+    ).map_err(CmakeBuildError::Configuration)?;
+    check_kernel_paths(&kernel_path_for(cargo_manifest_dir))
+        .map_err(CmakeBuildError::Configuration)?;
+    fs::create_dir_all(build_dir)
+        .map_err(|_| CmakeBuildError::LaunchFailure(build_dir.display().to_string()))?;
+
+    let cmake = recorded
+        .cmake_executable
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("cmake"));
+    let verbose = logging.verbosity == CmakeVerbosity::Verbose;
+    let mut configure = Command::new(&cmake);
+    configure
+        .current_dir(build_dir)
+        .args(configure_args(&recorded, verbose))
+        .arg(kernel_path_for(cargo_manifest_dir));
+    let mut build = Command::new(&cmake);
+    build
+        .current_dir(build_dir)
+        .args(build_args(&recorded.build_targets, recorded.jobs, verbose));
+    for name in &recorded.cleared_env {
+        configure.env_remove(name);
+        build.env_remove(name);
+    }
+    for (name, value) in &recorded.env {
+        configure.env(name, value);
+        build.env(name, value);
+    }
+
+    let mut log = Vec::new();
+    let mut outcome = Ok(());
+    let mut unused_variables = Vec::new();
+    for (step, mut command) in [("configure", configure), ("build", build)] {
+        let output = command
+            .output()
+            .map_err(|_| CmakeBuildError::LaunchFailure(cmake.display().to_string()))?;
+        if logging.verbosity != CmakeVerbosity::Quiet {
+            echo(&output);
+        }
+        if step == "configure" {
+            unused_variables = parse_unused_cmake_variables(&String::from_utf8_lossy(
+                &[&output.stdout[..], &output.stderr[..]].concat(),
+            ));
+        }
+        log.extend_from_slice(&output.stdout);
+        log.extend_from_slice(&output.stderr);
+        if !output.status.success() {
+            outcome = Err(CmakeBuildError::StepFailure(
+                step.to_string(),
+                output.status.to_string(),
+            ));
+            break;
+        }
+    }
+
+    let log_path = if logging.capture_log {
+        let artifact_dir = cargo_manifest_dir.join(fel4_config.output_dir());
+        let log_path = artifact_dir.join(CMAKE_BUILD_LOG_FILE_NAME);
+        fs::create_dir_all(&artifact_dir)
+            .and_then(|_| File::create(&log_path))
+            .and_then(|mut f| f.write_all(&log))
+            .map_err(|_| CmakeBuildError::LogWriteFailure(log_path.display().to_string()))?;
+        Some(log_path)
+    } else {
+        None
+    };
+    outcome.map(|_| CmakeBuildOutput {
+        build_dir: build_dir.to_path_buf(),
+        log_path,
+        unused_variables,
+    })
+}
+
+const UNUSED_VARIABLES_WARNING: &str = "Manually-specified variables were not used by the project";
+
+/// The variable names listed under CMake's "Manually-specified variables
+/// were not used by the project" warning in configure output, in the order
+/// CMake gave them
+pub fn parse_unused_cmake_variables(cmake_output: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut lines = cmake_output.lines();
+    while let Some(line) = lines.next() {
+        if !line.contains(UNUSED_VARIABLES_WARNING) {
+            continue;
+        }
+        let listed = lines
+            .by_ref()
+            .skip_while(|l| l.trim().is_empty())
+            .take_while(|l| l.starts_with(' ') && !l.trim().is_empty());
+        for name in listed {
+            let name = name.trim().to_string();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Warnings for the unused CMake variables that are the configuration's
+/// properties, each located at the manifest table that set it, so that
+/// options the kernel doesn't have are noticed without reading the CMake
+/// log. Variables fel4-config defined for itself, such as
+/// `CMAKE_TOOLCHAIN_FILE`, are left out.
+pub fn unused_variable_diagnostics(
+    fel4_config: &Fel4Config,
+    unused_variables: &[String],
+    manifest_text: &str,
+) -> Vec<Diagnostic> {
+    unused_variables
+        .iter()
+        .filter(|name| fel4_config.properties.contains_key(*name))
+        .map(|name| {
+            let table = fel4_config
+                .provenance
+                .get(name)
+                .map(|layer| layer.location(fel4_config));
+            let set_by = match table {
+                Some(ref table) => format!("[{}]", table),
+                None => "an override".to_string(),
+            };
+            let table = table.as_deref();
+            Diagnostic {
+                severity: Severity::Warning,
+                code: "unused-cmake-variable",
+                message: format!(
+                    "CMake did not use the {} property set by {}, so the kernel may not have \
+                     that option",
+                    name, set_by
+                ),
+                span: locate_in_manifest(manifest_text, table, Some(name))
+                    .or_else(|| locate_in_manifest(manifest_text, None, Some(name))),
+            }
+        })
+        .collect()
+}
+
+/// The definitions in the order they were made, so later ones win as they
+/// do for `cmake::Config`
+fn configure_args(recorded: &RecordedDefinitions, verbose: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(ref generator) = recorded.generator {
+        args.push("-G".to_string());
+        args.push(generator.clone());
+    }
+    args.extend(
+        recorded
+            .defines
+            .iter()
+            .map(|(name, value)| format!("-D{}={}", name, value)),
+    );
+    if verbose {
+        args.push("-DCMAKE_VERBOSE_MAKEFILE=ON".to_string());
+    }
+    args
+}
+
+/// Targets beyond the first are handed to Ninja directly, as
+/// `DefinitionSink::build_targets` does for `cmake::Config`
+fn build_args(targets: &[String], jobs: Option<usize>, verbose: bool) -> Vec<String> {
+    let mut args = vec!["--build".to_string(), ".".to_string()];
+    if let Some(jobs) = jobs {
+        // Requires CMake 3.12
+        args.push("--parallel".to_string());
+        args.push(jobs.to_string());
+    }
+    let mut native = Vec::new();
+    if let Some((first, rest)) = targets.split_first() {
+        args.push("--target".to_string());
+        args.push(first.clone());
+        native.extend(rest.iter().cloned());
+    }
+    if verbose {
+        native.push("-v".to_string());
+    }
+    if !native.is_empty() {
+        args.push("--".to_string());
+        args.extend(native);
+    }
+    args
+}
+
+fn echo(output: &Output) {
+    let _ = io::stdout().write_all(&output.stdout);
+    let _ = io::stderr().write_all(&output.stderr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn verbose_builds_pass_verbosity_to_cmake_and_ninja() {
+        assert_eq!(
+            vec!["--build", ".", "--target", "kernel.elf", "--", "sel4", "-v"],
+            build_args(
+                &[KERNEL_BUILD_TARGET.into(), LIBSEL4_BUILD_TARGET.into()],
+                None,
+                true
+            )
+        );
+        assert_eq!(vec!["--build", "."], build_args(&[], None, false));
+        assert_eq!(
+            vec!["--build", ".", "--parallel", "4"],
+            build_args(&[], Some(4), false)
+        );
+        let recorded = RecordedDefinitions {
+            generator: Some("Ninja".into()),
+            defines: vec![("KernelPrinting:BOOL".into(), "ON".into())],
+            ..RecordedDefinitions::default()
+        };
+        assert_eq!(
+            vec![
+                "-G",
+                "Ninja",
+                "-DKernelPrinting:BOOL=ON",
+                "-DCMAKE_VERBOSE_MAKEFILE=ON"
+            ],
+            configure_args(&recorded, true)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quiet_builds_capture_the_log_even_on_failure() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = ::tempfile::tempdir().unwrap();
+        let fake_cmake = dir.path().join("cmake");
+        fs::write(
+            &fake_cmake,
+            "#!/bin/sh\necho \"cmake $*\"\n[ \"$1\" != --build ] || exit 3\n",
+        ).unwrap();
+        fs::set_permissions(&fake_cmake, fs::Permissions::from_mode(0o755)).unwrap();
+        let full = parse_full_manifest(get_exemplar_default_toml()).unwrap();
+        let mut fel4_config = resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        fel4_config.toolchain.cmake = Some(fake_cmake.display().to_string());
+        let kernel_path = dir.path().join("deps").join("seL4_kernel");
+        fs::create_dir_all(&kernel_path).unwrap();
+        fs::write(kernel_path.join("gcc.cmake"), "").unwrap();
+
+        let logging = CmakeLogging {
+            verbosity: CmakeVerbosity::Quiet,
+            capture_log: true,
+        };
+        let result = run_cmake_build(&fel4_config, dir.path(), dir.path().join("build"), &logging);
+        match result {
+            Err(CmakeBuildError::StepFailure(ref step, _)) => assert_eq!("build", step),
+            ref other => panic!("Expected the build step to fail, got {:?}", other),
+        }
+        let log = fs::read_to_string(
+            dir.path()
+                .join(fel4_config.output_dir())
+                .join(CMAKE_BUILD_LOG_FILE_NAME),
+        ).unwrap();
+        assert!(log.contains("-DKernelPrinting:BOOL=ON"));
+        assert!(log.contains("cmake --build ."));
+    }
+
+    #[test]
+    fn unused_cmake_variables_become_manifest_warnings() {
+        let cmake_output = "-- Configuring done\n\
+                            CMake Warning:\n  \
+                            Manually-specified variables were not used by the project:\n\n    \
+                            CMAKE_TOOLCHAIN_FILE\n    \
+                            KernelFWholeProgram\n    \
+                            KernelPrinting\n\n\n\
+                            -- Generating done\n";
+        let unused = parse_unused_cmake_variables(cmake_output);
+        assert_eq!(
+            vec!["CMAKE_TOOLCHAIN_FILE", "KernelFWholeProgram", "KernelPrinting"],
+            unused
+        );
+        assert!(parse_unused_cmake_variables("-- Build files have been written").is_empty());
+
+        let manifest_text = get_exemplar_default_toml();
+        let full = parse_full_manifest(manifest_text).unwrap();
+        let fel4_config = resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        let diagnostics = unused_variable_diagnostics(&fel4_config, &unused, manifest_text);
+        assert_eq!(2, diagnostics.len());
+        assert!(diagnostics.iter().all(|d| d.code == "unused-cmake-variable"));
+        assert!(diagnostics[1]
+            .message
+            .contains("KernelPrinting property set by [x86_64-sel4-fel4.debug]"));
+        let debug_table = Some("x86_64-sel4-fel4.debug");
+        assert_eq!(
+            locate_in_manifest(manifest_text, debug_table, Some("KernelPrinting")),
+            diagnostics[1].span
+        );
+    }
+}
@@ -1,64 +1,823 @@
 use cmake::Config as CmakeConfig;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 /// Utilities for configuring the sel4_kernel CMake build based
 /// on fel4 configuration data
 ///
 use std::env;
-use std::path::Path;
+use std::ffi::OsStr;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use manifest::FullFel4Manifest;
+use super::{
+    check_cargo_profile_env, is_supported_target_platform_pair, resolve_fel4_config_for,
+    ConfigError,
+};
+use trace;
+use cmake_build::CmakeVerbosity;
+use toolchain::{configured_compiler_prefix, toolchain_with_env_defaults, HostOs};
+use toml;
 use types::*;
 #[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
 pub enum CmakeConfigurationError {
     #[fail(display = "Missing the required {} environment variable", _0)]
     MissingRequiredEnvVar(String),
+    #[fail(display = "{}", _0)]
+    CargoTargetToFel4TargetMismatch(Box<TargetMismatch>),
+    #[fail(display = "Unable to resolve the configuration for the cargo target: {}", _0)]
+    Resolution(ConfigError),
+    #[fail(display = "Unable to write the CMake configuration audit to {}", _0)]
+    AuditWriteFailure(String),
     #[fail(
-        display = "Cargo is attempting to build for the {} target, however fel4.toml has declared the target to be {}",
-        _0,
-        _1
+        display = "The seL4_kernel source directory {} is missing or unreadable",
+        _0
+    )]
+    UnreadableKernelPath(String),
+    #[fail(display = "The CMake toolchain file {} is missing or unreadable", _0)]
+    UnreadableToolchainFile(String),
+    #[fail(
+        display = "Unable to read the commit time of the seL4_kernel checkout {} for SOURCE_DATE_EPOCH: {}",
+        _0, _1
     )]
-    CargoTargetToFel4TargetMismatch(String, String),
+    UnknownSourceDateEpoch(String, String),
+}
+
+/// The cargo target differs from the target the fel4 manifest selects
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetMismatch {
+    pub cargo_target: String,
+    pub fel4_target: String,
+    /// The manifest the configuration was read from, if known
+    pub manifest_path: Option<PathBuf>,
+    /// The targets the manifest has tables for
+    pub declared_targets: Vec<SupportedTarget>,
+    /// How to bring the two into agreement
+    pub hint: String,
+}
+
+impl Display for TargetMismatch {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(
+            f,
+            "Cargo is attempting to build for the {} target, however fel4.toml has declared the target to be {}. {}",
+            self.cargo_target, self.fel4_target, self.hint
+        )
+    }
+}
+
+/// Receives the CMake settings derived from fel4 configuration data.
+///
+/// Implemented by `cmake::Config` for real builds, and by
+/// `RecordedDefinitions` for observing what would be configured.
+pub trait DefinitionSink {
+    /// Define a CMake cache variable
+    fn define(&mut self, name: &str, value: &OsStr);
+
+    /// Select the CMake generator
+    fn generator(&mut self, generator: &str);
+
+    /// Use a specific cmake executable rather than the one on PATH
+    fn cmake_executable(&mut self, path: &Path);
+
+    /// Build only the named CMake targets rather than every target. Sinks
+    /// that never build may ignore this.
+    fn build_targets(&mut self, _targets: &[String]) {}
+
+    /// Remove an environment variable from CMake's environment. Sinks that
+    /// never run cmake may ignore this.
+    fn clear_env(&mut self, _name: &str) {}
+
+    /// Set an environment variable in CMake's environment. Sinks that never
+    /// run cmake may ignore this.
+    fn set_env(&mut self, _name: &str, _value: &str) {}
+
+    /// Build with at most this many parallel jobs. Sinks that never build
+    /// may ignore this.
+    fn jobs(&mut self, _jobs: usize) {}
+
+    /// Configure and build in the given directory. Sinks that never run
+    /// cmake may ignore this.
+    fn build_dir(&mut self, _dir: &Path) {}
+
+    /// Whether the kernel path and toolchain file should be checked before
+    /// they are defined. Sinks that only record the configuration, and never
+    /// run cmake, may skip the check.
+    fn checks_paths(&self) -> bool {
+        true
+    }
+}
+
+impl DefinitionSink for CmakeConfig {
+    fn define(&mut self, name: &str, value: &OsStr) {
+        CmakeConfig::define(self, name, value);
+    }
+
+    fn generator(&mut self, generator: &str) {
+        CmakeConfig::generator(self, generator);
+    }
+
+    /// `cmake::Config` only takes its executable from the build script's
+    /// own `CMAKE` environment variable, which a sink shouldn't change for
+    /// the whole process, so choosing it is left to the caller; see
+    /// `AppliedCmakeConfiguration::cmake_executable`. `run_cmake_build` sets
+    /// it on the commands it runs instead.
+    fn cmake_executable(&mut self, _path: &Path) {}
+
+    /// `cmake::Config` passes a single `--target` to `cmake --build`, so any
+    /// further targets are handed to Ninja directly
+    fn build_targets(&mut self, targets: &[String]) {
+        if let Some((first, rest)) = targets.split_first() {
+            CmakeConfig::build_target(self, first);
+            for target in rest {
+                CmakeConfig::build_arg(self, target);
+            }
+        }
+    }
+
+    /// `cmake::Config` can only add to the environment, so the variable is
+    /// set empty, which CMake treats the same as unset for compiler flags
+    fn clear_env(&mut self, name: &str) {
+        CmakeConfig::env(self, name, "");
+    }
+
+    fn set_env(&mut self, name: &str, value: &str) {
+        CmakeConfig::env(self, name, value);
+    }
+
+    /// `cmake::Config` takes its parallelism from cargo, so the limit is
+    /// handed to the native build tool
+    fn jobs(&mut self, jobs: usize) {
+        CmakeConfig::build_arg(self, format!("-j{}", jobs));
+    }
+
+    fn build_dir(&mut self, dir: &Path) {
+        CmakeConfig::out_dir(self, dir);
+    }
+}
+
+/// A `DefinitionSink` that records everything it is given, in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordedDefinitions {
+    pub defines: Vec<(String, String)>,
+    pub generator: Option<String>,
+    pub cmake_executable: Option<PathBuf>,
+    pub build_targets: Vec<String>,
+    pub cleared_env: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub jobs: Option<usize>,
+    pub build_dir: Option<PathBuf>,
 }
+
+impl RecordedDefinitions {
+    /// The most recently defined value for the named variable
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.defines
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl DefinitionSink for RecordedDefinitions {
+    fn define(&mut self, name: &str, value: &OsStr) {
+        self.defines
+            .push((name.to_string(), value.to_string_lossy().into_owned()));
+    }
+
+    fn generator(&mut self, generator: &str) {
+        self.generator = Some(generator.to_string());
+    }
+
+    fn cmake_executable(&mut self, path: &Path) {
+        self.cmake_executable = Some(path.to_path_buf());
+    }
+
+    fn build_targets(&mut self, targets: &[String]) {
+        self.build_targets = targets.to_vec();
+    }
+
+    fn clear_env(&mut self, name: &str) {
+        self.cleared_env.push(name.to_string());
+    }
+
+    fn set_env(&mut self, name: &str, value: &str) {
+        self.env.push((name.to_string(), value.to_string()));
+    }
+
+    fn jobs(&mut self, jobs: usize) {
+        self.jobs = Some(jobs);
+    }
+
+    fn build_dir(&mut self, dir: &Path) {
+        self.build_dir = Some(dir.to_path_buf());
+    }
+
+    fn checks_paths(&self) -> bool {
+        false
+    }
+}
+
+/// What `configure_cmake_build` applied to a CMake build, for logging and for
+/// checking the paths exist before cmake runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppliedCmakeConfiguration {
+    pub kernel_path: PathBuf,
+    pub toolchain_file: PathBuf,
+    /// The generator selected, or None where it was left to cmake-rs
+    pub generator: Option<String>,
+    /// The number of cache variables defined
+    pub define_count: usize,
+    /// The cmake executable the toolchain settings name, which a
+    /// `cmake::Config` doesn't apply by itself
+    pub cmake_executable: Option<PathBuf>,
+}
+
+/// Forwards to another sink, counting the variables it defines
+struct CountingSink<'a, S: 'a> {
+    inner: &'a mut S,
+    defines: usize,
+    cmake_executable: Option<PathBuf>,
+}
+
+impl<'a, S: DefinitionSink> DefinitionSink for CountingSink<'a, S> {
+    fn define(&mut self, name: &str, value: &OsStr) {
+        self.defines += 1;
+        self.inner.define(name, value);
+    }
+
+    fn generator(&mut self, generator: &str) {
+        self.inner.generator(generator);
+    }
+
+    fn cmake_executable(&mut self, path: &Path) {
+        self.cmake_executable = Some(path.to_path_buf());
+        self.inner.cmake_executable(path);
+    }
+
+    fn build_targets(&mut self, targets: &[String]) {
+        self.inner.build_targets(targets);
+    }
+
+    fn clear_env(&mut self, name: &str) {
+        self.inner.clear_env(name);
+    }
+
+    fn set_env(&mut self, name: &str, value: &str) {
+        self.inner.set_env(name, value);
+    }
+
+    fn jobs(&mut self, jobs: usize) {
+        self.inner.jobs(jobs);
+    }
+
+    fn build_dir(&mut self, dir: &Path) {
+        self.inner.build_dir(dir);
+    }
+
+    fn checks_paths(&self) -> bool {
+        self.inner.checks_paths()
+    }
+}
+
+/// Forwards to another sink, tracing everything it is given
+#[cfg(feature = "trace")]
+struct TracingSink<'a, S: 'a>(&'a mut S);
+
+#[cfg(feature = "trace")]
+impl<'a, S: DefinitionSink> DefinitionSink for TracingSink<'a, S> {
+    fn define(&mut self, name: &str, value: &OsStr) {
+        fel4_trace!("cmake", "define {}={}", name, value.to_string_lossy());
+        self.0.define(name, value);
+    }
+
+    fn generator(&mut self, generator: &str) {
+        fel4_trace!("cmake", "generator {}", generator);
+        self.0.generator(generator);
+    }
+
+    fn cmake_executable(&mut self, path: &Path) {
+        fel4_trace!("cmake", "cmake executable {}", path.display());
+        self.0.cmake_executable(path);
+    }
+
+    fn build_targets(&mut self, targets: &[String]) {
+        fel4_trace!("cmake", "build targets {}", targets.join(" "));
+        self.0.build_targets(targets);
+    }
+
+    fn clear_env(&mut self, name: &str) {
+        fel4_trace!("cmake", "clear env {}", name);
+        self.0.clear_env(name);
+    }
+
+    fn set_env(&mut self, name: &str, value: &str) {
+        fel4_trace!("cmake", "set env {}={}", name, value);
+        self.0.set_env(name, value);
+    }
+
+    fn jobs(&mut self, jobs: usize) {
+        fel4_trace!("cmake", "jobs {}", jobs);
+        self.0.jobs(jobs);
+    }
+
+    fn build_dir(&mut self, dir: &Path) {
+        fel4_trace!("cmake", "build dir {}", dir.display());
+        self.0.build_dir(dir);
+    }
+
+    fn checks_paths(&self) -> bool {
+        self.0.checks_paths()
+    }
+}
+
 /// Configure a seL4_kernel CMake build configuration with data derived from
 /// the fel4.toml manifest
 ///
 /// Assumes `cargo_target` is a rust build target option
 /// Assumes the seL4_kernel is at `${cargo_manifest_dir}/deps/seL4_kernel`
-pub fn configure_cmake_build<P: AsRef<Path>>(
-    cmake_config: &mut CmakeConfig,
+pub fn configure_cmake_build<S: DefinitionSink, P: AsRef<Path>>(
+    cmake_config: &mut S,
+    fel4_config: &Fel4Config,
+    cargo_manifest_dir: P,
+    cargo_target: &str,
+) -> Result<AppliedCmakeConfiguration, CmakeConfigurationError> {
+    configure_cmake_build_for_host(
+        cmake_config,
+        fel4_config,
+        cargo_manifest_dir,
+        cargo_target,
+        HostOs::current(),
+    )
+}
+
+/// The environment variables removed from CMake's environment when the
+/// target's toolchain settings don't give a `clear-env` list
+pub const DEFAULT_CLEARED_ENV: &[&str] = &["CFLAGS", "CXXFLAGS", "CPPFLAGS", "ASMFLAGS", "LDFLAGS"];
+
+/// Names the environment variable that, set to anything nonempty, has
+/// `configure_cmake_build` leave the generator to cmake-rs instead of
+/// requiring Ninja
+pub const NO_NINJA_ENV_VAR: &str = "FEL4_NO_NINJA";
+
+/// The CMake generator a seL4_kernel build is configured with
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum CmakeGenerator {
+    /// Ninja, which the seL4 build is developed and tested with
+    Ninja,
+    /// Whichever generator cmake-rs would otherwise select, for build
+    /// environments that can't run ninja
+    CmakeDefault,
+}
+
+impl CmakeGenerator {
+    /// `CmakeGenerator::CmakeDefault` when the `NO_NINJA_ENV_VAR` environment
+    /// variable is set, and Ninja otherwise
+    pub fn from_env() -> CmakeGenerator {
+        generator_from(|name| trace::var(name).ok())
+    }
+}
+
+fn generator_from<F: Fn(&str) -> Option<String>>(lookup: F) -> CmakeGenerator {
+    match lookup(NO_NINJA_ENV_VAR) {
+        Some(ref v) if !v.is_empty() => CmakeGenerator::CmakeDefault,
+        _ => CmakeGenerator::Ninja,
+    }
+}
+
+/// Configure a seL4_kernel CMake build as `configure_cmake_build` does, but
+/// for the given host operating system rather than the current one
+pub fn configure_cmake_build_for_host<S: DefinitionSink, P: AsRef<Path>>(
+    cmake_config: &mut S,
     fel4_config: &Fel4Config,
     cargo_manifest_dir: P,
     cargo_target: &str,
-) -> Result<(), CmakeConfigurationError> {
-    let kernel_path = cargo_manifest_dir.as_ref().join("deps").join("seL4_kernel");
+    host: HostOs,
+) -> Result<AppliedCmakeConfiguration, CmakeConfigurationError> {
+    configure_cmake_build_with_options(
+        cmake_config,
+        fel4_config,
+        cargo_manifest_dir,
+        cargo_target,
+        &CmakeBuildOptions::new().host(host),
+    )
+}
+
+/// Configure a seL4_kernel CMake build as `configure_cmake_build` does, but
+/// with the given generator whatever the environment says
+pub fn configure_cmake_build_with_generator<S: DefinitionSink, P: AsRef<Path>>(
+    cmake_config: &mut S,
+    fel4_config: &Fel4Config,
+    cargo_manifest_dir: P,
+    cargo_target: &str,
+    generator: CmakeGenerator,
+) -> Result<AppliedCmakeConfiguration, CmakeConfigurationError> {
+    configure_cmake_build_with_options(
+        cmake_config,
+        fel4_config,
+        cargo_manifest_dir,
+        cargo_target,
+        &CmakeBuildOptions::new().generator(generator),
+    )
+}
+
+/// Which variables of the ambient environment are removed from CMake's
+/// environment before configuring the kernel
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub enum CmakeEnvPolicy {
+    /// The target toolchain's `clear-env` list, or `DEFAULT_CLEARED_ENV`
+    #[default]
+    Toolchain,
+    /// None, so CMake inherits the whole environment
+    Inherit,
+    /// Exactly the named variables, whatever the toolchain says
+    Clear(Vec<String>),
+}
+
+/// How `configure_cmake_build_with_options` sets up a kernel CMake build,
+/// beyond what the configuration itself determines.
+///
+/// Built up from `CmakeBuildOptions::new`, e.g.
+/// `CmakeBuildOptions::new().jobs(4).define("KernelFoo", "ON")`.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct CmakeBuildOptions {
+    pub generator: CmakeGenerator,
+    /// The host the build runs on, which decides path rendering and the
+    /// default cross compiler prefix
+    pub host: HostOs,
+    /// The most parallel jobs to build with, or None to leave it to cargo
+    /// and the build tool
+    pub jobs: Option<usize>,
+    /// The directory to configure and build in, or None for cargo's OUT_DIR
+    pub build_dir: Option<PathBuf>,
+    /// Cache variables defined after the configuration's own, so they take
+    /// precedence
+    pub defines: Vec<(String, String)>,
+    pub env_policy: CmakeEnvPolicy,
+    /// With `CmakeVerbosity::Verbose`, every compiler command line is printed
+    pub verbosity: CmakeVerbosity,
+}
+
+impl Default for CmakeBuildOptions {
+    fn default() -> CmakeBuildOptions {
+        CmakeBuildOptions::new()
+    }
+}
+
+impl CmakeBuildOptions {
+    /// The options `configure_cmake_build` uses: the generator from
+    /// `CmakeGenerator::from_env`, for the current host
+    pub fn new() -> CmakeBuildOptions {
+        CmakeBuildOptions {
+            generator: CmakeGenerator::from_env(),
+            host: HostOs::current(),
+            jobs: None,
+            build_dir: None,
+            defines: Vec::new(),
+            env_policy: CmakeEnvPolicy::default(),
+            verbosity: CmakeVerbosity::default(),
+        }
+    }
+
+    pub fn generator(mut self, generator: CmakeGenerator) -> CmakeBuildOptions {
+        self.generator = generator;
+        self
+    }
+
+    pub fn host(mut self, host: HostOs) -> CmakeBuildOptions {
+        self.host = host;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> CmakeBuildOptions {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    pub fn build_dir<P: Into<PathBuf>>(mut self, build_dir: P) -> CmakeBuildOptions {
+        self.build_dir = Some(build_dir.into());
+        self
+    }
+
+    /// Define an extra cache variable, replacing any earlier one of the name
+    pub fn define<N, V>(mut self, name: N, value: V) -> CmakeBuildOptions
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        let name = name.into();
+        self.defines.retain(|(n, _)| *n != name);
+        self.defines.push((name, value.into()));
+        self
+    }
+
+    pub fn env_policy(mut self, env_policy: CmakeEnvPolicy) -> CmakeBuildOptions {
+        self.env_policy = env_policy;
+        self
+    }
+
+    pub fn verbosity(mut self, verbosity: CmakeVerbosity) -> CmakeBuildOptions {
+        self.verbosity = verbosity;
+        self
+    }
+}
+
+/// Configure a seL4_kernel CMake build as `configure_cmake_build` does, with
+/// the generator, host, parallelism, build directory, extra definitions,
+/// environment handling, and verbosity the `options` give
+pub fn configure_cmake_build_with_options<S: DefinitionSink, P: AsRef<Path>>(
+    cmake_config: &mut S,
+    fel4_config: &Fel4Config,
+    cargo_manifest_dir: P,
+    cargo_target: &str,
+    options: &CmakeBuildOptions,
+) -> Result<AppliedCmakeConfiguration, CmakeConfigurationError> {
+    let host = options.host;
+    let mut counting = CountingSink {
+        inner: cmake_config,
+        defines: 0,
+        cmake_executable: None,
+    };
+    let cmake_config = &mut counting;
+    #[cfg(feature = "trace")]
+    let mut traced = TracingSink(cmake_config);
+    #[cfg(feature = "trace")]
+    let cmake_config = &mut traced;
+    let kernel_path = kernel_path_for(cargo_manifest_dir);
 
     if cargo_target != fel4_config.target.full_name() {
-        return Err(CmakeConfigurationError::CargoTargetToFel4TargetMismatch(
-            cargo_target.to_string(),
-            fel4_config.target.full_name().to_string(),
-        ));
+        return Err(target_mismatch(fel4_config, cargo_target));
+    }
+    if cmake_config.checks_paths() {
+        check_kernel_paths(&kernel_path)?;
     }
 
     // CMAKE_TOOLCHAIN_FILE is resolved immediately by CMake
-    cmake_config.define("CMAKE_TOOLCHAIN_FILE", kernel_path.join("gcc.cmake"));
-    cmake_config.define("KERNEL_PATH", kernel_path);
+    cmake_config.define(
+        "CMAKE_TOOLCHAIN_FILE",
+        &host.cmake_path(&kernel_path.join("gcc.cmake")),
+    );
+    cmake_config.define("KERNEL_PATH", &host.cmake_path(&kernel_path));
+    // Defined explicitly so cmake-rs doesn't infer it from cargo's profile
+    cmake_config.define("CMAKE_BUILD_TYPE", fel4_config.cmake_build_type.as_ref());
 
-    add_cmake_definitions(cmake_config, &fel4_config.properties);
+    add_cmake_definitions(cmake_config, fel4_config.kernel_properties());
 
     // Supply additional cross compilation toolchain guidance for arm,
-    // since the seL4-CMake inferred option doesn't support hardware floating point
-    if fel4_config.target == SupportedTarget::Armv7Sel4Fel4 {
-        cmake_config.define("CROSS_COMPILER_PREFIX", "arm-linux-gnueabihf-");
-    } else if fel4_config.target == SupportedTarget::Aarch64Sel4Fel4 {
-        cmake_config.define("CROSS_COMPILER_PREFIX", "aarch64-linux-gnu-");
+    // since the seL4-CMake inferred option doesn't support hardware floating point,
+    // and for every target on macOS, where the system compiler can't produce ELF
+    let toolchain = &toolchain_with_env_defaults(&fel4_config.toolchain);
+    if let Some(prefix) = configured_compiler_prefix(fel4_config, host) {
+        cmake_config.define("CROSS_COMPILER_PREFIX", prefix.as_ref());
+    }
+    for variable in &toolchain.variables {
+        add_cmake_definition(cmake_config, &variable.name, &variable.value);
+    }
+    match toolchain.ninja {
+        Some(ref ninja) if options.generator == CmakeGenerator::Ninja => {
+            cmake_config.define("CMAKE_MAKE_PROGRAM", &host.cmake_path(Path::new(ninja)))
+        }
+        _ => {}
+    }
+    if let Some(ref cmake) = toolchain.cmake {
+        cmake_config.cmake_executable(Path::new(cmake));
+    }
+    if let Some(ref launcher) = toolchain.compiler_launcher {
+        for language in &["C", "CXX"] {
+            cmake_config.define(
+                &format!("CMAKE_{}_COMPILER_LAUNCHER", language),
+                launcher.as_ref(),
+            );
+        }
+    }
+    if !toolchain.build_targets.is_empty() {
+        cmake_config.build_targets(&toolchain.build_targets);
+    }
+    // Host compiler and linker flags from the ambient environment would
+    // otherwise be picked up by the kernel's cross build
+    match (&options.env_policy, &toolchain.cleared_env) {
+        (CmakeEnvPolicy::Toolchain, Some(names)) | (CmakeEnvPolicy::Clear(names), _) => {
+            names.iter().for_each(|n| cmake_config.clear_env(n))
+        }
+        (CmakeEnvPolicy::Toolchain, None) => {
+            DEFAULT_CLEARED_ENV.iter().for_each(|n| cmake_config.clear_env(n))
+        }
+        (CmakeEnvPolicy::Inherit, _) => {}
+    }
+    if let Some(epoch) = fel4_config.source_date_epoch {
+        let seconds = source_date_epoch_seconds(epoch, &kernel_path)?;
+        add_reproducible_build_settings(cmake_config, seconds);
     }
 
     // seL4 handles these so we clear them to prevent cmake-rs from
     // auto-populating
-    cmake_config.define("CMAKE_C_FLAGS", "");
-    cmake_config.define("CMAKE_CXX_FLAGS", "");
+    cmake_config.define("CMAKE_C_FLAGS", "".as_ref());
+    cmake_config.define("CMAKE_CXX_FLAGS", "".as_ref());
+
+    if options.verbosity == CmakeVerbosity::Verbose {
+        cmake_config.define("CMAKE_VERBOSE_MAKEFILE:BOOL", "ON".as_ref());
+    }
+    for (name, value) in &options.defines {
+        cmake_config.define(name, value.as_ref());
+    }
+    if let Some(jobs) = options.jobs {
+        cmake_config.jobs(jobs);
+    }
+    if let Some(ref build_dir) = options.build_dir {
+        cmake_config.build_dir(build_dir);
+    }
+
+    // Ninja generator, which is also required on Windows hosts, where
+    // cmake-rs would otherwise default to a Visual Studio generator
+    let generator = match options.generator {
+        CmakeGenerator::Ninja => {
+            cmake_config.generator("Ninja");
+            Some("Ninja".to_string())
+        }
+        CmakeGenerator::CmakeDefault => None,
+    };
+    Ok(AppliedCmakeConfiguration {
+        toolchain_file: kernel_path.join("gcc.cmake"),
+        kernel_path,
+        generator,
+        define_count: counting.defines,
+        cmake_executable: counting.cmake_executable,
+    })
+}
+
+fn target_mismatch(fel4_config: &Fel4Config, cargo_target: &str) -> CmakeConfigurationError {
+    let manifest = fel4_config
+        .manifest_path
+        .as_ref()
+        .map_or_else(|| "The fel4 manifest".to_string(), |p| p.display().to_string());
+    let declared = fel4_config
+        .declared_targets
+        .iter()
+        .map(|t| t.full_name())
+        .collect::<Vec<_>>();
+    let mut hint = format!(
+        "{} declares tables for {}. Build with `cargo build --target {}`, or set `target = \"{}\"` in the [build] table of .cargo/config",
+        manifest,
+        declared.join(", "),
+        fel4_config.target,
+        fel4_config.target
+    );
+    if declared.contains(&cargo_target) {
+        hint.push_str(&format!(
+            ", or select the {} target in the [fel4] table",
+            cargo_target
+        ));
+    }
+    CmakeConfigurationError::CargoTargetToFel4TargetMismatch(Box::new(TargetMismatch {
+        cargo_target: cargo_target.to_string(),
+        fel4_target: fel4_config.target.full_name().to_string(),
+        manifest_path: fel4_config.manifest_path.clone(),
+        declared_targets: fel4_config.declared_targets.clone(),
+        hint,
+    }))
+}
+
+/// Configure a seL4_kernel CMake build for whichever target cargo is
+/// building, provided the manifest declares it, rather than requiring it to
+/// be the selected target.
+///
+/// The configuration is resolved for the cargo target with the manifest's
+/// selected platform, or the target's supported platform if the two can't
+/// be paired, and returned.
+pub fn configure_cmake_build_for_declared_target<S: DefinitionSink, P: AsRef<Path>>(
+    cmake_config: &mut S,
+    full: &FullFel4Manifest,
+    build_profile: &BuildProfile,
+    cargo_manifest_dir: P,
+    cargo_target: &str,
+) -> Result<Fel4Config, CmakeConfigurationError> {
+    let target = match cargo_target.parse::<SupportedTarget>() {
+        Ok(t) if full.targets.contains_key(&t) => t,
+        _ => {
+            let selected = resolve_fel4_config_for(
+                full,
+                full.selected_target,
+                full.selected_platform,
+                build_profile,
+            ).map_err(CmakeConfigurationError::Resolution)?;
+            return Err(target_mismatch(&selected, cargo_target));
+        }
+    };
+    let platform = if is_supported_target_platform_pair(target, full.selected_platform) {
+        full.selected_platform
+    } else {
+        SupportedPlatform::platforms()
+            .into_iter()
+            .find(|&p| is_supported_target_platform_pair(target, p))
+            .unwrap_or(full.selected_platform)
+    };
+    let fel4_config = resolve_fel4_config_for(full, target, platform, build_profile)
+        .map_err(CmakeConfigurationError::Resolution)?;
+    configure_cmake_build(cmake_config, &fel4_config, cargo_manifest_dir, cargo_target)?;
+    Ok(fel4_config)
+}
+
+/// Everything `configure_cmake_build` would apply to a CMake build
+#[derive(Clone, Debug, PartialEq)]
+pub struct CmakePreview {
+    pub kernel_path: PathBuf,
+    pub toolchain_file: PathBuf,
+    /// The CMake generator, or None to leave it to CMake's default
+    pub generator: Option<String>,
+    /// The cmake executable configured for the target, if not the one on PATH
+    pub cmake_executable: Option<PathBuf>,
+    /// Cache variable definitions, sorted by name
+    pub defines: Vec<(String, String)>,
+    /// The CMake targets to build, or empty to build every target
+    pub build_targets: Vec<String>,
+}
 
-    // Ninja generator
-    cmake_config.generator("Ninja");
-    Ok(())
+impl CmakePreview {
+    /// The equivalent command line arguments for a CMake configure step
+    pub fn to_cmake_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(ref generator) = self.generator {
+            args.push("-G".to_string());
+            args.push(generator.clone());
+        }
+        args.extend(
+            self.defines
+                .iter()
+                .map(|(name, value)| format!("-D{}={}", name, value)),
+        );
+        args
+    }
+}
+
+/// Determine the CMake configuration that `configure_cmake_build` would
+/// apply, without constructing a `cmake::Config` or touching the filesystem
+pub fn preview_cmake_configuration<P: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    cargo_manifest_dir: P,
+    cargo_target: &str,
+) -> Result<CmakePreview, CmakeConfigurationError> {
+    let mut recorded = RecordedDefinitions::default();
+    configure_cmake_build(
+        &mut recorded,
+        fel4_config,
+        cargo_manifest_dir.as_ref(),
+        cargo_target,
+    )?;
+    let kernel_path = kernel_path_for(cargo_manifest_dir);
+    let mut defines = recorded.defines;
+    defines.sort();
+    Ok(CmakePreview {
+        toolchain_file: kernel_path.join("gcc.cmake"),
+        kernel_path,
+        generator: recorded.generator,
+        cmake_executable: recorded.cmake_executable,
+        defines,
+        build_targets: recorded.build_targets,
+    })
+}
+
+/// Configure a seL4_kernel CMake build for the fel4 target from a crate that
+/// cargo is building for the host, such as a code generation helper, rather
+/// than for the fel4 target.
+///
+/// This is `configure_cmake_build` without the check that cargo is building
+/// for the selected fel4 target. It is only ever used when called directly,
+/// so a build that forgot `--target` still fails the check.
+pub fn configure_cmake_build_for_host_tools<S: DefinitionSink, P: AsRef<Path>>(
+    cmake_config: &mut S,
+    fel4_config: &Fel4Config,
+    cargo_manifest_dir: P,
+) -> Result<AppliedCmakeConfiguration, CmakeConfigurationError> {
+    fel4_trace!("cmake", "configuring for host tools");
+    configure_cmake_build(
+        cmake_config,
+        fel4_config,
+        cargo_manifest_dir,
+        fel4_config.target.full_name(),
+    )
+}
+
+/// Define the configuration's user properties, those named with the
+/// `USER_PROPERTY_PREFIX`, which `configure_cmake_build` leaves out.
+///
+/// For builds that include the application's own CMake code alongside the
+/// kernel's. Values are defined as the kernel properties' are.
+pub fn add_user_property_definitions<S: DefinitionSink>(
+    cmake_config: &mut S,
+    fel4_config: &Fel4Config,
+) {
+    add_cmake_definitions(
+        cmake_config,
+        fel4_config
+            .ordered_properties()
+            .filter(|&(name, _)| is_user_property(name)),
+    );
 }
 
 /// Configure a seL4_kernel CMake build configuration with data derived from
@@ -67,36 +826,409 @@ pub fn configure_cmake_build<P: AsRef<Path>>(
 /// Assumes the presence of the CARGO_MANIFEST_DIR and TARGET environment
 /// variables from cargo Assumes the seL4_kernel is at
 /// `${CARGO_MANIFEST_DIR}/deps/seL4_kernel`
-pub fn configure_cmake_build_from_env(
-    cmake_config: &mut CmakeConfig,
+///
+/// Where cargo's profile variables contradict the configuration, as
+/// `check_cargo_profile_env` reports, the warnings are traced.
+pub fn configure_cmake_build_from_env<S: DefinitionSink>(
+    cmake_config: &mut S,
     fel4_config: &Fel4Config,
-) -> Result<(), CmakeConfigurationError> {
-    let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").map_err(|_| {
+) -> Result<AppliedCmakeConfiguration, CmakeConfigurationError> {
+    for warning in check_cargo_profile_env(fel4_config) {
+        fel4_trace!("env", "warning: {}", warning);
+    }
+    let cargo_manifest_dir = trace::var("CARGO_MANIFEST_DIR").map_err(|_| {
         CmakeConfigurationError::MissingRequiredEnvVar("CARGO_MANIFEST_DIR".to_string())
     })?;
-    let cargo_target = env::var("TARGET")
+    let cargo_target = trace::var("TARGET")
         .map_err(|_| CmakeConfigurationError::MissingRequiredEnvVar("TARGET".to_string()))?;
     configure_cmake_build(cmake_config, fel4_config, cargo_manifest_dir, &cargo_target)
 }
 
-fn add_cmake_definitions(
-    cmake_config: &mut CmakeConfig,
-    properties: &HashMap<String, FlatTomlValue>,
-) {
+/// One (target, platform) combination's share of a batch CMake configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchCmakeOutput<S> {
+    pub target: SupportedTarget,
+    pub platform: SupportedPlatform,
+    /// The dedicated CMake build directory for the combination
+    pub build_dir: PathBuf,
+    /// The configuration resolved for the combination
+    pub fel4_config: Fel4Config,
+    /// The configured sink, ready to be built
+    pub cmake_config: S,
+}
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum BatchCmakeError {
+    #[fail(
+        display = "Unable to resolve the {} {} configuration: {}",
+        _0,
+        _1,
+        _2
+    )]
+    Resolution(SupportedTarget, SupportedPlatform, ConfigError),
+    #[fail(
+        display = "Unable to configure the {} {} CMake build: {}",
+        _0,
+        _1,
+        _2
+    )]
+    Configuration(SupportedTarget, SupportedPlatform, CmakeConfigurationError),
+    #[fail(display = "Unable to create the CMake build directory {}", _0)]
+    BuildDirectory(String),
+}
+
+/// Configure a separate seL4_kernel CMake build for each of several
+/// (target, platform) combinations of one manifest.
+///
+/// Each combination is resolved as though the manifest's `[fel4]` table had
+/// selected it, and gets its own build directory named
+/// `${build_root}/${target}-${platform}`, which is created if necessary.
+/// `new_sink` produces the sink for a combination given its configuration
+/// and build directory, e.g. a `cmake::Config` with a matching `out_dir`.
+///
+/// Stops at the first combination that can't be resolved or configured.
+pub fn configure_cmake_batch<S, F, P, Q>(
+    full: &FullFel4Manifest,
+    build_profile: &BuildProfile,
+    combinations: &[(SupportedTarget, SupportedPlatform)],
+    cargo_manifest_dir: P,
+    build_root: Q,
+    mut new_sink: F,
+) -> Result<Vec<BatchCmakeOutput<S>>, BatchCmakeError>
+where
+    S: DefinitionSink,
+    F: FnMut(&Fel4Config, &Path) -> S,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let mut outputs = Vec::with_capacity(combinations.len());
+    for &(target, platform) in combinations {
+        let fel4_config = resolve_fel4_config_for(full, target, platform, build_profile)
+            .map_err(|e| BatchCmakeError::Resolution(target, platform, e))?;
+        let build_dir = build_root
+            .as_ref()
+            .join(format!("{}-{}", target, platform));
+        fs::create_dir_all(&build_dir)
+            .map_err(|_| BatchCmakeError::BuildDirectory(build_dir.display().to_string()))?;
+        let mut cmake_config = new_sink(&fel4_config, &build_dir);
+        configure_cmake_build(
+            &mut cmake_config,
+            &fel4_config,
+            cargo_manifest_dir.as_ref(),
+            target.full_name(),
+        ).map_err(|e| BatchCmakeError::Configuration(target, platform, e))?;
+        outputs.push(BatchCmakeOutput {
+            target,
+            platform,
+            build_dir,
+            fel4_config,
+            cmake_config,
+        });
+    }
+    Ok(outputs)
+}
+
+/// The file `configure_cmake_build_with_audit` writes into the output directory
+pub const CMAKE_AUDIT_FILE_NAME: &str = "cmake-configuration-audit.toml";
+
+/// Forwards to another sink, keeping a record of everything it is given
+struct AuditingSink<'a, S: 'a> {
+    inner: &'a mut S,
+    recorded: RecordedDefinitions,
+}
+
+impl<'a, S: DefinitionSink> DefinitionSink for AuditingSink<'a, S> {
+    fn define(&mut self, name: &str, value: &OsStr) {
+        self.recorded.define(name, value);
+        self.inner.define(name, value);
+    }
+
+    fn generator(&mut self, generator: &str) {
+        self.recorded.generator(generator);
+        self.inner.generator(generator);
+    }
+
+    fn cmake_executable(&mut self, path: &Path) {
+        self.recorded.cmake_executable(path);
+        self.inner.cmake_executable(path);
+    }
+
+    fn build_targets(&mut self, targets: &[String]) {
+        self.recorded.build_targets(targets);
+        self.inner.build_targets(targets);
+    }
+
+    fn clear_env(&mut self, name: &str) {
+        self.recorded.clear_env(name);
+        self.inner.clear_env(name);
+    }
+
+    fn set_env(&mut self, name: &str, value: &str) {
+        self.recorded.set_env(name, value);
+        self.inner.set_env(name, value);
+    }
+
+    fn jobs(&mut self, jobs: usize) {
+        self.recorded.jobs(jobs);
+        self.inner.jobs(jobs);
+    }
+
+    fn build_dir(&mut self, dir: &Path) {
+        self.recorded.build_dir(dir);
+        self.inner.build_dir(dir);
+    }
+
+    fn checks_paths(&self) -> bool {
+        self.inner.checks_paths()
+    }
+}
+
+/// Configure a seL4_kernel CMake build as `configure_cmake_build` does, and
+/// record exactly how it was configured in an audit file.
+///
+/// The audit is written to `CMAKE_AUDIT_FILE_NAME` within the output
+/// directory, relative to `cargo_manifest_dir`, and its path is returned.
+/// It is a toml document naming the selection, toolchain file, and generator,
+/// with a `[[define]]` entry for every definition in the order it was made,
+/// giving its name, value, and `source`: the manifest table the value came
+/// from, `rules` for values implied by a `PropertyRule`, `host default` for a
+/// cross compiler prefix inferred for the host, or `fel4-config` for
+/// definitions this crate always makes.
+pub fn configure_cmake_build_with_audit<S: DefinitionSink, P: AsRef<Path>>(
+    cmake_config: &mut S,
+    fel4_config: &Fel4Config,
+    cargo_manifest_dir: P,
+    cargo_target: &str,
+) -> Result<PathBuf, CmakeConfigurationError> {
+    let mut auditing = AuditingSink {
+        inner: cmake_config,
+        recorded: RecordedDefinitions::default(),
+    };
+    configure_cmake_build(
+        &mut auditing,
+        fel4_config,
+        cargo_manifest_dir.as_ref(),
+        cargo_target,
+    )?;
+    let audit = cmake_audit(
+        fel4_config,
+        &kernel_path_for(cargo_manifest_dir.as_ref()),
+        &auditing.recorded,
+    );
+    let artifact_dir = cargo_manifest_dir
+        .as_ref()
+        .join(fel4_config.output_dir());
+    let audit_path = artifact_dir.join(CMAKE_AUDIT_FILE_NAME);
+    fs::create_dir_all(&artifact_dir)
+        .and_then(|_| File::create(&audit_path))
+        .and_then(|mut f| f.write_all(audit.as_bytes()))
+        .map_err(|_| {
+            CmakeConfigurationError::AuditWriteFailure(audit_path.display().to_string())
+        })?;
+    Ok(audit_path)
+}
+
+fn cmake_audit(
+    fel4_config: &Fel4Config,
+    kernel_path: &Path,
+    recorded: &RecordedDefinitions,
+) -> String {
+    let string = |s: &str| toml::Value::String(s.to_string());
+    let mut root = BTreeMap::new();
+    root.insert("target".to_string(), string(fel4_config.target.full_name()));
+    root.insert(
+        "platform".to_string(),
+        string(fel4_config.platform.full_name()),
+    );
+    root.insert(
+        "build-profile".to_string(),
+        string(fel4_config.build_profile.full_name()),
+    );
+    root.insert(
+        "kernel-path".to_string(),
+        string(&kernel_path.display().to_string()),
+    );
+    root.insert(
+        "toolchain-file".to_string(),
+        string(&kernel_path.join("gcc.cmake").display().to_string()),
+    );
+    if let Some(ref generator) = recorded.generator {
+        root.insert("generator".to_string(), string(generator));
+    }
+    if let Some(ref cmake) = recorded.cmake_executable {
+        root.insert(
+            "cmake-executable".to_string(),
+            string(&cmake.display().to_string()),
+        );
+    }
+    if !recorded.build_targets.is_empty() {
+        root.insert(
+            "build-targets".to_string(),
+            toml::Value::Array(recorded.build_targets.iter().map(|t| string(t)).collect()),
+        );
+    }
+    if !recorded.cleared_env.is_empty() {
+        root.insert(
+            "cleared-env".to_string(),
+            toml::Value::Array(recorded.cleared_env.iter().map(|n| string(n)).collect()),
+        );
+    }
+    if !recorded.env.is_empty() {
+        root.insert(
+            "env".to_string(),
+            toml::Value::Table(
+                recorded
+                    .env
+                    .iter()
+                    .map(|(name, value)| (name.clone(), string(value)))
+                    .collect(),
+            ),
+        );
+    }
+    let defines = recorded
+        .defines
+        .iter()
+        .map(|(name, value)| {
+            let mut entry = BTreeMap::new();
+            entry.insert("name".to_string(), string(name));
+            entry.insert("value".to_string(), string(value));
+            entry.insert(
+                "source".to_string(),
+                string(&definition_source(fel4_config, name)),
+            );
+            toml::Value::Table(entry)
+        })
+        .collect();
+    root.insert("define".to_string(), toml::Value::Array(defines));
+    format!(
+        "# CMake configuration audit generated by fel4-config {}\n{}",
+        env!("CARGO_PKG_VERSION"),
+        toml::to_string(&toml::Value::Table(root))
+            .expect("CMake audit records should always serialize")
+    )
+}
+
+/// Where the value of a definition made by `configure_cmake_build` came from
+fn definition_source(fel4_config: &Fel4Config, definition: &str) -> String {
+    let name = definition.trim_end_matches(":BOOL");
+    let toolchain_table = format!("toolchain.{}", fel4_config.target.full_name());
+    let toolchain = &fel4_config.toolchain;
+    if toolchain.variables.iter().any(|v| v.name == name) {
+        return format!("{}.variables", toolchain_table);
+    }
+    if let Some(layer) = fel4_config.provenance.get(name) {
+        return layer.location(fel4_config);
+    }
+    match name {
+        "CROSS_COMPILER_PREFIX" if toolchain.compiler_prefix.is_some() => toolchain_table,
+        "CROSS_COMPILER_PREFIX" => "host default".to_string(),
+        "CMAKE_MAKE_PROGRAM" => toolchain_table,
+        _ if name.contains("_ARCHIVE_") && fel4_config.source_date_epoch.is_some() => {
+            "fel4.source-date-epoch".to_string()
+        }
+        "CMAKE_BUILD_TYPE"
+            if fel4_config.cmake_build_type
+                != fel4_config.build_profile.default_cmake_build_type() =>
+        {
+            "fel4.cmake-build-types".to_string()
+        }
+        _ => "fel4-config".to_string(),
+    }
+}
+
+/// The seconds since the Unix epoch a `SourceDateEpoch` stands for
+fn source_date_epoch_seconds(
+    epoch: SourceDateEpoch,
+    kernel_path: &Path,
+) -> Result<i64, CmakeConfigurationError> {
+    let kernel_commit_time = || -> Result<i64, String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(kernel_path)
+            .args(["log", "-1", "--format=%ct"])
+            .output()
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.trim().parse().map_err(|_| stdout.trim().to_string())
+    };
+    match epoch {
+        SourceDateEpoch::Fixed(seconds) => Ok(seconds),
+        SourceDateEpoch::KernelCommit => kernel_commit_time().map_err(|e| {
+            CmakeConfigurationError::UnknownSourceDateEpoch(kernel_path.display().to_string(), e)
+        }),
+    }
+}
+
+/// Pin the timestamps a kernel build would otherwise take from the clock:
+/// compilers honour `SOURCE_DATE_EPOCH` for `__DATE__` and `__TIME__`, and
+/// the archives are created in deterministic mode, without member
+/// timestamps, owners, or modes
+fn add_reproducible_build_settings<S: DefinitionSink>(cmake_config: &mut S, seconds: i64) {
+    cmake_config.set_env("SOURCE_DATE_EPOCH", &seconds.to_string());
+    // The equivalent for the Apple archiver, which has no deterministic flag
+    cmake_config.set_env("ZERO_AR_DATE", "1");
+    for language in &["C", "CXX", "ASM"] {
+        cmake_config.define(
+            &format!("CMAKE_{}_ARCHIVE_CREATE", language),
+            "<CMAKE_AR> qcD <TARGET> <LINK_FLAGS> <OBJECTS>".as_ref(),
+        );
+        cmake_config.define(
+            &format!("CMAKE_{}_ARCHIVE_APPEND", language),
+            "<CMAKE_AR> qD <TARGET> <LINK_FLAGS> <OBJECTS>".as_ref(),
+        );
+        cmake_config.define(
+            &format!("CMAKE_{}_ARCHIVE_FINISH", language),
+            "<CMAKE_RANLIB> -D <TARGET>".as_ref(),
+        );
+    }
+}
+
+/// Check that the seL4_kernel source directory and its `gcc.cmake` toolchain
+/// file are readable, so that a missing checkout is reported as such rather
+/// than as a cascade of unrelated CMake errors
+pub fn check_kernel_paths(kernel_path: &Path) -> Result<(), CmakeConfigurationError> {
+    if fs::read_dir(kernel_path).is_err() {
+        return Err(CmakeConfigurationError::UnreadableKernelPath(
+            kernel_path.display().to_string(),
+        ));
+    }
+    let toolchain_file = kernel_path.join("gcc.cmake");
+    match File::open(&toolchain_file).and_then(|f| f.metadata()) {
+        Ok(ref metadata) if metadata.is_file() => Ok(()),
+        _ => Err(CmakeConfigurationError::UnreadableToolchainFile(
+            toolchain_file.display().to_string(),
+        )),
+    }
+}
+
+pub(crate) fn kernel_path_for<P: AsRef<Path>>(cargo_manifest_dir: P) -> PathBuf {
+    cargo_manifest_dir.as_ref().join("deps").join("seL4_kernel")
+}
+
+fn add_cmake_definitions<'a, S, I>(cmake_config: &mut S, properties: I)
+where
+    S: DefinitionSink,
+    I: IntoIterator<Item = (&'a str, &'a FlatTomlValue)>,
+{
     for (name, value) in properties {
         add_cmake_definition(cmake_config, name, value);
     }
 }
 
-fn add_cmake_definition(config: &mut CmakeConfig, name: &str, value: &FlatTomlValue) {
+fn add_cmake_definition<S: DefinitionSink>(config: &mut S, name: &str, value: &FlatTomlValue) {
     match *value {
-        FlatTomlValue::Boolean(b) => {
-            config.define(format!("{}:BOOL", name), if b { "ON" } else { "OFF" })
-        }
-        FlatTomlValue::Integer(i) => config.define(name, i.to_string()),
-        FlatTomlValue::String(ref s) => config.define(name, s),
-        FlatTomlValue::Float(f) => config.define(name, f.to_string()),
-        FlatTomlValue::Datetime(ref d) => config.define(name, format!("{}", d)),
+        FlatTomlValue::Boolean(b) => config.define(
+            &format!("{}:BOOL", name),
+            if b { "ON" } else { "OFF" }.as_ref(),
+        ),
+        FlatTomlValue::Integer(i) => config.define(name, i.to_string().as_ref()),
+        FlatTomlValue::String(ref s) => config.define(name, s.as_ref()),
+        FlatTomlValue::Float(f) => config.define(name, f.to_string().as_ref()),
+        FlatTomlValue::Datetime(ref d) => config.define(name, format!("{}", d).as_ref()),
     };
 }
 #[cfg(test)]
@@ -112,13 +1244,103 @@ mod tests {
             .expect("Should be able to get the default fel4.toml");
         let fel4_config =
             resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
-        let r = configure_cmake_build(
-            &mut c,
+        let repo = ::tempfile::tempdir().unwrap();
+        let kernel_path = repo.path().join("deps").join("seL4_kernel");
+        fs::create_dir_all(&kernel_path).unwrap();
+        fs::write(kernel_path.join("gcc.cmake"), "").unwrap();
+        let applied = configure_cmake_build(&mut c, &fel4_config, repo.path(), "x86_64-sel4-fel4")
+            .expect("Should be able to configure the build");
+        assert_eq!(kernel_path, applied.kernel_path);
+        assert_eq!(kernel_path.join("gcc.cmake"), applied.toolchain_file);
+        assert_eq!(Some("Ninja"), applied.generator.as_deref());
+        assert_eq!(None, applied.cmake_executable);
+
+        let mut recorded = RecordedDefinitions::default();
+        configure_cmake_build(
+            &mut recorded,
             &fel4_config,
             Path::new("./some/repo"),
             "x86_64-sel4-fel4",
+        ).expect("Recorded configurations shouldn't touch the filesystem");
+        assert_eq!(recorded.defines.len(), applied.define_count);
+    }
+
+    #[test]
+    fn cmake_executable_is_reported_without_touching_the_environment() {
+        let mut fel4_config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        let cmake = "/opt/fel4-test-cmake/bin/cmake";
+        fel4_config.toolchain.cmake = Some(cmake.into());
+        let repo = ::tempfile::tempdir().unwrap();
+        let kernel_path = repo.path().join("deps").join("seL4_kernel");
+        fs::create_dir_all(&kernel_path).unwrap();
+        fs::write(kernel_path.join("gcc.cmake"), "").unwrap();
+        let mut c = CmakeConfig::new(PathBuf::from("./somewhere/bogus"));
+        let applied =
+            configure_cmake_build(&mut c, &fel4_config, repo.path(), "x86_64-sel4-fel4").unwrap();
+        assert_eq!(Some(PathBuf::from(cmake)), applied.cmake_executable);
+        assert_ne!(Some(OsStr::new(cmake).to_os_string()), env::var_os("CMAKE"));
+    }
+
+    #[test]
+    fn linker_script_properties_are_not_kernel_definitions() {
+        let mut fel4_config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        for &(name, ref value) in &[
+            ("UserLinkerScript", FlatTomlValue::String("link.ld.in".into())),
+            ("UserLinkerEntryPoint", FlatTomlValue::String("_start".into())),
+            ("UserImageBase", FlatTomlValue::Integer(0x1000_0000)),
+        ] {
+            fel4_config.properties.insert(name.into(), value.clone());
+            fel4_config.property_order.push(name.into());
+        }
+        let mut recorded = RecordedDefinitions::default();
+        configure_cmake_build(
+            &mut recorded,
+            &fel4_config,
+            Path::new("./some/repo"),
+            "x86_64-sel4-fel4",
+        ).unwrap();
+        assert!(recorded.get("KernelPrinting:BOOL").is_some());
+        for (name, _) in &recorded.defines {
+            assert!(!name.starts_with("UserLinkerScript"), "{}", name);
+            assert!(!name.starts_with("UserLinkerEntryPoint"), "{}", name);
+            assert!(!name.starts_with("UserImageBase"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn missing_kernel_sources_are_reported_before_cmake_runs() {
+        let fel4_config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        let repo = ::tempfile::tempdir().unwrap();
+        let kernel_path = repo.path().join("deps").join("seL4_kernel");
+        let configure = || {
+            let mut c = CmakeConfig::new(repo.path().join("build"));
+            configure_cmake_build(&mut c, &fel4_config, repo.path(), "x86_64-sel4-fel4")
+        };
+        assert_eq!(
+            Err(CmakeConfigurationError::UnreadableKernelPath(
+                kernel_path.display().to_string()
+            )),
+            configure()
+        );
+        fs::create_dir_all(&kernel_path).unwrap();
+        assert_eq!(
+            Err(CmakeConfigurationError::UnreadableToolchainFile(
+                kernel_path.join("gcc.cmake").display().to_string()
+            )),
+            configure()
         );
-        assert_eq!(Ok(()), r);
     }
 
     #[test]
@@ -134,15 +1356,559 @@ mod tests {
             Path::new("./some/repo"),
             "armv7-sel4-fel4",
         );
+        match r {
+            Err(CmakeConfigurationError::CargoTargetToFel4TargetMismatch(ref mismatch)) => {
+                assert_eq!("armv7-sel4-fel4", mismatch.cargo_target);
+                assert_eq!("x86_64-sel4-fel4", mismatch.fel4_target);
+                assert_eq!(SupportedTarget::targets(), mismatch.declared_targets);
+                assert!(mismatch.hint.contains("--target x86_64-sel4-fel4"));
+                assert!(mismatch.hint.contains("select the armv7-sel4-fel4 target"));
+            }
+            other => panic!("Expected a target mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn host_tools_builds_skip_the_target_check() {
+        let mut recorded = RecordedDefinitions::default();
+        let full = parse_full_manifest(get_exemplar_default_toml()).unwrap();
+        let fel4_config = resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        configure_cmake_build_for_host_tools(&mut recorded, &fel4_config, "/some/repo")
+            .expect("Host tools builds configure the fel4 target");
+        assert_eq!(Some("nehalem"), recorded.get("KernelX86MicroArch"));
+    }
+
+    #[test]
+    fn user_properties_are_only_defined_on_request() {
+        let fel4_config = test_helpers::ManifestFixture::new()
+            .with_property("x86_64-sel4-fel4", "X-AppLogLevel", 3)
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        let mut recorded = RecordedDefinitions::default();
+        configure_cmake_build(
+            &mut recorded,
+            &fel4_config,
+            "/some/repo",
+            "x86_64-sel4-fel4",
+        ).unwrap();
+        assert_eq!(None, recorded.get("X-AppLogLevel"));
+        add_user_property_definitions(&mut recorded, &fel4_config);
+        assert_eq!(Some("3"), recorded.get("X-AppLogLevel"));
+    }
+
+    #[test]
+    fn declared_cargo_targets_can_be_configured_directly() {
+        let mut recorded = RecordedDefinitions::default();
+        let full = parse_full_manifest(get_exemplar_default_toml()).unwrap();
+        let fel4_config = configure_cmake_build_for_declared_target(
+            &mut recorded,
+            &full,
+            &BuildProfile::Debug,
+            "/some/repo",
+            "armv7-sel4-fel4",
+        ).expect("The armv7 target is declared");
+        assert_eq!(SupportedTarget::Armv7Sel4Fel4, fel4_config.target);
+        assert_eq!(SupportedPlatform::Sabre, fel4_config.platform);
+        assert_eq!(Some("sabre"), recorded.get("KernelARMPlatform"));
+        match configure_cmake_build_for_declared_target(
+            &mut recorded,
+            &full,
+            &BuildProfile::Debug,
+            "/some/repo",
+            "riscv-sel4-fel4",
+        ) {
+            Err(CmakeConfigurationError::CargoTargetToFel4TargetMismatch(_)) => (),
+            other => panic!("Expected a target mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn exemplar_cmake_configuration_defines_expected_values() {
+        let mut recorded = RecordedDefinitions::default();
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let fel4_config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        configure_cmake_build_for_host(
+            &mut recorded,
+            &fel4_config,
+            Path::new("/some/repo"),
+            "x86_64-sel4-fel4",
+            HostOs::Linux,
+        ).expect("Should be able to configure the build");
+
+        let kernel_path = PathBuf::from("/some/repo/deps/seL4_kernel");
+        assert_eq!(Some("Ninja"), recorded.generator.as_deref());
+        assert_eq!(
+            Some(kernel_path.join("gcc.cmake").to_string_lossy().as_ref()),
+            recorded.get("CMAKE_TOOLCHAIN_FILE")
+        );
+        assert_eq!(
+            Some(kernel_path.to_string_lossy().as_ref()),
+            recorded.get("KERNEL_PATH")
+        );
+        assert_eq!(Some("ON"), recorded.get("KernelPrinting:BOOL"));
+        assert_eq!(Some("OFF"), recorded.get("KernelVerificationBuild:BOOL"));
+        assert_eq!(Some("256"), recorded.get("KernelNumPriorities"));
+        assert_eq!(Some("nehalem"), recorded.get("KernelX86MicroArch"));
+        assert_eq!(Some(""), recorded.get("CMAKE_C_FLAGS"));
+        assert_eq!(Some("Debug"), recorded.get("CMAKE_BUILD_TYPE"));
+        assert_eq!(None, recorded.get("CROSS_COMPILER_PREFIX"));
+    }
+
+    #[test]
+    fn arm_cmake_configuration_defines_cross_compiler_prefix() {
+        let mut recorded = RecordedDefinitions::default();
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml")
+            .with_selection(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
+            .expect("Should be able to select the armv7 target");
+        let fel4_config = resolve_fel4_config(full, &BuildProfile::Release)
+            .expect("Trouble in config resolution");
+        configure_cmake_build_for_host(
+            &mut recorded,
+            &fel4_config,
+            Path::new("/some/repo"),
+            "armv7-sel4-fel4",
+            HostOs::Linux,
+        ).expect("Should be able to configure the build");
         assert_eq!(
-            Err(CmakeConfigurationError::CargoTargetToFel4TargetMismatch(
-                "armv7-sel4-fel4".to_string(),
-                "x86_64-sel4-fel4".to_string()
+            Some("arm-linux-gnueabihf-"),
+            recorded.get("CROSS_COMPILER_PREFIX")
+        );
+        assert_eq!(Some("sabre"), recorded.get("KernelARMPlatform"));
+        assert_eq!(Some("OFF"), recorded.get("KernelPrinting:BOOL"));
+    }
+
+    #[test]
+    fn aarch64_cmake_configuration_defines_cross_compiler_prefix() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml")
+            .with_selection(SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1)
+            .expect("Should be able to select the aarch64 target");
+        let fel4_config = resolve_fel4_config(full, &BuildProfile::Debug)
+            .expect("Trouble in config resolution");
+        for &(host, prefix) in &[
+            (HostOs::Linux, "aarch64-linux-gnu-"),
+            (HostOs::MacOs, "aarch64-elf-"),
+        ] {
+            let mut recorded = RecordedDefinitions::default();
+            configure_cmake_build_for_host(
+                &mut recorded,
+                &fel4_config,
+                Path::new("/some/repo"),
+                "aarch64-sel4-fel4",
+                host,
+            ).expect("Should be able to configure the build");
+            assert_eq!(Some(prefix), recorded.get("CROSS_COMPILER_PREFIX"));
+            assert_eq!(Some("aarch64"), recorded.get("KernelArmSel4Arch"));
+            assert_eq!(Some("tx1"), recorded.get("KernelARMPlatform"));
+        }
+    }
+
+    #[test]
+    fn toolchain_settings_override_cmake_defaults() {
+        let mut recorded = RecordedDefinitions::default();
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let mut fel4_config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        fel4_config.toolchain = ToolchainSettings {
+            compiler_prefix: Some("x86_64-elf-".into()),
+            platform_compiler_prefixes: Default::default(),
+            float_abi: None,
+            cmake: Some("/opt/cmake/bin/cmake".into()),
+            ninja: Some("/opt/ninja/bin/ninja".into()),
+            compiler_launcher: Some("ccache".into()),
+            variables: vec![FlatTomlProperty::new(
+                "GCC_SYSROOT".into(),
+                FlatTomlValue::String("/opt/sysroot".into()),
+            )],
+            build_targets: vec![KERNEL_BUILD_TARGET.into()],
+            cleared_env: Some(vec!["LDFLAGS".into()]),
+        };
+        configure_cmake_build(
+            &mut recorded,
+            &fel4_config,
+            Path::new("/some/repo"),
+            "x86_64-sel4-fel4",
+        ).expect("Should be able to configure the build");
+        assert_eq!(Some("x86_64-elf-"), recorded.get("CROSS_COMPILER_PREFIX"));
+        assert_eq!(Some("/opt/sysroot"), recorded.get("GCC_SYSROOT"));
+        assert_eq!(
+            Some("/opt/ninja/bin/ninja"),
+            recorded.get("CMAKE_MAKE_PROGRAM")
+        );
+        assert_eq!(
+            Some(PathBuf::from("/opt/cmake/bin/cmake")),
+            recorded.cmake_executable
+        );
+        assert_eq!(vec![KERNEL_BUILD_TARGET.to_string()], recorded.build_targets);
+        assert_eq!(vec!["LDFLAGS".to_string()], recorded.cleared_env);
+        assert_eq!(Some("ccache"), recorded.get("CMAKE_C_COMPILER_LAUNCHER"));
+        assert_eq!(Some("ccache"), recorded.get("CMAKE_CXX_COMPILER_LAUNCHER"));
+
+        fel4_config.toolchain.cleared_env = None;
+        let mut recorded = RecordedDefinitions::default();
+        configure_cmake_build(
+            &mut recorded,
+            &fel4_config,
+            Path::new("/some/repo"),
+            "x86_64-sel4-fel4",
+        ).unwrap();
+        assert_eq!(DEFAULT_CLEARED_ENV.to_vec(), recorded.cleared_env);
+    }
+
+    #[test]
+    fn macos_hosts_get_elf_cross_compiler_prefixes() {
+        let mut recorded = RecordedDefinitions::default();
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let fel4_config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        configure_cmake_build_for_host(
+            &mut recorded,
+            &fel4_config,
+            Path::new("/some/repo"),
+            "x86_64-sel4-fel4",
+            HostOs::MacOs,
+        ).expect("Should be able to configure the build");
+        assert_eq!(Some("x86_64-elf-"), recorded.get("CROSS_COMPILER_PREFIX"));
+    }
+
+    #[test]
+    fn windows_hosts_get_cmake_style_paths() {
+        let mut recorded = RecordedDefinitions::default();
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let fel4_config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        configure_cmake_build_for_host(
+            &mut recorded,
+            &fel4_config,
+            Path::new(r"C:\work\app"),
+            "x86_64-sel4-fel4",
+            HostOs::Windows,
+        ).expect("Should be able to configure the build");
+        assert_eq!(Some("Ninja"), recorded.generator.as_deref());
+        assert!(!recorded.get("CMAKE_TOOLCHAIN_FILE").unwrap().contains('\\'));
+        assert!(!recorded.get("KERNEL_PATH").unwrap().contains('\\'));
+    }
+
+    #[test]
+    fn build_options_are_applied_after_the_configuration() {
+        let fel4_config = test_helpers::ManifestFixture::new()
+            .with_property("toolchain.x86_64-sel4-fel4", "clear-env", vec!["CFLAGS"])
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        let options = CmakeBuildOptions::new()
+            .generator(CmakeGenerator::CmakeDefault)
+            .host(HostOs::Linux)
+            .jobs(4)
+            .build_dir("/tmp/kernel-build")
+            .define("KernelPrinting:BOOL", "ON")
+            .define("KernelPrinting:BOOL", "OFF")
+            .env_policy(CmakeEnvPolicy::Inherit)
+            .verbosity(CmakeVerbosity::Verbose);
+        let mut recorded = RecordedDefinitions::default();
+        let applied = configure_cmake_build_with_options(
+            &mut recorded,
+            &fel4_config,
+            Path::new("/some/repo"),
+            "x86_64-sel4-fel4",
+            &options,
+        ).expect("Should be able to configure the build");
+        assert_eq!(None, applied.generator);
+        assert_eq!(Some(4), recorded.jobs);
+        assert_eq!(Some(PathBuf::from("/tmp/kernel-build")), recorded.build_dir);
+        assert_eq!(1, options.defines.len());
+        assert_eq!(Some("OFF"), recorded.get("KernelPrinting:BOOL"));
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(true)),
+            fel4_config.properties.get("KernelPrinting")
+        );
+        assert_eq!(Some("ON"), recorded.get("CMAKE_VERBOSE_MAKEFILE:BOOL"));
+        assert!(recorded.cleared_env.is_empty());
+
+        let clear = |policy| {
+            let mut recorded = RecordedDefinitions::default();
+            configure_cmake_build_with_options(
+                &mut recorded,
+                &fel4_config,
+                Path::new("/some/repo"),
+                "x86_64-sel4-fel4",
+                &CmakeBuildOptions::new().env_policy(policy),
+            ).unwrap();
+            recorded.cleared_env
+        };
+        assert_eq!(vec!["CFLAGS"], clear(CmakeEnvPolicy::Toolchain));
+        assert_eq!(
+            vec!["LDFLAGS"],
+            clear(CmakeEnvPolicy::Clear(vec!["LDFLAGS".into()]))
+        );
+        assert_eq!(None, CmakeBuildOptions::default().jobs);
+    }
+
+    #[test]
+    fn ninja_can_be_left_out_for_the_cmake_default_generator() {
+        let fel4_config = test_helpers::ManifestFixture::new()
+            .with_property("toolchain.x86_64-sel4-fel4", "ninja", "/opt/bin/ninja")
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        let mut recorded = RecordedDefinitions::default();
+        configure_cmake_build_with_generator(
+            &mut recorded,
+            &fel4_config,
+            Path::new("/some/repo"),
+            "x86_64-sel4-fel4",
+            CmakeGenerator::CmakeDefault,
+        ).expect("Should be able to configure the build");
+        assert_eq!(None, recorded.generator);
+        assert_eq!(None, recorded.get("CMAKE_MAKE_PROGRAM"));
+        assert!(recorded.get("CMAKE_TOOLCHAIN_FILE").is_some());
+
+        let mut recorded = RecordedDefinitions::default();
+        configure_cmake_build_with_generator(
+            &mut recorded,
+            &fel4_config,
+            Path::new("/some/repo"),
+            "x86_64-sel4-fel4",
+            CmakeGenerator::Ninja,
+        ).expect("Should be able to configure the build");
+        assert_eq!(Some("Ninja"), recorded.generator.as_deref());
+        assert_eq!(Some("/opt/bin/ninja"), recorded.get("CMAKE_MAKE_PROGRAM"));
+
+        assert_eq!(
+            CmakeGenerator::CmakeDefault,
+            generator_from(|_| Some("1".to_string()))
+        );
+        assert_eq!(CmakeGenerator::Ninja, generator_from(|_| Some(String::new())));
+        assert_eq!(CmakeGenerator::Ninja, generator_from(|_| None));
+    }
+
+    #[test]
+    fn source_date_epochs_pin_timestamps_and_archive_rules() {
+        let configure = |fel4_config: &Fel4Config, kernel_repo: &Path| {
+            let mut recorded = RecordedDefinitions::default();
+            configure_cmake_build(&mut recorded, fel4_config, kernel_repo, "x86_64-sel4-fel4")
+                .map(|_| recorded)
+        };
+        let unpinned = test_helpers::ManifestFixture::new()
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        let recorded = configure(&unpinned, Path::new("/some/repo")).unwrap();
+        assert!(recorded.env.is_empty());
+        assert_eq!(None, recorded.get("CMAKE_C_ARCHIVE_CREATE"));
+
+        let pinned = test_helpers::ManifestFixture::new()
+            .with_property("fel4", "source-date-epoch", 1_500_000_000)
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        assert_eq!(Some(SourceDateEpoch::Fixed(1_500_000_000)), pinned.source_date_epoch);
+        let recorded = configure(&pinned, Path::new("/some/repo")).unwrap();
+        assert!(recorded
+            .env
+            .contains(&("SOURCE_DATE_EPOCH".to_string(), "1500000000".to_string())));
+        assert_eq!(
+            Some("<CMAKE_AR> qcD <TARGET> <LINK_FLAGS> <OBJECTS>"),
+            recorded.get("CMAKE_C_ARCHIVE_CREATE")
+        );
+        assert_eq!(
+            Some("<CMAKE_RANLIB> -D <TARGET>"),
+            recorded.get("CMAKE_ASM_ARCHIVE_FINISH")
+        );
+
+        assert!(match test_helpers::ManifestFixture::new()
+            .with_property("fel4", "source-date-epoch", -1)
+            .parse()
+        {
+            Err(ConfigError::InvalidValueOption("source-date-epoch", _, value)) => value == "-1",
+            _ => false,
+        });
+
+        let from_commit = test_helpers::ManifestFixture::new()
+            .with_property("fel4", "source-date-epoch", "kernel-commit")
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        let repo = ::tempfile::tempdir().unwrap();
+        match configure(&from_commit, repo.path()) {
+            Err(CmakeConfigurationError::UnknownSourceDateEpoch(location, _)) => {
+                assert!(location.ends_with("seL4_kernel"))
+            }
+            other => panic!("Expected the missing checkout to be reported: {:?}", other),
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_toolchain_file_path_uses_forward_slashes() {
+        let mut recorded = RecordedDefinitions::default();
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let fel4_config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        configure_cmake_build(
+            &mut recorded,
+            &fel4_config,
+            Path::new(r"C:\work\app"),
+            "x86_64-sel4-fel4",
+        ).expect("Should be able to configure the build");
+        assert_eq!(
+            Some("C:/work/app/deps/seL4_kernel/gcc.cmake"),
+            recorded.get("CMAKE_TOOLCHAIN_FILE")
+        );
+    }
+
+    #[test]
+    fn preview_reports_sorted_defines_and_paths() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let fel4_config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        let preview =
+            preview_cmake_configuration(&fel4_config, Path::new("/some/repo"), "x86_64-sel4-fel4")
+                .expect("Should be able to preview the configuration");
+        assert_eq!(
+            PathBuf::from("/some/repo/deps/seL4_kernel"),
+            preview.kernel_path
+        );
+        assert_eq!(
+            PathBuf::from("/some/repo/deps/seL4_kernel/gcc.cmake"),
+            preview.toolchain_file
+        );
+        assert_eq!(Some("Ninja"), preview.generator.as_deref());
+        let names: Vec<&String> = preview.defines.iter().map(|(n, _)| n).collect();
+        let mut sorted_names = names.clone();
+        sorted_names.sort();
+        assert_eq!(sorted_names, names);
+        let args = preview.to_cmake_args();
+        assert_eq!(vec!["-G", "Ninja"], args[..2].to_vec());
+        assert!(args.contains(&"-DKernelX86MicroArch=nehalem".to_string()));
+
+        let cmake_default = CmakePreview {
+            generator: None,
+            ..preview
+        };
+        let args = cmake_default.to_cmake_args();
+        assert!(!args.contains(&"-G".to_string()));
+        assert!(args.iter().all(|arg| arg.starts_with("-D")));
+    }
+
+    #[test]
+    fn preview_reports_target_mismatch() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let fel4_config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        assert_eq!(
+            Err(target_mismatch(&fel4_config, "aarch64-sel4-fel4")),
+            preview_cmake_configuration(&fel4_config, "/some/repo", "aarch64-sel4-fel4")
+        );
+    }
+
+    #[test]
+    fn batch_configures_a_build_directory_per_combination() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let root = ::tempfile::tempdir().unwrap();
+        let outputs = configure_cmake_batch(
+            &full,
+            &BuildProfile::Release,
+            &[
+                (SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99),
+                (SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre),
+                (SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1),
+            ],
+            Path::new("./some/repo"),
+            root.path(),
+            |_, _| RecordedDefinitions::default(),
+        ).expect("Every exemplar combination should configure");
+        assert_eq!(3, outputs.len());
+        for output in &outputs {
+            assert!(output.build_dir.is_dir());
+            assert_eq!(output.target, output.fel4_config.target);
+        }
+        assert_eq!(
+            Some("sabre"),
+            outputs[1].cmake_config.get("KernelARMPlatform")
+        );
+        assert_eq!(
+            root.path().join("armv7-sel4-fel4-sabre"),
+            outputs[1].build_dir
+        );
+
+        assert_eq!(
+            Err(BatchCmakeError::Resolution(
+                SupportedTarget::Armv7Sel4Fel4,
+                SupportedPlatform::PC99,
+                ConfigError::TargetPlatformMismatch(
+                    SupportedTarget::Armv7Sel4Fel4,
+                    SupportedPlatform::PC99
+                )
             )),
-            r
+            configure_cmake_batch(
+                &full,
+                &BuildProfile::Release,
+                &[(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::PC99)],
+                Path::new("./some/repo"),
+                root.path(),
+                |_, _| RecordedDefinitions::default(),
+            )
         );
     }
 
-    // TODO - better testing after environment variable usage is factored out of
-    // configure_cmake_build
+    #[test]
+    fn audit_records_every_define_with_its_source() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Debug,
+        );
+        config.toolchain.variables.push(FlatTomlProperty::new(
+            "GCC_SYSROOT".into(),
+            FlatTomlValue::String("/opt/sysroot".into()),
+        ));
+        let mut recorded = RecordedDefinitions::default();
+        let audit_path =
+            configure_cmake_build_with_audit(&mut recorded, &config, dir.path(), "armv7-sel4-fel4")
+                .expect("Should configure and write the audit");
+        assert_eq!(
+            dir.path()
+                .join(config.output_dir())
+                .join(CMAKE_AUDIT_FILE_NAME),
+            audit_path
+        );
+
+        let audit: toml::Value = fs::read_to_string(&audit_path)
+            .unwrap()
+            .parse()
+            .expect("The audit should be valid toml");
+        assert_eq!(Some("Ninja"), audit["generator"].as_str());
+        let defines = audit["define"].as_array().unwrap();
+        assert_eq!(recorded.defines.len(), defines.len());
+        let source_of = |name: &str| {
+            defines
+                .iter()
+                .find(|d| d["name"].as_str() == Some(name))
+                .and_then(|d| d["source"].as_str())
+                .map(str::to_string)
+        };
+        assert_eq!(
+            Some("armv7-sel4-fel4.debug".to_string()),
+            source_of("KernelPrinting:BOOL")
+        );
+        assert_eq!(
+            Some("armv7-sel4-fel4.sabre".to_string()),
+            source_of("KernelARMPlatform")
+        );
+        assert_eq!(
+            Some("toolchain.armv7-sel4-fel4.variables".to_string()),
+            source_of("GCC_SYSROOT")
+        );
+        assert_eq!(
+            Some("fel4-config".to_string()),
+            source_of("CMAKE_TOOLCHAIN_FILE")
+        );
+    }
 }
@@ -0,0 +1,566 @@
+/// Property values in the `[$TARGET]` table that depend on the resolved
+/// build profile or platform, written as inline tables such as
+/// `KernelMaxNumNodes = { default = 1, release = 4 }`, and the `when`
+/// conditions they may be guarded by
+use std::collections::BTreeMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+use toml;
+
+use types::*;
+
+/// A property value chosen when a configuration is resolved.
+///
+/// A value is chosen by build profile name, by platform name, or by a `when`
+/// condition, falling back to the `default` if none applies. A property with
+/// no applicable value and no default is left unset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConditionalValue {
+    /// Values keyed by the name of either build profiles or platforms, never
+    /// a mix of the two
+    pub by_name: BTreeMap<String, FlatTomlValue>,
+    /// A `when` condition with the `value` chosen while it holds
+    pub when: Option<(Condition, FlatTomlValue)>,
+    pub default: Option<FlatTomlValue>,
+}
+
+impl ConditionalValue {
+    /// The value chosen for a selection, if any
+    pub fn value_for(
+        &self,
+        target: SupportedTarget,
+        platform: SupportedPlatform,
+        build_profile: BuildProfile,
+    ) -> Option<&FlatTomlValue> {
+        let when = self
+            .when
+            .as_ref()
+            .filter(|(condition, _)| condition.holds(target, platform, build_profile))
+            .map(|(_, value)| value);
+        when.or_else(|| self.by_name.get(build_profile.full_name()))
+            .or_else(|| self.by_name.get(platform.full_name()))
+            .or(self.default.as_ref())
+    }
+
+    /// The value's keys as a property table, in the order
+    /// `parse_conditional_value` documents them
+    pub(crate) fn to_properties(&self) -> PropertySet {
+        let default = self
+            .default
+            .iter()
+            .map(|v| ("default".to_string(), v.clone()));
+        let by_name = self.by_name.iter().map(|(k, v)| (k.clone(), v.clone()));
+        let when = self.when.iter().flat_map(|(condition, value)| {
+            vec![
+                (
+                    "when".to_string(),
+                    FlatTomlValue::String(condition.to_string()),
+                ),
+                ("value".to_string(), value.clone()),
+            ]
+        });
+        PropertySet::from_properties(
+            default
+                .chain(by_name)
+                .chain(when)
+                .map(|(name, value)| FlatTomlProperty::new(name, value)),
+        ).expect("Conditional value keys are unique")
+    }
+}
+
+/// Read a `[$TARGET]` table value that is itself a table as a conditional
+/// value, made of an optional `default` and then either build profile or
+/// platform names, or a `when` condition and its `value`.
+///
+/// Returns None where the table has keys that are none of these, so it is
+/// an unexpected structure rather than a malformed conditional value.
+pub(crate) fn parse_conditional_value(
+    table: &BTreeMap<String, toml::Value>,
+) -> Option<Result<ConditionalValue, String>> {
+    let profile_names = BuildProfile::build_profile_names();
+    let platform_names = SupportedPlatform::platform_names();
+    let is_key = |k: &String| {
+        ["default", "when", "value"].contains(&k.as_str())
+            || profile_names.contains(k)
+            || platform_names.contains(k)
+    };
+    if !table.keys().all(is_key) {
+        return None;
+    }
+    let flat = |k: &str| {
+        table.get(k).map_or(Ok(None), |v| {
+            FlatTomlValue::from_toml(v)
+                .map(Some)
+                .ok_or_else(|| format!("{} should be a string, integer, float, or boolean", k))
+        })
+    };
+    let parse = || {
+        let mut by_name = BTreeMap::new();
+        for k in table
+            .keys()
+            .filter(|k| !["default", "when", "value"].contains(&k.as_str()))
+        {
+            by_name.insert(k.clone(), flat(k)?.expect("The key is in the table"));
+        }
+        if by_name.keys().any(|k| profile_names.contains(k))
+            && by_name.keys().any(|k| platform_names.contains(k))
+        {
+            return Err("values may be chosen by build profile or by platform, not both".into());
+        }
+        let when = match (table.get("when"), flat("value")?) {
+            (None, None) => None,
+            (Some(_), _) if !by_name.is_empty() => {
+                return Err("when may not be combined with build profile or platform names".into())
+            }
+            (Some(toml::Value::String(condition)), Some(value)) => {
+                Some((condition.parse()?, value))
+            }
+            (Some(toml::Value::String(_)), None) => {
+                return Err("when is given without a value".into())
+            }
+            (Some(_), _) => return Err("when should be a string".into()),
+            (None, Some(_)) => return Err("value is given without a when condition".into()),
+        };
+        let default = flat("default")?;
+        if by_name.is_empty() && when.is_none() && default.is_none() {
+            return Err("no value is given".into());
+        }
+        Ok(ConditionalValue {
+            by_name,
+            when,
+            default,
+        })
+    };
+    Some(parse())
+}
+
+/// The part of a configuration's selection a `Condition` compares
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ConditionDimension {
+    Target,
+    Platform,
+    Profile,
+}
+const CONDITION_DIMENSION_TARGET: &str = "target";
+const CONDITION_DIMENSION_PLATFORM: &str = "platform";
+const CONDITION_DIMENSION_PROFILE: &str = "profile";
+impl ConditionDimension {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            ConditionDimension::Target => CONDITION_DIMENSION_TARGET,
+            ConditionDimension::Platform => CONDITION_DIMENSION_PLATFORM,
+            ConditionDimension::Profile => CONDITION_DIMENSION_PROFILE,
+        }
+    }
+
+    pub fn dimensions() -> Vec<ConditionDimension> {
+        vec![
+            ConditionDimension::Target,
+            ConditionDimension::Platform,
+            ConditionDimension::Profile,
+        ]
+    }
+
+    /// The names a condition may compare the dimension with
+    pub fn value_names(&self) -> Vec<String> {
+        match *self {
+            ConditionDimension::Target => SupportedTarget::target_names(),
+            ConditionDimension::Platform => SupportedPlatform::platform_names(),
+            ConditionDimension::Profile => BuildProfile::build_profile_names(),
+        }
+    }
+}
+
+impl Display for ConditionDimension {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for ConditionDimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            CONDITION_DIMENSION_TARGET => Ok(ConditionDimension::Target),
+            CONDITION_DIMENSION_PLATFORM => Ok(ConditionDimension::Platform),
+            CONDITION_DIMENSION_PROFILE => Ok(ConditionDimension::Profile),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// A `when` condition, such as `platform == 'sabre' && profile != 'debug'`.
+///
+/// Comparisons of `target`, `platform`, or `profile` with a quoted name are
+/// combined with `!`, `&&`, and `||`, which bind in that order, and grouped
+/// with parentheses. Names are checked when the condition is parsed, so a
+/// misspelt platform is an error rather than a condition that never holds.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Condition {
+    Equals(ConditionDimension, String),
+    NotEquals(ConditionDimension, String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    pub fn holds(
+        &self,
+        target: SupportedTarget,
+        platform: SupportedPlatform,
+        build_profile: BuildProfile,
+    ) -> bool {
+        let selected = |dimension: &ConditionDimension| match *dimension {
+            ConditionDimension::Target => target.full_name(),
+            ConditionDimension::Platform => platform.full_name(),
+            ConditionDimension::Profile => build_profile.full_name(),
+        };
+        match *self {
+            Condition::Equals(ref d, ref name) => selected(d) == name,
+            Condition::NotEquals(ref d, ref name) => selected(d) != name,
+            Condition::Not(ref c) => !c.holds(target, platform, build_profile),
+            Condition::And(ref a, ref b) => {
+                a.holds(target, platform, build_profile) && b.holds(target, platform, build_profile)
+            }
+            Condition::Or(ref a, ref b) => {
+                a.holds(target, platform, build_profile) || b.holds(target, platform, build_profile)
+            }
+        }
+    }
+}
+
+/// Conditions display as text that parses back to the same condition
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let nested = |c: &Condition| match *c {
+            Condition::And(_, _) | Condition::Or(_, _) => format!("({})", c),
+            _ => c.to_string(),
+        };
+        match *self {
+            Condition::Equals(ref d, ref name) => write!(f, "{} == '{}'", d, name),
+            Condition::NotEquals(ref d, ref name) => write!(f, "{} != '{}'", d, name),
+            Condition::Not(ref c) => write!(f, "!{}", nested(c)),
+            Condition::And(ref a, ref b) => write!(f, "{} && {}", nested(a), nested(b)),
+            Condition::Or(ref a, ref b) => write!(f, "{} || {}", nested(a), nested(b)),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        let mut parser = ConditionParser {
+            tokens: tokenize(s)?,
+            next: 0,
+        };
+        let condition = parser.or()?;
+        match parser.tokens.get(parser.next) {
+            None => Ok(condition),
+            Some(t) => Err(format!("unexpected {} in the condition {:?}", t, s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Quoted(String),
+    Equals,
+    NotEquals,
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            Token::Name(ref name) => f.write_str(name),
+            Token::Quoted(ref name) => write!(f, "'{}'", name),
+            Token::Equals => f.write_str("=="),
+            Token::NotEquals => f.write_str("!="),
+            Token::Not => f.write_str("!"),
+            Token::And => f.write_str("&&"),
+            Token::Or => f.write_str("||"),
+            Token::Open => f.write_str("("),
+            Token::Close => f.write_str(")"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '!' if chars.peek() != Some(&'=') => Token::Not,
+            '=' | '!' | '&' | '|' => {
+                let token = match (c, chars.next()) {
+                    ('=', Some('=')) => Token::Equals,
+                    ('!', Some('=')) => Token::NotEquals,
+                    ('&', Some('&')) => Token::And,
+                    ('|', Some('|')) => Token::Or,
+                    _ => return Err(format!("{} is not an operator in {:?}", c, text)),
+                };
+                token
+            }
+            '\'' | '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(q) => quoted.push(q),
+                        None => return Err(format!("a quote is never closed in {:?}", text)),
+                    }
+                }
+                Token::Quoted(quoted)
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut name = c.to_string();
+                while let Some(&n) = chars
+                    .peek()
+                    .filter(|n| n.is_ascii_alphanumeric() || **n == '_')
+                {
+                    name.push(n);
+                    chars.next();
+                }
+                Token::Name(name)
+            }
+            _ => return Err(format!("unexpected {} in the condition {:?}", c, text)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct ConditionParser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl ConditionParser {
+    fn take_if(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.next) == Some(token);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.take_if(&Token::Or) {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.unary()?;
+        while self.take_if(&Token::And) {
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition, String> {
+        if self.take_if(&Token::Not) {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        if self.take_if(&Token::Open) {
+            let condition = self.or()?;
+            if !self.take_if(&Token::Close) {
+                return Err("a parenthesis is never closed".into());
+            }
+            return Ok(condition);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Condition, String> {
+        let mut take = || {
+            let token = self.tokens.get(self.next).cloned();
+            self.next += 1;
+            token
+        };
+        let expected = "a comparison such as platform == 'sabre'";
+        let dimension: ConditionDimension = match take() {
+            Some(Token::Name(name)) => name.parse().map_err(|_| {
+                format!(
+                    "{} is not one of {:?}",
+                    name,
+                    ConditionDimension::dimensions()
+                        .iter()
+                        .map(ConditionDimension::full_name)
+                        .collect::<Vec<_>>()
+                )
+            })?,
+            Some(t) => return Err(format!("expected {}, found {}", expected, t)),
+            None => return Err(format!("expected {}", expected)),
+        };
+        let equal = match take() {
+            Some(Token::Equals) => true,
+            Some(Token::NotEquals) => false,
+            _ => return Err(format!("expected == or != after {}", dimension)),
+        };
+        let name = match take() {
+            Some(Token::Quoted(name)) => name,
+            _ => return Err(format!("expected a quoted name after {}", dimension)),
+        };
+        if !dimension.value_names().contains(&name) {
+            return Err(format!(
+                "{} is not a {}, which is one of {:?}",
+                name,
+                dimension,
+                dimension.value_names()
+            ));
+        }
+        Ok(if equal {
+            Condition::Equals(dimension, name)
+        } else {
+            Condition::NotEquals(dimension, name)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn conditions_parse_and_display() {
+        let condition: Condition =
+            "platform == 'sabre' && !(profile == \"debug\" || target != 'armv7-sel4-fel4')"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            Condition::And(
+                Box::new(Condition::Equals(
+                    ConditionDimension::Platform,
+                    "sabre".into()
+                )),
+                Box::new(Condition::Not(Box::new(Condition::Or(
+                    Box::new(Condition::Equals(
+                        ConditionDimension::Profile,
+                        "debug".into()
+                    )),
+                    Box::new(Condition::NotEquals(
+                        ConditionDimension::Target,
+                        "armv7-sel4-fel4".into()
+                    )),
+                ))))
+            ),
+            condition
+        );
+        assert_eq!(Ok(condition.clone()), condition.to_string().parse());
+        assert!(condition.holds(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Release
+        ));
+        assert!(!condition.holds(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Debug
+        ));
+
+        for text in &[
+            "platform == 'sabr'",
+            "arch == 'x86'",
+            "platform = 'sabre'",
+            "platform == sabre",
+            "(platform == 'sabre'",
+            "platform == 'sabre' profile == 'debug'",
+            "platform == 'sabre",
+            "",
+        ] {
+            assert!(
+                text.parse::<Condition>().is_err(),
+                "{} should not parse",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn conditional_values_are_chosen_during_resolution() {
+        let text = get_exemplar_default_toml()
+            .replacen(
+                "KernelMaxNumNodes = 1",
+                "KernelMaxNumNodes = { default = 1, release = 4 }",
+                1,
+            )
+            .replacen(
+                "KernelRetypeFanOutLimit = 256",
+                concat!(
+                    "KernelRetypeFanOutLimit = { when = \"profile == 'release' && ",
+                    "platform == 'pc99'\", value = 128, default = 256 }"
+                ),
+                1,
+            );
+        let full = parse_full_manifest(&text).expect("Conditional values should parse");
+        assert_eq!(
+            Ok(full.clone()),
+            parse_full_manifest(full_manifest_to_toml_string(&full))
+        );
+        let debug = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        let release = resolve_fel4_config(&full, &BuildProfile::Release).unwrap();
+        let value = |config: &Fel4Config, name: &str| config.properties.get(name).cloned();
+        assert_eq!(
+            Some(FlatTomlValue::Integer(1)),
+            value(&debug, "KernelMaxNumNodes")
+        );
+        assert_eq!(
+            Some(FlatTomlValue::Integer(4)),
+            value(&release, "KernelMaxNumNodes")
+        );
+        assert_eq!(
+            Some(FlatTomlValue::Integer(256)),
+            value(&debug, "KernelRetypeFanOutLimit")
+        );
+        assert_eq!(
+            Some(FlatTomlValue::Integer(128)),
+            value(&release, "KernelRetypeFanOutLimit")
+        );
+
+        let invalid = |value: &str| {
+            parse_full_manifest(get_exemplar_default_toml().replacen(
+                "KernelMaxNumNodes = 1",
+                &format!("KernelMaxNumNodes = {}", value),
+                1,
+            ))
+        };
+        for value in &[
+            "{ debug = 1, sabre = 2 }",
+            "{ when = \"platform == 'sabre2'\", value = 2 }",
+            "{ when = \"platform == 'pc99'\" }",
+            "{ value = 2 }",
+            "{ release = [4] }",
+        ] {
+            match invalid(value) {
+                Err(ConfigError::InvalidConditionalValue(ref table, ref name, _)) => {
+                    assert_eq!("x86_64-sel4-fel4", table);
+                    assert_eq!("KernelMaxNumNodes", name);
+                }
+                other => panic!("Expected {} to be rejected, got {:?}", value, other),
+            }
+        }
+        assert_eq!(
+            Err(ConfigError::UnexpectedStructure(
+                "x86_64-sel4-fel4.KernelMaxNumNodes".into()
+            )),
+            invalid("{ nodes = 4 }")
+        );
+    }
+}
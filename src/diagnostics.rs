@@ -0,0 +1,630 @@
+/// Validation findings for fel4 manifests, located within the manifest text
+/// and serializable for code-scanning tools
+use std::collections::BTreeMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use toml;
+
+use super::{ConfigError, ConfigWarning, ResolutionOptions};
+use json::Json;
+use manifest::{normalize_manifest_text, toml_to_full_manifest_with_warnings};
+use matrix::validate_build_matrix;
+
+/// How serious a `Diagnostic` is
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[non_exhaustive]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+/// A location within manifest text. Lines and columns count from 1, and
+/// `length` is the number of characters highlighted from the column.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SourceSpan {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+/// A single validation finding about a manifest
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A stable identifier for the kind of finding, e.g. `non-whitelist-property`
+    pub code: &'static str,
+    pub message: String,
+    /// Where in the manifest the finding applies, when that could be found
+    pub span: Option<SourceSpan>,
+}
+
+impl Diagnostic {
+    /// A diagnostic for a `ConfigError`, located within the manifest text it
+    /// arose from where possible
+    pub fn from_config_error(error: &ConfigError, manifest_text: &str) -> Self {
+        let (table, key) = error_location(error);
+        let span = match (table.as_ref(), key.as_ref()) {
+            (None, None) => None,
+            (t, k) => {
+                locate_in_manifest(manifest_text, t.map(String::as_str), k.map(String::as_str))
+            }
+        };
+        Diagnostic {
+            severity: Severity::Error,
+            code: error_code(error),
+            message: error.to_string(),
+            span,
+        }
+    }
+
+    /// A warning diagnostic for a `ConfigWarning`, located in the manifest
+    /// text where possible
+    pub fn from_config_warning(warning: &ConfigWarning, manifest_text: &str) -> Self {
+        let (code, table) = match *warning {
+            ConfigWarning::MissingProfileTable(t, _) => ("missing-profile-table", t.full_name()),
+            ConfigWarning::MissingPlatformTable(t, _) => ("missing-platform-table", t.full_name()),
+            ConfigWarning::LegacyManifestLayout(_) => ("legacy-manifest-layout", "fel4"),
+            ConfigWarning::CargoProfileMismatch { .. } => ("cargo-profile-mismatch", "fel4"),
+        };
+        Diagnostic {
+            severity: Severity::Warning,
+            code,
+            message: warning.to_string(),
+            span: locate_in_manifest(manifest_text, Some(table), None),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self.span {
+            Some(span) => write!(
+                f,
+                "{}[{}] at {}:{}: {}",
+                self.severity, self.code, span.line, span.column, self.message
+            ),
+            None => write!(f, "{}[{}]: {}", self.severity, self.code, self.message),
+        }
+    }
+}
+
+/// Validate manifest text, reporting every problem found rather than just
+/// the first.
+///
+/// Covers toml syntax, manifest structure, and the resolution of every
+/// target, platform, and build profile combination the manifest describes.
+/// Identical findings from several combinations are reported once.
+pub fn validate_manifest_source(manifest_text: &str) -> Vec<Diagnostic> {
+    let manifest_text: &str = &normalize_manifest_text(manifest_text);
+    let raw = match manifest_text.parse::<toml::Value>() {
+        Ok(raw) => raw,
+        Err(e) => {
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                code: error_code(&ConfigError::TomlParseFailure),
+                message: format!("{}: {}", ConfigError::TomlParseFailure, e),
+                span: e.line_col().map(|(line, column)| SourceSpan {
+                    line: line + 1,
+                    column: column + 1,
+                    length: 1,
+                }),
+            }]
+        }
+    };
+    let (full, warnings) = match toml_to_full_manifest_with_warnings(&raw) {
+        Ok(parsed) => parsed,
+        Err(e) => return vec![Diagnostic::from_config_error(&e, manifest_text)],
+    };
+    let mut diagnostics: Vec<Diagnostic> = warnings
+        .iter()
+        .map(|w| Diagnostic::from_config_warning(w, manifest_text))
+        .collect();
+    let report = validate_build_matrix(&full, &ResolutionOptions::default());
+    for (_entry, error) in report.errors() {
+        let diagnostic = Diagnostic::from_config_error(error, manifest_text);
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+/// Find the line of a table header, or of a key within a table, in
+/// manifest text.
+///
+/// Table paths are dotted as written in a header, e.g.
+/// `x86_64-sel4-fel4.debug`. With no table, the key is searched for in every
+/// table and its first occurrence is returned.
+pub fn locate_in_manifest(
+    text: &str,
+    table: Option<&str>,
+    key: Option<&str>,
+) -> Option<SourceSpan> {
+    let mut current_table = String::new();
+    for (index, raw_line) in text.lines().enumerate() {
+        let indent = raw_line.len() - raw_line.trim_start().len();
+        let line = raw_line.trim();
+        if line.starts_with('[') {
+            let header = line.trim_start_matches('[').split(']').next().unwrap_or("");
+            current_table = normalize_table_path(header);
+            if key.is_none() && table.map(normalize_table_path).as_ref() == Some(&current_table) {
+                return Some(span_at(index, raw_line, indent, line.len()));
+            }
+            continue;
+        }
+        let key = match key {
+            Some(k) => k,
+            None => continue,
+        };
+        if let Some(t) = table {
+            if normalize_table_path(t) != current_table {
+                continue;
+            }
+        }
+        let written_key = match line.split('=').next() {
+            Some(k) if line.contains('=') => k.trim(),
+            _ => continue,
+        };
+        if written_key.trim_matches('"') == key {
+            return Some(span_at(
+                index,
+                raw_line,
+                indent,
+                written_key.chars().count(),
+            ));
+        }
+    }
+    None
+}
+
+fn span_at(index: usize, raw_line: &str, byte_offset: usize, length: usize) -> SourceSpan {
+    SourceSpan {
+        line: index + 1,
+        column: raw_line[..byte_offset].chars().count() + 1,
+        length,
+    }
+}
+
+fn normalize_table_path(path: &str) -> String {
+    path.split('.')
+        .map(|part| part.trim().trim_matches('"'))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The manifest table and key an error concerns, where known
+fn error_location(error: &ConfigError) -> (Option<String>, Option<String>) {
+    let fel4 = Some("fel4".to_string());
+    match *error {
+        ConfigError::MissingTargetTable(_) => (fel4, Some("target".into())),
+        ConfigError::MissingProfileTable(t, _) | ConfigError::MissingPlatformTable(t, _) => {
+            (Some(t.full_name().to_string()), None)
+        }
+        ConfigError::UnexpectedStructure(ref path) => match path.rfind('.') {
+            Some(i) => (Some(path[..i].to_string()), Some(path[i + 1..].to_string())),
+            None => (Some(path.clone()), None),
+        },
+        ConfigError::MissingRequiredProperty(ref table, _) => (Some(table.clone()), None),
+        ConfigError::NonStringProperty(name) => (None, Some(name.to_string())),
+        ConfigError::InvalidValueOption(name, _, _) => (fel4, Some(name.to_string())),
+        ConfigError::UnsupportedTarget(_, _) => (fel4, Some("target".into())),
+        ConfigError::UnsupportedPlatform(_, _) => (fel4, Some("platform".into())),
+        ConfigError::DuplicateProperty(ref name, _, ref later) => {
+            (Some(later.table.clone()), Some(name.clone()))
+        }
+        ConfigError::NonWhitelistProperty(ref name)
+        | ConfigError::LockedProperty(ref name, _)
+        | ConfigError::PolicyRequiredProperty(ref name)
+        | ConfigError::PolicyForbiddenProperty(ref name)
+        | ConfigError::PolicyDisallowedValue(ref name, _, _)
+        | ConfigError::PropertyOutsideKernelVersion(ref name, _, _) => (None, Some(name.clone())),
+        ConfigError::FloatForIntegerProperty(ref name, ref definition)
+        | ConfigError::DatetimeProperty(ref name, ref definition)
+        | ConfigError::SimulationOnlyProperty(ref name, ref definition) => {
+            (Some(definition.table.clone()), Some(name.clone()))
+        }
+        ConfigError::TargetPlatformMismatch(_, _) => (fel4, Some("platform".into())),
+        ConfigError::InvalidKernelSource(_) => (Some("fel4.kernel-source".into()), None),
+        ConfigError::InvalidKernelCommit(_, _) => (fel4, Some("kernel-commit".into())),
+        ConfigError::InvalidImageNaming(name, _) => (fel4, Some(name.to_string())),
+        ConfigError::InvalidConditionalValue(ref table, ref name, _)
+        | ConfigError::InvalidDerivedProperty(ref table, ref name, _)
+        | ConfigError::UndefinedVariable(_, ref table, ref name) => {
+            (Some(table.clone()), Some(name.clone()))
+        }
+        ConfigError::InvalidRootserverTable(ref table, _)
+        | ConfigError::InvalidVariant(ref table, _) => (Some(table.clone()), None),
+        ConfigError::InvalidManifestVersion(_)
+        | ConfigError::OutdatedManifestVersion(_)
+        | ConfigError::UnsupportedManifestVersion(_) => (fel4, Some("fel4-version".into())),
+        _ => (None, None),
+    }
+}
+
+/// A stable identifier for each kind of `ConfigError`
+pub fn error_code(error: &ConfigError) -> &'static str {
+    match *error {
+        ConfigError::FileReadFailure => "file-read-failure",
+        ConfigError::TomlParseFailure => "toml-parse-failure",
+        ConfigError::InvalidEncoding(_) => "invalid-encoding",
+        ConfigError::MissingTable(_) => "missing-table",
+        ConfigError::MissingTargetTable(_) => "missing-target-table",
+        ConfigError::MissingProfileTable(_, _) => "missing-profile-table",
+        ConfigError::MissingPlatformTable(_, _) => "missing-platform-table",
+        ConfigError::UnexpectedStructure(_) => "unexpected-structure",
+        ConfigError::MissingRequiredProperty(_, _) => "missing-required-property",
+        ConfigError::NonStringProperty(_) => "non-string-property",
+        ConfigError::InvalidValueOption(_, _, _) => "invalid-value-option",
+        ConfigError::UnsupportedTarget(_, _) => "unsupported-target",
+        ConfigError::UnsupportedPlatform(_, _) => "unsupported-platform",
+        ConfigError::DuplicateProperty(_, _, _) => "duplicate-property",
+        ConfigError::NonWhitelistProperty(_) => "non-whitelist-property",
+        ConfigError::FloatForIntegerProperty(_, _) => "float-for-integer-property",
+        ConfigError::DatetimeProperty(_, _) => "datetime-property",
+        ConfigError::SimulationOnlyProperty(_, _) => "simulation-only-property",
+        ConfigError::TargetPlatformMismatch(_, _) => "target-platform-mismatch",
+        ConfigError::RuleViolation(_) => "rule-violation",
+        ConfigError::InvalidBundle(_, _) => "invalid-bundle",
+        ConfigError::InvalidKernelSource(_) => "invalid-kernel-source",
+        ConfigError::InvalidKernelCommit(_, _) => "invalid-kernel-commit",
+        ConfigError::InvalidImageNaming(_, _) => "invalid-image-naming",
+        ConfigError::InvalidRootserverTable(_, _) => "invalid-rootserver-table",
+        ConfigError::InvalidVariant(_, _) => "invalid-variant",
+        ConfigError::InvalidConditionalValue(_, _, _) => "invalid-conditional-value",
+        ConfigError::InvalidDerivedProperty(_, _, _) => "invalid-derived-property",
+        ConfigError::UndefinedVariable(_, _, _) => "undefined-variable",
+        ConfigError::UnknownVariant(_) => "unknown-variant",
+        ConfigError::InvalidKconfigLine(_, _) => "invalid-kconfig-line",
+        ConfigError::InvalidManifestVersion(_) => "invalid-manifest-version",
+        ConfigError::OutdatedManifestVersion(_) => "outdated-manifest-version",
+        ConfigError::UnsupportedManifestVersion(_) => "unsupported-manifest-version",
+        ConfigError::LockedProperty(_, _) => "locked-property",
+        ConfigError::InvalidPolicy(_) => "invalid-policy",
+        ConfigError::PolicyRequiredProperty(_) => "policy-required-property",
+        ConfigError::PolicyForbiddenProperty(_) => "policy-forbidden-property",
+        ConfigError::PolicyDisallowedValue(_, _, _) => "policy-disallowed-value",
+        ConfigError::MissingGeneratedConfig(_) => "missing-generated-config",
+        ConfigError::PropertyOutsideKernelVersion(_, _, _) => "property-outside-kernel-version",
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_BOLD: &str = "\x1b[1m";
+const ANSI_BLUE: &str = "\x1b[1;34m";
+
+impl Severity {
+    fn ansi_color(&self) -> &'static str {
+        match *self {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+            Severity::Note => "\x1b[1;36m",
+        }
+    }
+}
+
+/// Render a diagnostic for a terminal, with the offending manifest line
+/// quoted and the located span underlined.
+///
+/// `manifest_name` is how the manifest is referred to, e.g. `fel4.toml`.
+/// With `color`, ANSI escapes highlight the severity, line numbers, and
+/// underline; callers decide whether their terminal supports them.
+pub fn render_diagnostic(
+    diagnostic: &Diagnostic,
+    manifest_text: &str,
+    manifest_name: &str,
+    color: bool,
+) -> String {
+    let paint = |style: &str, text: &str| {
+        if color {
+            format!("{}{}{}", style, text, ANSI_RESET)
+        } else {
+            text.to_string()
+        }
+    };
+    let severity_style = diagnostic.severity.ansi_color();
+    let mut out = format!(
+        "{}{}\n",
+        paint(
+            severity_style,
+            &format!("{}[{}]", diagnostic.severity, diagnostic.code)
+        ),
+        paint(ANSI_BOLD, &format!(": {}", diagnostic.message))
+    );
+    let span = match diagnostic.span {
+        Some(span) => span,
+        None => {
+            out.push_str(&format!(
+                "  {} {}\n",
+                paint(ANSI_BLUE, "-->"),
+                manifest_name
+            ));
+            return out;
+        }
+    };
+    let gutter_width = span.line.to_string().len();
+    let gutter = " ".repeat(gutter_width);
+    out.push_str(&format!(
+        "{}{} {}:{}:{}\n",
+        gutter,
+        paint(ANSI_BLUE, "-->"),
+        manifest_name,
+        span.line,
+        span.column
+    ));
+    let source_line = manifest_text.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+    out.push_str(&format!("{} {}\n", gutter, paint(ANSI_BLUE, "|")));
+    out.push_str(&format!(
+        "{} {}\n",
+        paint(ANSI_BLUE, &format!("{} |", span.line)),
+        source_line
+    ));
+    out.push_str(&format!(
+        "{} {} {}{}\n",
+        gutter,
+        paint(ANSI_BLUE, "|"),
+        " ".repeat(span.column.saturating_sub(1)),
+        paint(severity_style, &"^".repeat(span.length.max(1)))
+    ));
+    out
+}
+
+/// Render every diagnostic as `render_diagnostic` does, separated by blank
+/// lines and followed by a count of errors and warnings
+pub fn render_diagnostics(
+    diagnostics: &[Diagnostic],
+    manifest_text: &str,
+    manifest_name: &str,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    for diagnostic in diagnostics {
+        out.push_str(&render_diagnostic(
+            diagnostic,
+            manifest_text,
+            manifest_name,
+            color,
+        ));
+        out.push('\n');
+    }
+    let count = |severity: Severity| {
+        diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    };
+    out.push_str(&format!(
+        "{}: {} error(s), {} warning(s)\n",
+        manifest_name,
+        count(Severity::Error),
+        count(Severity::Warning)
+    ));
+    out
+}
+
+/// Serialize diagnostics as a SARIF 2.1.0 log, for display by code-scanning
+/// tools as annotations on the manifest.
+///
+/// `manifest_uri` is the manifest's path as the tool should show it,
+/// typically relative to the repository root, e.g. `fel4.toml`.
+pub fn diagnostics_to_sarif(diagnostics: &[Diagnostic], manifest_uri: &str) -> String {
+    let mut rules: BTreeMap<&str, &str> = BTreeMap::new();
+    for d in diagnostics {
+        rules.entry(d.code).or_insert(&d.message);
+    }
+    let rules = rules
+        .into_iter()
+        .map(|(id, example)| {
+            Json::object(vec![
+                ("id", Json::string(id)),
+                (
+                    "shortDescription",
+                    Json::object(vec![("text", Json::string(example))]),
+                ),
+            ])
+        })
+        .collect();
+    let results = diagnostics
+        .iter()
+        .map(|d| {
+            let mut physical = vec![(
+                "artifactLocation",
+                Json::object(vec![("uri", Json::string(manifest_uri))]),
+            )];
+            if let Some(span) = d.span {
+                physical.push((
+                    "region",
+                    Json::object(vec![
+                        ("startLine", Json::Integer(span.line as i64)),
+                        ("startColumn", Json::Integer(span.column as i64)),
+                        (
+                            "endColumn",
+                            Json::Integer((span.column + span.length) as i64),
+                        ),
+                    ]),
+                ));
+            }
+            Json::object(vec![
+                ("ruleId", Json::string(d.code)),
+                ("level", Json::string(d.severity.full_name())),
+                (
+                    "message",
+                    Json::object(vec![("text", Json::string(d.message.as_str()))]),
+                ),
+                (
+                    "locations",
+                    Json::Array(vec![Json::object(vec![(
+                        "physicalLocation",
+                        Json::object(physical),
+                    )])]),
+                ),
+            ])
+        })
+        .collect();
+    let log = Json::object(vec![
+        (
+            "$schema",
+            Json::string("https://json.schemastore.org/sarif-2.1.0.json"),
+        ),
+        ("version", Json::string("2.1.0")),
+        (
+            "runs",
+            Json::Array(vec![Json::object(vec![
+                (
+                    "tool",
+                    Json::object(vec![(
+                        "driver",
+                        Json::object(vec![
+                            ("name", Json::string("fel4-config")),
+                            ("version", Json::string(env!("CARGO_PKG_VERSION"))),
+                            (
+                                "informationUri",
+                                Json::string("https://github.com/PolySync/fel4-config"),
+                            ),
+                            ("rules", Json::Array(rules)),
+                        ]),
+                    )]),
+                ),
+                ("results", Json::Array(results)),
+            ])]),
+        ),
+    ]);
+    format!("{}\n", log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn keys_and_tables_are_located() {
+        let text = "[fel4]\ntarget = \"x86_64-sel4-fel4\"\n\n[x86_64-sel4-fel4]\n  KernelPrinting = true\n\n[x86_64-sel4-fel4.debug]\nKernelPrinting = true\n";
+        assert_eq!(
+            Some(SourceSpan {
+                line: 5,
+                column: 3,
+                length: 14,
+            }),
+            locate_in_manifest(text, None, Some("KernelPrinting"))
+        );
+        assert_eq!(
+            Some(SourceSpan {
+                line: 8,
+                column: 1,
+                length: 14,
+            }),
+            locate_in_manifest(text, Some("x86_64-sel4-fel4.debug"), Some("KernelPrinting"))
+        );
+        assert_eq!(
+            Some(SourceSpan {
+                line: 7,
+                column: 1,
+                length: 24,
+            }),
+            locate_in_manifest(text, Some("x86_64-sel4-fel4.debug"), None)
+        );
+        assert_eq!(
+            None,
+            locate_in_manifest(text, Some("fel4"), Some("platform"))
+        );
+    }
+
+    #[test]
+    fn validation_reports_located_findings_once() {
+        let text = test_helpers::ManifestFixture::new()
+            .with_property("armv7-sel4-fel4", "KernelBogusOption", true)
+            .to_toml_string();
+        let diagnostics = validate_manifest_source(&text);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("non-whitelist-property", diagnostics[0].code);
+        let span = diagnostics[0]
+            .span
+            .expect("Should locate the bogus property");
+        assert!(text
+            .lines()
+            .nth(span.line.saturating_sub(1))
+            .unwrap()
+            .starts_with("KernelBogusOption"));
+
+        let syntax = validate_manifest_source("[fel4]\ntarget = \n");
+        assert_eq!("toml-parse-failure", syntax[0].code);
+        assert_eq!(Some(2), syntax[0].span.map(|s| s.line));
+        assert!(validate_manifest_source(get_exemplar_default_toml()).is_empty());
+    }
+
+    #[test]
+    fn sarif_log_carries_rules_results_and_regions() {
+        let diagnostics = vec![Diagnostic {
+            severity: Severity::Error,
+            code: "non-whitelist-property",
+            message: "KernelBogusOption is not allowed".into(),
+            span: Some(SourceSpan {
+                line: 12,
+                column: 1,
+                length: 17,
+            }),
+        }];
+        let sarif = diagnostics_to_sarif(&diagnostics, "fel4.toml");
+        assert!(sarif.contains("\"version\": \"2.1.0\""));
+        assert!(sarif.contains("\"ruleId\": \"non-whitelist-property\""));
+        assert!(sarif.contains("\"uri\": \"fel4.toml\""));
+        assert!(sarif.contains("\"startLine\": 12"));
+        assert!(sarif.contains("\"endColumn\": 18"));
+    }
+
+    #[test]
+    fn rendered_diagnostics_quote_and_underline_the_span() {
+        let text = "[fel4]\ntarget = \"x86_64-sel4-fel4\"\nplatform = \"sabre\"\n";
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            code: "target-platform-mismatch",
+            message: "The sabre platform can't be used with x86_64-sel4-fel4".into(),
+            span: locate_in_manifest(text, Some("fel4"), Some("platform")),
+        };
+        assert_eq!(
+            "error[target-platform-mismatch]: The sabre platform can't be used with x86_64-sel4-fel4\n\
+             \x20--> fel4.toml:3:1\n\
+             \x20 |\n\
+             3 | platform = \"sabre\"\n\
+             \x20 | ^^^^^^^^\n",
+            render_diagnostic(&diagnostic, text, "fel4.toml", false)
+        );
+        let colored = render_diagnostic(&diagnostic, text, "fel4.toml", true);
+        assert!(colored.starts_with("\x1b[1;31merror[target-platform-mismatch]\x1b[0m"));
+        assert!(render_diagnostics(&[diagnostic], text, "fel4.toml", false)
+            .ends_with("fel4.toml: 1 error(s), 0 warning(s)\n"));
+    }
+
+    #[test]
+    fn zero_spans_render_without_underflowing() {
+        let diagnostic = Diagnostic {
+            severity: Severity::Warning,
+            code: "legacy-manifest-layout",
+            message: "Built by hand".into(),
+            span: Some(SourceSpan {
+                line: 0,
+                column: 0,
+                length: 0,
+            }),
+        };
+        let rendered = render_diagnostic(&diagnostic, "[fel4]\n", "fel4.toml", false);
+        assert!(rendered.contains("fel4.toml:0:0"));
+        assert!(rendered.ends_with(" | ^\n"));
+    }
+}
@@ -0,0 +1,145 @@
+/// Comparing resolved configurations property by property, such as the debug
+/// and release configurations of one selection
+use std::borrow::Borrow;
+use std::collections::BTreeSet;
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use super::{resolve_fel4_config_for, ConfigError, FullFel4Manifest};
+use types::*;
+
+/// One resolved property whose value differs between two configurations
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertyDifference {
+    pub name: String,
+    /// The value in the first configuration, or None where it is unset
+    pub before: Option<FlatTomlValue>,
+    /// The value in the second configuration, or None where it is unset
+    pub after: Option<FlatTomlValue>,
+}
+
+impl Display for PropertyDifference {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let show = |value: &Option<FlatTomlValue>| match *value {
+            Some(ref v) => v.to_string(),
+            None => "unset".to_string(),
+        };
+        write!(f, "{}: {} -> {}", self.name, show(&self.before), show(&self.after))
+    }
+}
+
+/// Every resolved property that differs between two configurations, in name
+/// order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub differences: Vec<PropertyDifference>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    /// The names of the differing properties, in name order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.differences.iter().map(|d| d.name.as_str())
+    }
+
+    /// The differences in properties outside the `approved` names, for
+    /// checking that two configurations differ only where expected
+    pub fn unapproved(&self, approved: &[&str]) -> Vec<&PropertyDifference> {
+        self.differences
+            .iter()
+            .filter(|d| !approved.contains(&d.name.as_str()))
+            .collect()
+    }
+}
+
+/// The resolved properties that differ between two configurations
+pub fn diff_configs(before: &Fel4Config, after: &Fel4Config) -> ConfigDiff {
+    let names: BTreeSet<&String> = before
+        .properties
+        .keys()
+        .chain(after.properties.keys())
+        .collect();
+    ConfigDiff {
+        differences: names
+            .into_iter()
+            .filter_map(|name| {
+                let (b, a) = (before.properties.get(name), after.properties.get(name));
+                if b == a {
+                    return None;
+                }
+                Some(PropertyDifference {
+                    name: name.clone(),
+                    before: b.cloned(),
+                    after: a.cloned(),
+                })
+            })
+            .collect(),
+    }
+}
+
+/// The resolved properties that differ between two build profiles of the
+/// manifest's selected target and platform, such as every option release
+/// changes relative to debug
+pub fn profile_delta<M: Borrow<FullFel4Manifest>>(
+    full: M,
+    from: BuildProfile,
+    to: BuildProfile,
+) -> Result<ConfigDiff, ConfigError> {
+    let full = full.borrow();
+    let resolve = |profile: &BuildProfile| {
+        resolve_fel4_config_for(full, full.selected_target, full.selected_platform, profile)
+    };
+    Ok(diff_configs(&resolve(&from)?, &resolve(&to)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn profile_deltas_list_exactly_the_differing_properties() {
+        let full = test_helpers::ManifestFixture::new()
+            .with_property("x86_64-sel4-fel4.release", "X-ReleaseOnly", 1)
+            .parse()
+            .unwrap();
+        let delta = profile_delta(&full, BuildProfile::Debug, BuildProfile::Release).unwrap();
+        let debug = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        let release = resolve_fel4_config(&full, &BuildProfile::Release).unwrap();
+        for (name, value) in &debug.properties {
+            let differs = delta.names().any(|n| n == name);
+            assert_eq!(release.properties.get(name) != Some(value), differs, "{}", name);
+        }
+        let names: Vec<&str> = delta.names().collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(sorted, names);
+        assert!(names.contains(&"KernelDebugBuild"));
+        assert_eq!(
+            Some(&PropertyDifference {
+                name: "X-ReleaseOnly".into(),
+                before: None,
+                after: Some(FlatTomlValue::Integer(1)),
+            }),
+            delta.differences.iter().find(|d| d.name == "X-ReleaseOnly")
+        );
+        assert_eq!(
+            "KernelDebugBuild: true -> false",
+            delta
+                .differences
+                .iter()
+                .find(|d| d.name == "KernelDebugBuild")
+                .unwrap()
+                .to_string()
+        );
+
+        assert!(delta.unapproved(&names).is_empty());
+        let unapproved = delta.unapproved(&["KernelDebugBuild"]);
+        assert_eq!(names.len() - 1, unapproved.len());
+        assert!(profile_delta(&full, BuildProfile::Debug, BuildProfile::Debug)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+/// Writing exemplar manifests to disk, for tools that generate new projects
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::exemplars;
+use types::*;
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum ExemplarWriteError {
+    #[fail(
+        display = "There is no exemplar for the {} target with the {} platform",
+        _0, _1
+    )]
+    UnsupportedPair(SupportedTarget, SupportedPlatform),
+    #[fail(
+        display = "{} already exists, and overwriting it was not requested",
+        _0
+    )]
+    AlreadyExists(String),
+    #[fail(display = "Unable to back up the existing manifest to {}", _0)]
+    BackupFailure(String),
+    #[fail(display = "Unable to write the exemplar manifest to {}", _0)]
+    WriteFailure(String),
+}
+
+/// Write the exemplar manifest for a target and platform pair to `path`,
+/// creating any missing parent directories.
+///
+/// An existing file is left alone unless `overwrite` is set, in which case
+/// it is first renamed to a `.bak` file beside it, whose path is returned.
+/// Earlier backups are kept, with later ones numbered `.bak.1`, `.bak.2`,
+/// and so on.
+pub fn write_exemplar_manifest<P: AsRef<Path>>(
+    path: P,
+    target: SupportedTarget,
+    platform: SupportedPlatform,
+    overwrite: bool,
+) -> Result<Option<PathBuf>, ExemplarWriteError> {
+    let (_, _, text) = exemplars()
+        .find(|&(t, p, _)| t == target && p == platform)
+        .ok_or(ExemplarWriteError::UnsupportedPair(target, platform))?;
+    write_new_manifest(path.as_ref(), text, overwrite)
+}
+
+/// Write generated manifest text as `write_exemplar_manifest` does, backing
+/// up any existing file when overwriting
+pub(crate) fn write_new_manifest(
+    path: &Path,
+    text: &str,
+    overwrite: bool,
+) -> Result<Option<PathBuf>, ExemplarWriteError> {
+    let backup = if path.exists() {
+        if !overwrite {
+            return Err(ExemplarWriteError::AlreadyExists(
+                path.display().to_string(),
+            ));
+        }
+        let backup = unused_backup_path(path);
+        fs::rename(path, &backup)
+            .map_err(|_| ExemplarWriteError::BackupFailure(backup.display().to_string()))?;
+        Some(backup)
+    } else {
+        None
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|_| ExemplarWriteError::WriteFailure(path.display().to_string()))?;
+    }
+    File::create(path)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+        .map_err(|_| ExemplarWriteError::WriteFailure(path.display().to_string()))?;
+    Ok(backup)
+}
+
+/// The first of `<name>.bak`, `<name>.bak.1`, `<name>.bak.2`, ... beside the
+/// file that doesn't exist yet, so earlier backups are never overwritten
+fn unused_backup_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default();
+    (0..)
+        .map(|n| {
+            let mut backup_name = file_name.to_os_string();
+            backup_name.push(".bak");
+            if n > 0 {
+                backup_name.push(format!(".{}", n));
+            }
+            path.with_file_name(backup_name)
+        })
+        .find(|backup| !backup.exists())
+        .expect("Some backup name is unused")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn exemplars_are_written_without_clobbering() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let path = dir.path().join("project").join("fel4.toml");
+        assert_eq!(
+            Ok(None),
+            write_exemplar_manifest(
+                &path,
+                SupportedTarget::Armv7Sel4Fel4,
+                SupportedPlatform::Sabre,
+                false
+            )
+        );
+        let written = get_full_manifest(&path).unwrap();
+        assert_eq!(SupportedTarget::Armv7Sel4Fel4, written.selected_target);
+
+        assert_eq!(
+            Err(ExemplarWriteError::AlreadyExists(
+                path.display().to_string()
+            )),
+            write_exemplar_manifest(
+                &path,
+                SupportedTarget::X8664Sel4Fel4,
+                SupportedPlatform::PC99,
+                false
+            )
+        );
+        let backup = write_exemplar_manifest(
+            &path,
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            true,
+        ).unwrap()
+            .expect("The existing manifest should be backed up");
+        assert_eq!(dir.path().join("project").join("fel4.toml.bak"), backup);
+        assert_eq!(written, get_full_manifest(&backup).unwrap());
+        assert_eq!(
+            get_exemplar_default_toml(),
+            fs::read_to_string(&path).unwrap()
+        );
+        let second_backup = write_exemplar_manifest(
+            &path,
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            true,
+        ).unwrap()
+            .expect("The existing manifest should be backed up again");
+        assert_eq!(dir.path().join("project").join("fel4.toml.bak.1"), second_backup);
+        assert_eq!(written, get_full_manifest(&backup).unwrap());
+
+        assert_eq!(
+            Err(ExemplarWriteError::UnsupportedPair(
+                SupportedTarget::X8664Sel4Fel4,
+                SupportedPlatform::Tx1
+            )),
+            write_exemplar_manifest(
+                &path,
+                SupportedTarget::X8664Sel4Fel4,
+                SupportedPlatform::Tx1,
+                true
+            )
+        );
+    }
+}
@@ -0,0 +1,165 @@
+/// Exporting resolved configurations as toml and JSON, optionally annotated
+/// with where each property's value came from
+use std::collections::BTreeMap;
+use toml;
+
+use json::Json;
+use manifest::toml_key;
+use types::*;
+
+impl Fel4Config {
+    /// The selection and resolved properties as a toml document, with the
+    /// properties in `ordered_properties` order in a `[properties]` table.
+    ///
+    /// With `annotate`, each property is followed by a comment giving its
+    /// `PropertyOrigin` and the manifest table it was set in, such as
+    /// `# explicit, x86_64-sel4-fel4.debug`.
+    pub fn export_toml(&self, annotate: bool) -> String {
+        let mut header = BTreeMap::new();
+        for &(name, value) in &self.export_selection() {
+            header.insert(name.to_string(), toml::Value::String(value.to_string()));
+        }
+        let mut text = format!(
+            "# fel4 configuration for {} {} {}\n{}\n[properties]\n",
+            self.target,
+            self.platform,
+            self.build_profile,
+            toml::to_string(&toml::Value::Table(header))
+                .expect("The selection should always serialize")
+        );
+        for (name, value) in self.ordered_properties() {
+            let line = format!("{} = {}", toml_key(name), value);
+            match self.export_annotation(name) {
+                Some((origin, table)) if annotate => {
+                    text.push_str(&format!("{}  # {}, {}\n", line, origin, table))
+                }
+                _ => text.push_str(&format!("{}\n", line)),
+            }
+        }
+        text
+    }
+
+    /// The selection and resolved properties as a JSON object, with the
+    /// properties in `ordered_properties` order.
+    ///
+    /// With `annotate`, a `provenance` object gives each property's `origin`
+    /// and the manifest `table` it was set in.
+    pub fn export_json(&self, annotate: bool) -> String {
+        let mut members: Vec<(&str, Json)> = self
+            .export_selection()
+            .iter()
+            .map(|&(name, value)| (name, Json::string(value)))
+            .collect();
+        members.push((
+            "properties",
+            Json::Object(
+                self.ordered_properties()
+                    .map(|(name, value)| (name.to_string(), Json::from(value)))
+                    .collect(),
+            ),
+        ));
+        if annotate {
+            members.push((
+                "provenance",
+                Json::Object(
+                    self.ordered_properties()
+                        .filter_map(|(name, _)| {
+                            let (origin, table) = self.export_annotation(name)?;
+                            Some((
+                                name.to_string(),
+                                Json::object(vec![
+                                    ("origin", Json::string(origin.full_name())),
+                                    ("table", Json::string(table)),
+                                ]),
+                            ))
+                        })
+                        .collect(),
+                ),
+            ));
+        }
+        format!("{}\n", Json::object(members))
+    }
+
+    fn export_selection(&self) -> [(&'static str, &'static str); 3] {
+        [
+            ("target", self.target.full_name()),
+            ("platform", self.platform.full_name()),
+            ("build-profile", self.build_profile.full_name()),
+        ]
+    }
+
+    /// A property's origin and the table it came from, or `rules` for
+    /// defaulted and `none` for overridden properties
+    fn export_annotation(&self, name: &str) -> Option<(PropertyOrigin, String)> {
+        let origin = self.property_origin(name)?;
+        let table = match self.provenance.get(name) {
+            Some(layer) => layer.location(self),
+            None => "none".to_string(),
+        };
+        Some((origin, table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn config() -> Fel4Config {
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        config.properties.retain(|name, _| {
+            ["KernelPrinting", "KernelX86MicroArch", "KernelBenchmarks"].contains(&name.as_str())
+        });
+        config
+            .properties
+            .insert("X-Override".into(), FlatTomlValue::Integer(1));
+        config
+            .provenance
+            .insert("KernelBenchmarks".into(), PropertyLayer::Rule);
+        config
+    }
+
+    #[test]
+    fn toml_exports_annotate_each_property() {
+        let config = config();
+        assert_eq!(
+            Some(PropertyOrigin::Defaulted),
+            config.property_origin("KernelBenchmarks")
+        );
+        let annotated = config.export_toml(true);
+        assert!(annotated.starts_with(
+            "# fel4 configuration for x86_64-sel4-fel4 pc99 debug\n\
+             build-profile = \"debug\"\n"
+        ));
+        assert!(
+            annotated.contains("\n[properties]\nKernelBenchmarks = \"none\"  # defaulted, rules\n")
+        );
+        assert!(annotated.contains("KernelPrinting = true  # explicit, x86_64-sel4-fel4.debug\n"));
+        assert!(annotated.contains("X-Override = 1  # overridden, none\n"));
+        assert!(config
+            .export_toml(false)
+            .contains("\nKernelPrinting = true\n"));
+
+        let exported: toml::Value = config.export_toml(true).parse().unwrap();
+        assert_eq!(
+            Some(&toml::Value::Boolean(true)),
+            exported["properties"].get("KernelPrinting")
+        );
+    }
+
+    #[test]
+    fn json_exports_include_provenance_when_asked() {
+        let config = config();
+        let annotated = config.export_json(true);
+        assert!(annotated.contains(
+            "\"KernelX86MicroArch\": {\n      \"origin\": \"explicit\",\n      \"table\": \"x86_64-sel4-fel4.pc99\"\n    }"
+        ));
+        assert!(annotated.contains("\"origin\": \"overridden\""));
+        let plain = config.export_json(false);
+        assert!(plain.contains("\"KernelPrinting\": true"));
+        assert!(!plain.contains("provenance"));
+    }
+}
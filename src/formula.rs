@@ -0,0 +1,506 @@
+/// Derived properties, whose integer values are computed during resolution
+/// from the other resolved properties, written in the `[$TARGET]` table as
+/// `KernelRootCNodeSizeBits = { formula = "'X-CNodeBase' + log2('X-Components')" }`
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+use toml;
+
+use super::ConfigError;
+use types::*;
+
+/// The functions a formula may call
+pub const FORMULA_FUNCTIONS: &[&str] = &["log2", "min", "max"];
+
+/// Integer arithmetic on the resolved properties.
+///
+/// Formulas are made of integers (decimal, or hexadecimal with `0x`),
+/// property names, the operators `+`, `-`, `*`, `/`, `%`, `<<`, and `>>` with
+/// their usual precedence, parentheses, and the `FORMULA_FUNCTIONS`, where
+/// `log2` rounds down. Property names that aren't plain identifiers, such as
+/// user properties, are quoted as in `'X-Components'`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Formula {
+    text: String,
+    expression: Expression,
+}
+
+impl Formula {
+    /// The names of the properties the formula reads, in the order they
+    /// first appear
+    pub fn references(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.expression.collect_references(&mut names);
+        names
+    }
+
+    /// Compute the formula, looking up the properties it reads by name
+    pub fn evaluate<'a, F>(&self, lookup: F) -> Result<i64, String>
+    where
+        F: Fn(&str) -> Option<&'a FlatTomlValue>,
+    {
+        self.expression.evaluate(&lookup)
+    }
+}
+
+/// Formulas display as the text they were parsed from
+impl Display for Formula {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(&self.text)
+    }
+}
+
+impl FromStr for Formula {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        let mut parser = FormulaParser {
+            tokens: tokenize(s)?,
+            next: 0,
+        };
+        let expression = parser.shift()?;
+        match parser.tokens.get(parser.next) {
+            None => Ok(Formula {
+                text: s.to_string(),
+                expression,
+            }),
+            Some(t) => Err(format!("unexpected {} in the formula {:?}", t, s)),
+        }
+    }
+}
+
+/// Read a `[$TARGET]` table value of the form `{ formula = "..." }`
+pub(crate) fn parse_derived_value(
+    table: &BTreeMap<String, toml::Value>,
+) -> Option<Result<Formula, String>> {
+    let formula = table.get("formula")?;
+    if let Some(k) = table.keys().find(|k| *k != "formula") {
+        return Some(Err(format!("{} may not be given alongside a formula", k)));
+    }
+    Some(match formula.as_str() {
+        Some(text) => text.parse(),
+        None => Err("formula should be a string".into()),
+    })
+}
+
+/// Compute a target's derived properties from the resolved properties,
+/// those that read other derived properties after them
+pub(crate) fn evaluate_derived_properties(
+    derived: &BTreeMap<String, Formula>,
+    properties: &HashMap<String, FlatTomlValue>,
+    table: &str,
+) -> Result<PropertySet, ConfigError> {
+    let invalid = |name: &str, reason: String| {
+        ConfigError::InvalidDerivedProperty(table.to_string(), name.to_string(), reason)
+    };
+    let mut computed: HashMap<String, FlatTomlValue> = HashMap::new();
+    let mut evaluated = PropertySet::new();
+    let mut pending: Vec<(&String, &Formula)> = derived.iter().collect();
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|&(_, formula)| {
+            formula
+                .references()
+                .iter()
+                .all(|r| !derived.contains_key(*r) || computed.contains_key(*r))
+        });
+        let (name, formula) = match ready {
+            Some(i) => pending.remove(i),
+            None => {
+                let names: Vec<&str> = pending.iter().map(|&(n, _)| n.as_str()).collect();
+                return Err(invalid(
+                    names[0],
+                    format!("the formulas of {:?} depend on each other", names),
+                ));
+            }
+        };
+        let value = formula
+            .evaluate(|r| computed.get(r).or_else(|| properties.get(r)))
+            .map_err(|reason| invalid(name, reason))?;
+        fel4_trace!("resolve", "derived {} = {} from {}", name, value, formula);
+        computed.insert(name.clone(), FlatTomlValue::Integer(value));
+        let _ = evaluated.insert(FlatTomlProperty::new(
+            name.clone(),
+            FlatTomlValue::Integer(value),
+        ));
+    }
+    Ok(evaluated)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expression {
+    Integer(i64),
+    Property(String),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    Call(String, Vec<Expression>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl Expression {
+    fn collect_references<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match *self {
+            Expression::Integer(_) => (),
+            Expression::Property(ref name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name)
+                }
+            }
+            Expression::Negate(ref e) => e.collect_references(names),
+            Expression::Binary(_, ref a, ref b) => {
+                a.collect_references(names);
+                b.collect_references(names);
+            }
+            Expression::Call(_, ref arguments) => {
+                for a in arguments {
+                    a.collect_references(names);
+                }
+            }
+        }
+    }
+
+    fn evaluate<'a, F>(&self, lookup: &F) -> Result<i64, String>
+    where
+        F: Fn(&str) -> Option<&'a FlatTomlValue>,
+    {
+        let overflow = || "the result overflows a 64 bit integer".to_string();
+        match *self {
+            Expression::Integer(i) => Ok(i),
+            Expression::Property(ref name) => match lookup(name) {
+                Some(&FlatTomlValue::Integer(i)) => Ok(i),
+                Some(other) => Err(format!("{} is {}, not an integer", name, other)),
+                None => Err(format!("{} is not set", name)),
+            },
+            Expression::Negate(ref e) => e.evaluate(lookup)?.checked_neg().ok_or_else(overflow),
+            Expression::Binary(operator, ref a, ref b) => {
+                let (a, b) = (a.evaluate(lookup)?, b.evaluate(lookup)?);
+                let result = match operator {
+                    Operator::Add => a.checked_add(b),
+                    Operator::Subtract => a.checked_sub(b),
+                    Operator::Multiply => a.checked_mul(b),
+                    Operator::Divide | Operator::Remainder if b == 0 => {
+                        return Err("the formula divides by zero".into())
+                    }
+                    Operator::Divide => a.checked_div(b),
+                    Operator::Remainder => a.checked_rem(b),
+                    Operator::ShiftLeft | Operator::ShiftRight if !(0..64).contains(&b) => {
+                        return Err(format!("{} is not a shift between 0 and 63", b))
+                    }
+                    Operator::ShiftLeft => a.checked_shl(b as u32).filter(|r| r >> b == a),
+                    Operator::ShiftRight => a.checked_shr(b as u32),
+                };
+                result.ok_or_else(overflow)
+            }
+            Expression::Call(ref function, ref arguments) => {
+                let values = arguments
+                    .iter()
+                    .map(|a| a.evaluate(lookup))
+                    .collect::<Result<Vec<i64>, String>>()?;
+                match function.as_str() {
+                    "log2" if values[0] > 0 => Ok(63 - i64::from(values[0].leading_zeros())),
+                    "log2" => Err(format!("log2 of {} is undefined", values[0])),
+                    "min" => Ok(*values.iter().min().expect("min has arguments")),
+                    _ => Ok(*values.iter().max().expect("max has arguments")),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Integer(i64),
+    Name(String),
+    Quoted(String),
+    Operator(Operator),
+    Open,
+    Close,
+    Comma,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            Token::Integer(i) => write!(f, "{}", i),
+            Token::Name(ref name) => f.write_str(name),
+            Token::Quoted(ref name) => write!(f, "'{}'", name),
+            Token::Operator(operator) => f.write_str(match operator {
+                Operator::Add => "+",
+                Operator::Subtract => "-",
+                Operator::Multiply => "*",
+                Operator::Divide => "/",
+                Operator::Remainder => "%",
+                Operator::ShiftLeft => "<<",
+                Operator::ShiftRight => ">>",
+            }),
+            Token::Open => f.write_str("("),
+            Token::Close => f.write_str(")"),
+            Token::Comma => f.write_str(","),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '+' => Token::Operator(Operator::Add),
+            '-' => Token::Operator(Operator::Subtract),
+            '*' => Token::Operator(Operator::Multiply),
+            '/' => Token::Operator(Operator::Divide),
+            '%' => Token::Operator(Operator::Remainder),
+            '<' | '>' if chars.peek() == Some(&c) => {
+                chars.next();
+                Token::Operator(if c == '<' {
+                    Operator::ShiftLeft
+                } else {
+                    Operator::ShiftRight
+                })
+            }
+            '\'' | '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(q) => quoted.push(q),
+                        None => return Err(format!("a quote is never closed in {:?}", text)),
+                    }
+                }
+                Token::Quoted(quoted)
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&n) = chars
+                    .peek()
+                    .filter(|n| n.is_ascii_alphanumeric() || **n == '_')
+                {
+                    word.push(n);
+                    chars.next();
+                }
+                if c.is_ascii_digit() {
+                    let integer = match word.strip_prefix("0x") {
+                        Some(hex) => i64::from_str_radix(hex, 16),
+                        None => word.parse(),
+                    };
+                    Token::Integer(
+                        integer.map_err(|_| format!("{} is not a 64 bit integer", word))?,
+                    )
+                } else {
+                    Token::Name(word)
+                }
+            }
+            _ => return Err(format!("unexpected {} in the formula {:?}", c, text)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct FormulaParser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl FormulaParser {
+    fn take_operator(&mut self, operators: &[Operator]) -> Option<Operator> {
+        match self.tokens.get(self.next) {
+            Some(&Token::Operator(o)) if operators.contains(&o) => {
+                self.next += 1;
+                Some(o)
+            }
+            _ => None,
+        }
+    }
+
+    fn take_if(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.next) == Some(token);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn shift(&mut self) -> Result<Expression, String> {
+        let mut e = self.additive()?;
+        while let Some(o) = self.take_operator(&[Operator::ShiftLeft, Operator::ShiftRight]) {
+            e = Expression::Binary(o, Box::new(e), Box::new(self.additive()?));
+        }
+        Ok(e)
+    }
+
+    fn additive(&mut self) -> Result<Expression, String> {
+        let mut e = self.multiplicative()?;
+        while let Some(o) = self.take_operator(&[Operator::Add, Operator::Subtract]) {
+            e = Expression::Binary(o, Box::new(e), Box::new(self.multiplicative()?));
+        }
+        Ok(e)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expression, String> {
+        let mut e = self.unary()?;
+        let operators = [Operator::Multiply, Operator::Divide, Operator::Remainder];
+        while let Some(o) = self.take_operator(&operators) {
+            e = Expression::Binary(o, Box::new(e), Box::new(self.unary()?));
+        }
+        Ok(e)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if self.take_operator(&[Operator::Subtract]).is_some() {
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        match token {
+            Some(Token::Integer(i)) => Ok(Expression::Integer(i)),
+            Some(Token::Quoted(name)) => Ok(Expression::Property(name)),
+            Some(Token::Name(name)) => {
+                if !self.take_if(&Token::Open) {
+                    return Ok(Expression::Property(name));
+                }
+                if !FORMULA_FUNCTIONS.contains(&name.as_str()) {
+                    return Err(format!(
+                        "{} is not one of the functions {:?}",
+                        name, FORMULA_FUNCTIONS
+                    ));
+                }
+                let mut arguments = vec![self.shift()?];
+                while self.take_if(&Token::Comma) {
+                    arguments.push(self.shift()?);
+                }
+                if !self.take_if(&Token::Close) {
+                    return Err(format!("the arguments of {} are never closed", name));
+                }
+                if name == "log2" && arguments.len() != 1 {
+                    return Err("log2 takes exactly one argument".into());
+                }
+                Ok(Expression::Call(name, arguments))
+            }
+            Some(Token::Open) => {
+                let e = self.shift()?;
+                if !self.take_if(&Token::Close) {
+                    return Err("a parenthesis is never closed".into());
+                }
+                Ok(e)
+            }
+            Some(t) => Err(format!("expected a number or property name, found {}", t)),
+            None => Err("the formula ends where a number or property name was expected".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn formulas_compute_integer_arithmetic() {
+        let properties: HashMap<String, FlatTomlValue> = vec![
+            ("X-Components", FlatTomlValue::Integer(6)),
+            ("KernelMaxNumNodes", FlatTomlValue::Integer(4)),
+            ("KernelPrinting", FlatTomlValue::Boolean(true)),
+        ].into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        let evaluate = |text: &str| {
+            text.parse::<Formula>()
+                .and_then(|f| f.evaluate(|name| properties.get(name)))
+        };
+        assert_eq!(Ok(13), evaluate("12 + log2('X-Components') - 1 * 2 / 2"));
+        assert_eq!(
+            Ok(0x1000 + 24),
+            evaluate("0x1000 + (1 << KernelMaxNumNodes) + 8")
+        );
+        assert_eq!(Ok(-2), evaluate("-max(1, KernelMaxNumNodes % 3, -5) * 2"));
+        assert_eq!(
+            vec!["X-Components", "KernelMaxNumNodes"],
+            "min('X-Components', KernelMaxNumNodes)"
+                .parse::<Formula>()
+                .unwrap()
+                .references()
+        );
+
+        assert!(evaluate("KernelPrinting + 1").is_err());
+        assert!(evaluate("KernelBenchmarks + 1").is_err());
+        assert!(evaluate("1 / (KernelMaxNumNodes - 4)").is_err());
+        assert!(evaluate("log2(0)").is_err());
+        assert!(evaluate("1 << 64").is_err());
+        assert!(evaluate("0x7fffffffffffffff + 1").is_err());
+        for text in &["sqrt(4)", "log2(4, 2)", "(1 + 2", "1 +", "1 2", "'X-Open"] {
+            assert!(
+                text.parse::<Formula>().is_err(),
+                "{} should not parse",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn derived_properties_are_resolved_before_validation() {
+        let text = get_exemplar_default_toml().replacen(
+            "KernelRootCNodeSizeBits = 19\nKernelMaxNumBootinfoUntypedCaps = 230",
+            concat!(
+                "X-CNodeBase = 16\nX-Components = 5\n",
+                "KernelRootCNodeSizeBits = ",
+                "{ formula = \"'X-CNodeBase' + log2('X-Components')\" }\n",
+                "KernelMaxNumBootinfoUntypedCaps = { formula = \"KernelRootCNodeSizeBits * 12\" }"
+            ),
+            1,
+        );
+        let full = parse_full_manifest(&text).expect("Derived properties should parse");
+        assert_eq!(
+            Ok(full.clone()),
+            parse_full_manifest(full_manifest_to_toml_string(&full))
+        );
+        let config = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(18)),
+            config.properties.get("KernelRootCNodeSizeBits")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(216)),
+            config.properties.get("KernelMaxNumBootinfoUntypedCaps")
+        );
+
+        let mut cyclic = full.clone();
+        {
+            let target = cyclic
+                .targets
+                .get_mut(&SupportedTarget::X8664Sel4Fel4)
+                .unwrap();
+            target.direct_properties.remove("X-CNodeBase");
+            target.derived_properties.insert(
+                "X-CNodeBase".into(),
+                "KernelMaxNumBootinfoUntypedCaps - 1".parse().unwrap(),
+            );
+        }
+        match resolve_fel4_config(&cyclic, &BuildProfile::Debug) {
+            Err(ConfigError::InvalidDerivedProperty(ref table, _, ref reason)) => {
+                assert_eq!("x86_64-sel4-fel4", table);
+                assert!(reason.contains("depend on each other"));
+            }
+            other => panic!("Expected the cycle to be rejected, got {:?}", other),
+        }
+        match parse_full_manifest(text.replacen("log2(", "sqrt(", 1)) {
+            Err(ConfigError::InvalidDerivedProperty(_, ref name, _)) => {
+                assert_eq!("KernelRootCNodeSizeBits", name)
+            }
+            other => panic!("Expected the formula to be rejected, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,493 @@
+/// A compact binary encoding of resolved configurations, for caching them
+/// and passing them between processes faster than toml allows
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use toml;
+
+use types::*;
+
+/// The version of the binary format `freeze_fel4_config` writes, which
+/// `thaw_fel4_config` requires
+pub const FROZEN_CONFIG_FORMAT_VERSION: u32 = 10;
+
+/// The bytes every frozen configuration starts with, ahead of the format
+/// version
+const FROZEN_CONFIG_MAGIC: &[u8] = b"fel4cfg\0";
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum ThawError {
+    #[fail(display = "The data is not a frozen fel4 configuration")]
+    NotAFrozenConfig,
+    #[fail(
+        display = "The frozen fel4 configuration is format version {}, but this release of fel4-config reads version {}",
+        _0, _1
+    )]
+    UnsupportedFormatVersion(u32, u32),
+    #[fail(display = "The frozen fel4 configuration ends unexpectedly")]
+    Truncated,
+    #[fail(display = "The frozen fel4 configuration contains an invalid {}", _0)]
+    InvalidValue(String),
+}
+
+/// Encode a resolved configuration in the binary format, tagged with
+/// `FROZEN_CONFIG_FORMAT_VERSION`.
+///
+/// Every field is kept, including provenance and property order. Properties
+/// are written in `ordered_properties` order, and provenance, property
+/// scopes, and effective properties in name order, so equal configurations
+/// freeze to equal bytes. A `manifest_path` that isn't valid Unicode is
+/// written lossily.
+pub fn freeze_fel4_config(fel4_config: &Fel4Config) -> Vec<u8> {
+    let mut w = Writer(Vec::with_capacity(1024));
+    w.0.extend_from_slice(FROZEN_CONFIG_MAGIC);
+    w.0.extend_from_slice(&FROZEN_CONFIG_FORMAT_VERSION.to_le_bytes());
+    w.string(&fel4_config.artifact_path);
+    w.string(fel4_config.artifact_layout.full_name());
+    w.string(&fel4_config.target_specs_path);
+    w.string(fel4_config.target.full_name());
+    w.string(fel4_config.platform.full_name());
+    w.string(fel4_config.build_profile.full_name());
+    w.string(&fel4_config.cmake_build_type);
+    w.len(fel4_config.properties.len());
+    for (name, value) in fel4_config.ordered_properties() {
+        w.string(name);
+        w.value(value);
+    }
+
+    let toolchain = &fel4_config.toolchain;
+    w.optional(&toolchain.compiler_prefix);
+    w.optional(&toolchain.cmake);
+    w.optional(&toolchain.ninja);
+    w.optional(&toolchain.compiler_launcher);
+    w.optional(&toolchain.float_abi.map(|abi| abi.full_name().to_string()));
+    w.len(toolchain.variables.len());
+    for variable in &toolchain.variables {
+        w.string(&variable.name);
+        w.value(&variable.value);
+    }
+    w.strings(&toolchain.build_targets);
+    match toolchain.cleared_env {
+        None => w.0.push(0),
+        Some(ref names) => {
+            w.0.push(1);
+            w.strings(names);
+        }
+    }
+    w.optional_integer(fel4_config.rootserver.stack_size);
+    w.optional_integer(fel4_config.rootserver.heap_size);
+    w.optional_integer(fel4_config.rootserver.cnode_size_bits);
+
+    match fel4_config.kernel_source {
+        None => w.0.push(0),
+        Some(KernelSource::Git { ref url, ref rev }) => {
+            w.0.push(1);
+            w.string(url);
+            w.optional(rev);
+        }
+        Some(KernelSource::Tarball {
+            ref url,
+            ref sha256,
+        }) => {
+            w.0.push(2);
+            w.string(url);
+            w.string(sha256);
+        }
+    }
+    w.optional(&fel4_config.kernel_version);
+    w.optional(&fel4_config.kernel_commit);
+    w.optional(&fel4_config.image_name);
+    w.optional(&fel4_config.image_name_pattern);
+    w.optional(&fel4_config.source_date_epoch.map(|e| e.to_string()));
+
+    let mut provenance: Vec<(&String, &PropertyLayer)> = fel4_config.provenance.iter().collect();
+    provenance.sort_by(|a, b| a.0.cmp(b.0));
+    w.len(provenance.len());
+    for (name, layer) in provenance {
+        w.string(name);
+        w.string(layer.full_name());
+    }
+    w.strings(&fel4_config.property_order);
+    w.len(fel4_config.declared_targets.len());
+    for target in &fel4_config.declared_targets {
+        w.string(target.full_name());
+    }
+    w.optional(
+        &fel4_config
+            .manifest_path
+            .as_ref()
+            .map(|p| p.to_string_lossy().into_owned()),
+    );
+    let mut scopes: Vec<(&String, &PropertyScope)> = fel4_config.property_scopes.iter().collect();
+    scopes.sort_by(|a, b| a.0.cmp(b.0));
+    w.len(scopes.len());
+    for (name, scope) in scopes {
+        w.string(name);
+        w.string(scope.full_name());
+    }
+    w.strings(&fel4_config.locked_properties);
+    let mut effective: Vec<(&String, &FlatTomlValue)> =
+        fel4_config.effective_properties.iter().collect();
+    effective.sort_by(|a, b| a.0.cmp(b.0));
+    w.len(effective.len());
+    for (name, value) in effective {
+        w.string(name);
+        w.value(value);
+    }
+    w.0
+}
+
+/// Decode a configuration written by `freeze_fel4_config`.
+///
+/// Data from another format version is an `UnsupportedFormatVersion` error,
+/// rather than being read by guesswork.
+pub fn thaw_fel4_config(frozen: &[u8]) -> Result<Fel4Config, ThawError> {
+    if !frozen.starts_with(FROZEN_CONFIG_MAGIC) {
+        return Err(ThawError::NotAFrozenConfig);
+    }
+    let mut r = Reader {
+        bytes: frozen,
+        position: FROZEN_CONFIG_MAGIC.len(),
+    };
+    let version = r.u32()?;
+    if version != FROZEN_CONFIG_FORMAT_VERSION {
+        return Err(ThawError::UnsupportedFormatVersion(
+            version,
+            FROZEN_CONFIG_FORMAT_VERSION,
+        ));
+    }
+    let artifact_path = r.string()?;
+    let artifact_layout = r.parsed("artifact layout")?;
+    let target_specs_path = r.string()?;
+    let target = r.parsed("target")?;
+    let platform = r.parsed("platform")?;
+    let build_profile = r.parsed("build profile")?;
+    let cmake_build_type = r.string()?;
+    let mut properties = HashMap::new();
+    for _ in 0..r.len()? {
+        let name = r.string()?;
+        properties.insert(name, r.value()?);
+    }
+
+    let compiler_prefix = r.optional()?;
+    let cmake = r.optional()?;
+    let ninja = r.optional()?;
+    let compiler_launcher = r.optional()?;
+    let float_abi = match r.optional()? {
+        None => None,
+        Some(name) => Some(
+            name.parse()
+                .map_err(|_| ThawError::InvalidValue("float ABI".into()))?,
+        ),
+    };
+    let mut variables = Vec::new();
+    for _ in 0..r.len()? {
+        let name = r.string()?;
+        variables.push(FlatTomlProperty::new(name, r.value()?));
+    }
+    let build_targets = r.strings()?;
+    let cleared_env = match r.u8()? {
+        0 => None,
+        1 => Some(r.strings()?),
+        _ => return Err(ThawError::InvalidValue("cleared environment".into())),
+    };
+    let rootserver = RootserverSettings {
+        stack_size: r.optional_integer()?,
+        heap_size: r.optional_integer()?,
+        cnode_size_bits: r.optional_integer()?,
+    };
+
+    let kernel_source = match r.u8()? {
+        0 => None,
+        1 => Some(KernelSource::Git {
+            url: r.string()?,
+            rev: r.optional()?,
+        }),
+        2 => Some(KernelSource::Tarball {
+            url: r.string()?,
+            sha256: r.string()?,
+        }),
+        _ => return Err(ThawError::InvalidValue("kernel source".into())),
+    };
+    let kernel_version = r.optional()?;
+    let kernel_commit = r.optional()?;
+    let image_name = r.optional()?;
+    let image_name_pattern = r.optional()?;
+    let source_date_epoch = match r.optional()? {
+        None => None,
+        Some(epoch) => Some(
+            epoch
+                .parse()
+                .map_err(|_| ThawError::InvalidValue("source date epoch".into()))?,
+        ),
+    };
+
+    let mut provenance = HashMap::new();
+    for _ in 0..r.len()? {
+        let name = r.string()?;
+        provenance.insert(name, r.parsed("property layer")?);
+    }
+    let property_order = r.strings()?;
+    let mut declared_targets = Vec::new();
+    for _ in 0..r.len()? {
+        declared_targets.push(r.parsed("target")?);
+    }
+    let manifest_path = r.optional()?.map(PathBuf::from);
+    let mut property_scopes = HashMap::new();
+    for _ in 0..r.len()? {
+        let name = r.string()?;
+        property_scopes.insert(name, r.parsed("property scope")?);
+    }
+    let locked_properties = r.strings()?;
+    let mut effective_properties = HashMap::new();
+    for _ in 0..r.len()? {
+        let name = r.string()?;
+        effective_properties.insert(name, r.value()?);
+    }
+    if r.position != frozen.len() {
+        return Err(ThawError::InvalidValue("trailing data".into()));
+    }
+
+    Ok(Fel4Config {
+        artifact_path,
+        artifact_layout,
+        target_specs_path,
+        target,
+        platform,
+        build_profile,
+        cmake_build_type,
+        properties,
+        effective_properties,
+        toolchain: ToolchainSettings {
+            compiler_prefix,
+            platform_compiler_prefixes: HashMap::new(),
+            float_abi,
+            cmake,
+            ninja,
+            compiler_launcher,
+            variables,
+            build_targets,
+            cleared_env,
+        },
+        rootserver,
+        kernel_source,
+        kernel_version,
+        kernel_commit,
+        image_name,
+        image_name_pattern,
+        source_date_epoch,
+        provenance,
+        property_order,
+        declared_targets,
+        manifest_path,
+        property_scopes,
+        locked_properties,
+    })
+}
+
+/// Lengths and integers are little-endian, strings are length-prefixed
+/// UTF-8, and enums are written by their `full_name` so the format doesn't
+/// depend on variant order
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn len(&mut self, len: usize) {
+        self.0.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) {
+        self.len(s.len());
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn strings(&mut self, strings: &[String]) {
+        self.len(strings.len());
+        for s in strings {
+            self.string(s);
+        }
+    }
+
+    fn optional(&mut self, s: &Option<String>) {
+        match *s {
+            Some(ref s) => {
+                self.0.push(1);
+                self.string(s);
+            }
+            None => self.0.push(0),
+        }
+    }
+
+    fn optional_integer(&mut self, i: Option<i64>) {
+        match i {
+            Some(i) => {
+                self.0.push(1);
+                self.0.extend_from_slice(&i.to_le_bytes());
+            }
+            None => self.0.push(0),
+        }
+    }
+
+    fn value(&mut self, value: &FlatTomlValue) {
+        match *value {
+            FlatTomlValue::String(ref s) => {
+                self.0.push(0);
+                self.string(s);
+            }
+            FlatTomlValue::Integer(i) => {
+                self.0.push(1);
+                self.0.extend_from_slice(&i.to_le_bytes());
+            }
+            FlatTomlValue::Float(f) => {
+                self.0.push(2);
+                self.0.extend_from_slice(&f.to_bits().to_le_bytes());
+            }
+            FlatTomlValue::Boolean(b) => {
+                self.0.push(3);
+                self.0.push(b as u8);
+            }
+            FlatTomlValue::Datetime(ref d) => {
+                self.0.push(4);
+                self.string(&d.to_string());
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ThawError> {
+        let end = self.position.checked_add(n).ok_or(ThawError::Truncated)?;
+        let taken = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(ThawError::Truncated)?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ThawError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ThawError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, ThawError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn len(&mut self) -> Result<usize, ThawError> {
+        self.u32().map(|n| n as usize)
+    }
+
+    fn string(&mut self) -> Result<String, ThawError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| ThawError::InvalidValue("string".into()))
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, ThawError> {
+        (0..self.len()?).map(|_| self.string()).collect()
+    }
+
+    fn optional(&mut self) -> Result<Option<String>, ThawError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => self.string().map(Some),
+            _ => Err(ThawError::InvalidValue("optional string".into())),
+        }
+    }
+
+    fn optional_integer(&mut self) -> Result<Option<i64>, ThawError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => self.u64().map(|i| Some(i as i64)),
+            _ => Err(ThawError::InvalidValue("optional integer".into())),
+        }
+    }
+
+    fn parsed<T: FromStr>(&mut self, what: &str) -> Result<T, ThawError> {
+        self.string()?
+            .parse()
+            .map_err(|_| ThawError::InvalidValue(what.into()))
+    }
+
+    fn value(&mut self) -> Result<FlatTomlValue, ThawError> {
+        Ok(match self.u8()? {
+            0 => FlatTomlValue::String(self.string()?),
+            1 => FlatTomlValue::Integer(self.u64()? as i64),
+            2 => FlatTomlValue::Float(f64::from_bits(self.u64()?)),
+            3 => match self.u8()? {
+                0 => FlatTomlValue::Boolean(false),
+                1 => FlatTomlValue::Boolean(true),
+                _ => return Err(ThawError::InvalidValue("boolean".into())),
+            },
+            4 => FlatTomlValue::Datetime(self.parsed::<toml::value::Datetime>("datetime")?),
+            _ => return Err(ThawError::InvalidValue("property value".into())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn frozen_configs_thaw_to_the_same_config() {
+        let full = parse_full_manifest(get_exemplar_default_toml()).unwrap();
+        let mut config = resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        config.manifest_path = Some(PathBuf::from("/work/fel4.toml"));
+        config.kernel_source = Some(KernelSource::Git {
+            url: "https://github.com/seL4/seL4".into(),
+            rev: None,
+        });
+        config.rootserver.heap_size = Some(1 << 20);
+        config.properties.insert(
+            "X-AppBuildDate".into(),
+            FlatTomlValue::Datetime("2018-06-01T12:00:00Z".parse().unwrap()),
+        );
+        config
+            .properties
+            .insert("X-AppRatio".into(), FlatTomlValue::Float(-0.5));
+
+        let frozen = freeze_fel4_config(&config);
+        assert_eq!(Ok(config.clone()), thaw_fel4_config(&frozen));
+        assert_eq!(frozen, freeze_fel4_config(&config.clone()));
+        assert_eq!(
+            Err(ThawError::Truncated),
+            thaw_fel4_config(&frozen[..frozen.len() - 1])
+        );
+    }
+
+    #[test]
+    fn other_formats_are_rejected() {
+        assert_eq!(
+            Err(ThawError::NotAFrozenConfig),
+            thaw_fel4_config(get_exemplar_default_toml().as_bytes())
+        );
+        let mut frozen = freeze_fel4_config(&test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Release,
+        ));
+        let version = FROZEN_CONFIG_MAGIC.len();
+        frozen[version] = 0xff;
+        match thaw_fel4_config(&frozen) {
+            Err(ThawError::UnsupportedFormatVersion(found, FROZEN_CONFIG_FORMAT_VERSION)) => {
+                assert_ne!(FROZEN_CONFIG_FORMAT_VERSION, found)
+            }
+            other => panic!("Expected a format version error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,450 @@
+/// Reading the effective kernel configuration back out of the headers the
+/// seL4 CMake build generates, such as `gen_config.h` and `autoconf.h`
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use super::ConfigError;
+use known_properties::KnownProperty;
+use types::*;
+
+/// Parse the `#define` lines of a generated kernel configuration header.
+///
+/// Each `#define CONFIG_NAME value` becomes an entry keyed by the macro name.
+/// Quoted values become strings, decimal and hexadecimal numbers become
+/// integers, a bare `#define` becomes `true`, and any other value is kept as
+/// an unquoted string. The `/* disabled: CONFIG_NAME */` comments seL4 emits
+/// for switched-off options become `false`.
+pub fn parse_generated_config(header: &str) -> HashMap<String, FlatTomlValue> {
+    let mut config = HashMap::new();
+    for line in header.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("#define") {
+            let rest = rest.trim_start();
+            let mut parts = rest.splitn(2, char::is_whitespace);
+            let name = match parts.next() {
+                Some(n) if !n.is_empty() && !n.contains('(') => n,
+                _ => continue,
+            };
+            let value = parse_define_value(parts.next().unwrap_or("").trim());
+            config.insert(name.to_string(), value);
+        } else if let Some(rest) = line.strip_prefix("/* disabled:") {
+            let name = rest.trim_end_matches("*/").trim();
+            if !name.is_empty() {
+                config.insert(name.to_string(), FlatTomlValue::Boolean(false));
+            }
+        }
+    }
+    config
+}
+
+/// Read and parse a generated kernel configuration header from a file
+pub fn read_generated_config<P: AsRef<Path>>(
+    path: P,
+) -> Result<HashMap<String, FlatTomlValue>, ConfigError> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|_| ConfigError::FileReadFailure)?;
+    Ok(parse_generated_config(&text))
+}
+
+fn parse_define_value(raw: &str) -> FlatTomlValue {
+    if raw.is_empty() {
+        return FlatTomlValue::Boolean(true);
+    }
+    if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+        return FlatTomlValue::String(raw[1..raw.len() - 1].replace("\\\"", "\""));
+    }
+    let integer = if let Some(hex) = raw.strip_prefix("0x").or_else(|| raw.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else {
+        raw.parse::<i64>().ok()
+    };
+    match integer {
+        Some(i) => FlatTomlValue::Integer(i),
+        None => FlatTomlValue::String(raw.to_string()),
+    }
+}
+
+/// The C macro name seL4 conventionally generates for a CMake configuration
+/// property, e.g. `KernelNumPriorities` becomes `CONFIG_NUM_PRIORITIES`.
+///
+/// A leading `Kernel` is dropped and the remaining words are upper-cased and
+/// joined with underscores, keeping acronyms and the digits that follow a
+/// word together, as in `KernelX86MicroArch` to `CONFIG_X86_MICRO_ARCH`.
+/// Hyphens, as in user properties, become underscores.
+pub fn config_macro_name(property: &str) -> String {
+    let base = match property.strip_prefix("Kernel") {
+        Some(rest) if !rest.is_empty() => rest,
+        _ => property,
+    };
+    format!("CONFIG_{}", upper_snake_case(base))
+}
+
+/// A property name's words upper-cased and joined with underscores, as
+/// `config_macro_name` splits them
+pub(crate) fn upper_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if i > 0 && c.is_ascii_uppercase() {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_is_lower)
+            {
+                snake.push('_');
+            }
+        }
+        snake.push(if c == '-' { '_' } else { c.to_ascii_uppercase() });
+    }
+    snake
+}
+
+/// The result of comparing manifest-derived properties against the effective
+/// kernel configuration
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GeneratedConfigComparison {
+    /// Properties whose effective value agrees with the manifest
+    pub matching: Vec<String>,
+    /// Properties the kernel configured differently, with the manifest value
+    /// followed by the effective value
+    pub differing: Vec<(String, FlatTomlValue, FlatTomlValue)>,
+    /// Properties with no counterpart in the generated configuration
+    pub missing: Vec<String>,
+}
+
+impl GeneratedConfigComparison {
+    /// True if every manifest property was found with the expected value
+    pub fn is_consistent(&self) -> bool {
+        self.differing.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Compare each of the configuration's properties to the corresponding
+/// entry of a parsed generated header.
+///
+/// Booleans match `1` as well as a bare define, and string choices match
+/// either a string-valued macro or the `CONFIG_NAME_CHOICE` flag seL4 defines
+/// for the selected option. Results are sorted by property name.
+pub fn compare_generated_config(
+    fel4_config: &Fel4Config,
+    generated: &HashMap<String, FlatTomlValue>,
+) -> GeneratedConfigComparison {
+    let mut names: Vec<&String> = fel4_config.properties.keys().collect();
+    names.sort();
+    let mut comparison = GeneratedConfigComparison::default();
+    for name in names {
+        let expected = &fel4_config.properties[name];
+        let macro_name = config_macro_name(name);
+        match effective_value(&macro_name, expected, generated) {
+            None => comparison.missing.push(name.clone()),
+            Some(ref effective) if values_agree(expected, effective) => {
+                comparison.matching.push(name.clone())
+            }
+            Some(effective) => {
+                comparison
+                    .differing
+                    .push((name.clone(), expected.clone(), effective))
+            }
+        }
+    }
+    comparison
+}
+
+fn effective_value(
+    macro_name: &str,
+    expected: &FlatTomlValue,
+    generated: &HashMap<String, FlatTomlValue>,
+) -> Option<FlatTomlValue> {
+    if let Some(v) = generated.get(macro_name) {
+        return Some(v.clone());
+    }
+    if let FlatTomlValue::String(ref choice) = *expected {
+        let choice_prefix = format!("{}_", macro_name);
+        let expected_flag = format!("{}{}", choice_prefix, choice.to_ascii_uppercase());
+        if generated.get(&expected_flag).is_some_and(is_enabled) {
+            return Some(expected.clone());
+        }
+        return selected_choice(macro_name, generated);
+    }
+    None
+}
+
+/// The option named by the first enabled `CONFIG_NAME_CHOICE` flag, lower
+/// cased
+fn selected_choice(
+    macro_name: &str,
+    generated: &HashMap<String, FlatTomlValue>,
+) -> Option<FlatTomlValue> {
+    let choice_prefix = format!("{}_", macro_name);
+    let mut selected: Vec<&String> = generated
+        .iter()
+        .filter(|&(k, v)| k.starts_with(&choice_prefix) && is_enabled(v))
+        .map(|(k, _)| k)
+        .collect();
+    selected.sort();
+    selected
+        .first()
+        .map(|flag| FlatTomlValue::String(flag[choice_prefix.len()..].to_ascii_lowercase()))
+}
+
+/// The names of the headers `augment_from_generated_headers` reads
+pub const GENERATED_CONFIG_HEADER_NAMES: &[&str] = &["gen_config.h", "autoconf.h"];
+
+/// How many directories deep beneath the build directory generated headers
+/// are looked for, enough for `kernel/gen_config/kernel/gen_config.h`
+const GENERATED_HEADER_SEARCH_DEPTH: usize = 5;
+
+/// Fill in the configuration's `effective_properties` from the headers a
+/// kernel CMake build generated in `build_dir`, so that code can rely on the
+/// values the kernel build chose for the known properties the manifest
+/// leaves to its defaults, see `Fel4Config::effective_property`.
+///
+/// Every `GENERATED_CONFIG_HEADER_NAMES` header beneath the build directory
+/// is read. Properties the manifest sets, those of other targets, and values
+/// that don't fit the property's type are left out. Fails with
+/// `MissingGeneratedConfig` if the build hasn't generated any header yet.
+pub fn augment_from_generated_headers<P: AsRef<Path>>(
+    fel4_config: &mut Fel4Config,
+    build_dir: P,
+) -> Result<(), ConfigError> {
+    let build_dir = build_dir.as_ref();
+    let mut headers = Vec::new();
+    find_generated_headers(build_dir, GENERATED_HEADER_SEARCH_DEPTH, &mut headers);
+    if headers.is_empty() {
+        return Err(ConfigError::MissingGeneratedConfig(
+            build_dir.display().to_string(),
+        ));
+    }
+    headers.sort();
+    let mut generated = HashMap::new();
+    for header in &headers {
+        fel4_trace!("cmake", "reading generated configuration {}", header.display());
+        generated.extend(read_generated_config(header)?);
+    }
+    for property in KnownProperty::properties() {
+        let name = property.full_name();
+        if fel4_config.properties.contains_key(name)
+            || !property.targets().contains(&fel4_config.target)
+        {
+            continue;
+        }
+        let macro_name = config_macro_name(name);
+        let value = match (property.value_type(), generated.get(&macro_name)) {
+            (FlatTomlType::Boolean, Some(v)) if v.value_type() != FlatTomlType::String => {
+                Some(FlatTomlValue::Boolean(is_enabled(v)))
+            }
+            (FlatTomlType::Integer, Some(FlatTomlValue::Integer(i))) => {
+                Some(FlatTomlValue::Integer(*i))
+            }
+            (FlatTomlType::String, Some(FlatTomlValue::String(s))) => {
+                Some(FlatTomlValue::String(s.clone()))
+            }
+            (FlatTomlType::String, Some(FlatTomlValue::Integer(i))) => {
+                Some(FlatTomlValue::String(i.to_string()))
+            }
+            (FlatTomlType::String, None) => selected_choice(&macro_name, &generated),
+            _ => None,
+        };
+        if let Some(value) = value {
+            fel4_config
+                .effective_properties
+                .insert(name.to_string(), value);
+        }
+    }
+    Ok(())
+}
+
+fn find_generated_headers(dir: &Path, depth: usize, headers: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth > 0 {
+                find_generated_headers(&path, depth - 1, headers);
+            }
+        } else if path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| GENERATED_CONFIG_HEADER_NAMES.contains(&n))
+        {
+            headers.push(path);
+        }
+    }
+}
+
+fn is_enabled(value: &FlatTomlValue) -> bool {
+    matches!(
+        *value,
+        FlatTomlValue::Boolean(true) | FlatTomlValue::Integer(1)
+    )
+}
+
+fn values_agree(expected: &FlatTomlValue, effective: &FlatTomlValue) -> bool {
+    match (expected, effective) {
+        (FlatTomlValue::Boolean(b), e) => *b == is_enabled(e),
+        (FlatTomlValue::String(s), FlatTomlValue::String(e)) => s.eq_ignore_ascii_case(e),
+        (FlatTomlValue::String(s), FlatTomlValue::Integer(i)) => s.parse::<i64>() == Ok(*i),
+        (FlatTomlValue::Float(f), FlatTomlValue::Integer(i)) => *f == *i as f64,
+        (e, a) => e == a,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    const HEADER: &str = r#"
+#pragma once
+
+#define CONFIG_ARCH_X86  1
+#define CONFIG_NUM_PRIORITIES 256
+#define CONFIG_ROOT_CNODE_SIZE_BITS 0x13
+#define CONFIG_PRINTING  1
+/* disabled: CONFIG_VERIFICATION_BUILD */
+#define CONFIG_X86_MICRO_ARCH nehalem
+#define CONFIG_OPTIMISATION "-O2"
+#define CONFIG_DEBUG_BUILD
+"#;
+
+    #[test]
+    fn generated_header_defines_are_parsed() {
+        let config = parse_generated_config(HEADER);
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(256)),
+            config.get("CONFIG_NUM_PRIORITIES")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(19)),
+            config.get("CONFIG_ROOT_CNODE_SIZE_BITS")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(false)),
+            config.get("CONFIG_VERIFICATION_BUILD")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(true)),
+            config.get("CONFIG_DEBUG_BUILD")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::String("-O2".into())),
+            config.get("CONFIG_OPTIMISATION")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::String("nehalem".into())),
+            config.get("CONFIG_X86_MICRO_ARCH")
+        );
+    }
+
+    #[test]
+    fn property_names_map_to_config_macros() {
+        assert_eq!("CONFIG_PRINTING", config_macro_name("KernelPrinting"));
+        assert_eq!(
+            "CONFIG_NUM_PRIORITIES",
+            config_macro_name("KernelNumPriorities")
+        );
+        assert_eq!(
+            "CONFIG_X86_MICRO_ARCH",
+            config_macro_name("KernelX86MicroArch")
+        );
+        assert_eq!(
+            "CONFIG_IRQ_CONTROLLER",
+            config_macro_name("KernelIRQController")
+        );
+        assert_eq!(
+            "CONFIG_LIB_SEL4_FUNCTION_ATTRIBUTES",
+            config_macro_name("LibSel4FunctionAttributes")
+        );
+        assert_eq!("CONFIG_X_APP_LOG_LEVEL", config_macro_name("X-AppLogLevel"));
+    }
+
+    #[test]
+    fn comparison_reports_matches_differences_and_gaps() {
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        config.properties.clear();
+        let props = [
+            ("KernelArch", FlatTomlValue::String("x86".into())),
+            ("KernelNumPriorities", FlatTomlValue::Integer(256)),
+            ("KernelPrinting", FlatTomlValue::Boolean(true)),
+            ("KernelVerificationBuild", FlatTomlValue::Boolean(true)),
+            ("KernelMaxNumNodes", FlatTomlValue::Integer(1)),
+        ];
+        for (name, value) in props.iter() {
+            config.properties.insert(name.to_string(), value.clone());
+        }
+        let comparison = compare_generated_config(&config, &parse_generated_config(HEADER));
+        assert_eq!(
+            vec![
+                "KernelArch".to_string(),
+                "KernelNumPriorities".to_string(),
+                "KernelPrinting".to_string(),
+            ],
+            comparison.matching
+        );
+        assert_eq!(
+            vec![(
+                "KernelVerificationBuild".to_string(),
+                FlatTomlValue::Boolean(true),
+                FlatTomlValue::Boolean(false)
+            )],
+            comparison.differing
+        );
+        assert_eq!(vec!["KernelMaxNumNodes".to_string()], comparison.missing);
+        assert!(!comparison.is_consistent());
+    }
+
+    #[test]
+    fn generated_headers_fill_in_the_defaults_the_manifest_relies_on() {
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        for name in &["KernelRetypeFanOutLimit", "KernelVerificationBuild", "KernelFPU"] {
+            config.properties.remove(*name);
+        }
+        let build_dir = ::tempfile::tempdir().unwrap();
+        assert_eq!(
+            Err(ConfigError::MissingGeneratedConfig(
+                build_dir.path().display().to_string()
+            )),
+            augment_from_generated_headers(&mut config, build_dir.path())
+        );
+        let header_dir = build_dir.path().join("kernel/gen_config/kernel");
+        fs::create_dir_all(&header_dir).unwrap();
+        fs::write(
+            header_dir.join("gen_config.h"),
+            format!(
+                "{}#define CONFIG_RETYPE_FAN_OUT_LIMIT 256\n#define CONFIG_FPU_XSAVE 1\n\
+                 #define CONFIG_PRINTING 0\n",
+                HEADER
+            ),
+        ).unwrap();
+
+        augment_from_generated_headers(&mut config, build_dir.path()).unwrap();
+        let effective = |name| config.effective_properties.get(name);
+        assert_eq!(Some(&FlatTomlValue::Integer(256)), effective("KernelRetypeFanOutLimit"));
+        assert_eq!(Some(&FlatTomlValue::Boolean(false)), effective("KernelVerificationBuild"));
+        assert_eq!(Some(&FlatTomlValue::String("xsave".into())), effective("KernelFPU"));
+        assert_eq!(None, effective("KernelPrinting"));
+        assert_eq!(
+            config.properties.get("KernelPrinting"),
+            config.effective_property("KernelPrinting")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(256)),
+            config.effective_property("KernelRetypeFanOutLimit")
+        );
+    }
+}
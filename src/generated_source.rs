@@ -0,0 +1,41 @@
+/// Rendering and writing the files fel4-config generates for a build, such
+/// as the Rust constants a build script writes to `OUT_DIR`
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The note every generated file begins with, after its comment marker
+pub(crate) const GENERATED_FILE_NOTE: &str =
+    "Generated from fel4.toml by fel4-config. Regenerate rather than editing by hand.";
+
+/// The generated file note as a line comment, for the file's comment marker
+pub(crate) fn generated_file_header(comment_marker: &str) -> String {
+    format!("{} {}\n", comment_marker, GENERATED_FILE_NOTE)
+}
+
+/// Render Rust source declaring each named value as an `Option<usize>`
+/// constant, `None` where it is unset
+pub(crate) fn optional_usize_constants<T: Display>(constants: &[(&str, Option<T>)]) -> String {
+    let mut source = generated_file_header("//");
+    for &(name, ref value) in constants {
+        let value = match *value {
+            Some(ref v) => format!("Some({})", v),
+            None => "None".to_string(),
+        };
+        source.push_str(&format!("pub const {}: Option<usize> = {};\n", name, value));
+    }
+    source
+}
+
+/// Write a generated file, creating any missing parent directories
+pub(crate) fn write_generated_file<P: AsRef<Path>>(
+    path: P,
+    contents: &str,
+) -> Result<(), io::Error> {
+    if let Some(parent) = path.as_ref().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(path)?;
+    file.write_all(contents.as_bytes())
+}
@@ -0,0 +1,187 @@
+/// Naming the final feL4 image from the manifest's `image-name` and
+/// `image-name-pattern`, so artifacts carry their configuration in their name
+use std::path::PathBuf;
+
+use super::ConfigError;
+use build_info::fel4_config_hash;
+use types::*;
+
+/// The image base name when the manifest has no `image-name`
+pub const DEFAULT_IMAGE_NAME: &str = "feL4img";
+
+/// The naming pattern when the manifest has no `image-name-pattern`, which
+/// names the image after its base name alone
+pub const DEFAULT_IMAGE_NAME_PATTERN: &str = "{{name}}";
+
+/// The placeholders an `image-name-pattern` may use
+pub const IMAGE_NAME_PLACEHOLDERS: &[&str] = &["name", "target", "platform", "profile", "hash"];
+
+/// The number of hex digits of `fel4_config_hash` the `{{hash}}` placeholder
+/// is replaced with
+pub const IMAGE_NAME_HASH_LENGTH: usize = 12;
+
+impl Fel4Config {
+    /// The file name of the final image, from the `image-name-pattern` with
+    /// its placeholders filled in.
+    ///
+    /// `{{name}}` is the `image-name`, `{{target}}`, `{{platform}}` and
+    /// `{{profile}}` are the selection, and `{{hash}}` is the start of the
+    /// configuration's `fel4_config_hash`. Both keys are checked again here,
+    /// since the fields may have been set after the manifest was parsed.
+    pub fn image_file_name(&self) -> Result<String, ConfigError> {
+        let name = self.image_name.as_deref().unwrap_or(DEFAULT_IMAGE_NAME);
+        validate_image_name(name, false)
+            .map_err(|reason| ConfigError::InvalidImageNaming("image-name", reason))?;
+        let pattern = self
+            .image_name_pattern
+            .as_deref()
+            .unwrap_or(DEFAULT_IMAGE_NAME_PATTERN);
+        validate_image_name(pattern, true)
+            .map_err(|reason| ConfigError::InvalidImageNaming("image-name-pattern", reason))?;
+        let hash = fel4_config_hash(self);
+        render_image_name_pattern(pattern, |placeholder| match placeholder {
+            "name" => Some(name.to_string()),
+            "target" => Some(self.target.full_name().to_string()),
+            "platform" => Some(self.platform.full_name().to_string()),
+            "profile" => Some(self.build_profile.full_name().to_string()),
+            "hash" => Some(hash[..IMAGE_NAME_HASH_LENGTH].to_string()),
+            _ => None,
+        }).map_err(|reason| ConfigError::InvalidImageNaming("image-name-pattern", reason))
+    }
+
+    /// The path of the final image, relative to the project root, within the
+    /// `output_dir`
+    pub fn image_path(&self) -> Result<PathBuf, ConfigError> {
+        Ok(self.output_dir().join(self.image_file_name()?))
+    }
+}
+
+/// Fill in a pattern's `{{placeholder}}`s, failing with a reason for
+/// unknown or unterminated placeholders
+pub(crate) fn render_image_name_pattern<F>(pattern: &str, value_of: F) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut rendered = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| format!("the placeholder at {} is never closed", after))?;
+        let placeholder = after[..end].trim();
+        let value = value_of(placeholder).ok_or_else(|| {
+            format!(
+                "{{{{{}}}}} is not one of the placeholders {:?}",
+                placeholder, IMAGE_NAME_PLACEHOLDERS
+            )
+        })?;
+        rendered.push_str(&value);
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Check an `image-name` or `image-name-pattern` names a file within the
+/// output directory, returning the reason it doesn't
+pub(crate) fn validate_image_name(name: &str, is_pattern: bool) -> Result<(), String> {
+    let rendered = if is_pattern {
+        render_image_name_pattern(name, |placeholder| {
+            if IMAGE_NAME_PLACEHOLDERS.contains(&placeholder) {
+                Some("x".to_string())
+            } else {
+                None
+            }
+        })?
+    } else {
+        name.to_string()
+    };
+    if rendered.is_empty() || rendered == "." || rendered == ".." {
+        Err(format!("{:?} is not a file name", name))
+    } else if rendered.contains('/') || rendered.contains('\\') {
+        Err(format!("{:?} contains a path separator", name))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn image_names_fill_in_the_pattern() {
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Release,
+        );
+        assert_eq!(Ok("feL4img".to_string()), config.image_file_name());
+
+        config.image_name = Some("sensor-hub".into());
+        config.image_name_pattern = Some("{{name}}-{{ target }}-{{platform}}-{{profile}}".into());
+        assert_eq!(
+            Ok("sensor-hub-armv7-sel4-fel4-sabre-release".to_string()),
+            config.image_file_name()
+        );
+        config.image_name_pattern = Some("{{name}}.{{hash}}".into());
+        let expected = format!("sensor-hub.{}", &fel4_config_hash(&config)[..12]);
+        assert_eq!(Ok(expected.clone()), config.image_file_name());
+        assert_eq!(Ok(config.output_dir().join(expected)), config.image_path());
+    }
+
+    #[test]
+    fn image_names_set_after_parsing_are_checked() {
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Release,
+        );
+        config.image_name = Some("../hub".into());
+        match config.image_file_name() {
+            Err(ConfigError::InvalidImageNaming("image-name", _)) => (),
+            other => panic!("Expected the image name to be rejected, got {:?}", other),
+        }
+        config.image_name = None;
+        config.image_name_pattern = Some("{{name}}-{{version}}".into());
+        match config.image_path() {
+            Err(ConfigError::InvalidImageNaming("image-name-pattern", _)) => (),
+            other => panic!("Expected the pattern to be rejected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn image_naming_is_validated_when_parsed() {
+        let parse = |keys: &str| {
+            parse_full_manifest(get_exemplar_default_toml().replacen(
+                "[fel4]\n",
+                &format!("[fel4]\n{}\n", keys),
+                1,
+            ))
+        };
+        let full = parse("image-name = \"hub\"\nimage-name-pattern = \"{{name}}-{{hash}}\"")
+            .expect("Image naming keys should parse");
+        assert_eq!(Some("hub".to_string()), full.image_name);
+        assert_eq!(
+            Ok(full.clone()),
+            parse_full_manifest(full_manifest_to_toml_string(&full))
+        );
+        let config = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        assert!(config.image_file_name().unwrap().starts_with("hub-"));
+
+        for keys in &[
+            "image-name-pattern = \"{{name}}-{{version}}\"",
+            "image-name-pattern = \"{{name\"",
+            "image-name = \"images/hub\"",
+            "image-name = \"\"",
+        ] {
+            match parse(keys) {
+                Err(ConfigError::InvalidImageNaming(_, _)) => (),
+                other => panic!("Expected {} to be rejected, got {:?}", keys, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+/// A minimal JSON document model, for the machine-readable outputs that
+/// tooling outside of Rust consumes
+use std::fmt::{Display, Error as FmtError, Formatter, Write};
+
+use types::FlatTomlValue;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members are written in the order given
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn string<S: Into<String>>(s: S) -> Json {
+        Json::String(s.into())
+    }
+
+    pub(crate) fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    fn write_indented(&self, f: &mut Formatter, indent: usize) -> Result<(), FmtError> {
+        let pad = |f: &mut Formatter, n: usize| -> Result<(), FmtError> {
+            for _ in 0..n {
+                f.write_str("  ")?;
+            }
+            Ok(())
+        };
+        match *self {
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Integer(i) => write!(f, "{}", i),
+            Json::Float(x) if x.is_finite() => write!(f, "{:?}", x),
+            Json::Float(_) => f.write_str("null"),
+            Json::String(ref s) => write_quoted(f, s),
+            Json::Array(ref items) if items.is_empty() => f.write_str("[]"),
+            Json::Array(ref items) => {
+                f.write_str("[\n")?;
+                for (i, item) in items.iter().enumerate() {
+                    pad(f, indent + 1)?;
+                    item.write_indented(f, indent + 1)?;
+                    f.write_str(if i + 1 < items.len() { ",\n" } else { "\n" })?;
+                }
+                pad(f, indent)?;
+                f.write_str("]")
+            }
+            Json::Object(ref members) if members.is_empty() => f.write_str("{}"),
+            Json::Object(ref members) => {
+                f.write_str("{\n")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    pad(f, indent + 1)?;
+                    write_quoted(f, key)?;
+                    f.write_str(": ")?;
+                    value.write_indented(f, indent + 1)?;
+                    f.write_str(if i + 1 < members.len() { ",\n" } else { "\n" })?;
+                }
+                pad(f, indent)?;
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Datetimes have no JSON equivalent and are written as strings
+impl<'a> From<&'a FlatTomlValue> for Json {
+    fn from(value: &'a FlatTomlValue) -> Self {
+        match *value {
+            FlatTomlValue::Boolean(b) => Json::Bool(b),
+            FlatTomlValue::Integer(i) => Json::Integer(i),
+            FlatTomlValue::Float(f) => Json::Float(f),
+            FlatTomlValue::String(ref s) => Json::String(s.clone()),
+            FlatTomlValue::Datetime(ref d) => Json::String(d.to_string()),
+        }
+    }
+}
+
+/// Pretty-printed with two space indentation
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        self.write_indented(f, 0)
+    }
+}
+
+fn write_quoted(f: &mut Formatter, s: &str) -> Result<(), FmtError> {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_is_escaped_and_indented() {
+        let doc = Json::object(vec![
+            ("name", Json::string("say \"hi\"\n")),
+            (
+                "items",
+                Json::Array(vec![Json::Integer(1), Json::Bool(false)]),
+            ),
+            ("empty", Json::Object(vec![])),
+            ("value", Json::from(&FlatTomlValue::Float(0.5))),
+        ]);
+        assert_eq!(
+            "{\n  \"name\": \"say \\\"hi\\\"\\n\",\n  \"items\": [\n    1,\n    false\n  ],\n  \"empty\": {},\n  \"value\": 0.5\n}",
+            doc.to_string()
+        );
+    }
+}
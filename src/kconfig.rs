@@ -0,0 +1,196 @@
+/// Conversion between fel4 property sets and Kconfig-style `.config` files
+use std::collections::HashMap;
+
+use super::{ConfigError, ALL_PROPERTIES_WHITELIST};
+use generated_config::config_macro_name;
+use types::*;
+
+/// The properties recovered from a `.config` file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KconfigImport {
+    /// Whitelisted fel4 properties, keyed by their fel4 names
+    pub properties: HashMap<String, FlatTomlValue>,
+    /// `CONFIG_` symbols that don't correspond to any whitelisted property,
+    /// in file order
+    pub unrecognized: Vec<String>,
+}
+
+/// Render properties as the lines of a Kconfig `.config` file, sorted by
+/// symbol name.
+///
+/// Symbols are named as seL4 names the equivalent C macros. Booleans become
+/// `=y` or the `# CONFIG_X is not set` comment, integers and floats are
+/// written bare, and strings and datetimes are quoted.
+pub fn properties_to_kconfig(properties: &HashMap<String, FlatTomlValue>) -> String {
+    let mut lines: Vec<(String, String)> = properties
+        .iter()
+        .map(|(name, value)| {
+            let symbol = config_macro_name(name);
+            let line = match *value {
+                FlatTomlValue::Boolean(true) => format!("{}=y", symbol),
+                FlatTomlValue::Boolean(false) => format!("# {} is not set", symbol),
+                FlatTomlValue::Integer(i) => format!("{}={}", symbol, i),
+                FlatTomlValue::Float(f) => format!("{}={}", symbol, f),
+                FlatTomlValue::String(ref s) => format!("{}={}", symbol, quote(s)),
+                FlatTomlValue::Datetime(ref d) => format!("{}={}", symbol, quote(&d.to_string())),
+            };
+            (symbol, line)
+        })
+        .collect();
+    lines.sort();
+    lines.into_iter().fold(String::new(), |mut out, (_, line)| {
+        out.push_str(&line);
+        out.push('\n');
+        out
+    })
+}
+
+/// Parse the contents of a Kconfig `.config` file into fel4 properties.
+///
+/// `=y` and `=n` become booleans, as do `# CONFIG_X is not set` comments.
+/// Quoted values become strings, and bare values become integers where they
+/// parse as decimal or hexadecimal numbers. Tristate `=m` values have no fel4
+/// equivalent and are rejected along with any other malformed line.
+pub fn kconfig_to_properties(text: &str) -> Result<KconfigImport, ConfigError> {
+    let symbols: HashMap<String, &str> = ALL_PROPERTIES_WHITELIST
+        .iter()
+        .map(|name| (config_macro_name(name), *name))
+        .collect();
+    let mut import = KconfigImport::default();
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = raw_line.trim();
+        let invalid = || ConfigError::InvalidKconfigLine(index + 1, raw_line.to_string());
+        let (symbol, value) = if line.is_empty() {
+            continue;
+        } else if let Some(comment) = line.strip_prefix('#') {
+            match comment.trim().strip_suffix(" is not set") {
+                Some(symbol) if symbol.starts_with("CONFIG_") => {
+                    (symbol.trim(), FlatTomlValue::Boolean(false))
+                }
+                _ => continue,
+            }
+        } else {
+            let mut parts = line.splitn(2, '=');
+            let symbol = parts.next().unwrap_or("").trim();
+            let raw_value = parts.next().ok_or_else(invalid)?.trim();
+            if !symbol.starts_with("CONFIG_") {
+                return Err(invalid());
+            }
+            (symbol, parse_kconfig_value(raw_value).ok_or_else(invalid)?)
+        };
+        match symbols.get(symbol) {
+            Some(name) => {
+                import.properties.insert(name.to_string(), value);
+            }
+            None => import.unrecognized.push(symbol.to_string()),
+        }
+    }
+    Ok(import)
+}
+
+fn parse_kconfig_value(raw: &str) -> Option<FlatTomlValue> {
+    match raw {
+        "y" => return Some(FlatTomlValue::Boolean(true)),
+        "n" => return Some(FlatTomlValue::Boolean(false)),
+        "m" | "" => return None,
+        _ => {}
+    }
+    if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+        return Some(FlatTomlValue::String(unquote(&raw[1..raw.len() - 1])));
+    }
+    let integer = match raw.strip_prefix("0x").or_else(|| raw.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => raw.parse::<i64>().ok(),
+    };
+    Some(match integer {
+        Some(i) => FlatTomlValue::Integer(i),
+        None => FlatTomlValue::String(raw.to_string()),
+    })
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn unquote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(pairs: &[(&str, FlatTomlValue)]) -> HashMap<String, FlatTomlValue> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn properties_export_as_sorted_kconfig_lines() {
+        let p = props(&[
+            ("KernelPrinting", FlatTomlValue::Boolean(true)),
+            ("KernelNumPriorities", FlatTomlValue::Integer(256)),
+            ("KernelVerificationBuild", FlatTomlValue::Boolean(false)),
+            ("KernelOptimisation", FlatTomlValue::String("-O2".into())),
+        ]);
+        assert_eq!(
+            "CONFIG_NUM_PRIORITIES=256\n\
+             CONFIG_OPTIMISATION=\"-O2\"\n\
+             CONFIG_PRINTING=y\n\
+             # CONFIG_VERIFICATION_BUILD is not set\n",
+            properties_to_kconfig(&p)
+        );
+    }
+
+    #[test]
+    fn kconfig_round_trips_whitelisted_properties() {
+        let p = props(&[
+            ("KernelPrinting", FlatTomlValue::Boolean(true)),
+            ("KernelNumPriorities", FlatTomlValue::Integer(256)),
+            ("KernelVerificationBuild", FlatTomlValue::Boolean(false)),
+            (
+                "KernelX86MicroArch",
+                FlatTomlValue::String("nehalem".into()),
+            ),
+        ]);
+        let import = kconfig_to_properties(&properties_to_kconfig(&p))
+            .expect("Exported kconfig should import");
+        assert_eq!(p, import.properties);
+        assert!(import.unrecognized.is_empty());
+    }
+
+    #[test]
+    fn kconfig_import_reports_unrecognized_and_malformed_lines() {
+        let import = kconfig_to_properties(
+            "# Generated file\nCONFIG_PRINTING=y\nCONFIG_SOMETHING_ELSE=0x10\n\n# CONFIG_NOT_OURS is not set\n",
+        ).expect("Should import");
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(true)),
+            import.properties.get("KernelPrinting")
+        );
+        assert_eq!(
+            vec![
+                "CONFIG_SOMETHING_ELSE".to_string(),
+                "CONFIG_NOT_OURS".to_string()
+            ],
+            import.unrecognized
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidKconfigLine(
+                2,
+                "CONFIG_PRINTING=m".into()
+            )),
+            kconfig_to_properties("CONFIG_DEBUG_BUILD=y\nCONFIG_PRINTING=m")
+        );
+    }
+}
@@ -0,0 +1,379 @@
+/// Acquisition of the seL4 kernel source described by a `[fel4.kernel-source]`
+/// table, for projects that don't vendor the kernel themselves
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use digest::sha256_hex;
+use known_properties::{compare_versions, parse_kernel_version};
+use types::*;
+
+/// The directory name the kernel source is placed under, matching the
+/// `deps/seL4_kernel` location assumed by the CMake integration
+pub const KERNEL_DIRECTORY_NAME: &str = "seL4_kernel";
+
+/// The fewest hex digits a `kernel-commit` may give, git's default length
+/// for abbreviated commits, so a short prefix can't match any checkout
+pub const MIN_KERNEL_COMMIT_PREFIX_LENGTH: usize = 7;
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum KernelSourceError {
+    #[fail(
+        display = "The kernel source is not present at {}, and the fel4 manifest has no [fel4.kernel-source] table describing where to get it",
+        _0
+    )]
+    NoKernelSource(String),
+    #[fail(display = "Running `{}` failed: {}", _0, _1)]
+    CommandFailed(String, String),
+    #[fail(
+        display = "The downloaded kernel tarball has the sha256 digest {}, but {} was expected",
+        _1,
+        _0
+    )]
+    ChecksumMismatch(String, String),
+    #[fail(display = "Unable to prepare {}: {}", _0, _1)]
+    Io(String, String),
+}
+
+/// Ensure the kernel source is present at `${cache_dir}/seL4_kernel`,
+/// fetching it as described by the configuration's kernel source if the
+/// directory is missing, and return its path.
+///
+/// An existing directory is used as-is, whatever its contents. Git sources
+/// are cloned and checked out at the requested tag or revision with `git`;
+/// tarballs are downloaded with `curl`, verified against their sha256 digest,
+/// and unpacked with `tar`. The source is staged beside the destination and
+/// only moved into place once complete. URLs are passed after `--`, and
+/// manifest parsing refuses URLs and revisions that look like options.
+pub fn fetch_kernel_source<P: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    cache_dir: P,
+) -> Result<PathBuf, KernelSourceError> {
+    let cache_dir = cache_dir.as_ref();
+    let destination = cache_dir.join(KERNEL_DIRECTORY_NAME);
+    if destination.exists() {
+        return Ok(destination);
+    }
+    let source = fel4_config
+        .kernel_source
+        .as_ref()
+        .ok_or_else(|| KernelSourceError::NoKernelSource(destination.display().to_string()))?;
+
+    fs::create_dir_all(cache_dir).map_err(|e| io_error(cache_dir, &e))?;
+    let staging = cache_dir.join(format!(".{}.partial", KERNEL_DIRECTORY_NAME));
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| io_error(&staging, &e))?;
+    }
+
+    match *source {
+        KernelSource::Git { ref url, ref rev } => {
+            run(Command::new("git")
+                .arg("clone")
+                .arg("--quiet")
+                .arg("--")
+                .arg(url)
+                .arg(&staging))?;
+            if let Some(rev) = rev.as_ref() {
+                run(Command::new("git")
+                    .arg("-C")
+                    .arg(&staging)
+                    .args(["checkout", "--quiet", "--detach"])
+                    .arg(rev)
+                    .arg("--"))?;
+            }
+        }
+        KernelSource::Tarball {
+            ref url,
+            ref sha256,
+        } => {
+            let archive = cache_dir.join(format!(".{}.tar.gz", KERNEL_DIRECTORY_NAME));
+            run(Command::new("curl")
+                .args(["--fail", "--silent", "--show-error", "--location"])
+                .arg("--output")
+                .arg(&archive)
+                .arg("--")
+                .arg(url))?;
+            let found = file_sha256(&archive)?;
+            if found != *sha256 {
+                let _ = fs::remove_file(&archive);
+                return Err(KernelSourceError::ChecksumMismatch(sha256.clone(), found));
+            }
+            fs::create_dir_all(&staging).map_err(|e| io_error(&staging, &e))?;
+            // Release tarballs wrap the source in a single versioned directory
+            run(Command::new("tar")
+                .arg("-xzf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(&staging)
+                .arg("--strip-components=1"))?;
+            fs::remove_file(&archive).map_err(|e| io_error(&archive, &e))?;
+        }
+    }
+
+    fs::rename(&staging, &destination).map_err(|e| io_error(&destination, &e))?;
+    Ok(destination)
+}
+
+/// The identity of a kernel source checkout
+#[derive(Clone, Debug, PartialEq)]
+pub struct KernelSourceVersion {
+    /// The release version, from the kernel's `VERSION` file or CMake project
+    pub version: String,
+    /// The checked out git commit, read only when the manifest declares a
+    /// `kernel-commit` to compare against
+    pub commit: Option<String>,
+}
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum KernelVerificationError {
+    #[fail(
+        display = "Unable to determine the kernel version at {}; neither a VERSION file nor a CMake project version was found",
+        _0
+    )]
+    UnknownVersion(String),
+    #[fail(
+        display = "The fel4 manifest declares kernel-version {}, but the kernel source at {} is version {}",
+        _0,
+        _2,
+        _1
+    )]
+    VersionMismatch(String, String, String),
+    #[fail(
+        display = "The fel4 manifest declares kernel-commit {}, but the kernel source at {} is at commit {}",
+        _0,
+        _2,
+        _1
+    )]
+    CommitMismatch(String, String, String),
+    #[fail(
+        display = "Unable to determine the git commit of the kernel source at {}: {}",
+        _0,
+        _1
+    )]
+    UnknownCommit(String, String),
+}
+
+/// Check that the kernel source at `kernel_path` is the one the manifest's
+/// `kernel-version` and `kernel-commit` declare, returning what was found.
+///
+/// The version is taken from the kernel's `VERSION` file, falling back to
+/// the `VERSION` given to `project()` in its CMakeLists.txt. Versions are
+/// compared as `KernelVersionRange` compares them, so `10.0` matches
+/// `10.0.0`. A declared commit may be abbreviated, and is compared against `git rev-parse HEAD`.
+pub fn verify_kernel_source<P: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    kernel_path: P,
+) -> Result<KernelSourceVersion, KernelVerificationError> {
+    let kernel_path = kernel_path.as_ref();
+    let location = kernel_path.display().to_string();
+    let version = read_kernel_version(kernel_path)
+        .ok_or_else(|| KernelVerificationError::UnknownVersion(location.clone()))?;
+    if let Some(ref expected) = fel4_config.kernel_version {
+        let matches = match (parse_kernel_version(expected), parse_kernel_version(&version)) {
+            (Some(e), Some(v)) => compare_versions(&e, &v) == Ordering::Equal,
+            _ => *expected == version,
+        };
+        if !matches {
+            return Err(KernelVerificationError::VersionMismatch(
+                expected.clone(),
+                version,
+                location,
+            ));
+        }
+    }
+    let commit = match fel4_config.kernel_commit {
+        None => None,
+        Some(ref expected) => {
+            let found = read_kernel_commit(kernel_path)
+                .map_err(|e| KernelVerificationError::UnknownCommit(location.clone(), e))?;
+            if !found.starts_with(&expected.to_ascii_lowercase()) {
+                return Err(KernelVerificationError::CommitMismatch(
+                    expected.clone(),
+                    found,
+                    location,
+                ));
+            }
+            Some(found)
+        }
+    };
+    Ok(KernelSourceVersion { version, commit })
+}
+
+fn read_kernel_version(kernel_path: &Path) -> Option<String> {
+    let read = |name: &str| fs::read_to_string(kernel_path.join(name)).ok();
+    if let Some(v) = read("VERSION") {
+        let v = v.trim();
+        if !v.is_empty() {
+            return Some(v.to_string());
+        }
+    }
+    let cmake_lists = read("CMakeLists.txt")?;
+    cmake_project_version(&cmake_lists)
+}
+
+/// The `VERSION` argument of the first `project()` command in a CMake file
+fn cmake_project_version(cmake_lists: &str) -> Option<String> {
+    let lower = cmake_lists.to_ascii_lowercase();
+    let start = lower.find("project(")? + "project(".len();
+    let end = start + lower[start..].find(')')?;
+    let mut words = cmake_lists[start..end].split_whitespace();
+    words
+        .by_ref()
+        .find(|w| *w == "VERSION")
+        .and_then(|_| words.next())
+        .map(str::to_string)
+}
+
+fn read_kernel_commit(kernel_path: &Path) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(kernel_path)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn run(command: &mut Command) -> Result<(), KernelSourceError> {
+    let description = format!("{:?}", command);
+    let output = command
+        .output()
+        .map_err(|e| KernelSourceError::CommandFailed(description.clone(), e.to_string()))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(KernelSourceError::CommandFailed(
+            description,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+fn file_sha256(path: &Path) -> Result<String, KernelSourceError> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut bytes))
+        .map_err(|e| io_error(path, &e))?;
+    Ok(sha256_hex(&bytes))
+}
+
+fn io_error(path: &Path, error: &::std::io::Error) -> KernelSourceError {
+    KernelSourceError::Io(path.display().to_string(), error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    fn exemplar_config() -> Fel4Config {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution")
+    }
+
+    #[test]
+    fn existing_kernel_directory_is_used_as_is() {
+        let dir = ::tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(KERNEL_DIRECTORY_NAME)).unwrap();
+        assert_eq!(
+            Ok(dir.path().join(KERNEL_DIRECTORY_NAME)),
+            fetch_kernel_source(&exemplar_config(), dir.path())
+        );
+    }
+
+    #[test]
+    fn missing_kernel_without_source_is_reported() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let destination = dir.path().join(KERNEL_DIRECTORY_NAME);
+        assert_eq!(
+            Err(KernelSourceError::NoKernelSource(
+                destination.display().to_string()
+            )),
+            fetch_kernel_source(&exemplar_config(), dir.path())
+        );
+    }
+
+    #[test]
+    fn kernel_version_is_read_and_compared() {
+        let dir = ::tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("VERSION"), "10.0.0\n").unwrap();
+        let mut config = exemplar_config();
+        config.kernel_version = Some("10.0.0".into());
+        assert_eq!(
+            Ok(KernelSourceVersion {
+                version: "10.0.0".into(),
+                commit: None,
+            }),
+            verify_kernel_source(&config, dir.path())
+        );
+        config.kernel_version = Some("10.0".into());
+        assert!(verify_kernel_source(&config, dir.path()).is_ok());
+        config.kernel_version = Some("9.0.1".into());
+        assert_eq!(
+            Err(KernelVerificationError::VersionMismatch(
+                "9.0.1".into(),
+                "10.0.0".into(),
+                dir.path().display().to_string()
+            )),
+            verify_kernel_source(&config, dir.path())
+        );
+    }
+
+    #[test]
+    fn kernel_version_falls_back_to_cmake_project() {
+        assert_eq!(
+            Some("10.1.1".to_string()),
+            cmake_project_version(
+                "cmake_minimum_required(VERSION 3.7.2)\nproject(seL4 VERSION 10.1.1 LANGUAGES C ASM)"
+            )
+        );
+        assert_eq!(None, cmake_project_version("project(seL4 C ASM)"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tarball_sources_are_verified_and_unpacked() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let release = dir.path().join("seL4-10.0.0");
+        fs::create_dir(&release).unwrap();
+        fs::write(release.join("VERSION"), "10.0.0\n").unwrap();
+        let archive = dir.path().join("seL4-10.0.0.tar.gz");
+        run(Command::new("tar")
+            .arg("-czf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(dir.path())
+            .arg("seL4-10.0.0"))
+        .expect("Should be able to create a test tarball");
+        let url = format!("file://{}", archive.display());
+        let cache = dir.path().join("deps");
+
+        let mut config = exemplar_config();
+        config.kernel_source = Some(KernelSource::Tarball {
+            url: url.clone(),
+            sha256: "0".repeat(64),
+        });
+        match fetch_kernel_source(&config, &cache) {
+            Err(KernelSourceError::ChecksumMismatch(_, _)) => {}
+            other => panic!("Expected a checksum mismatch, got {:?}", other),
+        }
+        assert!(!cache.join(KERNEL_DIRECTORY_NAME).exists());
+
+        config.kernel_source = Some(KernelSource::Tarball {
+            url,
+            sha256: file_sha256(&archive).unwrap(),
+        });
+        let kernel = fetch_kernel_source(&config, &cache).expect("Should fetch the tarball");
+        assert_eq!(cache.join(KERNEL_DIRECTORY_NAME), kernel);
+        assert!(kernel.join("VERSION").is_file());
+    }
+}
@@ -0,0 +1,369 @@
+/// The registry of properties fel4-config recognizes, with the value type,
+/// targets, and kernel versions each applies to
+use std::cmp::Ordering;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use super::ConfigError;
+use types::*;
+
+const ALL_TARGETS: &[SupportedTarget] = &[
+    SupportedTarget::X8664Sel4Fel4,
+    SupportedTarget::Armv7Sel4Fel4,
+    SupportedTarget::Aarch64Sel4Fel4,
+];
+const X86_TARGETS: &[SupportedTarget] = &[SupportedTarget::X8664Sel4Fel4];
+const ARM_TARGETS: &[SupportedTarget] = &[
+    SupportedTarget::Armv7Sel4Fel4,
+    SupportedTarget::Aarch64Sel4Fel4,
+];
+const ARMV7_TARGETS: &[SupportedTarget] = &[SupportedTarget::Armv7Sel4Fel4];
+const AARCH64_TARGETS: &[SupportedTarget] = &[SupportedTarget::Aarch64Sel4Fel4];
+
+/// Defines `KnownProperty` and the whitelist of property names from one
+/// table, so that the two can't drift apart
+macro_rules! known_properties {
+    ($($name:ident: $value_type:ident, $targets:ident;)*) => {
+        /// A property on the whitelist, named as it is in the manifest.
+        ///
+        /// Deliberately exhaustive, so that code matching on it is revisited
+        /// whenever the whitelist changes.
+        #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+        pub enum KnownProperty {
+            $($name,)*
+        }
+
+        impl KnownProperty {
+            pub fn full_name(&self) -> &'static str {
+                match *self {
+                    $(KnownProperty::$name => stringify!($name),)*
+                }
+            }
+
+            /// Every known property, in whitelist order
+            pub fn properties() -> Vec<KnownProperty> {
+                vec![$(KnownProperty::$name,)*]
+            }
+
+            /// The type of value the kernel build expects for the property
+            pub fn value_type(&self) -> FlatTomlType {
+                match *self {
+                    $(KnownProperty::$name => FlatTomlType::$value_type,)*
+                }
+            }
+
+            /// The targets whose kernel builds have the property
+            pub fn targets(&self) -> &'static [SupportedTarget] {
+                match *self {
+                    $(KnownProperty::$name => $targets,)*
+                }
+            }
+        }
+
+        pub(crate) const ALL_PROPERTIES_WHITELIST: &[&str] = &[$(stringify!($name),)*];
+    };
+}
+
+known_properties! {
+    BuildWithCommonSimulationSettings: Boolean, ALL_TARGETS;
+    KernelOptimisation: String, ALL_TARGETS;
+    KernelVerificationBuild: Boolean, ALL_TARGETS;
+    KernelBenchmarks: String, ALL_TARGETS;
+    KernelMaxNumTracePoints: Integer, ALL_TARGETS;
+    KernelFastpath: Boolean, ALL_TARGETS;
+    LibSel4FunctionAttributes: String, ALL_TARGETS;
+    KernelNumDomains: Integer, ALL_TARGETS;
+    HardwareDebugAPI: Boolean, ALL_TARGETS;
+    KernelColourPrinting: Boolean, ALL_TARGETS;
+    KernelFWholeProgram: Boolean, ALL_TARGETS;
+    KernelResetChunkBits: Integer, ALL_TARGETS;
+    LibSel4DebugAllocBufferEntries: Integer, ALL_TARGETS;
+    LibSel4DebugFunctionInstrumentation: String, ALL_TARGETS;
+    KernelNumPriorities: Integer, ALL_TARGETS;
+    KernelStackBits: Integer, ALL_TARGETS;
+    KernelTimeSlice: Integer, ALL_TARGETS;
+    KernelTimerTickMS: Integer, ALL_TARGETS;
+    KernelUserStackTraceLength: Integer, ALL_TARGETS;
+    KernelArch: String, ALL_TARGETS;
+    KernelX86Sel4Arch: String, X86_TARGETS;
+    KernelMaxNumNodes: Integer, ALL_TARGETS;
+    KernelRetypeFanOutLimit: Integer, ALL_TARGETS;
+    KernelRootCNodeSizeBits: Integer, ALL_TARGETS;
+    KernelMaxNumBootinfoUntypedCaps: Integer, ALL_TARGETS;
+    KernelSupportPCID: Boolean, X86_TARGETS;
+    KernelCacheLnSz: Integer, X86_TARGETS;
+    KernelDebugDisablePrefetchers: Boolean, X86_TARGETS;
+    KernelExportPMCUser: Boolean, X86_TARGETS;
+    KernelFPU: String, X86_TARGETS;
+    KernelFPUMaxRestoresSinceSwitch: Integer, ALL_TARGETS;
+    KernelFSGSBase: String, X86_TARGETS;
+    KernelHugePage: Boolean, X86_TARGETS;
+    KernelIOMMU: Boolean, X86_TARGETS;
+    KernelIRQController: String, X86_TARGETS;
+    KernelIRQReporting: Boolean, X86_TARGETS;
+    KernelLAPICMode: String, X86_TARGETS;
+    KernelMaxNumIOAPIC: Integer, X86_TARGETS;
+    KernelMaxNumWorkUnitsPerPreemption: Integer, ALL_TARGETS;
+    KernelMultiboot1Header: Boolean, X86_TARGETS;
+    KernelMultiboot2Header: Boolean, X86_TARGETS;
+    KernelMultibootGFXMode: String, X86_TARGETS;
+    KernelSkimWindow: Boolean, X86_TARGETS;
+    KernelSyscall: String, X86_TARGETS;
+    KernelVTX: Boolean, X86_TARGETS;
+    KernelX86DangerousMSR: Boolean, X86_TARGETS;
+    KernelX86IBPBOnContextSwitch: Boolean, X86_TARGETS;
+    KernelX86IBRSMode: String, X86_TARGETS;
+    KernelX86RSBOnContextSwitch: Boolean, X86_TARGETS;
+    KernelXSaveSize: Integer, X86_TARGETS;
+    LinkPageSize: Integer, ALL_TARGETS;
+    UserLinkerGCSections: Boolean, ALL_TARGETS;
+    UserLinkerScript: String, ALL_TARGETS;
+    UserLinkerEntryPoint: String, ALL_TARGETS;
+    UserImageBase: Integer, ALL_TARGETS;
+    KernelX86MicroArch: String, X86_TARGETS;
+    LibPlatSupportX86ConsoleDevice: String, X86_TARGETS;
+    KernelDebugBuild: Boolean, ALL_TARGETS;
+    KernelPrinting: Boolean, ALL_TARGETS;
+    KernelArmSel4Arch: String, ARM_TARGETS;
+    KernelAArch32FPUEnableContextSwitch: Boolean, ARMV7_TARGETS;
+    KernelDebugDisableBranchPrediction: Boolean, ARM_TARGETS;
+    KernelIPCBufferLocation: String, ARM_TARGETS;
+    KernelARMPlatform: String, ARM_TARGETS;
+    ElfloaderImage: String, ARM_TARGETS;
+    ElfloaderMode: String, ARMV7_TARGETS;
+    ElfloaderErrata764369: Boolean, ARMV7_TARGETS;
+    KernelArmEnableA9Prefetcher: Boolean, ARMV7_TARGETS;
+    KernelArmExportPMUUser: Boolean, ARM_TARGETS;
+    KernelDebugDisableL2Cache: Boolean, ARMV7_TARGETS;
+    KernelArmHypervisorSupport: Boolean, ARM_TARGETS;
+    KernelArmExportPCNTUser: Boolean, ARM_TARGETS;
+    KernelArmExportVCNTUser: Boolean, ARM_TARGETS;
+    KernelAArch64SErrorIgnore: Boolean, AARCH64_TARGETS;
+    KernelArmSMMU: Boolean, AARCH64_TARGETS;
+    KernelArmGicV3: Boolean, ARM_TARGETS;
+    LibPlatSupportArmConsoleDevice: String, ARM_TARGETS;
+}
+
+impl KnownProperty {
+    pub fn property_names() -> Vec<String> {
+        KnownProperty::properties()
+            .iter()
+            .map(|p| p.full_name().into())
+            .collect()
+    }
+
+    /// Whether the property exists in the kernel build for the target
+    pub fn applies_to(&self, target: SupportedTarget) -> bool {
+        self.targets().contains(&target)
+    }
+
+    pub fn group(&self) -> PropertyGroup {
+        PropertyGroup::of(self.full_name())
+    }
+
+    /// The seL4 releases whose kernel builds have the property, for options
+    /// that newer kernels added or removed
+    pub fn kernel_versions(&self) -> KernelVersionRange {
+        match *self {
+            KnownProperty::KernelSkimWindow
+            | KnownProperty::KernelX86IBRSMode
+            | KnownProperty::KernelX86IBPBOnContextSwitch
+            | KnownProperty::KernelX86RSBOnContextSwitch => KernelVersionRange {
+                since: Some("9.0.0"),
+                until: None,
+            },
+            KnownProperty::KernelFWholeProgram => KernelVersionRange {
+                since: None,
+                until: Some("12.0.0"),
+            },
+            _ => KernelVersionRange::ANY,
+        }
+    }
+}
+
+/// The seL4 releases from `since` up to but not including `until`, where
+/// either may be open
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KernelVersionRange {
+    pub since: Option<&'static str>,
+    pub until: Option<&'static str>,
+}
+
+impl KernelVersionRange {
+    pub const ANY: KernelVersionRange = KernelVersionRange {
+        since: None,
+        until: None,
+    };
+
+    /// Whether a `kernel-version` such as `10.0.0` is in the range, or None
+    /// if it isn't a dotted version number
+    pub fn contains(&self, kernel_version: &str) -> Option<bool> {
+        let version = parse_kernel_version(kernel_version)?;
+        let at_or_after = |bound: &str| {
+            parse_kernel_version(bound).map(|b| compare_versions(&version, &b) != Ordering::Less)
+        };
+        let after_since = self.since.map_or(Some(true), &at_or_after)?;
+        let before_until = self.until.map_or(Some(false), &at_or_after)?;
+        Some(after_since && !before_until)
+    }
+}
+
+impl Display for KernelVersionRange {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match (self.since, self.until) {
+            (None, None) => f.write_str("in every seL4 release"),
+            (Some(since), None) => write!(f, "in seL4 {} and later", since),
+            (None, Some(until)) => write!(f, "before seL4 {}", until),
+            (Some(since), Some(until)) => write!(f, "from seL4 {} until {}", since, until),
+        }
+    }
+}
+
+/// Fail on the first of the named properties that the declared
+/// `kernel-version` doesn't have, skipping user properties and versions that
+/// aren't dotted numbers
+pub(crate) fn check_kernel_versions<'a, I: IntoIterator<Item = &'a String>>(
+    property_names: I,
+    kernel_version: &str,
+) -> Result<(), ConfigError> {
+    for name in property_names {
+        let range = match name.parse::<KnownProperty>() {
+            Ok(known) => known.kernel_versions(),
+            Err(_) => continue,
+        };
+        if range.contains(kernel_version) == Some(false) {
+            return Err(ConfigError::PropertyOutsideKernelVersion(
+                name.clone(),
+                kernel_version.to_string(),
+                range.to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The numeric components of a version such as `10.0.0`, ignoring a
+/// suffix like `-dev` on the last
+pub(crate) fn parse_kernel_version(version: &str) -> Option<Vec<u64>> {
+    version
+        .trim()
+        .split('.')
+        .map(|component| {
+            let digits: String = component.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse().ok()
+        })
+        .collect()
+}
+
+/// Compare versions, treating missing trailing components as zero so that
+/// `10.0` and `10.0.0` are equal
+pub(crate) fn compare_versions(a: &[u64], b: &[u64]) -> Ordering {
+    let len = a.len().max(b.len());
+    let component = |v: &[u64], i: usize| v.get(i).cloned().unwrap_or(0);
+    (0..len)
+        .map(|i| component(a, i).cmp(&component(b, i)))
+        .find(|o| *o != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+impl Display for KnownProperty {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for KnownProperty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        KnownProperty::properties()
+            .into_iter()
+            .find(|p| p.full_name() == s)
+            .ok_or_else(|| s.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn known_properties_describe_the_exemplar() {
+        assert_eq!(
+            ALL_PROPERTIES_WHITELIST.len(),
+            KnownProperty::properties().len()
+        );
+        for target in SupportedTarget::targets() {
+            let full = test_helpers::fixture_manifest(
+                target,
+                SupportedPlatform::platforms()
+                    .into_iter()
+                    .find(|&p| is_supported_target_platform_pair(target, p))
+                    .unwrap(),
+            );
+            for profile in BuildProfile::build_profiles() {
+                let config = resolve_fel4_config(&full, &profile).unwrap();
+                for (name, value) in &config.properties {
+                    let known: KnownProperty = name.parse().expect("Resolved names are known");
+                    assert_eq!(known.value_type(), value.value_type(), "{}", name);
+                    assert!(known.applies_to(target), "{} for {}", name, target);
+                }
+            }
+        }
+        assert_eq!(Ok(KnownProperty::KernelPrinting), "KernelPrinting".parse());
+        assert_eq!(
+            Err("KernelPrintin".to_string()),
+            "KernelPrintin".parse::<KnownProperty>()
+        );
+        assert_eq!(
+            PropertyGroup::Elfloader,
+            KnownProperty::ElfloaderMode.group()
+        );
+    }
+
+    #[test]
+    fn aarch64_properties_apply_only_where_the_kernel_has_them() {
+        let aarch64 = SupportedTarget::Aarch64Sel4Fel4;
+        let armv7 = SupportedTarget::Armv7Sel4Fel4;
+        assert!(KnownProperty::KernelAArch64SErrorIgnore.applies_to(aarch64));
+        assert!(!KnownProperty::KernelAArch64SErrorIgnore.applies_to(armv7));
+        assert!(!KnownProperty::KernelAArch32FPUEnableContextSwitch.applies_to(aarch64));
+        assert!(KnownProperty::KernelArmExportPMUUser.applies_to(aarch64));
+        assert!(!KnownProperty::KernelX86Sel4Arch.applies_to(aarch64));
+    }
+
+    #[test]
+    fn kernel_version_ranges_catch_options_the_declared_kernel_lacks() {
+        let range = KnownProperty::KernelFWholeProgram.kernel_versions();
+        assert_eq!(Some(true), range.contains("10.0.0"));
+        assert_eq!(Some(false), range.contains("12.0"));
+        assert_eq!(None, range.contains("master"));
+        assert_eq!(Some(true), KernelVersionRange::ANY.contains("1.0.0-dev"));
+
+        let resolve = |kernel_version: &str| {
+            test_helpers::ManifestFixture::new()
+                .with_property("fel4", "kernel-version", kernel_version)
+                .resolve(&BuildProfile::Debug)
+                .map(|_| ())
+        };
+        assert_eq!(Ok(()), resolve("10.0.0"));
+        assert_eq!(Ok(()), resolve("unreleased"));
+        assert_eq!(
+            Err(ConfigError::PropertyOutsideKernelVersion(
+                "KernelSkimWindow".into(),
+                "8.0.0".into(),
+                "in seL4 9.0.0 and later".into()
+            )),
+            resolve("8.0.0")
+        );
+        assert_eq!(
+            Err(ConfigError::PropertyOutsideKernelVersion(
+                "KernelFWholeProgram".into(),
+                "12.0.0".into(),
+                "before seL4 12.0.0".into()
+            )),
+            resolve("12.0.0")
+        );
+    }
+}
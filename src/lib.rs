@@ -1,35 +1,194 @@
+// The failure derive macro predates the non_local_definitions lint
+#![allow(non_local_definitions)]
 extern crate cmake;
 #[macro_use]
 extern crate failure;
-extern crate multimap;
+#[cfg(any(test, feature = "proptest"))]
+#[macro_use]
+extern crate proptest;
+#[cfg(test)]
+extern crate tempfile;
 extern crate toml;
 use std::borrow::Borrow;
-use std::collections::{HashMap, HashSet};
-use std::env;
+use std::collections::HashMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
 use std::path::{Path, PathBuf};
 
+use known_properties::ALL_PROPERTIES_WHITELIST;
+use locking::check_unlocked;
+
+#[macro_use]
+mod trace;
+
+#[cfg(any(test, feature = "proptest"))]
+mod arbitrary;
+mod audit;
+mod bootinfo;
+mod build_info;
+mod bundles;
+mod cache;
+mod cargo_integration;
+mod check;
+mod cmake_build;
 mod cmake_integration;
+mod conditional;
+mod diagnostics;
+mod diff;
+mod digest;
+mod exemplar;
+mod export;
+mod formula;
+mod freeze;
+mod generated_config;
+mod generated_source;
+mod image_name;
+mod json;
+mod kconfig;
+mod kernel_source;
+mod known_properties;
+mod linker_script;
+mod locking;
 mod manifest;
+mod matrix;
+mod migration;
+mod policy;
+mod redact;
+mod report;
+mod rootserver;
+mod rules;
+mod shared_build;
+mod stats;
+#[cfg(any(test, feature = "test-helpers"))]
+pub mod test_helpers;
+mod toolchain;
 mod types;
+mod variant;
+mod vars;
+mod verified;
+#[cfg(feature = "watch")]
+mod watch;
+#[cfg(feature = "cli")]
+mod wizard;
+mod workspace;
 // TODO - more selective use of types
+pub use audit::*;
+pub use bootinfo::*;
+pub use build_info::*;
+pub use bundles::*;
+pub use cache::*;
+pub use cargo_integration::*;
+pub use check::*;
+pub use cmake_build::*;
 pub use cmake_integration::*;
+pub use conditional::*;
+pub use diagnostics::*;
+pub use diff::*;
+pub use exemplar::*;
+pub use formula::{Formula, FORMULA_FUNCTIONS};
+pub use freeze::*;
+pub use generated_config::*;
+pub use image_name::*;
+pub use kconfig::*;
+pub use kernel_source::*;
+pub use known_properties::{KernelVersionRange, KnownProperty};
+pub use linker_script::*;
+pub use locking::*;
 pub use manifest::*;
+pub use matrix::*;
+pub use migration::*;
+pub use policy::*;
+pub use redact::*;
+pub use rootserver::*;
+pub use rules::*;
+pub use shared_build::*;
+pub use stats::*;
+pub use toolchain::*;
+pub use trace::{set_trace_hook, TraceEvent, TraceHook, TRACE_ENV_VAR};
 pub use types::*;
+pub use variant::*;
+pub use verified::*;
+#[cfg(feature = "watch")]
+pub use watch::*;
+#[cfg(feature = "cli")]
+pub use wizard::*;
+pub use workspace::*;
 
 /// Convenience function for getting a quick-working fel4.toml example
 pub fn get_exemplar_default_toml() -> &'static str {
     include_str!("../examples/exemplar.toml")
 }
 
+/// An example fel4.toml for every supported target and platform pair, each
+/// selecting that pair and resolving for every build profile.
+///
+/// The x86_64 exemplar is `get_exemplar_default_toml`, which also has tables
+/// for the other targets; the rest have only their own target's tables.
+pub fn exemplars() -> impl Iterator<Item = (SupportedTarget, SupportedPlatform, &'static str)> {
+    vec![
+        (
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            get_exemplar_default_toml(),
+        ),
+        (
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            include_str!("../examples/exemplar-armv7-sabre.toml"),
+        ),
+        (
+            SupportedTarget::Aarch64Sel4Fel4,
+            SupportedPlatform::Tx1,
+            include_str!("../examples/exemplar-aarch64-tx1.toml"),
+        ),
+        (
+            SupportedTarget::Aarch64Sel4Fel4,
+            SupportedPlatform::Tx2,
+            include_str!("../examples/exemplar-aarch64-tx2.toml"),
+        ),
+    ].into_iter()
+}
+
 /// All the things that could go wrong when reading fel4 configuration data
 #[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
 pub enum ConfigError {
     #[fail(display = "Unable to read the fel4 manifest file")]
     FileReadFailure,
     #[fail(display = "The fel4 manifest file is unparseable as toml")]
     TomlParseFailure,
+    #[fail(
+        display = "The fel4 manifest file must be UTF-8 encoded, but {}",
+        _0
+    )]
+    InvalidEncoding(String),
     #[fail(display = "The fel4 manifest file is missing the {} table", _0)]
     MissingTable(String),
+    #[fail(
+        display = "The fel4 manifest file is missing the [{}] table for the selected target. Add a [{}] table holding the properties for that target.",
+        _0,
+        _0
+    )]
+    MissingTargetTable(SupportedTarget),
+    #[fail(
+        display = "The fel4 manifest file is missing the [{}.{}] table for the {} build profile. Add a [{}.{}] table, which may be empty if there are no {} specific properties.",
+        _0,
+        _1,
+        _1,
+        _0,
+        _1,
+        _1
+    )]
+    MissingProfileTable(SupportedTarget, BuildProfile),
+    #[fail(
+        display = "The fel4 manifest file is missing the [{}.{}] table for the selected {} platform. Add a [{}.{}] table, which may be empty if there are no {} specific properties.",
+        _0,
+        _1,
+        _1,
+        _0,
+        _1,
+        _1
+    )]
+    MissingPlatformTable(SupportedTarget, SupportedPlatform),
     #[fail(display = "The fel4 manifest file contained an unexpected table or array {}", _0)]
     UnexpectedStructure(String),
     #[fail(display = "The [{}] table requires the {} property, but it is absent.", _0, _1)]
@@ -39,14 +198,105 @@ pub enum ConfigError {
     #[fail(display = "The {} property should be one of {:?}, but is instead {}", _0, _1, _2)]
     InvalidValueOption(&'static str, Vec<String>, String),
     #[fail(
-        display = "The fel4 manifest had a duplicate property {} when resolved to a canonical set",
-        _0
+        display = "The {} target is not supported by this release of fel4-config, which supports {:?}. The manifest may have been written for a newer release.",
+        _0,
+        _1
+    )]
+    UnsupportedTarget(String, Vec<String>),
+    #[fail(
+        display = "The {} platform is not supported by this release of fel4-config, which supports {:?}. The manifest may have been written for a newer release.",
+        _0,
+        _1
     )]
-    DuplicateProperty(String),
+    UnsupportedPlatform(String, Vec<String>),
+    #[fail(display = "The {} property is defined in {} and {}", _0, _1, _2)]
+    DuplicateProperty(String, PropertyDefinition, PropertyDefinition),
     #[fail(display = "The {} property was supplied, but is not on the permitted whitelist", _0)]
     NonWhitelistProperty(String),
+    #[fail(display = "The {} property takes an integer, but is set to a float in {}", _0, _1)]
+    FloatForIntegerProperty(String, PropertyDefinition),
+    #[fail(
+        display = "The {} property is set to a datetime in {}, but no kernel option takes one",
+        _0,
+        _1
+    )]
+    DatetimeProperty(String, PropertyDefinition),
+    #[fail(
+        display = "The {} property is set for simulation in {}, but the deployment is hardware",
+        _0,
+        _1
+    )]
+    SimulationOnlyProperty(String, PropertyDefinition),
     #[fail(display = "The {} target is not a supported combination with the {} platform", _0, _1)]
     TargetPlatformMismatch(SupportedTarget, SupportedPlatform),
+    #[fail(display = "The resolved fel4 properties violate the rule: {}", _0)]
+    RuleViolation(Box<PropertyRule>),
+    #[fail(display = "The {} configuration bundle is inconsistent: {}", _0, _1)]
+    InvalidBundle(&'static str, String),
+    #[fail(display = "The {} property is invalid: {}", _0, _1)]
+    InvalidImageNaming(&'static str, String),
+    #[fail(display = "The [{}] table is invalid: {}", _0, _1)]
+    InvalidRootserverTable(String, String),
+    #[fail(display = "The {} value in [{}] is invalid: {}", _1, _0, _2)]
+    InvalidConditionalValue(String, String, String),
+    #[fail(display = "The {} formula in [{}] is invalid: {}", _1, _0, _2)]
+    InvalidDerivedProperty(String, String, String),
+    #[fail(
+        display = "The {} value in [{}] refers to {{{{{}}}}}, which [vars] doesn't define",
+        _2, _1, _0
+    )]
+    UndefinedVariable(String, String, String),
+    #[fail(display = "The [{}] table is invalid: {}", _0, _1)]
+    InvalidVariant(String, String),
+    #[fail(display = "The manifest has no [variant.{}] table", _0)]
+    UnknownVariant(String),
+    #[fail(display = "The [fel4.kernel-source] table is invalid: {}", _0)]
+    InvalidKernelSource(String),
+    #[fail(display = "The kernel-commit {:?} is invalid: {}", _0, _1)]
+    InvalidKernelCommit(String, String),
+    #[fail(display = "Line {} of the Kconfig file is not a supported setting: {}", _0, _1)]
+    InvalidKconfigLine(usize, String),
+    #[fail(
+        display = "The fel4-version property should be a positive integer, but is instead {}",
+        _0
+    )]
+    InvalidManifestVersion(String),
+    #[fail(
+        display = "The fel4 manifest is fel4-version {}, an older format. Upgrade it with migrate_manifest.",
+        _0
+    )]
+    OutdatedManifestVersion(i64),
+    #[fail(
+        display = "The fel4 manifest is fel4-version {}, which is newer than this release of fel4-config understands. Upgrade fel4-config to use it.",
+        _0
+    )]
+    UnsupportedManifestVersion(i64),
+    #[fail(
+        display = "The {} property is locked by the manifest, so {} cannot change it",
+        _0, _1
+    )]
+    LockedProperty(String, String),
+    #[fail(display = "The fel4 policy is invalid: {}", _0)]
+    InvalidPolicy(String),
+    #[fail(display = "The fel4 policy requires the {} property, which isn't set", _0)]
+    PolicyRequiredProperty(String),
+    #[fail(display = "The fel4 policy forbids setting the {} property", _0)]
+    PolicyForbiddenProperty(String),
+    #[fail(
+        display = "The fel4 policy allows {} to be only one of {:?}, not {}",
+        _0, _1, _2
+    )]
+    PolicyDisallowedValue(String, Vec<String>, String),
+    #[fail(
+        display = "No generated kernel configuration header was found in {}. Configure the kernel build first.",
+        _0
+    )]
+    MissingGeneratedConfig(String),
+    #[fail(
+        display = "The {} property doesn't exist in seL4 {}, the manifest's kernel-version; it is only available {}",
+        _0, _1, _2
+    )]
+    PropertyOutsideKernelVersion(String, String, String),
 }
 
 /// Returns true if the target and platform are supported to work together
@@ -55,11 +305,115 @@ pub fn is_supported_target_platform_pair(
     target: SupportedTarget,
     platform: SupportedPlatform,
 ) -> bool {
-    match (target, platform) {
+    matches!(
+        (target, platform),
         (SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99)
-        | (SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
-        | (SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1) => true,
-        _ => false,
+            | (SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
+            | (SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1)
+            | (SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx2)
+    )
+}
+
+/// Non-fatal observations made while resolving fel4 configuration data
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ConfigWarning {
+    /// The build profile subtable was absent and treated as empty
+    MissingProfileTable(SupportedTarget, BuildProfile),
+    /// The platform subtable was absent and treated as empty
+    MissingPlatformTable(SupportedTarget, SupportedPlatform),
+    /// The manifest uses the deprecated layout of early cargo-fel4 releases,
+    /// with the properties of the selected target in the `[fel4]` table
+    LegacyManifestLayout(SupportedTarget),
+    /// Cargo's build script environment describes a different kind of build
+    /// than the build profile the configuration was resolved for
+    CargoProfileMismatch {
+        build_profile: BuildProfile,
+        /// The cargo environment variables involved, e.g. `OPT_LEVEL=0`
+        cargo_settings: String,
+        reason: String,
+    },
+}
+
+impl Display for ConfigWarning {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let (target, subtable) = match *self {
+            ConfigWarning::MissingProfileTable(t, p) => (t, p.full_name()),
+            ConfigWarning::MissingPlatformTable(t, p) => (t, p.full_name()),
+            ConfigWarning::LegacyManifestLayout(t) => {
+                return write!(
+                    f,
+                    "The fel4 manifest uses the deprecated layout with properties in the [fel4] table. Move them to a [{}] table, or upgrade the manifest with migrate_manifest.",
+                    t
+                )
+            }
+            ConfigWarning::CargoProfileMismatch {
+                build_profile,
+                ref cargo_settings,
+                ref reason,
+            } => {
+                return write!(
+                    f,
+                    "The fel4 configuration was resolved for the {} profile, but cargo set {}: {}",
+                    build_profile, cargo_settings, reason
+                )
+            }
+        };
+        write!(
+            f,
+            "The fel4 manifest has no [{}.{}] table, so no {} specific properties were applied",
+            target, subtable, subtable
+        )
+    }
+}
+
+/// Knobs that adjust how strictly a `FullFel4Manifest` is resolved.
+///
+/// Built up from `ResolutionOptions::new`, e.g.
+/// `ResolutionOptions::new().lenient_subtables(true)`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct ResolutionOptions {
+    /// Treat absent build profile and platform subtables as empty property
+    /// sets, recording a `ConfigWarning` rather than failing resolution
+    pub lenient_subtables: bool,
+    /// A policy the resolved configuration must satisfy, such as the one
+    /// `policy_alongside` finds
+    pub policy: Option<Fel4Policy>,
+    /// Apply the target's `[$TARGET.test]` table, replacing the values the
+    /// other tables set, as for building `cargo fel4 test` runs. Changing a
+    /// locked property this way is refused unless `lock_policy` allows it.
+    pub test: bool,
+    /// Whether layers may replace the values of the manifest's locked
+    /// properties
+    pub lock_policy: LockPolicy,
+}
+
+impl ResolutionOptions {
+    /// The options `resolve_fel4_config` uses: strict about subtables, with
+    /// no policy, without the `[$TARGET.test]` table, and enforcing locks
+    pub fn new() -> ResolutionOptions {
+        ResolutionOptions::default()
+    }
+
+    pub fn lenient_subtables(mut self, lenient_subtables: bool) -> ResolutionOptions {
+        self.lenient_subtables = lenient_subtables;
+        self
+    }
+
+    pub fn policy(mut self, policy: Option<Fel4Policy>) -> ResolutionOptions {
+        self.policy = policy;
+        self
+    }
+
+    pub fn test(mut self, test: bool) -> ResolutionOptions {
+        self.test = test;
+        self
+    }
+
+    pub fn lock_policy(mut self, lock_policy: LockPolicy) -> ResolutionOptions {
+        self.lock_policy = lock_policy;
+        self
     }
 }
 
@@ -70,58 +424,426 @@ pub fn resolve_fel4_config<M: Borrow<FullFel4Manifest>>(
     full: M,
     build_profile: &BuildProfile,
 ) -> Result<Fel4Config, ConfigError> {
-    let selected_target = full.borrow().selected_target;
-    let platform = full.borrow().selected_platform;
+    resolve_fel4_config_with_options(full, build_profile, &ResolutionOptions::default())
+        .map(|(config, _warnings)| config)
+}
+
+/// Resolve and validate a particular Fel4 configuration as
+/// `resolve_fel4_config` does, with adjustable strictness.
+/// Returns any warnings produced along the way alongside the `Fel4Config`.
+pub fn resolve_fel4_config_with_options<M: Borrow<FullFel4Manifest>>(
+    full: M,
+    build_profile: &BuildProfile,
+    options: &ResolutionOptions,
+) -> Result<(Fel4Config, Vec<ConfigWarning>), ConfigError> {
+    let full = full.borrow();
+    resolve_selection(
+        full,
+        full.selected_target,
+        full.selected_platform,
+        build_profile,
+        options,
+    )
+}
+
+/// Resolve and validate the configuration for `cargo fel4 test` runs of the
+/// given `BuildProfile`, which is the one `resolve_fel4_config` resolves with
+/// the selected target's `[$TARGET.test]` table applied over it
+pub fn resolve_fel4_test_config<M: Borrow<FullFel4Manifest>>(
+    full: M,
+    build_profile: &BuildProfile,
+) -> Result<Fel4Config, ConfigError> {
+    let options = ResolutionOptions::new().test(true);
+    resolve_fel4_config_with_options(full, build_profile, &options)
+        .map(|(config, _warnings)| config)
+}
+
+/// Resolve and validate the Fel4 configuration for the given target,
+/// platform, and `BuildProfile`, regardless of which the manifest's `[fel4]`
+/// table selects.
+///
+/// This suits tools that resolve every target a manifest declares, without
+/// cloning the manifest and overwriting its selection.
+pub fn resolve_fel4_config_for(
+    full: &FullFel4Manifest,
+    target: SupportedTarget,
+    platform: SupportedPlatform,
+    build_profile: &BuildProfile,
+) -> Result<Fel4Config, ConfigError> {
+    resolve_selection(
+        full,
+        target,
+        platform,
+        build_profile,
+        &ResolutionOptions::default(),
+    ).map(|(config, _warnings)| config)
+}
+
+/// The part of a selection's configuration that every build profile shares,
+/// from `resolve_common_config`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommonFel4Config {
+    pub target: SupportedTarget,
+    pub platform: SupportedPlatform,
+    /// The properties of the target table and its platform subtable,
+    /// including conditional properties whose value is the same for every
+    /// build profile.
+    ///
+    /// Property rules, bundles, and formulas aren't applied, since what they
+    /// imply depends on the profile's properties as well.
+    pub properties: HashMap<String, FlatTomlValue>,
+    /// The manifest layer each of the `properties` was read from
+    pub provenance: HashMap<String, PropertyLayer>,
+    /// The names of the `properties` in the order the manifest declared them
+    pub property_order: Vec<String>,
+    manifest: FullFel4Manifest,
+}
+
+impl CommonFel4Config {
+    /// Resolve the complete configuration for a build profile, exactly as
+    /// `resolve_fel4_config_for` would for the same selection
+    pub fn specialize(&self, build_profile: &BuildProfile) -> Result<Fel4Config, ConfigError> {
+        resolve_fel4_config_for(&self.manifest, self.target, self.platform, build_profile)
+    }
+}
+
+/// Resolve the build profile independent layers of the manifest's selected
+/// target and platform, for comparing the shared base of the debug and
+/// release configurations with what each profile adds.
+///
+/// Fails where resolving a profile would for the same layers: a missing
+/// target table or platform subtable, a property defined by two layers, or a
+/// property outside the whitelist or of the wrong type.
+pub fn resolve_common_config(full: &FullFel4Manifest) -> Result<CommonFel4Config, ConfigError> {
+    let (target_identity, platform) = (full.selected_target, full.selected_platform);
+    if !is_supported_target_platform_pair(target_identity, platform) {
+        return Err(ConfigError::TargetPlatformMismatch(
+            target_identity,
+            platform,
+        ));
+    }
+    let target = full
+        .targets
+        .get(&target_identity)
+        .ok_or(ConfigError::MissingTargetTable(target_identity))?;
+    let mut properties = HashMap::new();
+    let mut provenance = HashMap::new();
+    let mut property_order = Vec::new();
+    let location = |layer: PropertyLayer| match layer {
+        PropertyLayer::Platform => format!("{}.{}", target_identity, platform),
+        _ => target_identity.to_string(),
+    };
+    let profiles = BuildProfile::build_profiles();
+    let shared_conditional = PropertySet::from_properties(
+        target
+            .conditional_properties
+            .iter()
+            .filter_map(|(name, value)| {
+                let mut values = profiles
+                    .iter()
+                    .map(|&profile| value.value_for(target_identity, platform, profile));
+                let first = values.next()?;
+                match first {
+                    Some(v) if values.all(|other| other == first) => {
+                        Some(FlatTomlProperty::new(name.clone(), v.clone()))
+                    }
+                    _ => None,
+                }
+            }),
+    ).expect("Conditional property names are unique");
+    let platform_properties = target
+        .platform_properties
+        .get(&platform)
+        .ok_or(ConfigError::MissingPlatformTable(target_identity, platform))?;
+    for &(layer, source) in &[
+        (PropertyLayer::Target, &target.direct_properties),
+        (PropertyLayer::Target, &shared_conditional),
+        (PropertyLayer::Platform, platform_properties),
+    ] {
+        add_properties_to_map(
+            &mut properties,
+            &mut provenance,
+            &mut property_order,
+            source,
+            layer,
+            &location,
+        )?;
+    }
+    let kernel_names = properties.keys().filter(|name| !is_user_property(name));
+    if let Err(k) = contains_only_whitelisted_property_names(kernel_names) {
+        return Err(ConfigError::NonWhitelistProperty(k.to_string()));
+    }
+    check_property_values(&properties, &property_order, |name| {
+        location(provenance[name])
+    })?;
+    Ok(CommonFel4Config {
+        target: target_identity,
+        platform,
+        properties,
+        provenance,
+        property_order,
+        manifest: full.clone(),
+    })
+}
+
+pub(crate) fn resolve_selection(
+    full: &FullFel4Manifest,
+    selected_target: SupportedTarget,
+    platform: SupportedPlatform,
+    build_profile: &BuildProfile,
+    options: &ResolutionOptions,
+) -> Result<(Fel4Config, Vec<ConfigWarning>), ConfigError> {
     if !is_supported_target_platform_pair(selected_target, platform) {
         return Err(ConfigError::TargetPlatformMismatch(
             selected_target,
             platform,
         ));
     }
+    fel4_trace!(
+        "resolve",
+        "resolving {} {} {}",
+        selected_target,
+        platform,
+        build_profile
+    );
     let target = full
-        .borrow()
         .targets
         .get(&selected_target)
-        .ok_or_else(|| ConfigError::MissingTable(selected_target.full_name().to_string()))?;
+        .ok_or(ConfigError::MissingTargetTable(selected_target))?;
 
+    let mut warnings = Vec::new();
     let mut properties = HashMap::new();
-    add_properties_to_map(&mut properties, &target.direct_properties)?;
-    let profile_properties = target
-        .build_profile_properties
-        .get_vec(build_profile)
-        .ok_or_else(|| {
-            ConfigError::MissingTable(format!(
-                "{}.{}",
-                selected_target.full_name(),
-                build_profile.full_name()
+    let mut provenance = HashMap::new();
+    let mut property_order = Vec::new();
+    let location = |layer: PropertyLayer| match layer {
+        PropertyLayer::BuildProfile => format!("{}.{}", selected_target, build_profile),
+        PropertyLayer::Platform => format!("{}.{}", selected_target, platform),
+        PropertyLayer::Test => format!("{}.test", selected_target),
+        _ => selected_target.to_string(),
+    };
+    add_properties_to_map(
+        &mut properties,
+        &mut provenance,
+        &mut property_order,
+        &target.direct_properties,
+        PropertyLayer::Target,
+        &location,
+    )?;
+    let conditional = PropertySet::from_properties(
+        target
+            .conditional_properties
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .value_for(selected_target, platform, *build_profile)
+                    .map(|v| FlatTomlProperty::new(name.clone(), v.clone()))
+            }),
+    ).expect("Conditional property names are unique");
+    add_properties_to_map(
+        &mut properties,
+        &mut provenance,
+        &mut property_order,
+        &conditional,
+        PropertyLayer::Target,
+        &location,
+    )?;
+    match target.build_profile_properties.get(build_profile) {
+        Some(profile_properties) => add_properties_to_map(
+            &mut properties,
+            &mut provenance,
+            &mut property_order,
+            profile_properties,
+            PropertyLayer::BuildProfile,
+            &location,
+        )?,
+        None if options.lenient_subtables => warnings.push(ConfigWarning::MissingProfileTable(
+            selected_target,
+            *build_profile,
+        )),
+        None => {
+            return Err(ConfigError::MissingProfileTable(
+                selected_target,
+                *build_profile,
             ))
-        })?;
-    add_properties_to_map(&mut properties, profile_properties)?;
+        }
+    }
 
-    let platform_properties = target
-        .platform_properties
-        .get_vec(&platform)
-        .ok_or_else(|| {
-            ConfigError::MissingTable(format!(
-                "{}.{}",
-                selected_target.full_name(),
-                platform.full_name()
-            ))
-        })?;
-    add_properties_to_map(&mut properties, platform_properties)?;
+    match target.platform_properties.get(&platform) {
+        Some(platform_properties) => add_properties_to_map(
+            &mut properties,
+            &mut provenance,
+            &mut property_order,
+            platform_properties,
+            PropertyLayer::Platform,
+            &location,
+        )?,
+        None if options.lenient_subtables => warnings.push(ConfigWarning::MissingPlatformTable(
+            selected_target,
+            platform,
+        )),
+        None => return Err(ConfigError::MissingPlatformTable(selected_target, platform)),
+    }
 
-    if let Err(k) = contains_only_whitelisted_property_names(properties.keys()) {
+    let mut derived_properties = target.derived_properties.clone();
+    if options.test {
+        for p in &target.test_properties {
+            fel4_trace!("resolve", "applying test {} = {}", p.name, p.value);
+            if properties.get(&p.name) != Some(&p.value) {
+                check_unlocked(
+                    &full.locked_properties,
+                    &p.name,
+                    &location(PropertyLayer::Test),
+                    options.lock_policy,
+                )?;
+            }
+            if properties.insert(p.name.clone(), p.value.clone()).is_none() {
+                property_order.push(p.name.clone());
+            }
+            provenance.insert(p.name.clone(), PropertyLayer::Test);
+            derived_properties.remove(&p.name);
+        }
+    }
+    let derived = formula::evaluate_derived_properties(
+        &derived_properties,
+        &properties,
+        selected_target.full_name(),
+    )?;
+    add_properties_to_map(
+        &mut properties,
+        &mut provenance,
+        &mut property_order,
+        &derived,
+        PropertyLayer::Target,
+        &location,
+    )?;
+    let kernel_names = properties.keys().filter(|name| !is_user_property(name));
+    if let Err(k) = contains_only_whitelisted_property_names(kernel_names) {
         return Err(ConfigError::NonWhitelistProperty(k.to_string()));
     }
+    check_property_values(&properties, &property_order, |name| {
+        location(provenance[name])
+    })?;
+    if let Some(ref kernel_version) = full.kernel_version {
+        known_properties::check_kernel_versions(&property_order, kernel_version)?;
+    }
+    apply_property_rules(&mut properties, &sel4_dependency_rules())?;
+    let toolchain = full
+        .toolchains
+        .get(&selected_target)
+        .map(|toolchain| toolchain_for_platform(toolchain, platform))
+        .unwrap_or_default();
+    bundles::apply_property_bundles(&mut properties, selected_target, platform, &toolchain)?;
+    let mut implied: Vec<String> = properties
+        .keys()
+        .filter(|name| !provenance.contains_key(*name))
+        .cloned()
+        .collect();
+    implied.sort();
+    for name in &implied {
+        provenance.insert(name.clone(), PropertyLayer::Rule);
+    }
+    property_order.extend(implied);
+    if full.deployment == Some(Deployment::Hardware) {
+        check_hardware_properties(&properties, &property_order, |name| {
+            location(provenance[name])
+        })?;
+    }
+    for warning in &warnings {
+        fel4_trace!("resolve", "warning: {}", warning);
+    }
+    fel4_trace!("resolve", "resolved {} properties", properties.len());
 
-    Ok(Fel4Config {
-        artifact_path: full.borrow().artifact_path.clone(),
-        target_specs_path: full.borrow().target_specs_path.clone(),
+    let config = Fel4Config {
+        artifact_path: full.artifact_path.clone(),
+        artifact_layout: full.artifact_layout,
+        target_specs_path: full.target_specs_path.clone(),
         target: selected_target,
-        platform: full.borrow().selected_platform,
+        platform,
         build_profile: *build_profile,
+        cmake_build_type: full.cmake_build_type(build_profile),
         properties,
-    })
+        effective_properties: HashMap::new(),
+        toolchain,
+        rootserver: target.rootserver.clone(),
+        kernel_source: full.kernel_source.clone(),
+        kernel_version: full.kernel_version.clone(),
+        kernel_commit: full.kernel_commit.clone(),
+        image_name: full.image_name.clone(),
+        image_name_pattern: full.image_name_pattern.clone(),
+        source_date_epoch: full.source_date_epoch,
+        provenance,
+        property_order,
+        declared_targets: full.declared_targets(),
+        manifest_path: None,
+        property_scopes: full.property_scopes.clone(),
+        locked_properties: full.locked_properties.clone(),
+    };
+    if let Some(ref policy) = options.policy {
+        policy.check(&config)?;
+    }
+    Ok((config, warnings))
+}
+
+impl Fel4Config {
+    /// Interpret the properties, so that every consumer agrees on what the
+    /// configuration means. Computed on each call, so it follows
+    /// `override_property`, `augment_from_generated_headers`, and direct
+    /// edits.
+    pub fn traits(&self) -> ConfigTraits {
+        ConfigTraits {
+            architecture: self.target.architecture(),
+            word_size: self.target.word_size(),
+            debug: match self.effective_property("KernelDebugBuild") {
+                Some(&FlatTomlValue::Boolean(debug)) => debug,
+                _ => self.build_profile == BuildProfile::Debug,
+            },
+            smp: match self.effective_property("KernelMaxNumNodes") {
+                Some(&FlatTomlValue::Integer(nodes)) => nodes > 1,
+                _ => false,
+            },
+        }
+    }
+
+    pub fn architecture(&self) -> Architecture {
+        self.target.architecture()
+    }
+
+    pub fn is_x86(&self) -> bool {
+        self.architecture() == Architecture::X86
+    }
+
+    pub fn is_arm(&self) -> bool {
+        self.architecture() == Architecture::Arm
+    }
+
+    /// The kernel's word size in bits
+    pub fn word_size(&self) -> u32 {
+        self.target.word_size()
+    }
+
+    pub fn is_debug(&self) -> bool {
+        self.traits().debug
+    }
+
+    pub fn is_smp(&self) -> bool {
+        self.traits().smp
+    }
+}
+
+/// A target's toolchain settings as they apply to one of its platforms
+fn toolchain_for_platform(
+    toolchain: &ToolchainSettings,
+    platform: SupportedPlatform,
+) -> ToolchainSettings {
+    ToolchainSettings {
+        compiler_prefix: toolchain
+            .platform_compiler_prefixes
+            .get(&platform)
+            .or(toolchain.compiler_prefix.as_ref())
+            .cloned(),
+        platform_compiler_prefixes: HashMap::new(),
+        ..toolchain.clone()
+    }
 }
 
 /// Check an iterator to see if any of its contents are not found in the
@@ -134,102 +856,105 @@ where
     I: IntoIterator<Item = T>,
     T: AsRef<str>,
 {
-    let whitelist: HashSet<String> = ALL_PROPERTIES_WHITELIST
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
     for k in iter {
-        if !whitelist.contains(k.as_ref()) {
-            return Err(k.as_ref().to_string());
+        k.as_ref().parse::<KnownProperty>()?;
+    }
+    Ok(())
+}
+
+/// Checks the value types CMake would otherwise accept silently. A float
+/// given for an integer option would be handed over as its decimal text, and
+/// no known property takes a datetime, so either is an error. User
+/// properties are not checked. `table_of` gives the table path a property
+/// was defined in.
+fn check_property_values<F: Fn(&str) -> String>(
+    properties: &HashMap<String, FlatTomlValue>,
+    order: &[String],
+    table_of: F,
+) -> Result<(), ConfigError> {
+    for name in order {
+        let (value, known) = match (properties.get(name), name.parse::<KnownProperty>()) {
+            (Some(value), Ok(known)) => (value, known),
+            _ => continue,
+        };
+        let definition = || PropertyDefinition::new(table_of(name), value);
+        match *value {
+            FlatTomlValue::Float(_) if known.value_type() == FlatTomlType::Integer => {
+                return Err(ConfigError::FloatForIntegerProperty(
+                    name.clone(),
+                    definition(),
+                ))
+            }
+            FlatTomlValue::Datetime(_) => {
+                return Err(ConfigError::DatetimeProperty(name.clone(), definition()))
+            }
+            _ => {}
         }
     }
     Ok(())
 }
 
-fn add_properties_to_map(
+/// Whether a property value only makes sense in a simulator: the common
+/// simulation settings, or a platform or console device named for QEMU
+fn is_simulation_only_setting(name: &str, value: &FlatTomlValue) -> bool {
+    match (name, value) {
+        ("BuildWithCommonSimulationSettings", FlatTomlValue::Boolean(enabled)) => *enabled,
+        ("KernelARMPlatform", FlatTomlValue::String(s))
+        | ("LibPlatSupportX86ConsoleDevice", FlatTomlValue::String(s)) => {
+            s.to_ascii_lowercase().starts_with("qemu")
+        }
+        _ => false,
+    }
+}
+
+/// Rejects the first simulation-only setting of a hardware deployment.
+/// `table_of` gives the table path a property was defined in.
+fn check_hardware_properties<F: Fn(&str) -> String>(
+    properties: &HashMap<String, FlatTomlValue>,
+    order: &[String],
+    table_of: F,
+) -> Result<(), ConfigError> {
+    match order
+        .iter()
+        .find(|name| is_simulation_only_setting(name, &properties[*name]))
+    {
+        Some(name) => Err(ConfigError::SimulationOnlyProperty(
+            name.clone(),
+            PropertyDefinition::new(table_of(name), &properties[name]),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// `location` gives the table path of a layer, for reporting a property that
+/// more than one layer defines
+fn add_properties_to_map<F: Fn(PropertyLayer) -> String>(
     map: &mut HashMap<String, FlatTomlValue>,
-    source: &[FlatTomlProperty],
+    provenance: &mut HashMap<String, PropertyLayer>,
+    order: &mut Vec<String>,
+    source: &PropertySet,
+    layer: PropertyLayer,
+    location: &F,
 ) -> Result<(), ConfigError> {
     for p in source {
-        match map.insert(p.name.clone(), p.value.clone()) {
-            None => {}
-            Some(_) => return Err(ConfigError::DuplicateProperty(p.name.clone())),
+        fel4_trace!("resolve", "applying {} = {}", p.name, p.value);
+        if let Some(earlier) = map.insert(p.name.clone(), p.value.clone()) {
+            return Err(ConfigError::DuplicateProperty(
+                p.name.clone(),
+                PropertyDefinition::new(location(provenance[&p.name]), &earlier),
+                PropertyDefinition::new(location(layer), &p.value),
+            ));
         }
+        provenance.insert(p.name.clone(), layer);
+        order.push(p.name.clone());
     }
     Ok(())
 }
 
-const ALL_PROPERTIES_WHITELIST: &[&str] = &[
-    "BuildWithCommonSimulationSettings",
-    "KernelOptimisation",
-    "KernelVerificationBuild",
-    "KernelBenchmarks",
-    "KernelFastpath",
-    "LibSel4FunctionAttributes",
-    "KernelNumDomains",
-    "HardwareDebugAPI",
-    "KernelColourPrinting",
-    "KernelFWholeProgram",
-    "KernelResetChunkBits",
-    "LibSel4DebugAllocBufferEntries",
-    "LibSel4DebugFunctionInstrumentation",
-    "KernelNumPriorities",
-    "KernelStackBits",
-    "KernelTimeSlice",
-    "KernelTimerTickMS",
-    "KernelUserStackTraceLength",
-    "KernelArch",
-    "KernelX86Sel4Arch",
-    "KernelMaxNumNodes",
-    "KernelRetypeFanOutLimit",
-    "KernelRootCNodeSizeBits",
-    "KernelMaxNumBootinfoUntypedCaps",
-    "KernelSupportPCID",
-    "KernelCacheLnSz",
-    "KernelDebugDisablePrefetchers",
-    "KernelExportPMCUser",
-    "KernelFPU",
-    "KernelFPUMaxRestoresSinceSwitch",
-    "KernelFSGSBase",
-    "KernelHugePage",
-    "KernelIOMMU",
-    "KernelIRQController",
-    "KernelIRQReporting",
-    "KernelLAPICMode",
-    "KernelMaxNumIOAPIC",
-    "KernelMaxNumWorkUnitsPerPreemption",
-    "KernelMultiboot1Header",
-    "KernelMultiboot2Header",
-    "KernelMultibootGFXMode",
-    "KernelSkimWindow",
-    "KernelSyscall",
-    "KernelVTX",
-    "KernelX86DangerousMSR",
-    "KernelX86IBPBOnContextSwitch",
-    "KernelX86IBRSMode",
-    "KernelX86RSBOnContextSwitch",
-    "KernelXSaveSize",
-    "LinkPageSize",
-    "UserLinkerGCSections",
-    "KernelX86MicroArch",
-    "LibPlatSupportX86ConsoleDevice",
-    "KernelDebugBuild",
-    "KernelPrinting",
-    "KernelArmSel4Arch",
-    "KernelAArch32FPUEnableContextSwitch",
-    "KernelDebugDisableBranchPrediction",
-    "KernelIPCBufferLocation",
-    "KernelARMPlatform",
-    "ElfloaderImage",
-    "ElfloaderMode",
-    "ElfloaderErrata764369",
-    "KernelArmEnableA9Prefetcher",
-    "KernelArmExportPMUUser",
-    "KernelDebugDisableL2Cache",
-];
 /// Things that can go wrong when trying to rely on environment variables
 /// to locate the fel4 manifest and its parameterization.
 #[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
 pub enum ManifestDiscoveryError {
     #[fail(display = "Required environment variable {} was absent", _0)]
     MissingEnvVar(String),
@@ -238,15 +963,17 @@ pub enum ManifestDiscoveryError {
         _0
     )]
     InvalidBuildProfile(String),
+    #[fail(display = "{}", _0)]
+    Config(ConfigError),
 }
 
 /// Read environment variables to discover the information necessary to
 /// read and resolve a `Fel4Config`
 pub fn infer_manifest_location_from_env() -> Result<(PathBuf, BuildProfile), ManifestDiscoveryError>
 {
-    let manifest_path = env::var("FEL4_MANIFEST_PATH")
+    let manifest_path = trace::var("FEL4_MANIFEST_PATH")
         .map_err(|_| ManifestDiscoveryError::MissingEnvVar("FEL4_MANIFEST_PATH".to_string()))?;
-    let raw_profile = env::var("PROFILE")
+    let raw_profile = trace::var("PROFILE")
         .map_err(|_| ManifestDiscoveryError::MissingEnvVar("PROFILE".to_string()))?;
     let build_profile: BuildProfile = raw_profile
         .parse()
@@ -254,18 +981,155 @@ pub fn infer_manifest_location_from_env() -> Result<(PathBuf, BuildProfile), Man
     Ok((PathBuf::from(manifest_path), build_profile))
 }
 
-/// Load, parse, and resolve a Fel4Config
+/// Discover, load, and resolve the Fel4Config a build script should use, as
+/// `infer_manifest_location_from_env` and `get_fel4_config` do, along with
+/// the manifest's deprecation warnings, the resolution's warnings, and those
+/// `check_cargo_profile_env` gives for it
+pub fn get_fel4_config_from_env() -> Result<(Fel4Config, Vec<ConfigWarning>), ManifestDiscoveryError>
+{
+    let (manifest_path, build_profile) = infer_manifest_location_from_env()?;
+    load_fel4_config(&manifest_path, &build_profile, false).map_err(ManifestDiscoveryError::Config)
+}
+
+/// Cross check a resolved configuration against the `PROFILE`, `DEBUG`, and
+/// `OPT_LEVEL` environment variables cargo gives build scripts, warning
+/// where they describe a different kind of build, as a misconfigured CI
+/// matrix might
+pub fn check_cargo_profile_env(fel4_config: &Fel4Config) -> Vec<ConfigWarning> {
+    cargo_profile_warnings(fel4_config, |name| trace::var(name).ok())
+}
+
+fn cargo_profile_warnings<F>(fel4_config: &Fel4Config, lookup: F) -> Vec<ConfigWarning>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let profile = lookup("PROFILE");
+    let debug = lookup("DEBUG");
+    let opt_level = lookup("OPT_LEVEL");
+    let build_profile = fel4_config.build_profile;
+    let mismatch = |settings: &[(&str, &Option<String>)], reason: &str| {
+        ConfigWarning::CargoProfileMismatch {
+            build_profile,
+            cargo_settings: settings
+                .iter()
+                .filter_map(|&(name, value)| value.as_ref().map(|v| format!("{}={}", name, v)))
+                .collect::<Vec<_>>()
+                .join(" "),
+            reason: reason.to_string(),
+        }
+    };
+    let kernel_debug_build =
+        fel4_config.properties.get("KernelDebugBuild") == Some(&FlatTomlValue::Boolean(true));
+    let optimized = opt_level.as_ref().map(|o| o != "0");
+
+    let mut warnings = Vec::new();
+    match profile.as_ref().map(|p| p.parse::<BuildProfile>()) {
+        Some(Ok(p)) if p != build_profile => warnings.push(mismatch(
+            &[("PROFILE", &profile)],
+            "cargo is building a different profile",
+        )),
+        _ => (),
+    }
+    match (build_profile, optimized) {
+        (BuildProfile::Release, Some(false)) if kernel_debug_build => warnings.push(mismatch(
+            &[("OPT_LEVEL", &opt_level)],
+            "cargo is not optimizing and KernelDebugBuild is true, which suggests the debug profile was intended",
+        )),
+        (BuildProfile::Release, Some(false)) => warnings.push(mismatch(
+            &[("OPT_LEVEL", &opt_level)],
+            "cargo is not optimizing a release build",
+        )),
+        (BuildProfile::Debug, Some(true))
+            if debug.as_deref() == Some("false") && !kernel_debug_build =>
+        {
+            warnings.push(mismatch(
+                &[("OPT_LEVEL", &opt_level), ("DEBUG", &debug)],
+                "cargo is optimizing without debug information and KernelDebugBuild is not true, which suggests the release profile was intended",
+            ))
+        }
+        _ => (),
+    }
+    warnings
+}
+
+/// Load, parse, and resolve a Fel4Config, enforcing the `POLICY_FILE_NAME`
+/// policy beside the manifest if there is one
 pub fn get_fel4_config<P: AsRef<Path>>(
     fel4_manifest_path: P,
     build_profile: &BuildProfile,
 ) -> Result<Fel4Config, ConfigError> {
-    let full_manifest = get_full_manifest(fel4_manifest_path)?;
-    resolve_fel4_config(full_manifest, build_profile)
+    load_fel4_config(fel4_manifest_path.as_ref(), build_profile, false).map(trace_warnings)
+}
+
+/// Load, parse, and resolve the Fel4Config for `cargo fel4 test` runs, as
+/// `resolve_fel4_test_config` does, enforcing the policy beside the manifest
+/// like `get_fel4_config`
+pub fn get_fel4_test_config<P: AsRef<Path>>(
+    fel4_manifest_path: P,
+    build_profile: &BuildProfile,
+) -> Result<Fel4Config, ConfigError> {
+    load_fel4_config(fel4_manifest_path.as_ref(), build_profile, true).map(trace_warnings)
+}
+
+/// Load a manifest file and resolve it, collecting the warnings of the
+/// manifest, the resolution, and the cargo profile cross check
+fn load_fel4_config(
+    fel4_manifest_path: &Path,
+    build_profile: &BuildProfile,
+    test: bool,
+) -> Result<(Fel4Config, Vec<ConfigWarning>), ConfigError> {
+    let (full_manifest, mut warnings) = get_full_manifest_with_warnings(fel4_manifest_path)?;
+    let options = ResolutionOptions::new()
+        .policy(policy_alongside(fel4_manifest_path)?)
+        .test(test);
+    let (config, resolution_warnings) =
+        resolve_fel4_config_with_options(full_manifest, build_profile, &options)?;
+    warnings.extend(resolution_warnings);
+    warnings.extend(check_cargo_profile_env(&config));
+    let config = Fel4Config {
+        manifest_path: Some(fel4_manifest_path.to_path_buf()),
+        ..config
+    };
+    Ok((config, warnings))
+}
+
+fn trace_warnings((config, warnings): (Fel4Config, Vec<ConfigWarning>)) -> Fel4Config {
+    for warning in warnings {
+        fel4_trace!("config", "warning: {}", warning);
+    }
+    config
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn exemplars_cover_every_supported_pair() {
+        let mut covered = HashSet::new();
+        for (target, platform, text) in exemplars() {
+            assert!(is_supported_target_platform_pair(target, platform));
+            let full = parse_full_manifest(text).expect("Every exemplar should parse");
+            assert_eq!((target, platform), (full.selected_target, full.selected_platform));
+            for profile in BuildProfile::build_profiles() {
+                let (config, warnings) =
+                    resolve_fel4_config_with_options(&full, &profile, &ResolutionOptions::default())
+                        .expect("Every exemplar should resolve");
+                assert!(warnings.is_empty());
+                assert_eq!((target, platform), (config.target, config.platform));
+            }
+            covered.insert((target, platform));
+        }
+        for target in SupportedTarget::targets() {
+            for platform in SupportedPlatform::platforms() {
+                assert_eq!(
+                    is_supported_target_platform_pair(target, platform),
+                    covered.contains(&(target, platform))
+                );
+            }
+        }
+    }
 
     #[test]
     fn infer_manifest_location_from_env_happy_path() {
@@ -276,6 +1140,69 @@ mod tests {
         assert_eq!(BuildProfile::Debug, b);
     }
 
+    #[test]
+    fn optimized_cargo_builds_warn_about_debug_configurations() {
+        // Cargo optimizing without debug information suggests a release
+        // build, which the debug configuration should be warned about
+        let config = test_helpers::ManifestFixture::new()
+            .with_property("x86_64-sel4-fel4.debug", "KernelDebugBuild", false)
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        let lookup = |name: &str| match name {
+            "OPT_LEVEL" => Some("3".to_string()),
+            "DEBUG" => Some("false".to_string()),
+            _ => None,
+        };
+        match cargo_profile_warnings(&config, lookup).as_slice() {
+            [ConfigWarning::CargoProfileMismatch { ref reason, .. }] => {
+                assert!(reason.contains("release profile was intended"))
+            }
+            other => panic!("Expected a profile mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cargo_profile_env_is_cross_checked() {
+        let full = parse_full_manifest(get_exemplar_default_toml()).unwrap();
+        let mut release = resolve_fel4_config(&full, &BuildProfile::Release).unwrap();
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|&&(n, _)| n == name)
+                    .map(|&(_, v)| v.to_string())
+            }
+        };
+        let cargo_release = &[("PROFILE", "release"), ("DEBUG", "false"), ("OPT_LEVEL", "3")];
+        assert!(cargo_profile_warnings(&release, env(cargo_release)).is_empty());
+
+        let cargo_debug = &[("PROFILE", "debug"), ("DEBUG", "true"), ("OPT_LEVEL", "0")];
+        let warnings = cargo_profile_warnings(&release, env(cargo_debug));
+        assert_eq!(2, warnings.len());
+        assert_eq!(
+            "The fel4 configuration was resolved for the release profile, but cargo set PROFILE=debug: cargo is building a different profile",
+            warnings[0].to_string()
+        );
+
+        release
+            .properties
+            .insert("KernelDebugBuild".into(), FlatTomlValue::Boolean(true));
+        match cargo_profile_warnings(&release, env(&[("OPT_LEVEL", "0")])).as_slice() {
+            [ConfigWarning::CargoProfileMismatch {
+                ref cargo_settings,
+                ref reason,
+                ..
+            }] => {
+                assert_eq!("OPT_LEVEL=0", cargo_settings);
+                assert!(reason.contains("KernelDebugBuild is true"));
+            }
+            other => panic!("Expected one mismatch, got {:?}", other),
+        }
+
+        let debug = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        assert!(cargo_profile_warnings(&debug, env(cargo_debug)).is_empty());
+        assert_eq!(1, cargo_profile_warnings(&debug, env(cargo_release)).len());
+    }
+
     #[test]
     fn exemplar_toml_is_fully_valid() {
         let full = parse_full_manifest(get_exemplar_default_toml())
@@ -284,6 +1211,85 @@ mod tests {
             .expect("Should be able to resolve config");
     }
 
+    #[test]
+    fn resolution_records_the_layer_of_each_property() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let config = resolve_fel4_config(full, &BuildProfile::Debug)
+            .expect("Should be able to resolve config");
+        assert_eq!(
+            config.properties.keys().collect::<HashSet<_>>(),
+            config.provenance.keys().collect::<HashSet<_>>()
+        );
+        assert_eq!(
+            Some(&PropertyLayer::Target),
+            config.provenance.get("KernelOptimisation")
+        );
+        assert_eq!(
+            Some(&PropertyLayer::BuildProfile),
+            config.provenance.get("KernelPrinting")
+        );
+        assert_eq!(
+            Some(&PropertyLayer::Platform),
+            config.provenance.get("KernelX86MicroArch")
+        );
+    }
+
+    #[test]
+    fn output_dir_follows_artifact_layout() {
+        let fixture = test_helpers::ManifestFixture::new();
+        let debug = fixture
+            .resolve(&BuildProfile::Debug)
+            .expect("Should be able to resolve config");
+        assert_eq!(ArtifactLayout::PerProfile, debug.artifact_layout);
+        assert_eq!(
+            Path::new("artifacts/x86_64-sel4-fel4/debug"),
+            debug.output_dir()
+        );
+        let flat = fixture
+            .clone()
+            .with_property("fel4", "artifact-layout", "flat")
+            .resolve(&BuildProfile::Release)
+            .expect("Should be able to resolve config");
+        assert_eq!(Path::new("artifacts"), flat.output_dir());
+        assert_eq!(
+            Err(ConfigError::InvalidValueOption(
+                "artifact-layout",
+                ArtifactLayout::layout_names(),
+                "nested".into()
+            )),
+            fixture
+                .with_property("fel4", "artifact-layout", "nested")
+                .parse()
+        );
+    }
+
+    #[test]
+    fn properties_are_grouped() {
+        let config = test_helpers::fixture_config(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Debug,
+        );
+        let grouped: usize = PropertyGroup::groups()
+            .into_iter()
+            .map(|g| config.properties_in_group(g).count())
+            .sum();
+        assert_eq!(config.properties.len(), grouped);
+        let elfloader: Vec<&str> = config
+            .properties_in_group(PropertyGroup::Elfloader)
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            vec!["ElfloaderErrata764369", "ElfloaderImage", "ElfloaderMode"],
+            elfloader
+        );
+        assert_eq!(PropertyGroup::Kernel, PropertyGroup::of("HardwareDebugAPI"));
+        assert_eq!(PropertyGroup::Linker, PropertyGroup::of("UserImageBase"));
+        assert_eq!(PropertyGroup::Build, PropertyGroup::of("UserLinkerGCSections"));
+        assert_eq!(Ok(PropertyGroup::LibSel4), "libsel4".parse());
+    }
+
     #[test]
     fn exemplar_toml_calls_return_identical() {
         let a = get_exemplar_default_toml();
@@ -306,11 +1312,88 @@ mod tests {
             "#,
         ).expect("Should have been able to parse manifest");
         assert_eq!(
-            Err(ConfigError::MissingTable("x86_64-sel4-fel4".into())),
+            Err(ConfigError::MissingTargetTable(
+                SupportedTarget::X8664Sel4Fel4
+            )),
             resolve_fel4_config(manifest, &BuildProfile::Debug)
         );
     }
 
+    #[test]
+    fn missing_subtables_get_caught_in_strict_config_resolution() {
+        let manifest = parse_full_manifest(
+            r#"[fel4]
+            target = "x86_64-sel4-fel4"
+            platform = "pc99"
+            artifact-path = "artifacts/path/nested"
+            target-specs-path = "where/are/rust/targets"
+            [x86_64-sel4-fel4]
+            KernelArch = "x86"
+            [x86_64-sel4-fel4.pc99]
+            KernelX86MicroArch = "nehalem"
+            "#,
+        ).expect("Should have been able to parse manifest");
+        assert_eq!(
+            Err(ConfigError::MissingProfileTable(
+                SupportedTarget::X8664Sel4Fel4,
+                BuildProfile::Debug
+            )),
+            resolve_fel4_config(manifest, &BuildProfile::Debug)
+        );
+    }
+
+    #[test]
+    fn missing_subtables_become_warnings_in_lenient_config_resolution() {
+        let manifest = parse_full_manifest(
+            r#"[fel4]
+            target = "x86_64-sel4-fel4"
+            platform = "pc99"
+            artifact-path = "artifacts/path/nested"
+            target-specs-path = "where/are/rust/targets"
+            [x86_64-sel4-fel4]
+            KernelArch = "x86"
+            "#,
+        ).expect("Should have been able to parse manifest");
+        let options = ResolutionOptions::new().lenient_subtables(true);
+        let (config, warnings) =
+            resolve_fel4_config_with_options(manifest, &BuildProfile::Debug, &options)
+                .expect("Should have been able to leniently resolve config");
+        assert_eq!(1, config.properties.len());
+        assert_eq!(
+            vec![
+                ConfigWarning::MissingProfileTable(
+                    SupportedTarget::X8664Sel4Fel4,
+                    BuildProfile::Debug
+                ),
+                ConfigWarning::MissingPlatformTable(
+                    SupportedTarget::X8664Sel4Fel4,
+                    SupportedPlatform::PC99
+                ),
+            ],
+            warnings
+        );
+    }
+
+    #[test]
+    fn missing_table_errors_suggest_the_table_to_add() {
+        assert_eq!(
+            "The fel4 manifest file is missing the [armv7-sel4-fel4.sabre] table for the selected \
+             sabre platform. Add a [armv7-sel4-fel4.sabre] table, which may be empty if there are \
+             no sabre specific properties.",
+            ConfigError::MissingPlatformTable(
+                SupportedTarget::Armv7Sel4Fel4,
+                SupportedPlatform::Sabre
+            ).to_string()
+        );
+        assert_eq!(
+            "The fel4 manifest file is missing the [x86_64-sel4-fel4.release] table for the \
+             release build profile. Add a [x86_64-sel4-fel4.release] table, which may be empty if \
+             there are no release specific properties.",
+            ConfigError::MissingProfileTable(SupportedTarget::X8664Sel4Fel4, BuildProfile::Release)
+                .to_string()
+        );
+    }
+
     #[test]
     fn duplicate_property_gets_caught_in_config_resolution() {
         let manifest = parse_full_manifest(
@@ -330,12 +1413,46 @@ mod tests {
             KernelX86MicroArch = "nehalem"
             "#,
         ).expect("Should have been able to parse manifest");
+        let error = resolve_fel4_config(manifest, &BuildProfile::Debug).unwrap_err();
         assert_eq!(
-            Err(ConfigError::DuplicateProperty("KernelPrinting".into())),
-            resolve_fel4_config(manifest, &BuildProfile::Debug)
+            "The KernelPrinting property is defined in [x86_64-sel4-fel4] = false and \
+             [x86_64-sel4-fel4.debug] = true",
+            error.to_string()
+        );
+        assert_eq!(
+            ConfigError::DuplicateProperty(
+                "KernelPrinting".into(),
+                PropertyDefinition::new("x86_64-sel4-fel4".into(), &FlatTomlValue::Boolean(false)),
+                PropertyDefinition::new(
+                    "x86_64-sel4-fel4.debug".into(),
+                    &FlatTomlValue::Boolean(true)
+                ),
+            ),
+            error
         );
     }
 
+    #[test]
+    fn property_sets_keep_insertion_order_and_reject_duplicates() {
+        let property = |name: &str, value: i64| {
+            FlatTomlProperty::new(name.to_string(), FlatTomlValue::Integer(value))
+        };
+        let mut set = PropertySet::from_properties(vec![
+            property("KernelNumDomains", 1),
+            property("KernelArch", 2),
+        ]).unwrap();
+        assert_eq!(
+            Err(property("KernelArch", 3)),
+            set.insert(property("KernelArch", 3))
+        );
+        assert_eq!(Some(&FlatTomlValue::Integer(2)), set.get("KernelArch"));
+        assert_eq!(
+            vec!["KernelNumDomains", "KernelArch"],
+            set.iter().map(|p| p.name.as_str()).collect::<Vec<_>>()
+        );
+        assert!(PropertySet::from_properties(vec![property("A", 1), property("A", 1)]).is_err());
+    }
+
     #[test]
     fn non_whitelist_property_gets_caught_in_config_resolution() {
         let manifest = parse_full_manifest(
@@ -363,6 +1480,346 @@ mod tests {
         );
     }
 
+    #[test]
+    fn user_properties_bypass_the_whitelist() {
+        let config = test_helpers::ManifestFixture::new()
+            .with_property("x86_64-sel4-fel4.pc99", "X-AppLogLevel", "verbose")
+            .resolve(&BuildProfile::Debug)
+            .expect("User properties aren't checked against the whitelist");
+        assert_eq!(
+            Some(&FlatTomlValue::String("verbose".into())),
+            config.properties.get("X-AppLogLevel")
+        );
+        assert_eq!(
+            vec!["X-AppLogLevel"],
+            config
+                .properties_in_group(PropertyGroup::User)
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        );
+        assert!(config.kernel_properties().all(|(name, _)| name != "X-AppLogLevel"));
+        assert!(!is_user_property(USER_PROPERTY_PREFIX));
+        assert_eq!(
+            Err(ConfigError::NonWhitelistProperty("XAppLogLevel".into())),
+            test_helpers::ManifestFixture::new()
+                .with_property("x86_64-sel4-fel4", "XAppLogLevel", 1)
+                .resolve(&BuildProfile::Debug)
+        );
+    }
+
+    #[test]
+    fn floats_for_integer_properties_get_caught_in_config_resolution() {
+        let fixture = test_helpers::ManifestFixture::new();
+        assert_eq!(
+            Err(ConfigError::FloatForIntegerProperty(
+                "KernelMaxNumNodes".into(),
+                PropertyDefinition {
+                    table: "x86_64-sel4-fel4".into(),
+                    value: "2.5".into(),
+                }
+            )),
+            fixture
+                .clone()
+                .with_property("x86_64-sel4-fel4", "KernelMaxNumNodes", 2.5)
+                .resolve(&BuildProfile::Debug)
+        );
+        assert!(
+            fixture
+                .with_property("x86_64-sel4-fel4", "X-AppRatio", 2.5)
+                .resolve(&BuildProfile::Debug)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn datetimes_for_known_properties_get_caught_in_config_resolution() {
+        let datetime = "2018-06-01T12:00:00Z"
+            .parse::<toml::value::Datetime>()
+            .unwrap();
+        let with_datetime = |name: &str| {
+            test_helpers::ManifestFixture::new()
+                .with_property("x86_64-sel4-fel4", name, datetime.clone())
+                .resolve(&BuildProfile::Debug)
+        };
+        assert_eq!(
+            Err(ConfigError::DatetimeProperty(
+                "KernelTimerTickMS".into(),
+                PropertyDefinition {
+                    table: "x86_64-sel4-fel4".into(),
+                    value: "2018-06-01T12:00:00Z".into(),
+                }
+            )),
+            with_datetime("KernelTimerTickMS")
+        );
+        assert!(with_datetime("X-AppBuildDate").is_ok());
+    }
+
+    #[test]
+    fn hardware_deployments_reject_simulation_settings() {
+        let hardware =
+            test_helpers::ManifestFixture::new().with_property("fel4", "deployment", "hardware");
+        assert_eq!(
+            Err(ConfigError::SimulationOnlyProperty(
+                "BuildWithCommonSimulationSettings".into(),
+                PropertyDefinition {
+                    table: "x86_64-sel4-fel4".into(),
+                    value: "true".into(),
+                }
+            )),
+            hardware.resolve(&BuildProfile::Release)
+        );
+        let board = hardware.with_property(
+            "x86_64-sel4-fel4",
+            "BuildWithCommonSimulationSettings",
+            false,
+        );
+        assert!(board.resolve(&BuildProfile::Release).is_ok());
+        assert!(matches!(
+            board
+                .with_property("x86_64-sel4-fel4.pc99", "LibPlatSupportX86ConsoleDevice", "qemu")
+                .resolve(&BuildProfile::Release),
+            Err(ConfigError::SimulationOnlyProperty(ref name, _))
+                if name == "LibPlatSupportX86ConsoleDevice"
+        ));
+
+        let simulation =
+            test_helpers::ManifestFixture::new().with_property("fel4", "deployment", "simulation");
+        assert_eq!(
+            Some(Deployment::Simulation),
+            simulation.parse().unwrap().deployment
+        );
+        assert!(simulation.resolve(&BuildProfile::Release).is_ok());
+        assert_eq!(
+            Err(ConfigError::InvalidValueOption(
+                "deployment",
+                Deployment::deployment_names(),
+                "board".into()
+            )),
+            test_helpers::ManifestFixture::new()
+                .with_property("fel4", "deployment", "board")
+                .parse()
+        );
+    }
+
+    #[test]
+    fn resolution_interprets_architecture_word_size_debug_and_smp() {
+        let debug = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        assert!(debug.is_x86() && !debug.is_arm());
+        assert_eq!(64, debug.word_size());
+        assert!(debug.is_debug());
+        assert!(!debug.is_smp());
+
+        let sabre = test_helpers::fixture_config(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Release,
+        );
+        assert_eq!(Architecture::Arm, sabre.architecture());
+        assert_eq!(32, sabre.word_size());
+        assert!(!sabre.is_debug());
+
+        let multicore_release = test_helpers::ManifestFixture::new()
+            .without_property("x86_64-sel4-fel4.release", "KernelDebugBuild")
+            .with_property("x86_64-sel4-fel4", "KernelMaxNumNodes", 4)
+            .resolve(&BuildProfile::Release)
+            .unwrap();
+        assert!(multicore_release.is_smp());
+        assert!(!multicore_release.is_debug());
+
+        let mut overridden = multicore_release.clone();
+        overridden
+            .override_property(
+                "KernelDebugBuild",
+                FlatTomlValue::Boolean(true),
+                LockPolicy::Enforce,
+            )
+            .unwrap();
+        let _ = overridden.properties.remove("KernelMaxNumNodes");
+        assert!(overridden.is_debug());
+        assert!(!overridden.is_smp());
+        let _ = overridden
+            .effective_properties
+            .insert("KernelMaxNumNodes".into(), FlatTomlValue::Integer(2));
+        assert!(overridden.traits().smp);
+    }
+
+    #[test]
+    fn common_configs_hold_the_profile_independent_layers() {
+        let text = get_exemplar_default_toml()
+            .replacen(
+                "KernelMaxNumNodes = 1",
+                "KernelMaxNumNodes = { default = 1, release = 4 }",
+                1,
+            )
+            .replacen(
+                "KernelRetypeFanOutLimit = 256",
+                "KernelRetypeFanOutLimit = { default = 256, pc99 = 128 }",
+                1,
+            );
+        let full = parse_full_manifest(&text).unwrap();
+        let common = resolve_common_config(&full).expect("The common layers should resolve");
+        assert_eq!(
+            Some(&FlatTomlValue::String("x86".into())),
+            common.properties.get("KernelArch")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(128)),
+            common.properties.get("KernelRetypeFanOutLimit")
+        );
+        assert!(!common.properties.contains_key("KernelMaxNumNodes"));
+        assert!(!common.properties.contains_key("KernelDebugBuild"));
+        assert!(common
+            .provenance
+            .values()
+            .all(|layer| *layer != PropertyLayer::BuildProfile));
+        assert_eq!(common.properties.len(), common.property_order.len());
+
+        for profile in BuildProfile::build_profiles() {
+            let specialized = common.specialize(&profile).unwrap();
+            assert_eq!(resolve_fel4_config(&full, &profile).unwrap(), specialized);
+            for (name, value) in &common.properties {
+                assert_eq!(Some(value), specialized.properties.get(name), "{}", name);
+            }
+        }
+
+        let mut missing_platform = full.clone();
+        missing_platform
+            .targets
+            .get_mut(&SupportedTarget::X8664Sel4Fel4)
+            .unwrap()
+            .platform_properties
+            .clear();
+        assert_eq!(
+            Err(ConfigError::MissingPlatformTable(
+                SupportedTarget::X8664Sel4Fel4,
+                SupportedPlatform::PC99
+            )),
+            resolve_common_config(&missing_platform)
+        );
+    }
+
+    #[test]
+    fn selections_sort_in_declaration_order() {
+        let mut targets = SupportedTarget::targets();
+        targets.reverse();
+        targets.sort();
+        assert_eq!(SupportedTarget::targets(), targets);
+        let mut platforms = SupportedPlatform::platforms();
+        platforms.reverse();
+        platforms.sort();
+        assert_eq!(SupportedPlatform::platforms(), platforms);
+        assert!(BuildProfile::Debug < BuildProfile::Release);
+
+        let mut configs: Vec<Fel4Config> = vec![
+            (SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1, BuildProfile::Debug),
+            (SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99, BuildProfile::Release),
+            (SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99, BuildProfile::Debug),
+        ]
+        .into_iter()
+        .map(|(t, p, b)| test_helpers::fixture_config(t, p, b))
+        .collect();
+        configs.sort_by_key(Fel4Config::selection_key);
+        assert_eq!(
+            vec![
+                (SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99, BuildProfile::Debug),
+                (SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99, BuildProfile::Release),
+                (SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1, BuildProfile::Debug),
+            ],
+            configs.iter().map(Fel4Config::selection_key).collect::<Vec<_>>()
+        );
+
+        let mut names = vec!["X-AppLogLevel", "LibSel4FunctionAttributes", "KernelStackBits"];
+        names.sort_by_key(|name| PropertyGroup::sort_key(name));
+        assert_eq!(
+            vec!["KernelStackBits", "LibSel4FunctionAttributes", "X-AppLogLevel"],
+            names
+        );
+    }
+
+    #[test]
+    fn properties_can_be_queried_by_prefix_and_predicate() {
+        let config = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        let x86: Vec<&str> = config
+            .properties_with_prefix("KernelX86")
+            .map(|(name, _)| name)
+            .collect();
+        assert!(x86.contains(&"KernelX86Sel4Arch"));
+        assert_eq!(
+            config
+                .properties
+                .keys()
+                .filter(|name| name.starts_with("KernelX86"))
+                .count(),
+            x86.len()
+        );
+        let in_order: Vec<&str> = config
+            .ordered_properties()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with("KernelX86"))
+            .collect();
+        assert_eq!(in_order, x86);
+        assert_eq!(0, config.properties_with_prefix("Elfloader").count());
+
+        let enabled: Vec<&str> = config
+            .find_property(|_, value| *value == FlatTomlValue::Boolean(true))
+            .map(|(name, _)| name)
+            .collect();
+        assert!(enabled.contains(&"KernelDebugBuild"));
+        assert!(!enabled.contains(&"KernelX86Sel4Arch"));
+    }
+
+    #[test]
+    fn dependency_rule_violation_gets_caught_in_config_resolution() {
+        let manifest = parse_full_manifest(
+            r#"[fel4]
+            target = "x86_64-sel4-fel4"
+            platform = "pc99"
+            artifact-path = "artifacts/path/nested"
+            target-specs-path = "where/are/rust/targets"
+
+            [x86_64-sel4-fel4]
+            KernelBenchmarks = "generic"
+
+            [x86_64-sel4-fel4.release]
+            KernelDebugBuild = false
+
+            [x86_64-sel4-fel4.pc99]
+            KernelX86MicroArch = "nehalem"
+            "#,
+        ).expect("Should have been able to parse manifest");
+        match resolve_fel4_config(manifest, &BuildProfile::Release) {
+            Err(ConfigError::RuleViolation(rule)) => match *rule {
+                PropertyRule::Requires { ref when, .. } => {
+                    assert_eq!("KernelBenchmarks", when.name())
+                }
+                ref other => panic!("Expected a Requires rule, got {:?}", other),
+            },
+            other => panic!("Expected a rule violation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dependency_rules_only_mention_whitelisted_properties() {
+        for rule in sel4_dependency_rules() {
+            let names: Vec<&str> = match rule {
+                PropertyRule::Requires { ref when, ref then } => vec![when.name(), then.name()],
+                PropertyRule::Conflicts { ref when, ref with } => vec![when.name(), with.name()],
+                PropertyRule::Implies {
+                    ref when, ref name, ..
+                } => vec![when.name(), name],
+            };
+            assert_eq!(Ok(()), contains_only_whitelisted_property_names(names));
+        }
+    }
+
     #[test]
     fn mismatched_target_platform_pair_gets_caught_in_conflict_resolution() {
         let manifest = parse_full_manifest(
@@ -390,4 +1847,54 @@ mod tests {
             resolve_fel4_config(manifest, &BuildProfile::Debug)
         );
     }
+
+    #[test]
+    fn test_tables_replace_resolved_values_only_for_tests() {
+        let full = test_helpers::ManifestFixture::new()
+            .with_property("x86_64-sel4-fel4.test", "KernelPrinting", true)
+            .with_property("x86_64-sel4-fel4.test", "X-TestRootTask", "fel4-test-runner")
+            .parse()
+            .unwrap();
+        let release = resolve_fel4_config(&full, &BuildProfile::Release).unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(false)),
+            release.properties.get("KernelPrinting")
+        );
+        assert!(!release.properties.contains_key("X-TestRootTask"));
+
+        let test = resolve_fel4_test_config(&full, &BuildProfile::Release).unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(true)),
+            test.properties.get("KernelPrinting")
+        );
+        assert_eq!(
+            Some(&PropertyLayer::Test),
+            test.provenance.get("X-TestRootTask")
+        );
+        assert_eq!(
+            "x86_64-sel4-fel4.test",
+            PropertyLayer::Test.location(&test)
+        );
+        assert_eq!(
+            Ok(full.clone()),
+            parse_full_manifest(full_manifest_to_toml_string(&full))
+        );
+
+        let mut locked = full.clone();
+        locked.locked_properties = vec!["KernelPrinting".into()];
+        assert_eq!(
+            Err(ConfigError::LockedProperty(
+                "KernelPrinting".into(),
+                "x86_64-sel4-fel4.test".into()
+            )),
+            resolve_fel4_test_config(&locked, &BuildProfile::Release)
+        );
+        assert!(resolve_fel4_test_config(&locked, &BuildProfile::Debug).is_ok());
+        let allowing = ResolutionOptions::new()
+            .test(true)
+            .lock_policy(LockPolicy::AllowLocked);
+        assert!(
+            resolve_fel4_config_with_options(&locked, &BuildProfile::Release, &allowing).is_ok()
+        );
+    }
 }
@@ -0,0 +1,240 @@
+/// Selection and templating of the linker script for the feL4 userland image
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use trace;
+use types::*;
+
+/// The file name the final linker script is written under
+pub const LINKER_SCRIPT_FILE_NAME: &str = "fel4.ld";
+
+/// The template used when the manifest doesn't name one with
+/// `UserLinkerScript`
+pub const DEFAULT_LINKER_SCRIPT_TEMPLATE: &str = "ENTRY({{entry}})
+
+SECTIONS
+{
+    . = {{image_base}};
+
+    .text : ALIGN({{page_size}})
+    {
+        *(.text.start)
+        *(.text .text.*)
+    }
+
+    .rodata : ALIGN({{page_size}})
+    {
+        *(.rodata .rodata.*)
+    }
+
+    .data : ALIGN({{page_size}})
+    {
+        *(.data .data.*)
+    }
+
+    .bss : ALIGN({{page_size}})
+    {
+        *(.bss .bss.*)
+        *(COMMON)
+    }
+}
+";
+
+const DEFAULT_ENTRY_POINT: &str = "_start";
+const DEFAULT_IMAGE_BASE: i64 = 0x40_0000;
+const DEFAULT_PAGE_SIZE: i64 = 4096;
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum LinkerScriptError {
+    #[fail(display = "Missing the required {} environment variable", _0)]
+    MissingRequiredEnvVar(String),
+    #[fail(display = "Unable to read the linker script template {}", _0)]
+    TemplateReadFailure(String),
+    #[fail(
+        display = "The linker script template uses the unknown placeholder {{{{{}}}}}",
+        _0
+    )]
+    UnknownPlaceholder(String),
+    #[fail(display = "The {} property should be {}", _0, _1)]
+    InvalidProperty(&'static str, &'static str),
+    #[fail(display = "Unable to write the linker script to {}", _0)]
+    WriteFailure(String),
+}
+
+/// Fill in a linker script template from the configuration.
+///
+/// Templates may use the `{{entry}}`, `{{image_base}}` and `{{page_size}}`
+/// placeholders, which take their values from the `UserLinkerEntryPoint`,
+/// `UserImageBase` and `LinkPageSize` properties, defaulting to `_start`,
+/// `0x400000` and 4096 respectively.
+pub fn render_linker_script(
+    fel4_config: &Fel4Config,
+    template: &str,
+) -> Result<String, LinkerScriptError> {
+    let properties = &fel4_config.properties;
+    let entry = match properties.get("UserLinkerEntryPoint") {
+        None => DEFAULT_ENTRY_POINT.to_string(),
+        Some(FlatTomlValue::String(s)) if !s.is_empty() => s.clone(),
+        Some(_) => {
+            return Err(LinkerScriptError::InvalidProperty(
+                "UserLinkerEntryPoint",
+                "a non-empty symbol name",
+            ))
+        }
+    };
+    let image_base = positive_integer(fel4_config, "UserImageBase", DEFAULT_IMAGE_BASE)?;
+    let page_size = positive_integer(fel4_config, "LinkPageSize", DEFAULT_PAGE_SIZE)?;
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| LinkerScriptError::UnknownPlaceholder(after.to_string()))?;
+        match after[..end].trim() {
+            "entry" => rendered.push_str(&entry),
+            "image_base" => rendered.push_str(&format!("{:#x}", image_base)),
+            "page_size" => rendered.push_str(&page_size.to_string()),
+            other => return Err(LinkerScriptError::UnknownPlaceholder(other.to_string())),
+        }
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Render the configured linker script and write it into `out_dir`,
+/// returning the path of the written script.
+///
+/// The template is the file named by the `UserLinkerScript` property,
+/// relative to `project_dir`, or `DEFAULT_LINKER_SCRIPT_TEMPLATE` if the
+/// property is absent.
+pub fn write_linker_script<P: AsRef<Path>, Q: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    project_dir: P,
+    out_dir: Q,
+) -> Result<PathBuf, LinkerScriptError> {
+    let template = match fel4_config.properties.get("UserLinkerScript") {
+        None => DEFAULT_LINKER_SCRIPT_TEMPLATE.to_string(),
+        Some(FlatTomlValue::String(relative)) => {
+            let path = project_dir.as_ref().join(relative);
+            let mut text = String::new();
+            File::open(&path)
+                .and_then(|mut f| f.read_to_string(&mut text))
+                .map_err(|_| LinkerScriptError::TemplateReadFailure(path.display().to_string()))?;
+            text
+        }
+        Some(_) => {
+            return Err(LinkerScriptError::InvalidProperty(
+                "UserLinkerScript",
+                "a path to a linker script template",
+            ))
+        }
+    };
+    let rendered = render_linker_script(fel4_config, &template)?;
+    let destination = out_dir.as_ref().join(LINKER_SCRIPT_FILE_NAME);
+    fs::create_dir_all(out_dir.as_ref())
+        .and_then(|_| File::create(&destination))
+        .and_then(|mut f| f.write_all(rendered.as_bytes()))
+        .map_err(|_| LinkerScriptError::WriteFailure(destination.display().to_string()))?;
+    fel4_trace!("linker", "wrote {}", destination.display());
+    Ok(destination)
+}
+
+/// Write the configured linker script into cargo's OUT_DIR, resolving any
+/// template relative to CARGO_MANIFEST_DIR, for use from a build script
+pub fn write_linker_script_from_env(
+    fel4_config: &Fel4Config,
+) -> Result<PathBuf, LinkerScriptError> {
+    let project_dir = trace::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| LinkerScriptError::MissingRequiredEnvVar("CARGO_MANIFEST_DIR".to_string()))?;
+    let out_dir = trace::var("OUT_DIR")
+        .map_err(|_| LinkerScriptError::MissingRequiredEnvVar("OUT_DIR".to_string()))?;
+    write_linker_script(fel4_config, project_dir, out_dir)
+}
+
+fn positive_integer(
+    fel4_config: &Fel4Config,
+    name: &'static str,
+    default: i64,
+) -> Result<i64, LinkerScriptError> {
+    match fel4_config.properties.get(name) {
+        None => Ok(default),
+        Some(&FlatTomlValue::Integer(i)) if i > 0 => Ok(i),
+        Some(_) => Err(LinkerScriptError::InvalidProperty(
+            name,
+            "a positive integer",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    fn config() -> Fel4Config {
+        test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        )
+    }
+
+    #[test]
+    fn default_template_uses_defaults_and_page_size() {
+        let mut config = config();
+        config
+            .properties
+            .insert("LinkPageSize".into(), FlatTomlValue::Integer(65536));
+        let script = render_linker_script(&config, DEFAULT_LINKER_SCRIPT_TEMPLATE)
+            .expect("The default template should render");
+        assert!(script.starts_with("ENTRY(_start)"));
+        assert!(script.contains(". = 0x400000;"));
+        assert!(script.contains("ALIGN(65536)"));
+        assert!(!script.contains("{{"));
+    }
+
+    #[test]
+    fn properties_fill_custom_templates() {
+        let mut config = config();
+        config.properties.insert(
+            "UserLinkerEntryPoint".into(),
+            FlatTomlValue::String("fel4_main".into()),
+        );
+        config
+            .properties
+            .insert("UserImageBase".into(), FlatTomlValue::Integer(0x1000_0000));
+        assert_eq!(
+            Ok("ENTRY(fel4_main) . = 0x10000000; ALIGN(4096)".to_string()),
+            render_linker_script(
+                &config,
+                "ENTRY({{entry}}) . = {{ image_base }}; ALIGN({{page_size}})"
+            )
+        );
+        assert_eq!(
+            Err(LinkerScriptError::UnknownPlaceholder("stack".into())),
+            render_linker_script(&config, "{{stack}}")
+        );
+    }
+
+    #[test]
+    fn linker_script_is_written_into_out_dir() {
+        let project = ::tempfile::tempdir().unwrap();
+        fs::write(project.path().join("custom.ld"), "ENTRY({{entry}})\n").unwrap();
+        let out = project.path().join("out");
+        let mut config = config();
+        config.properties.insert(
+            "UserLinkerScript".into(),
+            FlatTomlValue::String("custom.ld".into()),
+        );
+        let written = write_linker_script(&config, project.path(), &out)
+            .expect("Should write the linker script");
+        assert_eq!(out.join(LINKER_SCRIPT_FILE_NAME), written);
+        assert_eq!("ENTRY(_start)\n", fs::read_to_string(written).unwrap());
+    }
+}
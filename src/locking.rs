@@ -0,0 +1,135 @@
+/// Locking properties in a manifest's `locked-properties` array, so that
+/// variants, workspace components, and overrides can't change them by
+/// accident
+use types::*;
+
+use super::ConfigError;
+
+/// Whether changes to locked properties are refused
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum LockPolicy {
+    /// Refuse them with a `LockedProperty` error
+    #[default]
+    Enforce,
+    /// Allow them, for deliberately departing from the manifest, like
+    /// cargo-fel4's `--allow-locked`
+    AllowLocked,
+}
+
+/// Fail with a `LockedProperty` error if `name` is among the `locked`
+/// properties and the policy enforces locks, naming what tried to change it
+pub(crate) fn check_unlocked(
+    locked: &[String],
+    name: &str,
+    changed_by: &str,
+    policy: LockPolicy,
+) -> Result<(), ConfigError> {
+    if policy == LockPolicy::Enforce && locked.iter().any(|l| l == name) {
+        return Err(ConfigError::LockedProperty(
+            name.to_string(),
+            changed_by.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl Fel4Config {
+    /// Whether the manifest locks a property against overrides
+    pub fn is_locked(&self, property_name: &str) -> bool {
+        self.locked_properties.iter().any(|l| l == property_name)
+    }
+
+    /// Replace a resolved property with a value from outside the manifest,
+    /// such as an environment or command line override.
+    ///
+    /// Locked properties are refused unless the policy is
+    /// `LockPolicy::AllowLocked`. The property then reports
+    /// `PropertyOrigin::Overridden`.
+    pub fn override_property(
+        &mut self,
+        property_name: &str,
+        value: FlatTomlValue,
+        policy: LockPolicy,
+    ) -> Result<(), ConfigError> {
+        check_unlocked(&self.locked_properties, property_name, "an override", policy)?;
+        if !self.properties.contains_key(property_name) {
+            self.property_order.push(property_name.to_string());
+        }
+        self.properties.insert(property_name.to_string(), value);
+        self.provenance.remove(property_name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn locked_properties_refuse_overrides_and_presets_unless_allowed() {
+        let full = test_helpers::ManifestFixture::new()
+            .with_property("fel4", "locked-properties", vec!["KernelPrinting"])
+            .with_property("preset.quiet", "KernelPrinting", false)
+            .with_property("variant.quiet", "target", "x86_64-sel4-fel4")
+            .with_property("variant.quiet", "platform", "pc99")
+            .with_property("variant.quiet", "profile", "debug")
+            .with_property("variant.quiet", "presets", vec!["quiet"])
+            .parse()
+            .unwrap();
+        assert_eq!(vec!["KernelPrinting".to_string()], full.locked_properties);
+
+        let mut config = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        assert!(config.is_locked("KernelPrinting"));
+        assert!(!config.is_locked("KernelVerificationBuild"));
+        assert_eq!(
+            Err(ConfigError::LockedProperty(
+                "KernelPrinting".into(),
+                "an override".into()
+            )),
+            config.override_property(
+                "KernelPrinting",
+                FlatTomlValue::Boolean(false),
+                LockPolicy::Enforce
+            )
+        );
+        config
+            .override_property(
+                "KernelVerificationBuild",
+                FlatTomlValue::Boolean(true),
+                LockPolicy::Enforce,
+            )
+            .unwrap();
+        config
+            .override_property(
+                "KernelPrinting",
+                FlatTomlValue::Boolean(false),
+                LockPolicy::AllowLocked,
+            )
+            .unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(false)),
+            config.properties.get("KernelPrinting")
+        );
+        assert_eq!(
+            Some(PropertyOrigin::Overridden),
+            config.property_origin("KernelPrinting")
+        );
+
+        assert_eq!(
+            Err(ConfigError::LockedProperty(
+                "KernelPrinting".into(),
+                "preset.quiet".into()
+            )),
+            resolve_variant(&full, "quiet")
+        );
+        assert!(resolve_variant_with_policy(&full, "quiet", LockPolicy::AllowLocked).is_ok());
+        assert_eq!(
+            Err(ConfigError::NonWhitelistProperty("KernelNonsense".into())),
+            test_helpers::ManifestFixture::new()
+                .with_property("fel4", "locked-properties", vec!["KernelNonsense"])
+                .parse()
+        );
+    }
+}
@@ -1,57 +1,422 @@
 /// Related to the parsing and representation of the full fel4 manifest
-use multimap::MultiMap;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 use toml;
 
-use super::ConfigError;
+use super::{is_supported_target_platform_pair, ConfigError, ConfigWarning};
+use conditional::{parse_conditional_value, ConditionalValue};
+use formula::{parse_derived_value, Formula};
+use image_name::validate_image_name;
+use kernel_source::MIN_KERNEL_COMMIT_PREFIX_LENGTH;
+use known_properties::KnownProperty;
 use types::*;
+use vars::expand_manifest_vars;
+use variant::{parse_presets, parse_variants, variant_to_toml, ManifestVariant};
+/// The `fel4-version` of the manifest format this release of fel4-config
+/// reads and writes. Manifests that omit `fel4-version` are read as the
+/// current version; older versions can be upgraded with `migrate_manifest`.
+pub const FEL4_MANIFEST_VERSION: i64 = 2;
+
 /// The full content of a fel4 manifest
 #[derive(Clone, Debug, PartialEq)]
 pub struct FullFel4Manifest {
     pub artifact_path: String,
+    pub artifact_layout: ArtifactLayout,
     pub target_specs_path: String,
     pub selected_target: SupportedTarget,
     pub selected_platform: SupportedPlatform,
+    /// Where the images run, which hardware deployments check simulation
+    /// settings against
+    pub deployment: Option<Deployment>,
     pub targets: HashMap<SupportedTarget, FullFel4Target>,
+    pub toolchains: HashMap<SupportedTarget, ToolchainSettings>,
+    pub kernel_source: Option<KernelSource>,
+    pub kernel_version: Option<String>,
+    pub kernel_commit: Option<String>,
+    /// The image base name, replacing `DEFAULT_IMAGE_NAME`
+    pub image_name: Option<String>,
+    /// The image naming pattern, replacing `DEFAULT_IMAGE_NAME_PATTERN`
+    pub image_name_pattern: Option<String>,
+    /// The timestamp reproducible kernel builds are pinned to
+    pub source_date_epoch: Option<SourceDateEpoch>,
+    /// Per-profile replacements for `BuildProfile::default_cmake_build_type`
+    pub cmake_build_types: HashMap<BuildProfile, String>,
+    /// Properties whose scope replaces `PropertyScope::default_for`, from the
+    /// `[fel4.property-scopes]` table
+    pub property_scopes: HashMap<String, PropertyScope>,
+    /// Properties that variants and overrides may not change without
+    /// `LockPolicy::AllowLocked`, from the `locked-properties` array
+    pub locked_properties: Vec<String>,
+    /// The `[preset.<name>]` property tables that variants apply
+    pub presets: BTreeMap<String, PropertySet>,
+    /// The `[variant.<name>]` tables, resolved with `resolve_variant`
+    pub variants: BTreeMap<String, ManifestVariant>,
+}
+
+impl FullFel4Manifest {
+    /// The targets the manifest has tables for, in `SupportedTarget::targets`
+    /// order
+    pub fn declared_targets(&self) -> Vec<SupportedTarget> {
+        let mut declared: Vec<SupportedTarget> = self.targets.keys().cloned().collect();
+        declared.sort();
+        declared
+    }
+
+    /// The `CMAKE_BUILD_TYPE` the kernel is built with for a build profile
+    pub fn cmake_build_type(&self, build_profile: &BuildProfile) -> String {
+        self.cmake_build_types
+            .get(build_profile)
+            .cloned()
+            .unwrap_or_else(|| build_profile.default_cmake_build_type().to_string())
+    }
+
+    /// Select a different target, keeping the selected platform.
+    ///
+    /// Fails if the manifest has no table for the target, or if the target
+    /// cannot be paired with the currently selected platform.
+    pub fn with_selected_target(self, target: SupportedTarget) -> Result<Self, ConfigError> {
+        let platform = self.selected_platform;
+        self.with_selection(target, platform)
+    }
+
+    /// Select a different platform, keeping the selected target.
+    ///
+    /// Fails if the platform cannot be paired with the currently selected
+    /// target.
+    pub fn with_selected_platform(self, platform: SupportedPlatform) -> Result<Self, ConfigError> {
+        let target = self.selected_target;
+        self.with_selection(target, platform)
+    }
+
+    /// Select a different target and platform together.
+    ///
+    /// Fails if the manifest has no table for the target, or if the pair is
+    /// not a supported combination.
+    pub fn with_selection(
+        mut self,
+        target: SupportedTarget,
+        platform: SupportedPlatform,
+    ) -> Result<Self, ConfigError> {
+        if !self.targets.contains_key(&target) {
+            return Err(ConfigError::MissingTargetTable(target));
+        }
+        if !is_supported_target_platform_pair(target, platform) {
+            return Err(ConfigError::TargetPlatformMismatch(target, platform));
+        }
+        self.selected_target = target;
+        self.selected_platform = platform;
+        Ok(self)
+    }
 }
 
 /// The full content of a target within a fel4 manifest
 #[derive(Clone, Debug, PartialEq)]
 pub struct FullFel4Target {
     pub identity: SupportedTarget,
-    pub direct_properties: Vec<FlatTomlProperty>,
-    pub build_profile_properties: MultiMap<BuildProfile, FlatTomlProperty>,
-    pub platform_properties: MultiMap<SupportedPlatform, FlatTomlProperty>,
+    pub direct_properties: PropertySet,
+    pub build_profile_properties: HashMap<BuildProfile, PropertySet>,
+    pub platform_properties: HashMap<SupportedPlatform, PropertySet>,
+    /// The properties of the `[$TARGET]` table whose values are chosen by
+    /// build profile, platform, or a `when` condition
+    pub conditional_properties: BTreeMap<String, ConditionalValue>,
+    /// The properties of the `[$TARGET]` table computed by a `formula`
+    pub derived_properties: BTreeMap<String, Formula>,
+    /// The `[<target>.rootserver]` table, which holds no properties
+    pub rootserver: RootserverSettings,
+    /// The `[<target>.test]` table, whose properties replace those of the
+    /// other tables when resolving for `cargo fel4 test` runs
+    pub test_properties: PropertySet,
 }
 
-/// Retrieve the complete contents of the fel4 toml from a file
+/// Retrieve the complete contents of the fel4 toml from a file, tracing
+/// any deprecation warnings
 pub fn get_full_manifest<P: AsRef<Path>>(path: P) -> Result<FullFel4Manifest, ConfigError> {
+    get_full_manifest_with_warnings(path).map(trace_dropped_warnings)
+}
+
+/// Retrieve the complete contents of the fel4 toml from a file as
+/// `get_full_manifest` does, along with any deprecation warnings
+pub fn get_full_manifest_with_warnings<P: AsRef<Path>>(
+    path: P,
+) -> Result<(FullFel4Manifest, Vec<ConfigWarning>), ConfigError> {
     let mut manifest_file = File::open(&path).map_err(|_| ConfigError::FileReadFailure)?;
-    let mut toml_string = String::new();
+    let mut contents = Vec::new();
     let _size = manifest_file
-        .read_to_string(&mut toml_string)
+        .read_to_end(&mut contents)
         .map_err(|_| ConfigError::FileReadFailure)?;
-    parse_full_manifest(toml_string)
+    parse_full_manifest_with_warnings(decode_manifest(&contents)?)
 }
 
-/// Retrieve the complete contents of the fel4 toml from a string
+/// Keep the manifest of a `_with_warnings` result, tracing the warnings
+/// that the caller has no way to receive
+fn trace_dropped_warnings(
+    (full, warnings): (FullFel4Manifest, Vec<ConfigWarning>),
+) -> FullFel4Manifest {
+    for warning in warnings {
+        fel4_trace!("manifest", "warning: {}", warning);
+    }
+    full
+}
+
+/// Decode the contents of a manifest file, which must be UTF-8 encoded.
+///
+/// A leading UTF-8 byte order mark, as some Windows editors write, is
+/// dropped and Windows line endings become `\n`. Text in any other encoding
+/// is an `InvalidEncoding` error.
+pub fn decode_manifest(contents: &[u8]) -> Result<String, ConfigError> {
+    if contents.starts_with(&[0xFF, 0xFE]) || contents.starts_with(&[0xFE, 0xFF]) {
+        return Err(ConfigError::InvalidEncoding(
+            "it is UTF-16 encoded".to_string(),
+        ));
+    }
+    let text = ::std::str::from_utf8(contents).map_err(|e| {
+        ConfigError::InvalidEncoding(format!("byte {} is not valid UTF-8", e.valid_up_to()))
+    })?;
+    Ok(normalize_manifest_text(text).into_owned())
+}
+
+/// Drop a leading byte order mark and convert Windows line endings
+pub(crate) fn normalize_manifest_text(text: &str) -> Cow<'_, str> {
+    let text = text.trim_start_matches('\u{feff}');
+    if text.contains('\r') {
+        Cow::Owned(text.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Retrieve the complete contents of the fel4 toml from a string, which may
+/// begin with a byte order mark and use Windows line endings. Deprecation
+/// warnings are traced.
 pub fn parse_full_manifest<S: AsRef<str>>(toml_string: S) -> Result<FullFel4Manifest, ConfigError> {
-    let manifest = toml_string
-        .as_ref()
+    parse_full_manifest_with_warnings(toml_string).map(trace_dropped_warnings)
+}
+
+/// Retrieve the complete contents of the fel4 toml from a string as
+/// `parse_full_manifest` does, along with any deprecation warnings
+pub fn parse_full_manifest_with_warnings<S: AsRef<str>>(
+    toml_string: S,
+) -> Result<(FullFel4Manifest, Vec<ConfigWarning>), ConfigError> {
+    let text = normalize_manifest_text(toml_string.as_ref());
+    let manifest = text
         .parse::<toml::Value>()
         .map_err(|_| ConfigError::TomlParseFailure)?;
-    toml_to_full_manifest(&manifest)
+    let (mut full, warnings) = toml_to_full_manifest_with_warnings(&manifest)?;
+    let legacy_target = warnings.iter().find_map(|w| match *w {
+        ConfigWarning::LegacyManifestLayout(t) => Some(t),
+        _ => None,
+    });
+    order_as_declared(&mut full, &text, legacy_target);
+    Ok((full, warnings))
+}
+
+/// The keys of each table of a toml document in the order they were written,
+/// by the dotted path of the table
+type DeclaredKeyOrder = HashMap<String, Vec<String>>;
+
+/// Recover the declaration order of keys that parsing into `toml::Value`
+/// discards.
+///
+/// This is a line-oriented scan of a document that is already known to
+/// parse. It understands table headers, bare and quoted keys, and values
+/// that continue over several lines, which covers every fel4 manifest;
+/// anything it misses simply sorts after the keys it found.
+fn declared_key_order(text: &str) -> DeclaredKeyOrder {
+    let mut order = DeclaredKeyOrder::new();
+    let mut table = String::new();
+    let mut continuation: Option<&str> = None;
+    let mut open_brackets = 0usize;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(delimiter) = continuation {
+            if line.contains(delimiter) {
+                continuation = None;
+            }
+            continue;
+        }
+        if open_brackets > 0 {
+            open_brackets = bracket_balance(line, open_brackets);
+            continue;
+        }
+        if line.starts_with('[') {
+            let header = line.trim_start_matches('[');
+            if let Some(end) = header.find(']') {
+                table = header[..end]
+                    .split('.')
+                    .map(|segment| segment.trim().trim_matches(|c| c == '"' || c == '\''))
+                    .collect::<Vec<_>>()
+                    .join(".");
+            }
+            continue;
+        }
+        let equals = match line.find('=') {
+            Some(i) if !line.starts_with('#') => i,
+            _ => continue,
+        };
+        let key = line[..equals]
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'');
+        order
+            .entry(table.clone())
+            .or_default()
+            .push(key.to_string());
+        let value = line[equals + 1..].trim();
+        for delimiter in &["\"\"\"", "'''"] {
+            if value.starts_with(delimiter) && !value[3..].contains(delimiter) {
+                continuation = Some(delimiter);
+            }
+        }
+        if value.starts_with('[') {
+            open_brackets = bracket_balance(value, 0);
+        }
+    }
+    order
+}
+
+/// The depth of array nesting after a line, ignoring brackets in strings
+fn bracket_balance(line: &str, mut depth: usize) -> usize {
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (None, '#') => break,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    depth
+}
+
+/// Put each property table of a parsed manifest back in the order the
+/// manifest text declared it. For a manifest in the legacy layout, the
+/// selected target's properties were declared in the `[fel4]` table.
+fn order_as_declared(
+    full: &mut FullFel4Manifest,
+    text: &str,
+    legacy_target: Option<SupportedTarget>,
+) {
+    let order = declared_key_order(text);
+    let sort = |properties: &mut PropertySet, table: &str| {
+        if let Some(keys) = order.get(table) {
+            properties
+                .sort_by_key(|p| keys.iter().position(|k| *k == p.name).unwrap_or(keys.len()));
+        }
+    };
+    for (target, content) in &mut full.targets {
+        let table = match legacy_target {
+            Some(t) if t == *target => "fel4",
+            _ => target.full_name(),
+        };
+        sort(&mut content.direct_properties, table);
+        for (profile, properties) in &mut content.build_profile_properties {
+            sort(properties, &format!("{}.{}", table, profile.full_name()));
+        }
+        for (platform, properties) in &mut content.platform_properties {
+            sort(properties, &format!("{}.{}", table, platform.full_name()));
+        }
+    }
+    for (name, properties) in &mut full.presets {
+        sort(properties, &format!("preset.{}", name));
+    }
+}
+
+/// The keys of the `[fel4]` table that configure fel4-config itself, rather
+/// than the kernel
+const FEL4_HEADER_KEYS: &[&str] = &[
+    "fel4-version",
+    "target",
+    "platform",
+    "deployment",
+    "artifact-path",
+    "artifact-layout",
+    "target-specs-path",
+    "kernel-source",
+    "kernel-version",
+    "kernel-commit",
+    "image-name",
+    "image-name-pattern",
+    "source-date-epoch",
+    "cmake-build-types",
+    "property-scopes",
+    "locked-properties",
+];
+
+/// Early cargo-fel4 releases had no target tables. Instead the properties
+/// of the one selected target were written directly into the `[fel4]`
+/// table, with optional `[fel4.$PROFILE]` and `[fel4.$PLATFORM]` subtables.
+///
+/// Returns the equivalent manifest in the current layout, with those
+/// properties moved to the selected target's table and empty profile and
+/// platform subtables added where absent, or None if the manifest does not
+/// use the legacy layout.
+pub(crate) fn convert_legacy_layout(
+    root: &toml::value::Table,
+) -> Option<(toml::value::Table, SupportedTarget)> {
+    if root.keys().any(|k| k.parse::<SupportedTarget>().is_ok()) {
+        return None;
+    }
+    let fel4_table = root.get("fel4")?.as_table()?;
+    let legacy_subtable_names: HashSet<String> = SupportedPlatform::platform_names()
+        .into_iter()
+        .chain(BuildProfile::build_profile_names())
+        .collect();
+    let mut legacy_keys = fel4_table
+        .iter()
+        .filter(|(k, _)| !FEL4_HEADER_KEYS.contains(&k.as_str()))
+        .peekable();
+    legacy_keys.peek()?;
+    if !legacy_keys.all(|(k, v)| !v.is_table() || legacy_subtable_names.contains(k)) {
+        return None;
+    }
+    let target: SupportedTarget = fel4_table.get("target")?.as_str()?.parse().ok()?;
+    let (header, mut target_table): (toml::value::Table, toml::value::Table) = fel4_table
+        .clone()
+        .into_iter()
+        .partition(|(k, _)| FEL4_HEADER_KEYS.contains(&k.as_str()));
+    let platform = fel4_table
+        .get("platform")
+        .and_then(toml::Value::as_str)
+        .and_then(|p| p.parse::<SupportedPlatform>().ok());
+    let subtables = BuildProfile::build_profiles()
+        .into_iter()
+        .map(|p| p.full_name())
+        .chain(platform.map(|p| p.full_name()));
+    for name in subtables {
+        target_table
+            .entry(name.to_string())
+            .or_insert_with(|| toml::Value::Table(BTreeMap::new()));
+    }
+    let mut converted = root.clone();
+    converted.insert("fel4".to_string(), toml::Value::Table(header));
+    converted.insert(
+        target.full_name().to_string(),
+        toml::Value::Table(target_table),
+    );
+    Some((converted, target))
 }
 
 #[derive(Clone, Debug, PartialEq)]
 struct Fel4Header {
     pub artifact_path: String,
+    pub artifact_layout: ArtifactLayout,
     pub target_specs_path: String,
     pub selected_target: SupportedTarget,
     pub selected_platform: SupportedPlatform,
+    pub deployment: Option<Deployment>,
+    pub kernel_source: Option<KernelSource>,
+    pub kernel_version: Option<String>,
+    pub kernel_commit: Option<String>,
+    pub image_name: Option<String>,
+    pub image_name_pattern: Option<String>,
+    pub source_date_epoch: Option<SourceDateEpoch>,
+    pub cmake_build_types: HashMap<BuildProfile, String>,
+    pub property_scopes: HashMap<String, PropertyScope>,
+    pub locked_properties: Vec<String>,
 }
 
 /// Internal convenience to break out the header table parsing
@@ -61,17 +426,40 @@ fn parse_fel4_header(raw: &toml::Value) -> Result<Fel4Header, ConfigError> {
         .and_then(toml::Value::as_table)
         .ok_or_else(|| ConfigError::MissingTable("fel4".into()))?;
 
-    has_only_approved_substructures(fel4_table, None)
+    let allowed_header_subtable_names: HashSet<String> = vec![
+        "kernel-source".to_string(),
+        "cmake-build-types".to_string(),
+        "property-scopes".to_string(),
+        "locked-properties".to_string(),
+    ].into_iter()
+        .collect();
+    has_only_approved_substructures(fel4_table, Some(&allowed_header_subtable_names))
         .map_err(|name| ConfigError::UnexpectedStructure(format!("fel4.{}", name)))?;
 
+    match fel4_table.get("fel4-version") {
+        None => (),
+        Some(toml::Value::Integer(v)) if *v == FEL4_MANIFEST_VERSION => (),
+        Some(toml::Value::Integer(v)) if *v > FEL4_MANIFEST_VERSION => {
+            return Err(ConfigError::UnsupportedManifestVersion(*v))
+        }
+        Some(toml::Value::Integer(v)) if *v > 0 => {
+            return Err(ConfigError::OutdatedManifestVersion(*v))
+        }
+        Some(v) => return Err(ConfigError::InvalidManifestVersion(v.to_string())),
+    }
+
     let selected_target: SupportedTarget = fel4_table
         .get("target")
         .and_then(toml::Value::as_str)
         .and_then(|s| if s.is_empty() { None } else { Some(s) })
         .ok_or_else(|| ConfigError::MissingRequiredProperty("fel4".into(), "target".into()))?
         .parse()
-        .map_err(|e| {
-            ConfigError::InvalidValueOption("target", SupportedTarget::target_names(), e)
+        .map_err(|e: String| {
+            if is_unsupported_target_name(&e) || raw.get(&e).is_some_and(toml::Value::is_table) {
+                ConfigError::UnsupportedTarget(e, SupportedTarget::target_names())
+            } else {
+                ConfigError::InvalidValueOption("target", SupportedTarget::target_names(), e)
+            }
         })?;
     let selected_platform: SupportedPlatform = fel4_table
         .get("platform")
@@ -79,16 +467,26 @@ fn parse_fel4_header(raw: &toml::Value) -> Result<Fel4Header, ConfigError> {
         .and_then(|s| if s.is_empty() { None } else { Some(s) })
         .ok_or_else(|| ConfigError::MissingRequiredProperty("fel4".into(), "platform".into()))?
         .parse()
-        .map_err(|e| {
-            ConfigError::InvalidValueOption("platform", SupportedPlatform::platform_names(), e)
+        .map_err(|e: String| {
+            if is_platform_subtable_name(raw, &e) {
+                ConfigError::UnsupportedPlatform(e, SupportedPlatform::platform_names())
+            } else {
+                ConfigError::InvalidValueOption("platform", SupportedPlatform::platform_names(), e)
+            }
         })?;
 
+    fel4_trace!(
+        "manifest",
+        "read [fel4] selecting {} {}",
+        selected_target,
+        selected_platform
+    );
     let artifact_path = fel4_table
         .get("artifact-path")
         .ok_or_else(|| ConfigError::MissingRequiredProperty("fel4".into(), "artifact-path".into()))
         .and_then(|o| {
             o.as_str()
-                .ok_or_else(|| ConfigError::NonStringProperty("artifact-path"))
+                .ok_or(ConfigError::NonStringProperty("artifact-path"))
         })
         .and_then(|s| {
             if s.is_empty() {
@@ -101,6 +499,35 @@ fn parse_fel4_header(raw: &toml::Value) -> Result<Fel4Header, ConfigError> {
             }
         })?
         .to_string();
+    let artifact_layout = match fel4_table.get("artifact-layout") {
+        None => ArtifactLayout::default(),
+        Some(v) => v
+            .as_str()
+            .ok_or(ConfigError::NonStringProperty("artifact-layout"))?
+            .parse()
+            .map_err(|e| {
+                ConfigError::InvalidValueOption(
+                    "artifact-layout",
+                    ArtifactLayout::layout_names(),
+                    e,
+                )
+            })?,
+    };
+    let deployment = match fel4_table.get("deployment") {
+        None => None,
+        Some(v) => Some(
+            v.as_str()
+                .ok_or(ConfigError::NonStringProperty("deployment"))?
+                .parse()
+                .map_err(|e| {
+                    ConfigError::InvalidValueOption("deployment", Deployment::deployment_names(), e)
+                })?,
+        ),
+    };
+    let source_date_epoch = match fel4_table.get("source-date-epoch") {
+        None => None,
+        Some(v) => Some(parse_source_date_epoch(v)?),
+    };
     let target_specs_path = fel4_table
         .get("target-specs-path")
         .ok_or_else(|| {
@@ -108,7 +535,7 @@ fn parse_fel4_header(raw: &toml::Value) -> Result<Fel4Header, ConfigError> {
         })
         .and_then(|o| {
             o.as_str()
-                .ok_or_else(|| ConfigError::NonStringProperty("target-specs-path"))
+                .ok_or(ConfigError::NonStringProperty("target-specs-path"))
         })
         .and_then(|s| {
             if s.is_empty() {
@@ -121,27 +548,308 @@ fn parse_fel4_header(raw: &toml::Value) -> Result<Fel4Header, ConfigError> {
             }
         })?
         .to_string();
+    let kernel_source = match fel4_table.get("kernel-source") {
+        None => None,
+        Some(toml::Value::Table(t)) => Some(parse_kernel_source(t)?),
+        Some(_) => {
+            return Err(ConfigError::UnexpectedStructure(
+                "fel4.kernel-source".into(),
+            ))
+        }
+    };
+    let cmake_build_types = match fel4_table.get("cmake-build-types") {
+        None => HashMap::new(),
+        Some(toml::Value::Table(t)) => parse_cmake_build_types(t)?,
+        Some(_) => {
+            return Err(ConfigError::UnexpectedStructure(
+                "fel4.cmake-build-types".into(),
+            ))
+        }
+    };
+    let property_scopes = match fel4_table.get("property-scopes") {
+        None => HashMap::new(),
+        Some(toml::Value::Table(t)) => parse_property_scopes(t)?,
+        Some(_) => {
+            return Err(ConfigError::UnexpectedStructure(
+                "fel4.property-scopes".into(),
+            ))
+        }
+    };
+    let locked_properties = match fel4_table.get("locked-properties") {
+        None => Vec::new(),
+        Some(v) => parse_locked_properties(v)?,
+    };
+    let optional_string = |name: &'static str| -> Result<Option<String>, ConfigError> {
+        match fel4_table.get(name) {
+            None => Ok(None),
+            Some(v) => v
+                .as_str()
+                .map(|s| Some(s.to_string()))
+                .ok_or(ConfigError::NonStringProperty(name)),
+        }
+    };
+    let image_naming = |name: &'static str, is_pattern: bool| -> Result<_, ConfigError> {
+        let value = optional_string(name)?;
+        if let Some(ref v) = value {
+            validate_image_name(v, is_pattern)
+                .map_err(|reason| ConfigError::InvalidImageNaming(name, reason))?;
+        }
+        Ok(value)
+    };
     Ok(Fel4Header {
         artifact_path,
+        artifact_layout,
         target_specs_path,
         selected_target,
         selected_platform,
+        deployment,
+        kernel_source,
+        kernel_version: optional_string("kernel-version")?,
+        kernel_commit: match optional_string("kernel-commit")? {
+            Some(commit) => Some(parse_kernel_commit(commit)?),
+            None => None,
+        },
+        image_name: image_naming("image-name", false)?,
+        image_name_pattern: image_naming("image-name-pattern", true)?,
+        source_date_epoch,
+        cmake_build_types,
+        property_scopes,
+        locked_properties,
     })
 }
 
-/// Parse the complete contents of the fel4 toml
+/// Check a `kernel-commit` is a hex commit prefix long enough to identify a
+/// single commit, since any checkout matches an empty prefix
+fn parse_kernel_commit(commit: String) -> Result<String, ConfigError> {
+    let reason = if commit.len() < MIN_KERNEL_COMMIT_PREFIX_LENGTH {
+        format!(
+            "it should give at least {} hex digits",
+            MIN_KERNEL_COMMIT_PREFIX_LENGTH
+        )
+    } else if !commit.chars().all(|c| c.is_ascii_hexdigit()) {
+        "it should be hex digits".to_string()
+    } else {
+        return Ok(commit);
+    };
+    Err(ConfigError::InvalidKernelCommit(commit, reason))
+}
+
+/// Parse `source-date-epoch`, either seconds since the Unix epoch or
+/// `"kernel-commit"`
+fn parse_source_date_epoch(value: &toml::Value) -> Result<SourceDateEpoch, ConfigError> {
+    let parsed = match *value {
+        toml::Value::Integer(seconds) if seconds >= 0 => Ok(SourceDateEpoch::Fixed(seconds)),
+        toml::Value::String(ref s) => s.parse(),
+        ref other => Err(other.to_string()),
+    };
+    parsed.map_err(|e| {
+        ConfigError::InvalidValueOption(
+            "source-date-epoch",
+            vec![
+                SourceDateEpoch::KernelCommit.to_string(),
+                "seconds since the Unix epoch".to_string(),
+            ],
+            e,
+        )
+    })
+}
+
+/// Parse the `locked-properties` array of known or user property names
+fn parse_locked_properties(value: &toml::Value) -> Result<Vec<String>, ConfigError> {
+    let names = value
+        .as_array()
+        .ok_or(ConfigError::NonStringProperty("locked-properties"))?;
+    let mut locked = Vec::new();
+    for name in names {
+        let name = name
+            .as_str()
+            .ok_or(ConfigError::NonStringProperty("locked-properties"))?;
+        if !is_user_property(name) && name.parse::<KnownProperty>().is_err() {
+            return Err(ConfigError::NonWhitelistProperty(name.to_string()));
+        }
+        locked.push(name.to_string());
+    }
+    Ok(locked)
+}
+
+/// Parse the `[fel4.property-scopes]` table, which maps names of known or
+/// user properties to their `PropertyScope`
+fn parse_property_scopes(
+    table: &BTreeMap<String, toml::Value>,
+) -> Result<HashMap<String, PropertyScope>, ConfigError> {
+    let mut scopes = HashMap::new();
+    for (k, v) in table {
+        if !is_user_property(k) && k.parse::<KnownProperty>().is_err() {
+            return Err(ConfigError::NonWhitelistProperty(k.clone()));
+        }
+        let name = v
+            .as_str()
+            .ok_or(ConfigError::NonStringProperty("property-scopes"))?;
+        let scope = name.parse().map_err(|_| {
+            ConfigError::InvalidValueOption(
+                "property-scopes",
+                PropertyScope::scope_names(),
+                name.to_string(),
+            )
+        })?;
+        scopes.insert(k.clone(), scope);
+    }
+    Ok(scopes)
+}
+
+/// Parse the `[fel4.cmake-build-types]` table, which maps build profile names
+/// to the `CMAKE_BUILD_TYPE` to use for them
+fn parse_cmake_build_types(
+    table: &BTreeMap<String, toml::Value>,
+) -> Result<HashMap<BuildProfile, String>, ConfigError> {
+    let mut build_types = HashMap::new();
+    for (k, v) in table {
+        let profile: BuildProfile = k.parse().map_err(|_| {
+            ConfigError::UnexpectedStructure(format!("fel4.cmake-build-types.{}", k))
+        })?;
+        let build_type = v
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .ok_or(ConfigError::NonStringProperty("cmake-build-types"))?;
+        build_types.insert(profile, build_type.to_string());
+    }
+    Ok(build_types)
+}
+
+/// Whether a name that isn't a `SupportedTarget` has the form of a fel4
+/// target, and so is likely one added by a newer release of fel4-config
+fn is_unsupported_target_name(name: &str) -> bool {
+    name.ends_with("-sel4-fel4")
+}
+
+/// Whether the manifest uses a name that isn't a `SupportedPlatform` as the
+/// name of a platform subtable of some target, and so is likely a platform
+/// added by a newer release of fel4-config rather than a mistake
+fn is_platform_subtable_name(raw: &toml::Value, name: &str) -> bool {
+    raw.as_table().is_some_and(|root| {
+        root.iter()
+            .filter(|(k, _)| !["fel4", "toolchain", "preset", "variant"].contains(&k.as_str()))
+            .any(|(_, v)| v.get(name).is_some_and(toml::Value::is_table))
+    })
+}
+
+/// Parse the `[fel4.kernel-source]` table, which names exactly one of a
+/// `git` repository (with an optional `tag` or `rev`) or a `tarball` (with a
+/// required `sha256`)
+fn parse_kernel_source(table: &BTreeMap<String, toml::Value>) -> Result<KernelSource, ConfigError> {
+    let mut strings: HashMap<&str, String> = HashMap::new();
+    for (k, v) in table {
+        let name: &'static str = match k.as_str() {
+            "git" => "git",
+            "tag" => "tag",
+            "rev" => "rev",
+            "tarball" => "tarball",
+            "sha256" => "sha256",
+            _ => {
+                return Err(ConfigError::UnexpectedStructure(format!(
+                    "fel4.kernel-source.{}",
+                    k
+                )))
+            }
+        };
+        let s = v.as_str().ok_or(ConfigError::NonStringProperty(name))?;
+        // git and curl would read these as options rather than locations
+        if s.starts_with('-') {
+            return Err(ConfigError::InvalidKernelSource(format!(
+                "{} may not begin with -",
+                name
+            )));
+        }
+        strings.insert(name, s.to_string());
+    }
+    let invalid = |reason: &str| Err(ConfigError::InvalidKernelSource(reason.to_string()));
+    match (strings.remove("git"), strings.remove("tarball")) {
+        (Some(_), Some(_)) => invalid("only one of git or tarball may be given"),
+        (None, None) => invalid("one of git or tarball must be given"),
+        (Some(url), None) => {
+            if strings.contains_key("sha256") {
+                return invalid("sha256 only applies to a tarball");
+            }
+            match (strings.remove("tag"), strings.remove("rev")) {
+                (Some(_), Some(_)) => invalid("only one of tag or rev may be given"),
+                (tag, rev) => Ok(KernelSource::Git {
+                    url,
+                    rev: tag.or(rev),
+                }),
+            }
+        }
+        (None, Some(url)) => {
+            if strings.contains_key("tag") || strings.contains_key("rev") {
+                return invalid("tag and rev only apply to a git repository");
+            }
+            match strings.remove("sha256") {
+                None => Err(ConfigError::MissingRequiredProperty(
+                    "fel4.kernel-source".into(),
+                    "sha256".into(),
+                )),
+                Some(ref sha256)
+                    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) =>
+                {
+                    invalid("sha256 must be 64 hexadecimal digits")
+                }
+                Some(sha256) => Ok(KernelSource::Tarball {
+                    url,
+                    sha256: sha256.to_ascii_lowercase(),
+                }),
+            }
+        }
+    }
+}
+
+/// Parse the complete contents of the fel4 toml, tracing any deprecation
+/// warnings
 pub fn toml_to_full_manifest(raw: &toml::Value) -> Result<FullFel4Manifest, ConfigError> {
+    toml_to_full_manifest_with_warnings(raw).map(trace_dropped_warnings)
+}
+
+/// Parse the complete contents of the fel4 toml as `toml_to_full_manifest`
+/// does, along with any deprecation warnings.
+///
+/// Manifests in the legacy layout of early cargo-fel4 releases are accepted
+/// with a `ConfigWarning::LegacyManifestLayout`.
+pub fn toml_to_full_manifest_with_warnings(
+    raw: &toml::Value,
+) -> Result<(FullFel4Manifest, Vec<ConfigWarning>), ConfigError> {
+    let expanded = expand_manifest_vars(raw)?;
+    let raw = &expanded;
+    if let Some((converted, target)) = raw.as_table().and_then(convert_legacy_layout) {
+        fel4_trace!("manifest", "converting the legacy layout for {}", target);
+        return parse_current_layout(&toml::Value::Table(converted))
+            .map(|full| (full, vec![ConfigWarning::LegacyManifestLayout(target)]));
+    }
+    parse_current_layout(raw).map(|full| (full, Vec::new()))
+}
+
+fn parse_current_layout(raw: &toml::Value) -> Result<FullFel4Manifest, ConfigError> {
     let Fel4Header {
         artifact_path,
+        artifact_layout,
         target_specs_path,
         selected_target,
         selected_platform,
-    } = parse_fel4_header(&raw)?;
+        deployment,
+        kernel_source,
+        kernel_version,
+        kernel_commit,
+        image_name,
+        image_name_pattern,
+        source_date_epoch,
+        cmake_build_types,
+        property_scopes,
+        locked_properties,
+    } = parse_fel4_header(raw)?;
 
     // Parse the target subtables
     let allowed_target_subtable_names: HashSet<String> = SupportedPlatform::platform_names()
         .into_iter()
-        .chain(BuildProfile::build_profile_names().into_iter())
+        .chain(BuildProfile::build_profile_names())
+        .chain(Some("rootserver".to_string()))
+        .chain(Some("test".to_string()))
         .collect();
     let mut targets: HashMap<SupportedTarget, FullFel4Target> = HashMap::new();
     for curr_target in SupportedTarget::targets() {
@@ -150,13 +858,52 @@ pub fn toml_to_full_manifest(raw: &toml::Value) -> Result<FullFel4Manifest, Conf
             None => continue,
             Some(t) => t,
         };
-        has_only_approved_substructures(curr_target_table, Some(&allowed_target_subtable_names))
+        let mut conditional_properties = BTreeMap::new();
+        let mut derived_properties = BTreeMap::new();
+        for (name, value) in curr_target_table {
+            let table = match value.as_table() {
+                Some(t) if !allowed_target_subtable_names.contains(name) => t,
+                _ => continue,
+            };
+            match parse_conditional_value(table) {
+                Some(Ok(conditional)) => {
+                    conditional_properties.insert(name.clone(), conditional);
+                }
+                Some(Err(reason)) => {
+                    return Err(ConfigError::InvalidConditionalValue(
+                        curr_target_name.to_string(),
+                        name.clone(),
+                        reason,
+                    ))
+                }
+                None => match parse_derived_value(table) {
+                    Some(Ok(formula)) => {
+                        derived_properties.insert(name.clone(), formula);
+                    }
+                    Some(Err(reason)) => {
+                        return Err(ConfigError::InvalidDerivedProperty(
+                            curr_target_name.to_string(),
+                            name.clone(),
+                            reason,
+                        ))
+                    }
+                    None => (),
+                },
+            }
+        }
+        let curr_target_table: BTreeMap<String, toml::Value> = curr_target_table
+            .iter()
+            .filter(|&(k, _)| {
+                !conditional_properties.contains_key(k) && !derived_properties.contains_key(k)
+            })
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        has_only_approved_substructures(&curr_target_table, Some(&allowed_target_subtable_names))
             .map_err(|prop_name| {
                 ConfigError::UnexpectedStructure(format!("{}.{}", curr_target_name, prop_name))
             })?;
 
-        let mut build_profile_properties: MultiMap<BuildProfile, FlatTomlProperty> =
-            MultiMap::new();
+        let mut build_profile_properties: HashMap<BuildProfile, PropertySet> = HashMap::new();
         for profile in BuildProfile::build_profiles() {
             let profile_name = profile.full_name();
             let properties = match curr_target_table
@@ -171,12 +918,9 @@ pub fn toml_to_full_manifest(raw: &toml::Value) -> Result<FullFel4Manifest, Conf
                     ))
                 })?,
             };
-            build_profile_properties
-                .entry(profile)
-                .or_insert_vec(properties);
+            build_profile_properties.insert(profile, properties);
         }
-        let mut platform_properties: MultiMap<SupportedPlatform, FlatTomlProperty> =
-            MultiMap::new();
+        let mut platform_properties: HashMap<SupportedPlatform, PropertySet> = HashMap::new();
         for platform in SupportedPlatform::platforms() {
             let platform_name = platform.full_name();
             let properties = match curr_target_table
@@ -191,10 +935,21 @@ pub fn toml_to_full_manifest(raw: &toml::Value) -> Result<FullFel4Manifest, Conf
                     ))
                 })?,
             };
-            platform_properties
-                .entry(platform)
-                .or_insert_vec(properties);
+            platform_properties.insert(platform, properties);
         }
+        let rootserver = match curr_target_table.get("rootserver") {
+            None => RootserverSettings::default(),
+            Some(v) => parse_rootserver(&format!("{}.rootserver", curr_target_name), v)?,
+        };
+        let test_properties = match curr_target_table.get("test").and_then(toml::Value::as_table) {
+            None => PropertySet::new(),
+            Some(t) => extract_flat_properties(t).map_err(|prop_name| {
+                ConfigError::UnexpectedStructure(format!(
+                    "{}.test.{}",
+                    curr_target_name, prop_name
+                ))
+            })?,
+        };
 
         let table_minus_approved_subtables = curr_target_table
             .iter()
@@ -206,6 +961,14 @@ pub fn toml_to_full_manifest(raw: &toml::Value) -> Result<FullFel4Manifest, Conf
                 ConfigError::UnexpectedStructure(format!("{}.{}", curr_target_name, prop_name))
             })?;
 
+        fel4_trace!(
+            "manifest",
+            "read [{}] with {} direct properties and {} profile and {} platform subtables",
+            curr_target_name,
+            direct_properties.len(),
+            build_profile_properties.len(),
+            platform_properties.len()
+        );
         targets.insert(
             curr_target,
             FullFel4Target {
@@ -213,26 +976,546 @@ pub fn toml_to_full_manifest(raw: &toml::Value) -> Result<FullFel4Manifest, Conf
                 direct_properties,
                 build_profile_properties,
                 platform_properties,
+                conditional_properties,
+                derived_properties,
+                rootserver,
+                test_properties,
             },
         );
     }
+    let presets = parse_presets(raw)?;
+    let variants = parse_variants(raw, &presets)?;
 
     Ok(FullFel4Manifest {
         artifact_path,
+        artifact_layout,
         target_specs_path,
         selected_target,
         selected_platform,
+        deployment,
         targets,
+        toolchains: parse_toolchains(raw)?,
+        kernel_source,
+        kernel_version,
+        kernel_commit,
+        image_name,
+        image_name_pattern,
+        source_date_epoch,
+        cmake_build_types,
+        property_scopes,
+        locked_properties,
+        presets,
+        variants,
     })
 }
 
+/// Parse the optional `[toolchain.<target>]` tables
+fn parse_toolchains(
+    raw: &toml::Value,
+) -> Result<HashMap<SupportedTarget, ToolchainSettings>, ConfigError> {
+    let mut toolchains = HashMap::new();
+    let toolchain_table = match raw.get("toolchain") {
+        None => return Ok(toolchains),
+        Some(toml::Value::Table(t)) => t,
+        Some(_) => return Err(ConfigError::UnexpectedStructure("toolchain".into())),
+    };
+    for (target_name, value) in toolchain_table {
+        let path = format!("toolchain.{}", target_name);
+        let target: SupportedTarget = match target_name.parse() {
+            Ok(t) => t,
+            // Like target tables, toolchains for targets this release does
+            // not support are ignored
+            Err(_) if is_unsupported_target_name(target_name) => continue,
+            Err(_) => return Err(ConfigError::UnexpectedStructure(path)),
+        };
+        let table = value
+            .as_table()
+            .ok_or_else(|| ConfigError::UnexpectedStructure(path.clone()))?;
+        let mut settings = ToolchainSettings::default();
+        for (k, v) in table {
+            match k.as_str() {
+                "compiler-prefix" => {
+                    settings.compiler_prefix = Some(toolchain_string(v, "compiler-prefix")?)
+                }
+                "float-abi" => {
+                    let name = toolchain_string(v, "float-abi")?;
+                    settings.float_abi = Some(name.parse().map_err(|name| {
+                        ConfigError::InvalidValueOption("float-abi", FloatAbi::abi_names(), name)
+                    })?)
+                }
+                "cmake" => settings.cmake = Some(toolchain_string(v, "cmake")?),
+                "ninja" => settings.ninja = Some(toolchain_string(v, "ninja")?),
+                "compiler-launcher" => {
+                    settings.compiler_launcher = Some(toolchain_string(v, "compiler-launcher")?)
+                }
+                "build-targets" => {
+                    settings.build_targets = v
+                        .as_array()
+                        .and_then(|targets| {
+                            targets
+                                .iter()
+                                .map(|t| t.as_str().map(str::to_string))
+                                .collect()
+                        })
+                        .ok_or(ConfigError::NonStringProperty("build-targets"))?
+                }
+                "clear-env" => {
+                    settings.cleared_env = Some(
+                        v.as_array()
+                            .and_then(|names| {
+                                names
+                                    .iter()
+                                    .map(|n| n.as_str().map(str::to_string))
+                                    .collect()
+                            })
+                            .ok_or(ConfigError::NonStringProperty("clear-env"))?,
+                    )
+                }
+                "variables" => {
+                    settings.variables = v
+                        .as_table()
+                        .ok_or_else(|| {
+                            ConfigError::UnexpectedStructure(format!("{}.variables", path))
+                        })
+                        .and_then(|t| {
+                            extract_flat_properties(t).map_err(|prop_name| {
+                                ConfigError::UnexpectedStructure(format!(
+                                    "{}.variables.{}",
+                                    path, prop_name
+                                ))
+                            })
+                        })?
+                        .into_iter()
+                        .collect()
+                }
+                platform_name => match (platform_name.parse(), v.as_table()) {
+                    (Ok(platform), Some(platform_table)) => {
+                        let platform_path = format!("{}.{}", path, platform_name);
+                        if let Some(k) = platform_table.keys().find(|k| *k != "compiler-prefix") {
+                            return Err(ConfigError::UnexpectedStructure(format!(
+                                "{}.{}",
+                                platform_path, k
+                            )));
+                        }
+                        if let Some(prefix) = platform_table.get("compiler-prefix") {
+                            settings.platform_compiler_prefixes.insert(
+                                platform,
+                                toolchain_string(prefix, "compiler-prefix")?,
+                            );
+                        }
+                    }
+                    _ => return Err(ConfigError::UnexpectedStructure(format!("{}.{}", path, k))),
+                },
+            }
+        }
+        fel4_trace!("manifest", "read [{}]", path);
+        toolchains.insert(target, settings);
+    }
+    Ok(toolchains)
+}
+
+/// Parse a `[<target>.rootserver]` table, whose sizes must be positive
+/// integers
+fn parse_rootserver(path: &str, value: &toml::Value) -> Result<RootserverSettings, ConfigError> {
+    let table = value
+        .as_table()
+        .ok_or_else(|| ConfigError::UnexpectedStructure(path.to_string()))?;
+    let mut settings = RootserverSettings::default();
+    for (k, v) in table {
+        let size = match v.as_integer() {
+            Some(i) if i > 0 => i,
+            _ => {
+                return Err(ConfigError::InvalidRootserverTable(
+                    path.to_string(),
+                    format!("{} should be a positive integer, but is {}", k, v),
+                ))
+            }
+        };
+        match k.as_str() {
+            "stack-size" => settings.stack_size = Some(size),
+            "heap-size" => settings.heap_size = Some(size),
+            "cnode-size-bits" if size < 64 => settings.cnode_size_bits = Some(size),
+            "cnode-size-bits" => {
+                return Err(ConfigError::InvalidRootserverTable(
+                    path.to_string(),
+                    format!("cnode-size-bits should be less than 64, but is {}", size),
+                ))
+            }
+            _ => return Err(ConfigError::UnexpectedStructure(format!("{}.{}", path, k))),
+        }
+    }
+    fel4_trace!("manifest", "read [{}]", path);
+    Ok(settings)
+}
+
+fn toolchain_string(value: &toml::Value, name: &'static str) -> Result<String, ConfigError> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or(ConfigError::NonStringProperty(name))
+}
+
+/// Render a `FullFel4Manifest` as manifest text that `parse_full_manifest`
+/// parses back to the same manifest, with every property table in its
+/// `PropertySet` order.
+///
+/// Target tables follow the `[fel4]` and `[toolchain]` tables, in the order
+/// of `SupportedTarget::targets`, each followed by its build profile and then
+/// its platform subtables.
+pub fn full_manifest_to_toml_string(full: &FullFel4Manifest) -> String {
+    let mut root = full_manifest_to_toml(full);
+    if let toml::Value::Table(ref mut table) = root {
+        table.retain(|name, _| name != "preset" && name.parse::<SupportedTarget>().is_err());
+    }
+    let mut text = toml::to_string(&root).expect("Manifest headers should always serialize");
+    let mut write_table = |name: String, properties: &PropertySet| {
+        text.push_str(&format!("\n[{}]\n", name));
+        for p in properties {
+            text.push_str(&format!("{} = {}\n", toml_key(&p.name), p.value));
+        }
+    };
+    for target in SupportedTarget::targets() {
+        let content = match full.targets.get(&target) {
+            Some(c) => c,
+            None => continue,
+        };
+        write_table(target.full_name().to_string(), &content.direct_properties);
+        for profile in BuildProfile::build_profiles() {
+            if let Some(properties) = content.build_profile_properties.get(&profile) {
+                write_table(
+                    format!("{}.{}", target.full_name(), profile.full_name()),
+                    properties,
+                );
+            }
+        }
+        for platform in SupportedPlatform::platforms() {
+            if let Some(properties) = content.platform_properties.get(&platform) {
+                write_table(
+                    format!("{}.{}", target.full_name(), platform.full_name()),
+                    properties,
+                );
+            }
+        }
+        if content.rootserver != RootserverSettings::default() {
+            write_table(
+                format!("{}.rootserver", target.full_name()),
+                &rootserver_properties(&content.rootserver),
+            );
+        }
+        if !content.test_properties.is_empty() {
+            write_table(
+                format!("{}.test", target.full_name()),
+                &content.test_properties,
+            );
+        }
+        for (name, conditional) in &content.conditional_properties {
+            write_table(
+                format!("{}.{}", target.full_name(), toml_key(name)),
+                &conditional.to_properties(),
+            );
+        }
+        for (name, formula) in &content.derived_properties {
+            write_table(
+                format!("{}.{}", target.full_name(), toml_key(name)),
+                &formula_properties(formula),
+            );
+        }
+    }
+    for (name, properties) in &full.presets {
+        write_table(format!("preset.{}", toml_key(name)), properties);
+    }
+    text
+}
+
+/// A key as it must be written in toml, quoted unless it is a bare key
+pub(crate) fn toml_key(name: &str) -> String {
+    let bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        name.to_string()
+    } else {
+        FlatTomlValue::String(name.to_string()).to_string()
+    }
+}
+
+/// Render a `FullFel4Manifest` as the toml structure that
+/// `toml_to_full_manifest` parses, such that the two functions round trip.
+/// Toml structures keep their keys in name order, so use
+/// `full_manifest_to_toml_string` where the order of properties matters.
+pub fn full_manifest_to_toml(full: &FullFel4Manifest) -> toml::Value {
+    let mut header = BTreeMap::new();
+    header.insert(
+        "fel4-version".to_string(),
+        toml::Value::Integer(FEL4_MANIFEST_VERSION),
+    );
+    header.insert(
+        "artifact-path".to_string(),
+        toml::Value::String(full.artifact_path.clone()),
+    );
+    if full.artifact_layout != ArtifactLayout::default() {
+        header.insert(
+            "artifact-layout".to_string(),
+            toml::Value::String(full.artifact_layout.full_name().to_string()),
+        );
+    }
+    header.insert(
+        "target-specs-path".to_string(),
+        toml::Value::String(full.target_specs_path.clone()),
+    );
+    header.insert(
+        "target".to_string(),
+        toml::Value::String(full.selected_target.full_name().to_string()),
+    );
+    header.insert(
+        "platform".to_string(),
+        toml::Value::String(full.selected_platform.full_name().to_string()),
+    );
+    if let Some(deployment) = full.deployment {
+        header.insert(
+            "deployment".to_string(),
+            toml::Value::String(deployment.full_name().to_string()),
+        );
+    }
+    let optional_strings = [
+        ("kernel-version", &full.kernel_version),
+        ("kernel-commit", &full.kernel_commit),
+        ("image-name", &full.image_name),
+        ("image-name-pattern", &full.image_name_pattern),
+    ];
+    for &(name, value) in &optional_strings {
+        if let Some(v) = value.as_ref() {
+            header.insert(name.to_string(), toml::Value::String(v.clone()));
+        }
+    }
+    match full.source_date_epoch {
+        Some(SourceDateEpoch::Fixed(seconds)) => {
+            header.insert(
+                "source-date-epoch".to_string(),
+                toml::Value::Integer(seconds),
+            );
+        }
+        Some(epoch) => {
+            header.insert(
+                "source-date-epoch".to_string(),
+                toml::Value::String(epoch.to_string()),
+            );
+        }
+        None => {}
+    }
+    if let Some(ref source) = full.kernel_source {
+        let mut table = BTreeMap::new();
+        let entries = match *source {
+            KernelSource::Git { ref url, ref rev } => vec![("git", Some(url)), ("rev", rev.as_ref())],
+            KernelSource::Tarball {
+                ref url,
+                ref sha256,
+            } => vec![("tarball", Some(url)), ("sha256", Some(sha256))],
+        };
+        for (name, value) in entries {
+            if let Some(v) = value {
+                table.insert(name.to_string(), toml::Value::String(v.clone()));
+            }
+        }
+        header.insert("kernel-source".to_string(), toml::Value::Table(table));
+    }
+    if !full.cmake_build_types.is_empty() {
+        let table = full
+            .cmake_build_types
+            .iter()
+            .map(|(profile, build_type)| {
+                (
+                    profile.full_name().to_string(),
+                    toml::Value::String(build_type.clone()),
+                )
+            })
+            .collect();
+        header.insert("cmake-build-types".to_string(), toml::Value::Table(table));
+    }
+    if !full.property_scopes.is_empty() {
+        let table = full
+            .property_scopes
+            .iter()
+            .map(|(name, scope)| (name.clone(), toml::Value::String(scope.to_string())))
+            .collect();
+        header.insert("property-scopes".to_string(), toml::Value::Table(table));
+    }
+    if !full.locked_properties.is_empty() {
+        let names = full
+            .locked_properties
+            .iter()
+            .map(|name| toml::Value::String(name.clone()))
+            .collect();
+        header.insert("locked-properties".to_string(), toml::Value::Array(names));
+    }
+    let mut root = BTreeMap::new();
+    root.insert("fel4".to_string(), toml::Value::Table(header));
+    for (target, target_content) in &full.targets {
+        let mut table = properties_to_table(&target_content.direct_properties);
+        for (profile, properties) in &target_content.build_profile_properties {
+            table.insert(
+                profile.full_name().to_string(),
+                toml::Value::Table(properties_to_table(properties)),
+            );
+        }
+        for (platform, properties) in &target_content.platform_properties {
+            table.insert(
+                platform.full_name().to_string(),
+                toml::Value::Table(properties_to_table(properties)),
+            );
+        }
+        if target_content.rootserver != RootserverSettings::default() {
+            table.insert(
+                "rootserver".to_string(),
+                toml::Value::Table(properties_to_table(&rootserver_properties(
+                    &target_content.rootserver,
+                ))),
+            );
+        }
+        if !target_content.test_properties.is_empty() {
+            table.insert(
+                "test".to_string(),
+                toml::Value::Table(properties_to_table(&target_content.test_properties)),
+            );
+        }
+        for (name, conditional) in &target_content.conditional_properties {
+            table.insert(
+                name.clone(),
+                toml::Value::Table(properties_to_table(&conditional.to_properties())),
+            );
+        }
+        for (name, formula) in &target_content.derived_properties {
+            table.insert(
+                name.clone(),
+                toml::Value::Table(properties_to_table(&formula_properties(formula))),
+            );
+        }
+        root.insert(target.full_name().to_string(), toml::Value::Table(table));
+    }
+    let mut toolchain_table = BTreeMap::new();
+    for (target, settings) in &full.toolchains {
+        let mut table = BTreeMap::new();
+        let strings = [
+            ("compiler-prefix", &settings.compiler_prefix),
+            ("cmake", &settings.cmake),
+            ("ninja", &settings.ninja),
+            ("compiler-launcher", &settings.compiler_launcher),
+        ];
+        for &(name, value) in &strings {
+            if let Some(v) = value.as_ref() {
+                table.insert(name.to_string(), toml::Value::String(v.clone()));
+            }
+        }
+        if let Some(abi) = settings.float_abi {
+            table.insert(
+                "float-abi".to_string(),
+                toml::Value::String(abi.full_name().to_string()),
+            );
+        }
+        if !settings.build_targets.is_empty() {
+            table.insert(
+                "build-targets".to_string(),
+                toml::Value::Array(
+                    settings
+                        .build_targets
+                        .iter()
+                        .map(|t| toml::Value::String(t.clone()))
+                        .collect(),
+                ),
+            );
+        }
+        if let Some(ref names) = settings.cleared_env {
+            table.insert(
+                "clear-env".to_string(),
+                toml::Value::Array(
+                    names
+                        .iter()
+                        .map(|n| toml::Value::String(n.clone()))
+                        .collect(),
+                ),
+            );
+        }
+        if !settings.variables.is_empty() {
+            table.insert(
+                "variables".to_string(),
+                toml::Value::Table(properties_to_table(&settings.variables)),
+            );
+        }
+        for (platform, prefix) in &settings.platform_compiler_prefixes {
+            let mut platform_table = BTreeMap::new();
+            platform_table.insert(
+                "compiler-prefix".to_string(),
+                toml::Value::String(prefix.clone()),
+            );
+            table.insert(
+                platform.full_name().to_string(),
+                toml::Value::Table(platform_table),
+            );
+        }
+        toolchain_table.insert(target.full_name().to_string(), toml::Value::Table(table));
+    }
+    if !toolchain_table.is_empty() {
+        root.insert("toolchain".to_string(), toml::Value::Table(toolchain_table));
+    }
+    if !full.presets.is_empty() {
+        let presets = full
+            .presets
+            .iter()
+            .map(|(name, properties)| {
+                (name.clone(), toml::Value::Table(properties_to_table(properties)))
+            })
+            .collect();
+        root.insert("preset".to_string(), toml::Value::Table(presets));
+    }
+    if !full.variants.is_empty() {
+        let variants = full
+            .variants
+            .iter()
+            .map(|(name, variant)| (name.clone(), variant_to_toml(variant)))
+            .collect();
+        root.insert("variant".to_string(), toml::Value::Table(variants));
+    }
+    toml::Value::Table(root)
+}
+
+/// The sizes a `RootserverSettings` sets, as the `[<target>.rootserver]`
+/// table holds them
+fn rootserver_properties(settings: &RootserverSettings) -> PropertySet {
+    let sizes = [
+        ("stack-size", settings.stack_size),
+        ("heap-size", settings.heap_size),
+        ("cnode-size-bits", settings.cnode_size_bits),
+    ];
+    PropertySet::from_properties(sizes.iter().filter_map(|&(name, size)| {
+        size.map(|s| FlatTomlProperty::new(name.to_string(), FlatTomlValue::Integer(s)))
+    })).expect("Rootserver keys are unique")
+}
+
+/// A derived property's `{ formula = "..." }` table
+fn formula_properties(formula: &Formula) -> PropertySet {
+    PropertySet::from_properties(Some(FlatTomlProperty::new(
+        "formula".to_string(),
+        FlatTomlValue::String(formula.to_string()),
+    ))).expect("A single property is unique")
+}
+
+fn properties_to_table<'a, I: IntoIterator<Item = &'a FlatTomlProperty>>(
+    properties: I,
+) -> BTreeMap<String, toml::Value> {
+    properties
+        .into_iter()
+        .map(|p| (p.name.clone(), toml::Value::from(&p.value)))
+        .collect()
+}
+
 fn has_only_approved_substructures(
     map: &BTreeMap<String, toml::Value>,
     approved_substructures: Option<&HashSet<String>>,
 ) -> Result<(), String> {
     for (k, v) in map {
         match v {
-            &toml::Value::Array(_) | toml::Value::Table(_) => {
+            toml::Value::Array(_) | toml::Value::Table(_) => {
                 if let Some(substructure_whitelist) = approved_substructures {
                     if substructure_whitelist.contains(k) {
                         continue;
@@ -249,27 +1532,20 @@ fn has_only_approved_substructures(
     Ok(())
 }
 
-fn extract_flat_properties(
+pub(crate) fn extract_flat_properties(
     table: &BTreeMap<String, toml::Value>,
-) -> Result<Vec<FlatTomlProperty>, String> {
-    let mut v = Vec::new();
+) -> Result<PropertySet, String> {
+    let mut set = PropertySet::new();
     for (prop_name, value) in table {
-        let flat_value = match value {
-            &toml::Value::String(ref v) => FlatTomlValue::String(v.to_string()),
-            &toml::Value::Integer(v) => FlatTomlValue::Integer(v),
-            &toml::Value::Float(v) => FlatTomlValue::Float(v),
-            &toml::Value::Boolean(v) => FlatTomlValue::Boolean(v),
-            &toml::Value::Datetime(ref v) => FlatTomlValue::Datetime(v.clone()),
-            &toml::Value::Array(_) | toml::Value::Table(_) => {
-                return Err(prop_name.to_string());
-            }
-        };
-        v.push(FlatTomlProperty::new(prop_name.to_string(), flat_value));
+        let flat_value = FlatTomlValue::from_toml(value).ok_or_else(|| prop_name.to_string())?;
+        set.insert(FlatTomlProperty::new(prop_name.to_string(), flat_value))
+            .expect("Toml tables never repeat a key");
     }
-    Ok(v)
+    Ok(set)
 }
 #[cfg(test)]
 mod tests {
+    use super::super::{get_exemplar_default_toml, resolve_fel4_config};
     use super::*;
     use std::path::PathBuf;
 
@@ -281,6 +1557,122 @@ mod tests {
         );
     }
 
+    #[test]
+    fn selection_builders_validate_the_selection() {
+        let full = parse_full_manifest(
+            r#"[fel4]
+            target = "x86_64-sel4-fel4"
+            platform = "pc99"
+            artifact-path = "artifacts/path/nested"
+            target-specs-path = "where/are/rust/targets"
+            [x86_64-sel4-fel4]
+            KernelArch = "x86"
+            [armv7-sel4-fel4]
+            KernelArch = "arm"
+            "#,
+        ).expect("Should have been able to parse manifest");
+        assert_eq!(
+            Err(ConfigError::TargetPlatformMismatch(
+                SupportedTarget::Armv7Sel4Fel4,
+                SupportedPlatform::PC99
+            )),
+            full.clone()
+                .with_selected_target(SupportedTarget::Armv7Sel4Fel4)
+        );
+        assert_eq!(
+            Err(ConfigError::TargetPlatformMismatch(
+                SupportedTarget::X8664Sel4Fel4,
+                SupportedPlatform::Tx1
+            )),
+            full.clone().with_selected_platform(SupportedPlatform::Tx1)
+        );
+        assert_eq!(
+            Err(ConfigError::MissingTargetTable(
+                SupportedTarget::Aarch64Sel4Fel4
+            )),
+            full.clone()
+                .with_selection(SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1)
+        );
+        let armv7 = full
+            .with_selection(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
+            .expect("Should be able to select a declared target");
+        assert_eq!(SupportedTarget::Armv7Sel4Fel4, armv7.selected_target);
+        assert_eq!(SupportedPlatform::Sabre, armv7.selected_platform);
+    }
+
+    #[test]
+    fn exemplar_manifest_round_trips_through_toml() {
+        let full = parse_full_manifest(super::super::get_exemplar_default_toml())
+            .expect("Should be able to parse the exemplar");
+        let rendered = toml::to_string(&full_manifest_to_toml(&full))
+            .expect("Should be able to serialize the manifest");
+        assert_eq!(
+            Ok(full),
+            parse_full_manifest(rendered)
+        );
+    }
+
+    #[test]
+    fn properties_keep_their_declaration_order() {
+        let text = super::super::get_exemplar_default_toml();
+        let full = parse_full_manifest(text).expect("Should be able to parse the exemplar");
+        let names = |full: &FullFel4Manifest| -> Vec<String> {
+            full.targets[&SupportedTarget::X8664Sel4Fel4]
+                .direct_properties
+                .iter()
+                .map(|p| p.name.clone())
+                .collect()
+        };
+        let declared = names(&full);
+        assert_eq!(
+            vec![
+                "BuildWithCommonSimulationSettings",
+                "KernelOptimisation",
+                "KernelVerificationBuild"
+            ],
+            declared[..3].to_vec()
+        );
+        assert_eq!(Some("UserLinkerGCSections"), declared.last().map(String::as_str));
+
+        let rewritten = parse_full_manifest(full_manifest_to_toml_string(&full))
+            .expect("Should be able to parse the rewritten manifest");
+        assert_eq!(full, rewritten);
+        assert_eq!(declared, names(&rewritten));
+
+        let config = super::super::resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        let resolved: Vec<&str> = config.ordered_properties().map(|(name, _)| name).collect();
+        assert_eq!(
+            declared.iter().map(String::as_str).collect::<Vec<_>>(),
+            resolved[..declared.len()].to_vec()
+        );
+    }
+
+    #[test]
+    fn windows_edited_manifests_are_read() {
+        let exemplar = super::super::get_exemplar_default_toml();
+        let expected = parse_full_manifest(exemplar);
+        let windows = format!("\u{feff}{}", exemplar.replace('\n', "\r\n"));
+        assert_eq!(expected, parse_full_manifest(&windows));
+        assert_eq!(
+            Ok(exemplar.to_string()),
+            decode_manifest(windows.as_bytes())
+        );
+    }
+
+    #[test]
+    fn manifests_in_other_encodings_are_rejected() {
+        assert_eq!(
+            Err(ConfigError::InvalidEncoding("it is UTF-16 encoded".into())),
+            decode_manifest(&[0xFF, 0xFE, b'[', 0])
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidEncoding(
+                "byte 7 is not valid UTF-8".into()
+            )),
+            decode_manifest(b"[fel4]\n\xe9")
+        );
+    }
+
     #[test]
     fn non_toml_file_unparseable() {
         assert_eq!(
@@ -343,6 +1735,244 @@ mod tests {
         );
     }
 
+    #[test]
+    fn toolchain_tables_are_parsed_per_target() {
+        let text = format!(
+            "{}\n{}",
+            get_exemplar_default_toml(),
+            r#"[toolchain.armv7-sel4-fel4]
+            compiler-prefix = "arm-none-eabi-"
+            float-abi = "hf"
+            ninja = "/opt/ninja/bin/ninja"
+            compiler-launcher = "sccache"
+            build-targets = ["kernel.elf", "sel4"]
+            clear-env = ["CFLAGS", "LDFLAGS"]
+            [toolchain.armv7-sel4-fel4.variables]
+            GCC_SYSROOT = "/opt/sysroot"
+            [toolchain.armv7-sel4-fel4.sabre]
+            compiler-prefix = "arm-fsl-linux-gnueabihf-"
+            "#
+        );
+        let full = parse_full_manifest(text).expect("Toolchain tables should parse");
+        assert_eq!(
+            Some(&ToolchainSettings {
+                compiler_prefix: Some("arm-none-eabi-".into()),
+                platform_compiler_prefixes: vec![(
+                    SupportedPlatform::Sabre,
+                    "arm-fsl-linux-gnueabihf-".to_string(),
+                )].into_iter()
+                    .collect(),
+                float_abi: Some(FloatAbi::Hard),
+                cmake: None,
+                ninja: Some("/opt/ninja/bin/ninja".into()),
+                compiler_launcher: Some("sccache".into()),
+                variables: vec![FlatTomlProperty::new(
+                    "GCC_SYSROOT".into(),
+                    FlatTomlValue::String("/opt/sysroot".into())
+                )],
+                build_targets: vec![KERNEL_BUILD_TARGET.into(), LIBSEL4_BUILD_TARGET.into()],
+                cleared_env: Some(vec!["CFLAGS".into(), "LDFLAGS".into()]),
+            }),
+            full.toolchains.get(&SupportedTarget::Armv7Sel4Fel4)
+        );
+        assert!(!full.toolchains.contains_key(&SupportedTarget::X8664Sel4Fel4));
+    }
+
+    #[test]
+    fn toolchain_tables_reject_unknown_keys_and_targets() {
+        for (extra, expected) in [
+            (
+                "[toolchain.x86_64-sel4-fel4]\ncompiler = \"gcc\"",
+                ConfigError::UnexpectedStructure("toolchain.x86_64-sel4-fel4.compiler".into()),
+            ),
+            (
+                "[toolchain.riscv]\ncmake = \"cmake\"",
+                ConfigError::UnexpectedStructure("toolchain.riscv".into()),
+            ),
+            (
+                "[toolchain.armv7-sel4-fel4.sabre]\ncmake = \"cmake\"",
+                ConfigError::UnexpectedStructure("toolchain.armv7-sel4-fel4.sabre.cmake".into()),
+            ),
+            (
+                "[toolchain.armv7-sel4-fel4]\nfloat-abi = \"softfp\"",
+                ConfigError::InvalidValueOption(
+                    "float-abi",
+                    FloatAbi::abi_names(),
+                    "softfp".into(),
+                ),
+            ),
+            (
+                "[toolchain.x86_64-sel4-fel4]\ncmake = 3",
+                ConfigError::NonStringProperty("cmake"),
+            ),
+            (
+                "[toolchain.x86_64-sel4-fel4]\nbuild-targets = \"kernel.elf\"",
+                ConfigError::NonStringProperty("build-targets"),
+            ),
+        ] {
+            let text = format!("{}\n{}", get_exemplar_default_toml(), extra);
+            assert_eq!(Err(expected), parse_full_manifest(text));
+        }
+    }
+
+    #[test]
+    fn names_from_newer_releases_are_unsupported_rather_than_invalid() {
+        let newer = format!(
+            "{}\n[toolchain.riscv-sel4-fel4]\ncmake = \"cmake\"\n[riscv-sel4-fel4.qemu]\n",
+            get_exemplar_default_toml()
+        );
+        assert!(parse_full_manifest(&newer).is_ok());
+        assert_eq!(
+            Err(ConfigError::UnsupportedTarget(
+                "riscv-sel4-fel4".into(),
+                SupportedTarget::target_names()
+            )),
+            parse_full_manifest(newer.replace(
+                "target = \"x86_64-sel4-fel4\"",
+                "target = \"riscv-sel4-fel4\""
+            ))
+        );
+        assert_eq!(
+            Err(ConfigError::UnsupportedPlatform(
+                "qemu".into(),
+                SupportedPlatform::platform_names()
+            )),
+            parse_full_manifest(newer.replace("platform = \"pc99\"", "platform = \"qemu\""))
+        );
+    }
+
+    #[test]
+    fn kernel_source_tables_are_parsed() {
+        let header = r#"[fel4]
+            target = "x86_64-sel4-fel4"
+            platform = "pc99"
+            artifact-path = "artifacts"
+            target-specs-path = "target_specs"
+            "#;
+        let git = parse_full_manifest(format!(
+            "{}[fel4.kernel-source]\ngit = \"https://github.com/seL4/seL4.git\"\ntag = \"10.0.0\"",
+            header
+        )).expect("A git kernel source should parse");
+        assert_eq!(
+            Some(KernelSource::Git {
+                url: "https://github.com/seL4/seL4.git".into(),
+                rev: Some("10.0.0".into()),
+            }),
+            git.kernel_source
+        );
+        let pinned = parse_full_manifest(format!(
+            "{}kernel-version = \"10.0.0\"\nkernel-commit = \"a1b2c3d\"",
+            header
+        )).expect("Kernel expectations should parse");
+        assert_eq!(Some("10.0.0".to_string()), pinned.kernel_version);
+        assert_eq!(Some("a1b2c3d".to_string()), pinned.kernel_commit);
+        for commit in &["", "a1b2c3", "v10.0.0"] {
+            match parse_full_manifest(format!("{}kernel-commit = {:?}", header, commit)) {
+                Err(ConfigError::InvalidKernelCommit(ref c, _)) => assert_eq!(commit, c),
+                other => panic!("Expected {:?} to be rejected, got {:?}", commit, other),
+            }
+        }
+        assert_eq!(
+            Err(ConfigError::MissingRequiredProperty(
+                "fel4.kernel-source".into(),
+                "sha256".into()
+            )),
+            parse_full_manifest(format!(
+                "{}[fel4.kernel-source]\ntarball = \"https://example.com/seL4.tar.gz\"",
+                header
+            ))
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidKernelSource(
+                "only one of git or tarball may be given".into()
+            )),
+            parse_full_manifest(format!(
+                "{}[fel4.kernel-source]\ngit = \"a\"\ntarball = \"b\"",
+                header
+            ))
+        );
+        for (key, value) in &[
+            ("git", "--upload-pack=touch /tmp/pwned"),
+            ("rev", "-b"),
+            ("tarball", "-o/etc/passwd"),
+        ] {
+            let table = if *key == "rev" {
+                format!("git = \"https://github.com/seL4/seL4.git\"\nrev = {:?}", value)
+            } else {
+                format!("{} = {:?}", key, value)
+            };
+            assert_eq!(
+                Err(ConfigError::InvalidKernelSource(format!(
+                    "{} may not begin with -",
+                    key
+                ))),
+                parse_full_manifest(format!("{}[fel4.kernel-source]\n{}", header, table))
+            );
+        }
+    }
+
+    #[test]
+    fn cmake_build_types_can_be_overridden_per_profile() {
+        let text = get_exemplar_default_toml().replace(
+            "[fel4]",
+            "[fel4.cmake-build-types]\nrelease = \"MinSizeRel\"\n\n[fel4]",
+        );
+        let full = parse_full_manifest(&text).expect("Build types should parse");
+        assert_eq!("MinSizeRel", full.cmake_build_type(&BuildProfile::Release));
+        assert_eq!("Debug", full.cmake_build_type(&BuildProfile::Debug));
+        assert_eq!(
+            "MinSizeRel",
+            resolve_fel4_config(full, &BuildProfile::Release)
+                .unwrap()
+                .cmake_build_type
+        );
+        assert_eq!(
+            Err(ConfigError::UnexpectedStructure(
+                "fel4.cmake-build-types.bench".into()
+            )),
+            parse_full_manifest(text.replace("release = ", "bench = "))
+        );
+        assert_eq!(
+            Err(ConfigError::NonStringProperty("cmake-build-types")),
+            parse_full_manifest(text.replace("\"MinSizeRel\"", "3"))
+        );
+    }
+
+    #[test]
+    fn property_scopes_partition_resolved_properties() {
+        let text = get_exemplar_default_toml().replace(
+            "[fel4]",
+            "[fel4.property-scopes]\nKernelPrinting = \"component-local\"\n\n[fel4]",
+        );
+        let full = parse_full_manifest(&text).expect("Property scopes should parse");
+        assert_eq!(Ok(full.clone()), parse_full_manifest(full_manifest_to_toml_string(&full)));
+        let config = resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        assert_eq!(
+            PropertyScope::ComponentLocal,
+            config.property_scope("KernelPrinting")
+        );
+        assert!(config.abi_affecting_properties().any(|(name, _)| name == "KernelNumPriorities"));
+        assert!(config.abi_affecting_properties().all(|(name, _)| name != "KernelPrinting"));
+        assert!(config.component_local_properties().any(|(name, _)| name == "KernelPrinting"));
+        assert_eq!(
+            config.properties.len(),
+            config.abi_affecting_properties().count() + config.component_local_properties().count()
+        );
+
+        assert_eq!(
+            Err(ConfigError::NonWhitelistProperty("KernelBogus".into())),
+            parse_full_manifest(text.replace("KernelPrinting = \"", "KernelBogus = \""))
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidValueOption(
+                "property-scopes",
+                PropertyScope::scope_names(),
+                "global".into()
+            )),
+            parse_full_manifest(text.replace("\"component-local\"", "\"global\""))
+        );
+    }
+
     #[test]
     fn fel4_table_invalid_platform() {
         assert_eq!(
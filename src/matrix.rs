@@ -0,0 +1,220 @@
+/// Resolution and validation of every target, platform and build profile
+/// combination a manifest describes
+#[cfg(feature = "parallel")]
+use std::thread;
+
+use super::{
+    is_supported_target_platform_pair, resolve_selection, ConfigError, ConfigWarning,
+    ResolutionOptions,
+};
+use manifest::FullFel4Manifest;
+use types::*;
+
+/// A single cell of a build matrix
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BuildMatrixEntry {
+    pub target: SupportedTarget,
+    pub platform: SupportedPlatform,
+    pub build_profile: BuildProfile,
+}
+
+/// The outcome of resolving one build matrix entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildMatrixResult {
+    pub entry: BuildMatrixEntry,
+    pub outcome: Result<Vec<ConfigWarning>, ConfigError>,
+}
+
+/// The aggregated diagnostics from validating a whole build matrix
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BuildMatrixReport {
+    /// One result per entry, in `build_matrix` order
+    pub results: Vec<BuildMatrixResult>,
+}
+
+impl BuildMatrixReport {
+    /// True if every entry resolved without error
+    pub fn is_valid(&self) -> bool {
+        self.results.iter().all(|r| r.outcome.is_ok())
+    }
+
+    /// The entries that failed to resolve, with their errors
+    pub fn errors(&self) -> Vec<(BuildMatrixEntry, &ConfigError)> {
+        self.results
+            .iter()
+            .filter_map(|r| r.outcome.as_ref().err().map(|e| (r.entry, e)))
+            .collect()
+    }
+
+    /// The warnings produced by entries that did resolve
+    pub fn warnings(&self) -> Vec<(BuildMatrixEntry, &ConfigWarning)> {
+        self.results
+            .iter()
+            .filter_map(|r| r.outcome.as_ref().ok().map(|w| (r.entry, w)))
+            .flat_map(|(entry, warnings)| warnings.iter().map(move |w| (entry, w)))
+            .collect()
+    }
+}
+
+/// Every supported (target, platform, build profile) combination for the
+/// targets the manifest has tables for, ordered by target, platform, then
+/// build profile
+pub fn build_matrix(full: &FullFel4Manifest) -> Vec<BuildMatrixEntry> {
+    let mut entries = Vec::new();
+    for target in SupportedTarget::targets() {
+        if !full.targets.contains_key(&target) {
+            continue;
+        }
+        for platform in SupportedPlatform::platforms() {
+            if !is_supported_target_platform_pair(target, platform) {
+                continue;
+            }
+            for build_profile in BuildProfile::build_profiles() {
+                entries.push(BuildMatrixEntry {
+                    target,
+                    platform,
+                    build_profile,
+                });
+            }
+        }
+    }
+    entries
+}
+
+/// Resolve the manifest for a single build matrix entry, as though its
+/// `[fel4]` table had selected the entry's target and platform
+pub fn resolve_build_matrix_entry(
+    full: &FullFel4Manifest,
+    entry: BuildMatrixEntry,
+    options: &ResolutionOptions,
+) -> BuildMatrixResult {
+    BuildMatrixResult {
+        entry,
+        outcome: resolve_selection(
+            full,
+            entry.target,
+            entry.platform,
+            &entry.build_profile,
+            options,
+        ).map(|(_config, warnings)| warnings),
+    }
+}
+
+/// Resolve every entry of the manifest's `build_matrix`, collecting the
+/// errors and warnings of each.
+///
+/// With the `parallel` feature enabled the entries are resolved across
+/// threads, one per available CPU; the report is in the same order either
+/// way.
+pub fn validate_build_matrix(
+    full: &FullFel4Manifest,
+    options: &ResolutionOptions,
+) -> BuildMatrixReport {
+    let entries = build_matrix(full);
+    BuildMatrixReport {
+        results: resolve_entries(full, &entries, options),
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn resolve_entries(
+    full: &FullFel4Manifest,
+    entries: &[BuildMatrixEntry],
+    options: &ResolutionOptions,
+) -> Vec<BuildMatrixResult> {
+    entries
+        .iter()
+        .map(|&entry| resolve_build_matrix_entry(full, entry, options))
+        .collect()
+}
+
+#[cfg(feature = "parallel")]
+fn resolve_entries(
+    full: &FullFel4Manifest,
+    entries: &[BuildMatrixEntry],
+    options: &ResolutionOptions,
+) -> Vec<BuildMatrixResult> {
+    if entries.is_empty() {
+        return Vec::new();
+    }
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let chunk_size = entries.len().div_ceil(workers);
+    thread::scope(|scope| {
+        let handles: Vec<_> = entries
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&entry| resolve_build_matrix_entry(full, entry, options))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Build matrix resolution panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn exemplar_matrix_covers_every_pair_and_profile() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let matrix = build_matrix(&full);
+        assert_eq!(8, matrix.len());
+        assert_eq!(
+            BuildMatrixEntry {
+                target: SupportedTarget::X8664Sel4Fel4,
+                platform: SupportedPlatform::PC99,
+                build_profile: BuildProfile::Debug,
+            },
+            matrix[0]
+        );
+        let report = validate_build_matrix(&full, &ResolutionOptions::default());
+        assert!(report.is_valid());
+        assert_eq!(
+            matrix,
+            report.results.iter().map(|r| r.entry).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn matrix_report_aggregates_errors_and_warnings() {
+        let toml = test_helpers::ManifestFixture::new()
+            .without_table("armv7-sel4-fel4.release")
+            .to_toml_string();
+        let full = parse_full_manifest(&toml).expect("Should parse");
+        let strict = validate_build_matrix(&full, &ResolutionOptions::default());
+        assert!(!strict.is_valid());
+        assert_eq!(
+            vec![(
+                BuildMatrixEntry {
+                    target: SupportedTarget::Armv7Sel4Fel4,
+                    platform: SupportedPlatform::Sabre,
+                    build_profile: BuildProfile::Release,
+                },
+                &ConfigError::MissingProfileTable(
+                    SupportedTarget::Armv7Sel4Fel4,
+                    BuildProfile::Release
+                )
+            )],
+            strict.errors()
+        );
+
+        let lenient = validate_build_matrix(
+            &full,
+            &ResolutionOptions::new().lenient_subtables(true),
+        );
+        assert!(lenient.is_valid());
+        assert_eq!(1, lenient.warnings().len());
+    }
+}
@@ -0,0 +1,344 @@
+/// Upgrading manifests written for older releases of fel4-config and
+/// cargo-fel4 to the current manifest format
+use std::fmt::{Display, Error as FmtError, Formatter};
+use toml;
+
+use super::ConfigError;
+use manifest::{convert_legacy_layout, normalize_manifest_text, FEL4_MANIFEST_VERSION};
+use types::*;
+
+/// A single change `migrate_manifest` made to a manifest
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum MigrationChange {
+    /// A key of the given table was renamed from its old spelling
+    RenamedKey {
+        table: String,
+        from: String,
+        to: String,
+    },
+    /// The properties of the legacy `[fel4]` table were moved to a table for
+    /// the selected target
+    MovedLegacyProperties(SupportedTarget),
+    /// The `fel4-version` of the `[fel4]` table was set
+    SetVersion(i64),
+}
+
+impl Display for MigrationChange {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            MigrationChange::RenamedKey {
+                ref table,
+                ref from,
+                ref to,
+            } => write!(f, "renamed {} to {} in [{}]", from, to, table),
+            MigrationChange::MovedLegacyProperties(t) => {
+                write!(f, "moved the properties of [fel4] to [{}]", t)
+            }
+            MigrationChange::SetVersion(v) => write!(f, "set fel4-version = {}", v),
+        }
+    }
+}
+
+/// What `migrate_manifest` did to bring a manifest up to date
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrationReport {
+    /// The `fel4-version` the manifest was read as, where manifests without
+    /// one are version 1
+    pub from_version: i64,
+    pub to_version: i64,
+    /// Every change made, in the order they were made
+    pub changes: Vec<MigrationChange>,
+}
+
+impl MigrationReport {
+    /// True if the manifest was already in the current format
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Upgrades a manifest from `from_version` to the version after it
+struct Migration {
+    from_version: i64,
+    apply: fn(&mut toml::value::Table, &mut Vec<MigrationChange>) -> Result<(), ConfigError>,
+}
+
+/// Every upgrade step, in version order
+const MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 1,
+    apply: migrate_from_unversioned,
+}];
+
+/// `[fel4]` keys spelled with underscores by early cargo-fel4 releases
+const RENAMED_HEADER_KEYS: &[(&str, &str)] = &[
+    ("artifact_path", "artifact-path"),
+    ("target_specs_path", "target-specs-path"),
+];
+
+/// Property names accepted by early cargo-fel4 releases, with the names
+/// that replaced them
+const RENAMED_PROPERTIES: &[(&str, &str)] = &[
+    ("KernelArmPlatform", "KernelARMPlatform"),
+    ("KernelX86MicroArchitecture", "KernelX86MicroArch"),
+];
+
+/// Upgrade the text of a fel4 manifest to the current `fel4-version`,
+/// returning the upgraded manifest and a report of what changed.
+///
+/// Manifests without a `fel4-version` are treated as version 1, the format
+/// written by early cargo-fel4 releases. Upgrading a manifest that is
+/// already current is harmless, though it gains an explicit `fel4-version`.
+///
+/// The upgraded manifest is rewritten from its parsed structure, so comments
+/// and formatting are not preserved.
+pub fn migrate_manifest<S: AsRef<str>>(
+    old_toml: S,
+) -> Result<(String, MigrationReport), ConfigError> {
+    let mut raw = normalize_manifest_text(old_toml.as_ref())
+        .parse::<toml::Value>()
+        .map_err(|_| ConfigError::TomlParseFailure)?;
+    let root = raw
+        .as_table_mut()
+        .ok_or_else(|| ConfigError::MissingTable("fel4".into()))?;
+    let from_version = match root
+        .get("fel4")
+        .and_then(toml::Value::as_table)
+        .ok_or_else(|| ConfigError::MissingTable("fel4".into()))?
+        .get("fel4-version")
+    {
+        None => 1,
+        Some(toml::Value::Integer(v)) if *v > FEL4_MANIFEST_VERSION => {
+            return Err(ConfigError::UnsupportedManifestVersion(*v))
+        }
+        Some(toml::Value::Integer(v)) if *v > 0 => *v,
+        Some(v) => return Err(ConfigError::InvalidManifestVersion(v.to_string())),
+    };
+
+    let mut changes = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.from_version >= from_version) {
+        (migration.apply)(root, &mut changes)?;
+    }
+    if root.get("fel4").and_then(|t| t.get("fel4-version"))
+        != Some(&toml::Value::Integer(FEL4_MANIFEST_VERSION))
+    {
+        if let Some(toml::Value::Table(header)) = root.get_mut("fel4") {
+            header.insert(
+                "fel4-version".to_string(),
+                toml::Value::Integer(FEL4_MANIFEST_VERSION),
+            );
+        }
+        changes.push(MigrationChange::SetVersion(FEL4_MANIFEST_VERSION));
+    }
+
+    let text = toml::to_string(&raw).map_err(|_| ConfigError::TomlParseFailure)?;
+    Ok((
+        text,
+        MigrationReport {
+            from_version,
+            to_version: FEL4_MANIFEST_VERSION,
+            changes,
+        },
+    ))
+}
+
+/// Version 1 to 2: hyphenate the `[fel4]` keys, move the properties of the
+/// legacy layout into target tables, and adopt the current property names in
+/// every target table and subtable
+fn migrate_from_unversioned(
+    root: &mut toml::value::Table,
+    changes: &mut Vec<MigrationChange>,
+) -> Result<(), ConfigError> {
+    if let Some(toml::Value::Table(header)) = root.get_mut("fel4") {
+        rename_keys(header, "fel4", RENAMED_HEADER_KEYS, changes)?;
+    }
+    if let Some((converted, target)) = convert_legacy_layout(root) {
+        *root = converted;
+        changes.push(MigrationChange::MovedLegacyProperties(target));
+    }
+    for (name, value) in root.iter_mut() {
+        if name.parse::<SupportedTarget>().is_err() {
+            continue;
+        }
+        if let toml::Value::Table(ref mut target) = *value {
+            rename_keys(target, name, RENAMED_PROPERTIES, changes)?;
+            for (subtable_name, subtable) in target.iter_mut() {
+                if let toml::Value::Table(ref mut subtable) = *subtable {
+                    let path = format!("{}.{}", name, subtable_name);
+                    rename_keys(subtable, &path, RENAMED_PROPERTIES, changes)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renaming onto a key that is already present would silently discard one of
+/// the two values, so is a `DuplicateProperty` error
+fn rename_keys(
+    table: &mut toml::value::Table,
+    table_path: &str,
+    renames: &[(&str, &str)],
+    changes: &mut Vec<MigrationChange>,
+) -> Result<(), ConfigError> {
+    for &(from, to) in renames {
+        if let Some(value) = table.remove(from) {
+            if let Some(existing) = table.get(to) {
+                return Err(ConfigError::DuplicateProperty(
+                    to.to_string(),
+                    PropertyDefinition {
+                        table: table_path.to_string(),
+                        value: existing.to_string(),
+                    },
+                    PropertyDefinition {
+                        table: table_path.to_string(),
+                        value: value.to_string(),
+                    },
+                ));
+            }
+            table.insert(to.to_string(), value);
+            changes.push(MigrationChange::RenamedKey {
+                table: table_path.to_string(),
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn unversioned_manifests_are_upgraded() {
+        let legacy = get_exemplar_default_toml()
+            .replace("artifact-path", "artifact_path")
+            .replace("KernelX86MicroArch", "KernelX86MicroArchitecture");
+        assert_eq!(
+            Err(ConfigError::MissingRequiredProperty(
+                "fel4".into(),
+                "artifact-path".into()
+            )),
+            parse_full_manifest(&legacy)
+        );
+        let (upgraded, report) = migrate_manifest(&legacy).expect("Should migrate the manifest");
+        assert_eq!(1, report.from_version);
+        assert_eq!(FEL4_MANIFEST_VERSION, report.to_version);
+        assert_eq!(
+            vec![
+                "renamed artifact_path to artifact-path in [fel4]".to_string(),
+                "renamed KernelX86MicroArchitecture to KernelX86MicroArch in [x86_64-sel4-fel4.pc99]"
+                    .to_string(),
+                format!("set fel4-version = {}", FEL4_MANIFEST_VERSION),
+            ],
+            report
+                .changes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            parse_full_manifest(get_exemplar_default_toml()),
+            parse_full_manifest(&upgraded)
+        );
+
+        let (again, report) = migrate_manifest(&upgraded).expect("Should migrate the manifest");
+        assert!(report.is_unchanged());
+        assert_eq!(upgraded, again);
+    }
+
+    #[test]
+    fn legacy_layouts_are_read_and_upgraded() {
+        let legacy = r#"[fel4]
+            target = "armv7-sel4-fel4"
+            platform = "sabre"
+            artifact_path = "artifacts"
+            target-specs-path = "target_specs"
+            KernelArmPlatform = "sabre"
+            KernelArmSel4Arch = "aarch32"
+            [fel4.debug]
+            KernelPrinting = true
+            "#;
+        let (upgraded, report) = migrate_manifest(legacy).expect("Should migrate the manifest");
+        assert_eq!(
+            vec![
+                MigrationChange::RenamedKey {
+                    table: "fel4".into(),
+                    from: "artifact_path".into(),
+                    to: "artifact-path".into(),
+                },
+                MigrationChange::MovedLegacyProperties(SupportedTarget::Armv7Sel4Fel4),
+                MigrationChange::RenamedKey {
+                    table: "armv7-sel4-fel4".into(),
+                    from: "KernelArmPlatform".into(),
+                    to: "KernelARMPlatform".into(),
+                },
+                MigrationChange::SetVersion(FEL4_MANIFEST_VERSION),
+            ],
+            report.changes
+        );
+        let (full, warnings) = parse_full_manifest_with_warnings(&upgraded)
+            .expect("Should parse the upgraded manifest");
+        assert!(warnings.is_empty());
+        let config = resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(true)),
+            config.properties.get("KernelPrinting")
+        );
+
+        let current_names = legacy
+            .replace("artifact_path", "artifact-path")
+            .replace("KernelArmPlatform", "KernelARMPlatform");
+        let (full, warnings) = parse_full_manifest_with_warnings(&current_names)
+            .expect("Should parse the legacy layout");
+        assert_eq!(
+            vec![ConfigWarning::LegacyManifestLayout(
+                SupportedTarget::Armv7Sel4Fel4
+            )],
+            warnings
+        );
+        assert_eq!(parse_full_manifest(&upgraded), Ok(full));
+
+        let dir = ::tempfile::tempdir().unwrap();
+        let path = dir.path().join("fel4.toml");
+        ::std::fs::write(&path, &current_names).unwrap();
+        let (_, warnings) = get_full_manifest_with_warnings(&path).unwrap();
+        assert_eq!(
+            vec![ConfigWarning::LegacyManifestLayout(
+                SupportedTarget::Armv7Sel4Fel4
+            )],
+            warnings
+        );
+    }
+
+    #[test]
+    fn manifest_versions_are_checked() {
+        let versioned = |v: &str| {
+            get_exemplar_default_toml().replace("[fel4]", &format!("[fel4]\nfel4-version = {}", v))
+        };
+        assert_eq!(
+            Err(ConfigError::OutdatedManifestVersion(1)),
+            parse_full_manifest(versioned("1"))
+        );
+        assert_eq!(
+            Err(ConfigError::UnsupportedManifestVersion(
+                FEL4_MANIFEST_VERSION + 1
+            )),
+            parse_full_manifest(versioned(&(FEL4_MANIFEST_VERSION + 1).to_string()))
+        );
+        assert_eq!(
+            Err(ConfigError::UnsupportedManifestVersion(
+                FEL4_MANIFEST_VERSION + 1
+            )),
+            migrate_manifest(versioned(&(FEL4_MANIFEST_VERSION + 1).to_string()))
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidManifestVersion("\"two\"".into())),
+            parse_full_manifest(versioned("\"two\""))
+        );
+        assert!(parse_full_manifest(versioned(&FEL4_MANIFEST_VERSION.to_string())).is_ok());
+    }
+}
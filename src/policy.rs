@@ -0,0 +1,236 @@
+/// Organization policies that resolved configurations must satisfy, kept in
+/// a `fel4-policy.toml` beside the manifest so they can be reviewed
+/// separately from the application's own settings
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use toml;
+
+use super::ConfigError;
+use known_properties::KnownProperty;
+use manifest::decode_manifest;
+use types::*;
+
+/// The policy file `policy_alongside` looks for beside a manifest
+pub const POLICY_FILE_NAME: &str = "fel4-policy.toml";
+
+/// The constraints of a policy file, e.g.
+///
+/// ```toml
+/// required = ["KernelVerificationBuild"]
+/// forbidden = ["KernelArmHypervisorSupport"]
+///
+/// [allowed]
+/// KernelMaxNumNodes = [1, 2, 4]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fel4Policy {
+    /// Properties every resolved configuration must set
+    pub required: BTreeSet<String>,
+    /// Properties no resolved configuration may set
+    pub forbidden: BTreeSet<String>,
+    /// The only values that properties may resolve to, where they are set
+    pub allowed: BTreeMap<String, Vec<FlatTomlValue>>,
+}
+
+impl Fel4Policy {
+    /// Check a resolved configuration against the policy, failing on the
+    /// first required property it lacks, then the first forbidden property it
+    /// sets, then the first property with a value outside its allowed set
+    pub fn check(&self, fel4_config: &Fel4Config) -> Result<(), ConfigError> {
+        let properties = &fel4_config.properties;
+        if let Some(name) = self.required.iter().find(|n| !properties.contains_key(*n)) {
+            return Err(ConfigError::PolicyRequiredProperty(name.clone()));
+        }
+        if let Some(name) = self.forbidden.iter().find(|n| properties.contains_key(*n)) {
+            return Err(ConfigError::PolicyForbiddenProperty(name.clone()));
+        }
+        for (name, allowed) in &self.allowed {
+            match properties.get(name) {
+                Some(value) if !allowed.contains(value) => {
+                    return Err(ConfigError::PolicyDisallowedValue(
+                        name.clone(),
+                        allowed.iter().map(|v| v.to_string()).collect(),
+                        value.to_string(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse the contents of a policy file.
+///
+/// Policies may only name known or user properties, and may not both require
+/// and forbid a property.
+pub fn parse_policy<S: AsRef<str>>(toml_string: S) -> Result<Fel4Policy, ConfigError> {
+    let raw = toml_string
+        .as_ref()
+        .parse::<toml::Value>()
+        .map_err(|_| ConfigError::TomlParseFailure)?;
+    let table = raw
+        .as_table()
+        .ok_or_else(|| ConfigError::InvalidPolicy("it isn't a table".to_string()))?;
+    if let Some(k) = table
+        .keys()
+        .find(|k| !["required", "forbidden", "allowed"].contains(&k.as_str()))
+    {
+        return Err(ConfigError::InvalidPolicy(format!("{} is not a policy key", k)));
+    }
+    let names = |key: &str| -> Result<BTreeSet<String>, ConfigError> {
+        let invalid =
+            || ConfigError::InvalidPolicy(format!("{} should be an array of names", key));
+        match table.get(key) {
+            None => Ok(BTreeSet::new()),
+            Some(v) => v
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|n| n.as_str().map(str::to_string).ok_or_else(invalid))
+                .map(|n| n.and_then(known_property_name))
+                .collect(),
+        }
+    };
+    let required = names("required")?;
+    let forbidden = names("forbidden")?;
+    if let Some(name) = required.intersection(&forbidden).next() {
+        return Err(ConfigError::InvalidPolicy(format!(
+            "{} is both required and forbidden",
+            name
+        )));
+    }
+    let mut allowed = BTreeMap::new();
+    match table.get("allowed") {
+        None => {}
+        Some(toml::Value::Table(t)) => {
+            for (name, values) in t {
+                let invalid = || {
+                    ConfigError::InvalidPolicy(format!(
+                        "allowed.{} should be an array of values",
+                        name
+                    ))
+                };
+                let values = values
+                    .as_array()
+                    .ok_or_else(invalid)?
+                    .iter()
+                    .map(|v| FlatTomlValue::from_toml(v).ok_or_else(invalid))
+                    .collect::<Result<Vec<_>, _>>()?;
+                allowed.insert(known_property_name(name.clone())?, values);
+            }
+        }
+        Some(_) => {
+            return Err(ConfigError::InvalidPolicy(
+                "allowed should be a table".to_string(),
+            ))
+        }
+    }
+    Ok(Fel4Policy {
+        required,
+        forbidden,
+        allowed,
+    })
+}
+
+fn known_property_name(name: String) -> Result<String, ConfigError> {
+    if !is_user_property(&name) && name.parse::<KnownProperty>().is_err() {
+        return Err(ConfigError::NonWhitelistProperty(name));
+    }
+    Ok(name)
+}
+
+/// Read and parse a policy file
+pub fn get_policy<P: AsRef<Path>>(path: P) -> Result<Fel4Policy, ConfigError> {
+    let mut contents = Vec::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_end(&mut contents))
+        .map_err(|_| ConfigError::FileReadFailure)?;
+    parse_policy(decode_manifest(&contents)?)
+}
+
+/// The `POLICY_FILE_NAME` policy in the manifest's directory, or None if
+/// there is no such file
+pub fn policy_alongside<P: AsRef<Path>>(
+    fel4_manifest_path: P,
+) -> Result<Option<Fel4Policy>, ConfigError> {
+    let path = policy_path_for(fel4_manifest_path.as_ref());
+    if !path.is_file() {
+        return Ok(None);
+    }
+    fel4_trace!("manifest", "enforcing the policy in {}", path.display());
+    get_policy(path).map(Some)
+}
+
+fn policy_path_for(fel4_manifest_path: &Path) -> PathBuf {
+    fel4_manifest_path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(POLICY_FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn policies_constrain_resolved_configurations() {
+        let config = test_helpers::ManifestFixture::new()
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        let policy = parse_policy(
+            "required = [\"KernelX86MicroArch\"]\nforbidden = [\"KernelArmHypervisorSupport\"]\n\n\
+             [allowed]\nKernelMaxNumNodes = [1, 2]\n",
+        ).unwrap();
+        assert_eq!(Ok(()), policy.check(&config));
+
+        let mut forbidding = policy.clone();
+        forbidding.forbidden.insert("KernelPrinting".into());
+        assert_eq!(
+            Err(ConfigError::PolicyForbiddenProperty("KernelPrinting".into())),
+            forbidding.check(&config)
+        );
+        let mut requiring = policy.clone();
+        requiring.required.insert("X-Sbom".into());
+        assert_eq!(
+            Err(ConfigError::PolicyRequiredProperty("X-Sbom".into())),
+            requiring.check(&config)
+        );
+        let mut restricting = policy.clone();
+        restricting
+            .allowed
+            .insert("KernelPrinting".into(), vec![FlatTomlValue::Boolean(false)]);
+        assert_eq!(
+            Err(ConfigError::PolicyDisallowedValue(
+                "KernelPrinting".into(),
+                vec!["false".into()],
+                "true".into()
+            )),
+            restricting.check(&config)
+        );
+
+        assert_eq!(
+            Err(ConfigError::NonWhitelistProperty("KernelNonsense".into())),
+            parse_policy("forbidden = [\"KernelNonsense\"]")
+        );
+        assert!(parse_policy("required = [\"KernelVTX\"]\nforbidden = [\"KernelVTX\"]").is_err());
+        assert!(parse_policy("exemptions = []").is_err());
+
+        let dir = ::tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("fel4.toml");
+        ::std::fs::write(&manifest_path, get_exemplar_default_toml()).unwrap();
+        assert_eq!(Ok(None), policy_alongside(&manifest_path));
+        assert!(get_fel4_config(&manifest_path, &BuildProfile::Debug).is_ok());
+        ::std::fs::write(
+            dir.path().join(POLICY_FILE_NAME),
+            "forbidden = [\"KernelPrinting\"]",
+        ).unwrap();
+        assert_eq!(
+            Err(ConfigError::PolicyForbiddenProperty("KernelPrinting".into())),
+            get_fel4_config(&manifest_path, &BuildProfile::Debug).map(|_| ())
+        );
+    }
+}
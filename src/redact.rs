@@ -0,0 +1,129 @@
+/// Masking sensitive values in resolved configurations, so they can be
+/// attached to bug reports and shared logs
+use std::path::PathBuf;
+
+use types::*;
+
+/// The text redacted values and paths are replaced with
+pub const REDACTED_VALUE: &str = "<redacted>";
+
+impl Fel4Config {
+    /// A copy of the configuration that is safe to share, for rendering
+    /// reports, exports, or Debug output.
+    ///
+    /// The values of the properties and toolchain variables matching any of
+    /// the `patterns` are replaced with `REDACTED_VALUE`. A pattern is a
+    /// property name, or a prefix followed by `*`, such as `"X-*"` for every
+    /// user property, and applies to the effective properties the kernel
+    /// build chose too. The manifest, artifact, and target specification
+    /// paths, the kernel source location, and toolchain programs given as
+    /// paths rather than names are always redacted, since they reveal the
+    /// internal filesystem and servers.
+    pub fn redacted(&self, patterns: &[&str]) -> Fel4Config {
+        let is_redacted = |name: &str| patterns.iter().any(|p| matches_pattern(p, name));
+        let redact_path = |value: &mut Option<String>| {
+            if value.as_deref().is_some_and(is_path) {
+                *value = Some(REDACTED_VALUE.to_string());
+            }
+        };
+        let mut redacted = self.clone();
+        for (name, value) in redacted
+            .properties
+            .iter_mut()
+            .chain(redacted.effective_properties.iter_mut())
+        {
+            if is_redacted(name) {
+                *value = FlatTomlValue::String(REDACTED_VALUE.to_string());
+            }
+        }
+        let toolchain = &mut redacted.toolchain;
+        for variable in &mut toolchain.variables {
+            if is_redacted(&variable.name) {
+                variable.value = FlatTomlValue::String(REDACTED_VALUE.to_string());
+            }
+        }
+        redact_path(&mut toolchain.compiler_prefix);
+        for prefix in toolchain.platform_compiler_prefixes.values_mut() {
+            if is_path(prefix) {
+                *prefix = REDACTED_VALUE.to_string();
+            }
+        }
+        redact_path(&mut toolchain.cmake);
+        redact_path(&mut toolchain.ninja);
+        redact_path(&mut toolchain.compiler_launcher);
+        match redacted.kernel_source {
+            Some(KernelSource::Git { ref mut url, .. })
+            | Some(KernelSource::Tarball { ref mut url, .. }) => *url = REDACTED_VALUE.to_string(),
+            None => {}
+        }
+        if redacted.manifest_path.is_some() {
+            redacted.manifest_path = Some(PathBuf::from(REDACTED_VALUE));
+        }
+        redacted.artifact_path = REDACTED_VALUE.to_string();
+        redacted.target_specs_path = REDACTED_VALUE.to_string();
+        redacted
+    }
+}
+
+/// Whether a toolchain setting names a program by path, rather than leaving
+/// it to be found on PATH
+pub(crate) fn is_path(value: &str) -> bool {
+    value.contains('/') || value.contains('\\')
+}
+
+/// Whether a property name matches a redaction pattern, either exactly or,
+/// for patterns ending in `*`, by prefix
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn redaction_masks_matching_properties_and_internal_paths() {
+        let mut config = test_helpers::ManifestFixture::new()
+            .with_property("x86_64-sel4-fel4", "X-AppSecret", "hunter2")
+            .with_property("toolchain.x86_64-sel4-fel4", "ninja", "/opt/internal/ninja")
+            .with_property("toolchain.x86_64-sel4-fel4", "cmake", "cmake3")
+            .resolve(&BuildProfile::Debug)
+            .unwrap();
+        config.manifest_path = Some(PathBuf::from("/home/someone/project/fel4.toml"));
+        config.artifact_path = "/home/someone/project/artifacts".into();
+        config.target_specs_path = "/home/someone/project/target_specs".into();
+        let _ = config.effective_properties.insert(
+            "X-KernelChosenSecret".into(),
+            FlatTomlValue::String("swordfish".into()),
+        );
+        let redacted = config.redacted(&["X-*", "KernelX86MicroArch"]);
+
+        let redacted_value = FlatTomlValue::String(REDACTED_VALUE.to_string());
+        assert_eq!(Some(&redacted_value), redacted.properties.get("X-AppSecret"));
+        assert_eq!(Some(&redacted_value), redacted.properties.get("KernelX86MicroArch"));
+        assert_eq!(
+            config.properties.get("KernelX86Sel4Arch"),
+            redacted.properties.get("KernelX86Sel4Arch")
+        );
+        assert_eq!(Some(REDACTED_VALUE), redacted.toolchain.ninja.as_deref());
+        assert_eq!(Some("cmake3"), redacted.toolchain.cmake.as_deref());
+        assert_eq!(Some(PathBuf::from(REDACTED_VALUE)), redacted.manifest_path);
+        assert_eq!(REDACTED_VALUE, redacted.artifact_path);
+        assert_eq!(REDACTED_VALUE, redacted.target_specs_path);
+        assert_eq!(
+            Some(&redacted_value),
+            redacted.effective_properties.get("X-KernelChosenSecret")
+        );
+
+        let debug = format!("{:?}", redacted);
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains("swordfish"));
+        assert!(!debug.contains("/opt/internal"));
+        assert!(!debug.contains("/home/someone"));
+        assert!(!redacted.render_report().contains("hunter2"));
+    }
+}
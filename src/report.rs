@@ -0,0 +1,150 @@
+/// Human-readable rendering of resolved fel4 configurations
+use toml;
+
+use cache::config_to_cached_manifest;
+use manifest::{full_manifest_to_toml, FullFel4Manifest};
+use types::*;
+
+/// A property name, its rendered value, and where it came from
+type ReportRow<'a> = (&'a str, String, String);
+
+impl Fel4Config {
+    /// Render the resolved properties as an aligned table, grouped by their
+    /// `PropertyGroup` and in manifest order within each group, suitable for
+    /// printing at the start of a build.
+    ///
+    /// Each row gives the property name, its value as it would be written in
+    /// toml, and the manifest table it was resolved from, with `rules` marking
+    /// values implied by a `PropertyRule` and `overridden` those set after
+    /// resolution.
+    pub fn render_report(&self) -> String {
+        let groups: Vec<(PropertyGroup, Vec<ReportRow>)> = PropertyGroup::groups()
+            .into_iter()
+            .map(|group| {
+                let rows = self
+                    .properties_in_group(group)
+                    .map(|(name, value)| {
+                        let source = self
+                            .provenance
+                            .get(name)
+                            .map(|layer| layer.location(self))
+                            .unwrap_or_else(|| PropertyOrigin::Overridden.to_string());
+                        (name, value.to_string(), source)
+                    })
+                    .collect();
+                (group, rows)
+            })
+            .collect();
+
+        let rows = groups.iter().flat_map(|g| g.1.iter());
+        let name_width = rows.clone().map(|r| r.0.len()).max().unwrap_or(0);
+        let value_width = rows.map(|r| r.1.chars().count()).max().unwrap_or(0);
+        let mut report = format!(
+            "fel4 configuration for {} {} {}\n",
+            self.target, self.platform, self.build_profile
+        );
+        for (group, rows) in groups {
+            if rows.is_empty() {
+                continue;
+            }
+            report.push_str(&format!("\n{}\n", group));
+            for (name, value, source) in rows {
+                report.push_str(&format!(
+                    "  {:name_width$}  {:value_width$}  [{}]\n",
+                    name,
+                    value,
+                    source,
+                    name_width = name_width,
+                    value_width = value_width
+                ));
+            }
+        }
+        report
+    }
+}
+
+impl FullFel4Manifest {
+    /// The manifest as toml with every table's keys sorted, for reading in
+    /// test failures and logs where Debug output of its maps is unreadable.
+    ///
+    /// Unlike `full_manifest_to_toml_string`, properties are not kept in
+    /// declaration order, so equal manifests always render identically.
+    pub fn pretty(&self) -> String {
+        toml::to_string(&full_manifest_to_toml(self)).expect("Manifests should always serialize")
+    }
+}
+
+impl Fel4Config {
+    /// The selection, followed by the configuration as a manifest that sets
+    /// every resolved property directly in the target table, with every
+    /// table's keys sorted, for reading in test failures and logs
+    pub fn pretty(&self) -> String {
+        format!(
+            "# fel4 configuration for {} {} {}\n{}",
+            self.target,
+            self.platform,
+            self.build_profile,
+            config_to_cached_manifest(self).pretty()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn report_groups_and_aligns_properties_with_sources() {
+        let mut config = test_helpers::fixture_config(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::Sabre,
+            BuildProfile::Debug,
+        );
+        config.properties.retain(|name, _| {
+            [
+                "KernelPrinting",
+                "KernelARMPlatform",
+                "LibSel4FunctionAttributes",
+                "ElfloaderMode",
+            ]
+            .contains(&name.as_str())
+        });
+        assert_eq!(
+            "fel4 configuration for armv7-sel4-fel4 sabre debug\n\
+             \n\
+             kernel\n\
+             \x20 KernelPrinting             true                 [armv7-sel4-fel4.debug]\n\
+             \x20 KernelARMPlatform          \"sabre\"              [armv7-sel4-fel4.sabre]\n\
+             \n\
+             libsel4\n\
+             \x20 LibSel4FunctionAttributes  \"public\"             [armv7-sel4-fel4]\n\
+             \n\
+             elfloader\n\
+             \x20 ElfloaderMode              \"secure supervisor\"  [armv7-sel4-fel4.sabre]\n",
+            config.render_report()
+        );
+    }
+
+    #[test]
+    fn pretty_output_groups_tables_and_sorts_keys() {
+        let full = test_helpers::ManifestFixture::new().parse().unwrap();
+        let pretty = full.pretty();
+        assert_eq!(pretty, full.clone().pretty());
+        assert!(pretty.contains("[x86_64-sel4-fel4.debug]"));
+        let position = |text: &str, name: &str| text.find(&format!("\n{} = ", name)).unwrap();
+        assert!(position(&pretty, "KernelArch") < position(&pretty, "KernelX86Sel4Arch"));
+
+        let config = resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        let pretty = config.pretty();
+        assert!(pretty.starts_with("# fel4 configuration for x86_64-sel4-fel4 pc99 debug\n"));
+        let names: Vec<&str> = pretty
+            .lines()
+            .skip_while(|line| *line != "[x86_64-sel4-fel4]")
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .map(|line| line.split(" = ").next().unwrap())
+            .collect();
+        assert_eq!(config.properties.len(), names.len());
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,138 @@
+/// Exporting the `[<target>.rootserver]` settings to the Rust side of the
+/// build, where the root task is sized
+use std::io;
+use std::path::Path;
+
+use generated_source::{optional_usize_constants, write_generated_file};
+use types::*;
+
+/// The root task's stack size in bytes, set by `rootserver_env`
+pub const ROOTSERVER_STACK_SIZE_ENV_VAR: &str = "FEL4_ROOTSERVER_STACK_SIZE";
+/// The root task's heap size in bytes, set by `rootserver_env`
+pub const ROOTSERVER_HEAP_SIZE_ENV_VAR: &str = "FEL4_ROOTSERVER_HEAP_SIZE";
+/// The root task's CNode size hint in bits, set by `rootserver_env`
+pub const ROOTSERVER_CNODE_SIZE_BITS_ENV_VAR: &str = "FEL4_ROOTSERVER_CNODE_SIZE_BITS";
+
+/// The rootserver settings as environment variables, for a build script to
+/// pass on with `cargo:rustc-env` so the root task can read them with
+/// `option_env!`.
+///
+/// Only the settings the manifest sets are included.
+pub fn rootserver_env(fel4_config: &Fel4Config) -> Vec<(&'static str, String)> {
+    rootserver_sizes(&fel4_config.rootserver)
+        .iter()
+        .filter_map(|&(var, _, size)| size.map(|s| (var, s.to_string())))
+        .collect()
+}
+
+/// Render Rust source declaring the rootserver settings as constants, for a
+/// build script to write to `OUT_DIR` and `include!`.
+///
+/// Each of `ROOTSERVER_STACK_SIZE`, `ROOTSERVER_HEAP_SIZE`, and
+/// `ROOTSERVER_CNODE_SIZE_BITS` is an `Option<usize>`, which is `None` where
+/// the manifest leaves the setting to the root task.
+pub fn rootserver_rust_source(fel4_config: &Fel4Config) -> String {
+    let sizes = rootserver_sizes(&fel4_config.rootserver);
+    let constants: Vec<(&str, Option<i64>)> =
+        sizes.iter().map(|&(_, name, size)| (name, size)).collect();
+    optional_usize_constants(&constants)
+}
+
+/// Write the `rootserver_rust_source` for the configuration to a file,
+/// creating any missing parent directories
+pub fn write_rootserver_rust_source<P: AsRef<Path>>(
+    fel4_config: &Fel4Config,
+    path: P,
+) -> Result<(), io::Error> {
+    write_generated_file(path, &rootserver_rust_source(fel4_config))
+}
+
+/// Each setting's environment variable, constant name, and value
+fn rootserver_sizes(
+    settings: &RootserverSettings,
+) -> [(&'static str, &'static str, Option<i64>); 3] {
+    [
+        (
+            ROOTSERVER_STACK_SIZE_ENV_VAR,
+            "ROOTSERVER_STACK_SIZE",
+            settings.stack_size,
+        ),
+        (
+            ROOTSERVER_HEAP_SIZE_ENV_VAR,
+            "ROOTSERVER_HEAP_SIZE",
+            settings.heap_size,
+        ),
+        (
+            ROOTSERVER_CNODE_SIZE_BITS_ENV_VAR,
+            "ROOTSERVER_CNODE_SIZE_BITS",
+            settings.cnode_size_bits,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn rootserver_table_is_exported_to_rust_and_not_cmake() {
+        let text = format!(
+            "{}\n[x86_64-sel4-fel4.rootserver]\nstack-size = 65536\ncnode-size-bits = 12\n",
+            get_exemplar_default_toml()
+        );
+        let full = parse_full_manifest(&text).expect("The rootserver table should parse");
+        let config = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        assert_eq!(
+            RootserverSettings {
+                stack_size: Some(65536),
+                heap_size: None,
+                cnode_size_bits: Some(12),
+            },
+            config.rootserver
+        );
+        assert!(!config.properties.contains_key("stack-size"));
+        let mut recorded = RecordedDefinitions::default();
+        configure_cmake_build(&mut recorded, &config, "/work", "x86_64-sel4-fel4").unwrap();
+        assert!(recorded.defines.iter().all(|(name, _)| !name.contains("stack")));
+        assert_eq!(
+            vec![
+                (ROOTSERVER_STACK_SIZE_ENV_VAR, "65536".to_string()),
+                (ROOTSERVER_CNODE_SIZE_BITS_ENV_VAR, "12".to_string()),
+            ],
+            rootserver_env(&config)
+        );
+        let source = rootserver_rust_source(&config);
+        assert!(source.contains("pub const ROOTSERVER_STACK_SIZE: Option<usize> = Some(65536);\n"));
+        assert!(source.contains("pub const ROOTSERVER_HEAP_SIZE: Option<usize> = None;\n"));
+        assert_eq!(
+            Ok(full.clone()),
+            parse_full_manifest(full_manifest_to_toml_string(&full))
+        );
+    }
+
+    #[test]
+    fn invalid_rootserver_tables_are_rejected() {
+        let parse = |table: &str| {
+            parse_full_manifest(format!(
+                "{}\n[x86_64-sel4-fel4.rootserver]\n{}\n",
+                get_exemplar_default_toml(),
+                table
+            ))
+        };
+        assert_eq!(
+            Err(ConfigError::UnexpectedStructure(
+                "x86_64-sel4-fel4.rootserver.priority".into()
+            )),
+            parse("priority = 255")
+        );
+        match parse("heap-size = 0") {
+            Err(ConfigError::InvalidRootserverTable(ref table, _)) => {
+                assert_eq!("x86_64-sel4-fel4.rootserver", table)
+            }
+            other => panic!("Expected an invalid rootserver table, got {:?}", other),
+        }
+        assert!(parse("cnode-size-bits = 64").is_err());
+        assert!(parse("stack-size = \"64K\"").is_err());
+    }
+}
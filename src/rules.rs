@@ -0,0 +1,263 @@
+/// Declarative dependency rules between resolved fel4 properties
+use std::collections::HashMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use super::ConfigError;
+use types::*;
+
+/// A test against a single property of a resolved property set
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum PropertyCondition {
+    /// Holds when the named property is present and has exactly the value
+    Equals(String, FlatTomlValue),
+    /// Holds when the named property is present and has any other value
+    NotEquals(String, FlatTomlValue),
+}
+
+impl PropertyCondition {
+    pub fn equals<S: Into<String>>(name: S, value: FlatTomlValue) -> Self {
+        PropertyCondition::Equals(name.into(), value)
+    }
+
+    pub fn not_equals<S: Into<String>>(name: S, value: FlatTomlValue) -> Self {
+        PropertyCondition::NotEquals(name.into(), value)
+    }
+
+    /// The name of the property this condition inspects
+    pub fn name(&self) -> &str {
+        match *self {
+            PropertyCondition::Equals(ref n, _) | PropertyCondition::NotEquals(ref n, _) => n,
+        }
+    }
+
+    /// Returns true if the condition holds for the given property set.
+    /// An absent property never satisfies a condition.
+    pub fn holds(&self, properties: &HashMap<String, FlatTomlValue>) -> bool {
+        match *self {
+            PropertyCondition::Equals(ref n, ref v) => properties.get(n) == Some(v),
+            PropertyCondition::NotEquals(ref n, ref v) => match properties.get(n) {
+                Some(actual) => actual != v,
+                None => false,
+            },
+        }
+    }
+}
+
+impl Display for PropertyCondition {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            PropertyCondition::Equals(ref n, ref v) => {
+                write!(f, "{} = {}", n, v)
+            }
+            PropertyCondition::NotEquals(ref n, ref v) => {
+                write!(f, "{} != {}", n, v)
+            }
+        }
+    }
+}
+
+/// A relationship between properties that must hold for a resolved
+/// configuration to be considered valid
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum PropertyRule {
+    /// When `when` holds, `then` must also hold
+    Requires {
+        when: PropertyCondition,
+        then: PropertyCondition,
+    },
+    /// When `when` holds, `with` must not hold
+    Conflicts {
+        when: PropertyCondition,
+        with: PropertyCondition,
+    },
+    /// When `when` holds, the named property takes on the given value.
+    /// The property is filled in if absent, and must agree if present.
+    Implies {
+        when: PropertyCondition,
+        name: String,
+        value: FlatTomlValue,
+    },
+}
+
+impl Display for PropertyRule {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            PropertyRule::Requires { ref when, ref then } => {
+                write!(f, "{} requires {}", when, then)
+            }
+            PropertyRule::Conflicts { ref when, ref with } => {
+                write!(f, "{} conflicts with {}", when, with)
+            }
+            PropertyRule::Implies {
+                ref when,
+                ref name,
+                ref value,
+            } => write!(f, "{} implies {} = {}", when, name, value),
+        }
+    }
+}
+
+/// The dependency rules between seL4 kernel options that fel4 enforces
+/// during configuration resolution
+pub fn sel4_dependency_rules() -> Vec<PropertyRule> {
+    vec![
+        PropertyRule::Requires {
+            when: PropertyCondition::not_equals(
+                "KernelBenchmarks",
+                FlatTomlValue::String("none".into()),
+            ),
+            then: PropertyCondition::equals("KernelDebugBuild", FlatTomlValue::Boolean(true)),
+        },
+        PropertyRule::Requires {
+            when: PropertyCondition::equals("KernelVTX", FlatTomlValue::Boolean(true)),
+            then: PropertyCondition::equals(
+                "KernelIRQController",
+                FlatTomlValue::String("IOAPIC".into()),
+            ),
+        },
+    ]
+}
+
+/// Evaluate the rules against a resolved property set.
+///
+/// All `Implies` rules are applied first, so that implied values are visible
+/// to the `Requires` and `Conflicts` rules. The first violated rule is
+/// returned as a `ConfigError::RuleViolation`.
+pub fn apply_property_rules(
+    properties: &mut HashMap<String, FlatTomlValue>,
+    rules: &[PropertyRule],
+) -> Result<(), ConfigError> {
+    for rule in rules {
+        if let PropertyRule::Implies {
+            ref when,
+            ref name,
+            ref value,
+        } = *rule
+        {
+            if !when.holds(properties) {
+                continue;
+            }
+            match properties.get(name) {
+                None => {}
+                Some(existing) if existing == value => continue,
+                Some(_) => return Err(ConfigError::RuleViolation(Box::new(rule.clone()))),
+            }
+            fel4_trace!("rules", "implied {} = {}", name, value);
+            properties.insert(name.clone(), value.clone());
+        }
+    }
+    for rule in rules {
+        let violated = match *rule {
+            PropertyRule::Requires { ref when, ref then } => {
+                when.holds(properties) && !then.holds(properties)
+            }
+            PropertyRule::Conflicts { ref when, ref with } => {
+                when.holds(properties) && with.holds(properties)
+            }
+            PropertyRule::Implies { .. } => false,
+        };
+        if violated {
+            return Err(ConfigError::RuleViolation(Box::new(rule.clone())));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(pairs: &[(&str, FlatTomlValue)]) -> HashMap<String, FlatTomlValue> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn requires_rule_violation_is_reported() {
+        let rules = sel4_dependency_rules();
+        let mut p = props(&[
+            (
+                "KernelBenchmarks",
+                FlatTomlValue::String("track_utilisation".into()),
+            ),
+            ("KernelDebugBuild", FlatTomlValue::Boolean(false)),
+        ]);
+        assert_eq!(
+            Err(ConfigError::RuleViolation(Box::new(rules[0].clone()))),
+            apply_property_rules(&mut p, &rules)
+        );
+    }
+
+    #[test]
+    fn requires_rule_is_inert_when_condition_does_not_hold() {
+        let mut p = props(&[
+            ("KernelBenchmarks", FlatTomlValue::String("none".into())),
+            ("KernelDebugBuild", FlatTomlValue::Boolean(false)),
+            ("KernelVTX", FlatTomlValue::Boolean(false)),
+        ]);
+        assert_eq!(
+            Ok(()),
+            apply_property_rules(&mut p, &sel4_dependency_rules())
+        );
+    }
+
+    #[test]
+    fn conflicts_rule_violation_is_reported() {
+        let rule = PropertyRule::Conflicts {
+            when: PropertyCondition::equals(
+                "KernelVerificationBuild",
+                FlatTomlValue::Boolean(true),
+            ),
+            with: PropertyCondition::equals("KernelPrinting", FlatTomlValue::Boolean(true)),
+        };
+        let mut p = props(&[
+            ("KernelVerificationBuild", FlatTomlValue::Boolean(true)),
+            ("KernelPrinting", FlatTomlValue::Boolean(true)),
+        ]);
+        assert_eq!(
+            Err(ConfigError::RuleViolation(Box::new(rule.clone()))),
+            apply_property_rules(&mut p, &[rule])
+        );
+    }
+
+    #[test]
+    fn implies_rule_fills_in_absent_property() {
+        let rule = PropertyRule::Implies {
+            when: PropertyCondition::equals("KernelColourPrinting", FlatTomlValue::Boolean(true)),
+            name: "KernelPrinting".into(),
+            value: FlatTomlValue::Boolean(true),
+        };
+        let mut p = props(&[("KernelColourPrinting", FlatTomlValue::Boolean(true))]);
+        assert_eq!(Ok(()), apply_property_rules(&mut p, &[rule]));
+        assert_eq!(Some(&FlatTomlValue::Boolean(true)), p.get("KernelPrinting"));
+    }
+
+    #[test]
+    fn implies_rule_rejects_contradicting_property() {
+        let rule = PropertyRule::Implies {
+            when: PropertyCondition::equals("KernelColourPrinting", FlatTomlValue::Boolean(true)),
+            name: "KernelPrinting".into(),
+            value: FlatTomlValue::Boolean(true),
+        };
+        let mut p = props(&[
+            ("KernelColourPrinting", FlatTomlValue::Boolean(true)),
+            ("KernelPrinting", FlatTomlValue::Boolean(false)),
+        ]);
+        assert_eq!(
+            Err(ConfigError::RuleViolation(Box::new(rule.clone()))),
+            apply_property_rules(&mut p, &[rule])
+        );
+    }
+
+    #[test]
+    fn rules_display_readably() {
+        assert_eq!(
+            "KernelBenchmarks != \"none\" requires KernelDebugBuild = true",
+            sel4_dependency_rules()[0].to_string()
+        );
+    }
+}
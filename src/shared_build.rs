@@ -0,0 +1,209 @@
+/// Sharing one kernel CMake build directory between the crates of a
+/// workspace whose configurations agree, instead of rebuilding the kernel in
+/// every crate's OUT_DIR
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use build_info::fel4_config_hash;
+use cmake_integration::kernel_path_for;
+use digest::sha256_hex;
+use trace;
+use types::*;
+
+/// Names the environment variable giving the directory that kernel builds
+/// are shared beneath, such as `target/fel4-kernel` of the workspace
+pub const SHARED_KERNEL_BUILD_ROOT_ENV_VAR: &str = "FEL4_SHARED_KERNEL_BUILD_ROOT";
+
+/// The file `lock_kernel_build_dir` holds locked within a build directory
+pub const KERNEL_BUILD_LOCK_FILE_NAME: &str = ".fel4-kernel-build.lock";
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum SharedBuildError {
+    #[fail(display = "Missing the required {} environment variable", _0)]
+    MissingRequiredEnvVar(String),
+    #[fail(display = "Unable to create the kernel build directory {}", _0)]
+    BuildDirectory(String),
+    #[fail(display = "Unable to lock the kernel build directory {}", _0)]
+    LockFailure(String),
+}
+
+/// The directory beneath `root` to build the configuration's kernel in.
+///
+/// It is keyed by the `fel4_config_hash` and the kernel source directory,
+/// since a CMake build directory is tied to one source tree, so crates share
+/// a build exactly when they configure the same kernel from the same source.
+pub fn shared_kernel_build_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+    root: P,
+    fel4_config: &Fel4Config,
+    cargo_manifest_dir: Q,
+) -> PathBuf {
+    let kernel_path = kernel_path_for(cargo_manifest_dir);
+    let kernel_path = fs::canonicalize(&kernel_path).unwrap_or(kernel_path);
+    let key = sha256_hex(
+        format!(
+            "{}\n{}",
+            fel4_config_hash(fel4_config),
+            kernel_path.display()
+        ).as_bytes(),
+    );
+    root.as_ref().join(format!(
+        "{}-{}-{}-{}",
+        fel4_config.target,
+        fel4_config.platform,
+        fel4_config.build_profile,
+        &key[..16]
+    ))
+}
+
+/// The directory a build script should build the kernel in: the shared
+/// directory beneath `SHARED_KERNEL_BUILD_ROOT_ENV_VAR` when it is set, and
+/// cargo's OUT_DIR otherwise.
+///
+/// Assumes the presence of the CARGO_MANIFEST_DIR environment variable from
+/// cargo when sharing.
+pub fn kernel_build_dir_from_env(fel4_config: &Fel4Config) -> Result<PathBuf, SharedBuildError> {
+    kernel_build_dir_from(fel4_config, |name| trace::var(name).ok())
+}
+
+fn kernel_build_dir_from<F>(
+    fel4_config: &Fel4Config,
+    lookup: F,
+) -> Result<PathBuf, SharedBuildError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let lookup = |name: &str| lookup(name).filter(|v| !v.is_empty());
+    let required = |name: &str| {
+        lookup(name).ok_or_else(|| SharedBuildError::MissingRequiredEnvVar(name.to_string()))
+    };
+    match lookup(SHARED_KERNEL_BUILD_ROOT_ENV_VAR) {
+        Some(root) => {
+            let dir =
+                shared_kernel_build_dir(root, fel4_config, required("CARGO_MANIFEST_DIR")?);
+            fel4_trace!("cmake", "sharing the kernel build in {}", dir.display());
+            Ok(dir)
+        }
+        None => required("OUT_DIR").map(PathBuf::from),
+    }
+}
+
+/// Exclusive use of a kernel build directory, held until dropped
+#[derive(Debug)]
+pub struct KernelBuildDirLock {
+    pub dir: PathBuf,
+    file: File,
+}
+
+impl Drop for KernelBuildDirLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Create a kernel build directory if needed and wait for exclusive use of
+/// it, so that crates sharing it don't run CMake in it at the same time.
+///
+/// The lock is released when the returned guard is dropped, or when the
+/// process exits.
+pub fn lock_kernel_build_dir<P: AsRef<Path>>(
+    dir: P,
+) -> Result<KernelBuildDirLock, SharedBuildError> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)
+        .map_err(|_| SharedBuildError::BuildDirectory(dir.display().to_string()))?;
+    let lock_path = dir.join(KERNEL_BUILD_LOCK_FILE_NAME);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .and_then(|f| f.lock().map(|_| f))
+        .map_err(|_| SharedBuildError::LockFailure(lock_path.display().to_string()))?;
+    fel4_trace!("cmake", "locked {}", dir.display());
+    Ok(KernelBuildDirLock {
+        dir: dir.to_path_buf(),
+        file,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn identical_configurations_share_a_kernel_build_dir() {
+        let config = |profile| {
+            test_helpers::fixture_config(
+                SupportedTarget::X8664Sel4Fel4,
+                SupportedPlatform::PC99,
+                profile,
+            )
+        };
+        let debug = shared_kernel_build_dir("/cache", &config(BuildProfile::Debug), "/work/a");
+        assert_eq!(
+            debug,
+            shared_kernel_build_dir("/cache", &config(BuildProfile::Debug), "/work/a")
+        );
+        assert!(debug.starts_with("/cache"));
+        assert!(debug
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("x86_64-sel4-fel4-pc99-debug-"));
+        assert_ne!(
+            debug,
+            shared_kernel_build_dir("/cache", &config(BuildProfile::Release), "/work/a")
+        );
+        assert_ne!(
+            debug,
+            shared_kernel_build_dir("/cache", &config(BuildProfile::Debug), "/work/b")
+        );
+
+        let env = |vars: Vec<(&'static str, &'static str)>| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|&&(n, _)| n == name)
+                    .map(|&(_, v)| v.to_string())
+            }
+        };
+        let debug_config = config(BuildProfile::Debug);
+        assert_eq!(
+            Ok(PathBuf::from("/work/a/target/out")),
+            kernel_build_dir_from(&debug_config, env(vec![("OUT_DIR", "/work/a/target/out")]))
+        );
+        assert_eq!(
+            Ok(debug.clone()),
+            kernel_build_dir_from(
+                &debug_config,
+                env(vec![
+                    ("OUT_DIR", "/work/a/target/out"),
+                    (SHARED_KERNEL_BUILD_ROOT_ENV_VAR, "/cache"),
+                    ("CARGO_MANIFEST_DIR", "/work/a"),
+                ])
+            )
+        );
+        assert_eq!(
+            Err(SharedBuildError::MissingRequiredEnvVar(
+                "CARGO_MANIFEST_DIR".into()
+            )),
+            kernel_build_dir_from(
+                &debug_config,
+                env(vec![(SHARED_KERNEL_BUILD_ROOT_ENV_VAR, "/cache")])
+            )
+        );
+    }
+
+    #[test]
+    fn kernel_build_dirs_are_locked_until_the_guard_is_dropped() {
+        let root = ::tempfile::tempdir().unwrap();
+        let dir = root.path().join("shared");
+        let lock = lock_kernel_build_dir(&dir).expect("Should lock a new build directory");
+        assert_eq!(dir, lock.dir);
+        let other = File::open(dir.join(KERNEL_BUILD_LOCK_FILE_NAME)).unwrap();
+        assert!(other.try_lock().is_err());
+        drop(lock);
+        assert!(other.try_lock().is_ok());
+    }
+}
@@ -0,0 +1,273 @@
+/// Summary counts describing the contents of a fel4 manifest, for tracking
+/// how configuration grows across projects
+use std::collections::HashSet;
+
+use super::{is_supported_target_platform_pair, ALL_PROPERTIES_WHITELIST};
+use json::Json;
+use manifest::FullFel4Manifest;
+use types::*;
+
+/// Counts and listings describing a `FullFel4Manifest`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestStats {
+    /// The number of target tables
+    pub target_count: usize,
+    /// Properties set directly in target tables
+    pub direct_property_count: usize,
+    /// Properties set in build profile subtables
+    pub build_profile_property_count: usize,
+    /// Properties set in platform subtables
+    pub platform_property_count: usize,
+    /// Tables that no supported selection ever resolves, as dotted paths:
+    /// platform subtables of targets that don't support that platform, and
+    /// toolchain tables of targets without a target table
+    pub unused_tables: Vec<String>,
+    /// The distinct whitelisted property names set anywhere in the manifest
+    pub whitelisted_properties_used: usize,
+    /// The number of property names on the whitelist
+    pub whitelist_size: usize,
+    /// One entry per target table, in the order of `SupportedTarget::targets`
+    pub targets: Vec<TargetStats>,
+}
+
+/// The property names set by the tables of one target, in manifest order
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetStats {
+    pub target: SupportedTarget,
+    pub direct_properties: Vec<String>,
+    /// In the order of `BuildProfile::build_profiles`, for the profile
+    /// subtables present
+    pub build_profile_properties: Vec<(BuildProfile, Vec<String>)>,
+    /// In the order of `SupportedPlatform::platforms`, for the platform
+    /// subtables present
+    pub platform_properties: Vec<(SupportedPlatform, Vec<String>)>,
+}
+
+impl TargetStats {
+    /// The number of properties set across every table of the target
+    pub fn property_count(&self) -> usize {
+        self.direct_properties.len()
+            + self
+                .build_profile_properties
+                .iter()
+                .map(|(_, names)| names.len())
+                .sum::<usize>()
+            + self
+                .platform_properties
+                .iter()
+                .map(|(_, names)| names.len())
+                .sum::<usize>()
+    }
+
+    fn to_json(&self) -> Json {
+        let names = |names: &[String]| {
+            Json::Array(names.iter().map(|n| Json::string(n.as_str())).collect())
+        };
+        Json::object(vec![
+            ("target", Json::string(self.target.full_name())),
+            ("direct-properties", names(&self.direct_properties)),
+            (
+                "build-profile-properties",
+                Json::Object(
+                    self.build_profile_properties
+                        .iter()
+                        .map(|&(p, ref n)| (p.full_name().to_string(), names(n)))
+                        .collect(),
+                ),
+            ),
+            (
+                "platform-properties",
+                Json::Object(
+                    self.platform_properties
+                        .iter()
+                        .map(|&(p, ref n)| (p.full_name().to_string(), names(n)))
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+}
+
+impl ManifestStats {
+    pub fn total_property_count(&self) -> usize {
+        self.direct_property_count
+            + self.build_profile_property_count
+            + self.platform_property_count
+    }
+
+    /// The fraction of the whitelist the manifest sets, from 0 to 1
+    pub fn whitelist_coverage(&self) -> f64 {
+        if self.whitelist_size == 0 {
+            0.0
+        } else {
+            self.whitelisted_properties_used as f64 / self.whitelist_size as f64
+        }
+    }
+
+    /// The statistics as a JSON object, for dashboards outside of Rust
+    pub fn to_json_string(&self) -> String {
+        let count = |n: usize| Json::Integer(n as i64);
+        let document = Json::object(vec![
+            ("target-count", count(self.target_count)),
+            ("direct-property-count", count(self.direct_property_count)),
+            (
+                "build-profile-property-count",
+                count(self.build_profile_property_count),
+            ),
+            (
+                "platform-property-count",
+                count(self.platform_property_count),
+            ),
+            (
+                "unused-tables",
+                Json::Array(
+                    self.unused_tables
+                        .iter()
+                        .map(|t| Json::string(t.as_str()))
+                        .collect(),
+                ),
+            ),
+            (
+                "whitelisted-properties-used",
+                count(self.whitelisted_properties_used),
+            ),
+            ("whitelist-size", count(self.whitelist_size)),
+            ("whitelist-coverage", Json::Float(self.whitelist_coverage())),
+            (
+                "targets",
+                Json::Array(self.targets.iter().map(TargetStats::to_json).collect()),
+            ),
+        ]);
+        format!("{}\n", document)
+    }
+}
+
+impl FullFel4Manifest {
+    /// Summarize the manifest: how many targets and properties per layer it
+    /// has, which of its tables are never used, and how much of the property
+    /// whitelist it sets
+    pub fn stats(&self) -> ManifestStats {
+        let names = |properties: &PropertySet| -> Vec<String> {
+            properties.iter().map(|p| p.name.clone()).collect()
+        };
+        let mut targets = Vec::new();
+        let mut unused_tables = Vec::new();
+        for target in SupportedTarget::targets() {
+            let content = match self.targets.get(&target) {
+                Some(c) => c,
+                None => continue,
+            };
+            let build_profile_properties = BuildProfile::build_profiles()
+                .into_iter()
+                .filter_map(|p| {
+                    content
+                        .build_profile_properties
+                        .get(&p)
+                        .map(|s| (p, names(s)))
+                })
+                .collect();
+            let platform_properties = SupportedPlatform::platforms()
+                .into_iter()
+                .filter_map(|p| content.platform_properties.get(&p).map(|s| (p, names(s))))
+                .collect::<Vec<_>>();
+            for &(platform, _) in &platform_properties {
+                if !is_supported_target_platform_pair(target, platform) {
+                    unused_tables.push(format!("{}.{}", target, platform));
+                }
+            }
+            targets.push(TargetStats {
+                target,
+                direct_properties: names(&content.direct_properties)
+                    .into_iter()
+                    .chain(content.conditional_properties.keys().cloned())
+                    .chain(content.derived_properties.keys().cloned())
+                    .collect(),
+                build_profile_properties,
+                platform_properties,
+            });
+        }
+        for target in SupportedTarget::targets() {
+            if self.toolchains.contains_key(&target) && !self.targets.contains_key(&target) {
+                unused_tables.push(format!("toolchain.{}", target));
+            }
+        }
+
+        let layer_count =
+            |layer: fn(&TargetStats) -> usize| -> usize { targets.iter().map(layer).sum() };
+        let used: HashSet<&str> = targets
+            .iter()
+            .flat_map(|t| {
+                t.direct_properties
+                    .iter()
+                    .chain(t.build_profile_properties.iter().flat_map(|p| p.1.iter()))
+                    .chain(t.platform_properties.iter().flat_map(|p| p.1.iter()))
+            })
+            .map(String::as_str)
+            .collect();
+        ManifestStats {
+            target_count: targets.len(),
+            direct_property_count: layer_count(|t| t.direct_properties.len()),
+            build_profile_property_count: layer_count(|t| {
+                t.build_profile_properties.iter().map(|p| p.1.len()).sum()
+            }),
+            platform_property_count: layer_count(|t| {
+                t.platform_properties.iter().map(|p| p.1.len()).sum()
+            }),
+            unused_tables,
+            whitelisted_properties_used: ALL_PROPERTIES_WHITELIST
+                .iter()
+                .filter(|name| used.contains(*name))
+                .count(),
+            whitelist_size: ALL_PROPERTIES_WHITELIST.len(),
+            targets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn exemplar_stats_count_every_layer() {
+        let full = parse_full_manifest(get_exemplar_default_toml()).unwrap();
+        let stats = full.stats();
+        assert_eq!(3, stats.target_count);
+        assert_eq!(
+            stats.total_property_count(),
+            stats
+                .targets
+                .iter()
+                .map(TargetStats::property_count)
+                .sum::<usize>()
+        );
+        let x86 = &stats.targets[0];
+        assert_eq!(SupportedTarget::X8664Sel4Fel4, x86.target);
+        assert_eq!(
+            Some("BuildWithCommonSimulationSettings"),
+            x86.direct_properties.first().map(String::as_str)
+        );
+        assert_eq!(
+            vec![SupportedPlatform::PC99],
+            x86.platform_properties
+                .iter()
+                .map(|p| p.0)
+                .collect::<Vec<_>>()
+        );
+        assert!(stats.unused_tables.is_empty());
+        assert!(stats.whitelist_coverage() > 0.0 && stats.whitelist_coverage() <= 1.0);
+        assert!(stats.to_json_string().contains("\"target-count\": 3"));
+    }
+
+    #[test]
+    fn tables_no_selection_reads_are_unused() {
+        let full = test_helpers::ManifestFixture::new()
+            .with_property("x86_64-sel4-fel4.sabre", "KernelPrinting", true)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            vec!["x86_64-sel4-fel4.sabre".to_string()],
+            full.stats().unused_tables
+        );
+    }
+}
@@ -0,0 +1,200 @@
+/// Canned fel4 manifests and configurations for use in tests, both here and
+/// in downstream crates that enable the `test-helpers` feature
+use toml;
+
+use super::{get_exemplar_default_toml, resolve_fel4_config, ConfigError};
+use manifest::*;
+use types::*;
+
+/// The exemplar fel4.toml with the `[fel4]` header selecting the given
+/// target and platform
+pub fn fixture_toml(target: SupportedTarget, platform: SupportedPlatform) -> String {
+    ManifestFixture::for_selection(target, platform).to_toml_string()
+}
+
+/// A parsed `FullFel4Manifest` derived from the exemplar, selecting the given
+/// target and platform
+///
+/// Panics if the selection is not a supported combination.
+pub fn fixture_manifest(target: SupportedTarget, platform: SupportedPlatform) -> FullFel4Manifest {
+    ManifestFixture::for_selection(target, platform)
+        .parse()
+        .expect("The exemplar-derived fixture manifest should parse")
+}
+
+/// A resolved `Fel4Config` derived from the exemplar for the given target,
+/// platform, and build profile
+///
+/// Panics if the selection is not a supported combination.
+pub fn fixture_config(
+    target: SupportedTarget,
+    platform: SupportedPlatform,
+    build_profile: BuildProfile,
+) -> Fel4Config {
+    ManifestFixture::for_selection(target, platform)
+        .resolve(&build_profile)
+        .expect("The exemplar-derived fixture manifest should resolve")
+}
+
+/// Builder for quickly producing valid and invalid variations of the
+/// exemplar fel4 manifest
+///
+/// Table paths are dotted, as they would be written in a toml table header,
+/// e.g. `"fel4"` or `"x86_64-sel4-fel4.debug"`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestFixture {
+    root: toml::Value,
+}
+
+impl Default for ManifestFixture {
+    fn default() -> Self {
+        ManifestFixture::new()
+    }
+}
+
+impl ManifestFixture {
+    /// Start from the unmodified exemplar manifest
+    pub fn new() -> Self {
+        let root = get_exemplar_default_toml()
+            .parse::<toml::Value>()
+            .expect("The exemplar fel4.toml should be valid toml");
+        ManifestFixture { root }
+    }
+
+    /// Start from the exemplar manifest, selecting the given target and
+    /// platform in the `[fel4]` header
+    pub fn for_selection(target: SupportedTarget, platform: SupportedPlatform) -> Self {
+        ManifestFixture::new()
+            .with_property("fel4", "target", target.full_name())
+            .with_property("fel4", "platform", platform.full_name())
+    }
+
+    /// Set a property within the table at the given path, creating the table
+    /// if necessary
+    pub fn with_property<V: Into<toml::Value>>(
+        mut self,
+        table_path: &str,
+        name: &str,
+        value: V,
+    ) -> Self {
+        self.table_mut(table_path)
+            .insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Remove a property from the table at the given path, if present
+    pub fn without_property(mut self, table_path: &str, name: &str) -> Self {
+        self.table_mut(table_path).remove(name);
+        self
+    }
+
+    /// Remove the table at the given path, if present
+    pub fn without_table(mut self, table_path: &str) -> Self {
+        let (parent, name) = match table_path.rfind('.') {
+            Some(i) => (Some(&table_path[..i]), &table_path[i + 1..]),
+            None => (None, table_path),
+        };
+        let table = match parent {
+            Some(p) => self.table_mut(p),
+            None => self.root_mut(),
+        };
+        table.remove(name);
+        self
+    }
+
+    /// The fixture rendered as a toml document
+    pub fn to_toml_string(&self) -> String {
+        toml::to_string(&self.root).expect("Fixture manifests should always serialize")
+    }
+
+    /// Parse the fixture as a `FullFel4Manifest`
+    pub fn parse(&self) -> Result<FullFel4Manifest, ConfigError> {
+        toml_to_full_manifest(&self.root)
+    }
+
+    /// Parse and resolve the fixture for the given build profile
+    pub fn resolve(&self, build_profile: &BuildProfile) -> Result<Fel4Config, ConfigError> {
+        resolve_fel4_config(self.parse()?, build_profile)
+    }
+
+    fn root_mut(&mut self) -> &mut toml::value::Table {
+        match self.root {
+            toml::Value::Table(ref mut t) => t,
+            _ => unreachable!("The root of a toml document is always a table"),
+        }
+    }
+
+    fn table_mut(&mut self, table_path: &str) -> &mut toml::value::Table {
+        let mut current = self.root_mut();
+        for segment in table_path.split('.') {
+            let entry = current
+                .entry(segment.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+            current = match *entry {
+                toml::Value::Table(ref mut t) => t,
+                _ => panic!("{} is not a table in the fixture manifest", segment),
+            };
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::is_supported_target_platform_pair;
+    use super::*;
+
+    #[test]
+    fn fixtures_exist_for_every_supported_combination() {
+        for target in SupportedTarget::targets() {
+            for platform in SupportedPlatform::platforms() {
+                if !is_supported_target_platform_pair(target, platform) {
+                    continue;
+                }
+                for profile in BuildProfile::build_profiles() {
+                    let config = fixture_config(target, platform, profile);
+                    assert_eq!(target, config.target);
+                    assert_eq!(platform, config.platform);
+                    assert_eq!(profile, config.build_profile);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fixture_toml_round_trips_through_the_parser() {
+        let text = fixture_toml(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre);
+        assert_eq!(
+            fixture_manifest(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre),
+            parse_full_manifest(text).expect("Fixture toml should parse")
+        );
+    }
+
+    #[test]
+    fn fixture_builder_produces_invalid_variants() {
+        assert_eq!(
+            Err(ConfigError::MissingProfileTable(
+                SupportedTarget::X8664Sel4Fel4,
+                BuildProfile::Release
+            )),
+            ManifestFixture::new()
+                .without_table("x86_64-sel4-fel4.release")
+                .resolve(&BuildProfile::Release)
+        );
+        assert_eq!(
+            Err(ConfigError::NonWhitelistProperty("NotAThing".into())),
+            ManifestFixture::new()
+                .with_property("x86_64-sel4-fel4.pc99", "NotAThing", true)
+                .resolve(&BuildProfile::Debug)
+        );
+        assert_eq!(
+            Err(ConfigError::MissingRequiredProperty(
+                "fel4".into(),
+                "artifact-path".into()
+            )),
+            ManifestFixture::new()
+                .without_property("fel4", "artifact-path")
+                .parse()
+        );
+    }
+}
@@ -0,0 +1,762 @@
+/// Preflight checks for the host tools needed to build the seL4 kernel for a
+/// particular fel4 configuration
+use std::cmp::Ordering;
+use std::env;
+use std::ffi::OsString;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use trace;
+use types::*;
+
+/// Names the cmake executable for targets whose toolchain settings don't,
+/// as it does for the cmake crate
+pub const CMAKE_ENV_VAR: &str = "CMAKE";
+
+/// Names the ninja executable for targets whose toolchain settings don't
+pub const NINJA_ENV_VAR: &str = "NINJA";
+
+/// Names the compiler launcher for targets whose toolchain settings don't,
+/// such as `ccache` on a CI machine
+pub const COMPILER_LAUNCHER_ENV_VAR: &str = "FEL4_COMPILER_LAUNCHER";
+
+/// The oldest CMake release the seL4 build system supports
+pub const MINIMUM_CMAKE_VERSION: ToolVersion = ToolVersion {
+    major: 3,
+    minor: 7,
+    patch: 2,
+};
+
+/// The oldest Ninja release the seL4 build system supports
+pub const MINIMUM_NINJA_VERSION: ToolVersion = ToolVersion {
+    major: 1,
+    minor: 7,
+    patch: 1,
+};
+
+/// A dotted release version as reported by a tool's `--version` output
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ToolVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ToolVersion {
+    /// Find the first dotted version number in the output of a `--version`
+    /// invocation
+    pub fn from_version_output(output: &str) -> Option<ToolVersion> {
+        output
+            .split_whitespace()
+            .filter_map(|w| w.parse().ok())
+            .next()
+    }
+}
+
+impl Ord for ToolVersion {
+    fn cmp(&self, other: &ToolVersion) -> Ordering {
+        (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch))
+    }
+}
+
+impl PartialOrd for ToolVersion {
+    fn partial_cmp(&self, other: &ToolVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for ToolVersion {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for ToolVersion {
+    type Err = String;
+
+    /// Parses the leading `major.minor[.patch]` portion of a word, ignoring
+    /// any suffix such as `-rc1` or a trailing parenthesis
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        let dotted: String = s
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == '.')
+            .collect();
+        let mut parts = dotted.split('.').map(|p| p.parse::<u32>());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), patch) => Ok(ToolVersion {
+                major,
+                minor,
+                patch: match patch {
+                    Some(Ok(p)) => p,
+                    _ => 0,
+                },
+            }),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// Problems with the host tools that would prevent a kernel build
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ToolchainIssue {
+    /// The named executable could not be found on the search path
+    MissingExecutable(String),
+    /// The executable was found, but its version could not be determined
+    UnknownVersion(PathBuf),
+    /// The executable was found, but is older than required
+    VersionTooOld {
+        executable: PathBuf,
+        found: ToolVersion,
+        minimum: ToolVersion,
+    },
+}
+
+impl ToolchainIssue {
+    /// The name of the tool the issue concerns, without any directory or
+    /// executable file extension
+    pub fn tool_name(&self) -> String {
+        match *self {
+            ToolchainIssue::MissingExecutable(ref name) => Path::new(name),
+            ToolchainIssue::UnknownVersion(ref path) => path.as_path(),
+            ToolchainIssue::VersionTooOld { ref executable, .. } => executable.as_path(),
+        }
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+    }
+
+    /// A description of the issue including, where known, how to install or
+    /// upgrade the tool on the given host
+    pub fn describe_for_host(&self, host: HostOs) -> String {
+        match install_hint(&self.tool_name(), host) {
+            Some(hint) => format!("{}. Try `{}`", self, hint),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl Display for ToolchainIssue {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            ToolchainIssue::MissingExecutable(ref name) => {
+                write!(f, "The required {} executable was not found on PATH", name)
+            }
+            ToolchainIssue::UnknownVersion(ref path) => {
+                write!(f, "Unable to determine the version of {}", path.display())
+            }
+            ToolchainIssue::VersionTooOld {
+                ref executable,
+                found,
+                minimum,
+            } => write!(
+                f,
+                "{} is version {}, but at least version {} is required",
+                executable.display(),
+                found,
+                minimum
+            ),
+        }
+    }
+}
+
+/// The operating system of the machine running the build
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum HostOs {
+    Linux,
+    MacOs,
+    Windows,
+    Other,
+}
+
+impl HostOs {
+    /// The operating system this crate was compiled for
+    pub fn current() -> HostOs {
+        if cfg!(target_os = "linux") {
+            HostOs::Linux
+        } else if cfg!(target_os = "macos") {
+            HostOs::MacOs
+        } else if cfg!(windows) {
+            HostOs::Windows
+        } else {
+            HostOs::Other
+        }
+    }
+
+    /// Render a path the way CMake expects to receive it on this host.
+    ///
+    /// CMake treats backslashes in cache values as escapes, so Windows paths
+    /// are given forward slashes and stripped of any `\\?\` verbatim prefix.
+    pub fn cmake_path(&self, path: &Path) -> OsString {
+        match *self {
+            HostOs::Windows => {
+                let lossy = path.to_string_lossy();
+                let trimmed = lossy.trim_start_matches(r"\\?\");
+                OsString::from(trimmed.replace('\\', "/"))
+            }
+            _ => path.as_os_str().to_os_string(),
+        }
+    }
+
+    /// The file names an executable may have on this host
+    pub fn executable_file_names(&self, name: &str) -> Vec<String> {
+        match *self {
+            HostOs::Windows if !name.to_ascii_lowercase().ends_with(".exe") => {
+                vec![format!("{}.exe", name), name.to_string()]
+            }
+            _ => vec![name.to_string()],
+        }
+    }
+
+    /// Directories, beyond PATH, where package managers on this host commonly
+    /// install build tools
+    ///
+    /// On Windows these are the scoop shims and chocolatey bin directories.
+    pub fn package_manager_dirs(&self) -> Vec<PathBuf> {
+        match *self {
+            HostOs::Windows => {
+                let scoop = env::var_os("SCOOP")
+                    .map(PathBuf::from)
+                    .or_else(|| env::var_os("USERPROFILE").map(|p| Path::new(&p).join("scoop")))
+                    .map(|p| p.join("shims"));
+                let chocolatey = env::var_os("ChocolateyInstall")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData\chocolatey"))
+                    .join("bin");
+                scoop.into_iter().chain(Some(chocolatey)).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Fel4Config {
+    /// The floating point ABI of the configuration, for ARM targets.
+    ///
+    /// This is the toolchain's `float-abi` where given. Otherwise armv7 is
+    /// soft float where `KernelAArch32FPUEnableContextSwitch = false`, since
+    /// the kernel won't preserve FPU state across threads, and hard float
+    /// otherwise. aarch64 is always hard float.
+    pub fn float_abi(&self) -> Option<FloatAbi> {
+        match self.target {
+            SupportedTarget::X8664Sel4Fel4 => None,
+            SupportedTarget::Armv7Sel4Fel4 => self.toolchain.float_abi.or_else(|| {
+                match self.properties.get("KernelAArch32FPUEnableContextSwitch") {
+                    Some(FlatTomlValue::Boolean(false)) => Some(FloatAbi::Soft),
+                    _ => Some(FloatAbi::Hard),
+                }
+            }),
+            SupportedTarget::Aarch64Sel4Fel4 => Some(FloatAbi::Hard),
+        }
+    }
+}
+
+/// A row of `CROSS_COMPILER_PREFIXES`, which applies to builds matching each
+/// of the fields it gives
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossCompilerPrefixRule {
+    /// The host the row applies to, or every host
+    pub host: Option<HostOs>,
+    pub target: SupportedTarget,
+    /// The platform the row applies to, or every platform of the target
+    pub platform: Option<SupportedPlatform>,
+    /// The floating point ABI the row applies to, or every ABI
+    pub float_abi: Option<FloatAbi>,
+    /// The prefix, or `None` where the target is built with the host compiler
+    pub prefix: Option<&'static str>,
+}
+
+impl CrossCompilerPrefixRule {
+    fn matches(
+        &self,
+        host: HostOs,
+        target: SupportedTarget,
+        platform: Option<SupportedPlatform>,
+        float_abi: Option<FloatAbi>,
+    ) -> bool {
+        self.host.is_none_or(|h| h == host)
+            && self.target == target
+            && self.platform.is_none_or(|p| Some(p) == platform)
+            && self.float_abi.is_none_or(|abi| Some(abi) == float_abi)
+    }
+}
+
+const fn prefix_rule(
+    host: Option<HostOs>,
+    target: SupportedTarget,
+    float_abi: Option<FloatAbi>,
+    prefix: Option<&'static str>,
+) -> CrossCompilerPrefixRule {
+    CrossCompilerPrefixRule {
+        host,
+        target,
+        platform: None,
+        float_abi,
+        prefix,
+    }
+}
+
+/// The default cross compiler prefixes, of which the first matching row
+/// applies. Supporting a new target, such as RISC-V, means adding its rows
+/// here.
+///
+/// macOS hosts can't build ELF kernels with the system compiler, so every
+/// target there uses the bare-metal toolchains available from homebrew.
+pub const CROSS_COMPILER_PREFIXES: &[CrossCompilerPrefixRule] = &[
+    prefix_rule(
+        Some(HostOs::MacOs),
+        SupportedTarget::X8664Sel4Fel4,
+        None,
+        Some("x86_64-elf-"),
+    ),
+    prefix_rule(
+        Some(HostOs::MacOs),
+        SupportedTarget::Armv7Sel4Fel4,
+        None,
+        Some("arm-none-eabi-"),
+    ),
+    prefix_rule(
+        Some(HostOs::MacOs),
+        SupportedTarget::Aarch64Sel4Fel4,
+        None,
+        Some("aarch64-elf-"),
+    ),
+    prefix_rule(None, SupportedTarget::X8664Sel4Fel4, None, None),
+    prefix_rule(
+        None,
+        SupportedTarget::Armv7Sel4Fel4,
+        Some(FloatAbi::Soft),
+        Some("arm-linux-gnueabi-"),
+    ),
+    prefix_rule(
+        None,
+        SupportedTarget::Armv7Sel4Fel4,
+        None,
+        Some("arm-linux-gnueabihf-"),
+    ),
+    prefix_rule(
+        None,
+        SupportedTarget::Aarch64Sel4Fel4,
+        None,
+        Some("aarch64-linux-gnu-"),
+    ),
+];
+
+/// The cross compiler prefix the seL4 build uses for a target on the current
+/// host, if the target is not built with the host compiler
+pub fn cross_compiler_prefix(target: SupportedTarget) -> Option<&'static str> {
+    cross_compiler_prefix_for_host(target, HostOs::current())
+}
+
+/// The default cross compiler prefix for a target on the given host, for any
+/// of its platforms with the target's usual floating point ABI
+pub fn cross_compiler_prefix_for_host(
+    target: SupportedTarget,
+    host: HostOs,
+) -> Option<&'static str> {
+    default_cross_compiler_prefix(host, target, None, None)
+}
+
+/// The first of `CROSS_COMPILER_PREFIXES` matching the build
+pub fn default_cross_compiler_prefix(
+    host: HostOs,
+    target: SupportedTarget,
+    platform: Option<SupportedPlatform>,
+    float_abi: Option<FloatAbi>,
+) -> Option<&'static str> {
+    CROSS_COMPILER_PREFIXES
+        .iter()
+        .find(|rule| rule.matches(host, target, platform, float_abi))
+        .and_then(|rule| rule.prefix)
+}
+
+/// The cross compiler prefix a configuration is built with on the given
+/// host: the one its toolchain settings give, or else the default for its
+/// target, platform, and floating point ABI
+pub fn configured_compiler_prefix(fel4_config: &Fel4Config, host: HostOs) -> Option<&str> {
+    fel4_config.toolchain.compiler_prefix.as_deref().or_else(|| {
+        default_cross_compiler_prefix(
+            host,
+            fel4_config.target,
+            Some(fel4_config.platform),
+            fel4_config.float_abi(),
+        )
+    })
+}
+
+/// The command that installs the named tool on the given host, for the
+/// tools `check_toolchain` looks for
+pub fn install_hint(executable: &str, host: HostOs) -> Option<&'static str> {
+    let hint = match (host, executable) {
+        (HostOs::MacOs, "x86_64-elf-gcc") => "brew install x86_64-elf-gcc",
+        (HostOs::MacOs, "arm-none-eabi-gcc") => "brew install arm-none-eabi-gcc",
+        (HostOs::MacOs, "aarch64-elf-gcc") => "brew install aarch64-elf-gcc",
+        (HostOs::MacOs, "cmake") => "brew install cmake",
+        (HostOs::MacOs, "ninja") => "brew install ninja",
+        (HostOs::Linux, "gcc") => "apt-get install gcc",
+        (HostOs::Linux, "arm-linux-gnueabihf-gcc") => "apt-get install gcc-arm-linux-gnueabihf",
+        (HostOs::Linux, "arm-linux-gnueabi-gcc") => "apt-get install gcc-arm-linux-gnueabi",
+        (HostOs::Linux, "aarch64-linux-gnu-gcc") => "apt-get install gcc-aarch64-linux-gnu",
+        (HostOs::Linux, "cmake") => "apt-get install cmake",
+        (HostOs::Linux, "ninja") => "apt-get install ninja-build",
+        (HostOs::Windows, "cmake") => "scoop install cmake",
+        (HostOs::Windows, "ninja") => "scoop install ninja",
+        _ => return None,
+    };
+    Some(hint)
+}
+
+/// The toolchain settings with the cmake and ninja executables named by the
+/// `CMAKE` and `NINJA` environment variables filled in, where the settings
+/// themselves don't name one
+pub fn toolchain_with_env_defaults(toolchain: &ToolchainSettings) -> ToolchainSettings {
+    toolchain_with_defaults_from(toolchain, |name| trace::var(name).ok())
+}
+
+fn toolchain_with_defaults_from<F>(toolchain: &ToolchainSettings, lookup: F) -> ToolchainSettings
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let lookup = |name| lookup(name).filter(|v: &String| !v.is_empty());
+    ToolchainSettings {
+        cmake: toolchain.cmake.clone().or_else(|| lookup(CMAKE_ENV_VAR)),
+        ninja: toolchain.ninja.clone().or_else(|| lookup(NINJA_ENV_VAR)),
+        compiler_launcher: toolchain
+            .compiler_launcher
+            .clone()
+            .or_else(|| lookup(COMPILER_LAUNCHER_ENV_VAR)),
+        ..toolchain.clone()
+    }
+}
+
+/// Verify that the compiler, cmake, and ninja executables needed to build
+/// the kernel for the configuration are present and recent enough.
+///
+/// Executables given explicitly in the target's toolchain settings, or else
+/// by the `CMAKE` and `NINJA` environment variables, are checked in place;
+/// the rest are searched for on PATH, followed by the host's usual package
+/// manager install directories.
+pub fn check_toolchain(fel4_config: &Fel4Config) -> Vec<ToolchainIssue> {
+    let mut search_path: Vec<PathBuf> = env::var_os("PATH")
+        .map(|p| env::split_paths(&p).collect())
+        .unwrap_or_default();
+    search_path.extend(HostOs::current().package_manager_dirs());
+    let fel4_config = Fel4Config {
+        toolchain: toolchain_with_env_defaults(&fel4_config.toolchain),
+        ..fel4_config.clone()
+    };
+    check_toolchain_in(&fel4_config, HostOs::current(), &search_path)
+}
+
+/// Verify the toolchain as `check_toolchain` does, for the given host and
+/// searching the given directories instead of PATH
+pub fn check_toolchain_in(
+    fel4_config: &Fel4Config,
+    host: HostOs,
+    search_path: &[PathBuf],
+) -> Vec<ToolchainIssue> {
+    let toolchain = &fel4_config.toolchain;
+    let prefix = configured_compiler_prefix(fel4_config, host).unwrap_or("");
+    let mut required = vec![
+        (format!("{}gcc", prefix), None, None),
+        (
+            "cmake".to_string(),
+            toolchain.cmake.as_ref(),
+            Some(MINIMUM_CMAKE_VERSION),
+        ),
+        (
+            "ninja".to_string(),
+            toolchain.ninja.as_ref(),
+            Some(MINIMUM_NINJA_VERSION),
+        ),
+    ];
+    // A launcher is named like `ccache`, to be searched for, or by its path
+    if let Some(ref launcher) = toolchain.compiler_launcher {
+        let is_path = launcher.contains('/') || launcher.contains('\\');
+        required.push((launcher.clone(), Some(launcher).filter(|_| is_path), None));
+    }
+    required
+        .into_iter()
+        .filter_map(|(name, configured, minimum)| {
+            let located = match configured {
+                Some(path) => Some(PathBuf::from(path)).filter(|p| p.is_file()),
+                None => find_executable(&name, host, search_path),
+            };
+            match located {
+                Some(executable) => check_version(executable, minimum),
+                None => Some(ToolchainIssue::MissingExecutable(
+                    configured.cloned().unwrap_or(name),
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Locate an executable by name within the given directories, allowing for
+/// the host's executable file extension
+pub fn find_executable(name: &str, host: HostOs, search_path: &[PathBuf]) -> Option<PathBuf> {
+    let file_names = host.executable_file_names(name);
+    search_path
+        .iter()
+        .flat_map(|dir| file_names.iter().map(move |f| dir.join(f)))
+        .find(|candidate| candidate.is_file())
+}
+
+fn check_version(executable: PathBuf, minimum: Option<ToolVersion>) -> Option<ToolchainIssue> {
+    let minimum = minimum?;
+    match query_version(&executable) {
+        None => Some(ToolchainIssue::UnknownVersion(executable)),
+        Some(found) if found < minimum => Some(ToolchainIssue::VersionTooOld {
+            executable,
+            found,
+            minimum,
+        }),
+        Some(_) => None,
+    }
+}
+
+fn query_version(executable: &Path) -> Option<ToolVersion> {
+    let output = Command::new(executable).arg("--version").output().ok()?;
+    ToolVersion::from_version_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn versions_parse_from_typical_tool_output() {
+        assert_eq!(
+            Some(ToolVersion {
+                major: 3,
+                minor: 10,
+                patch: 2
+            }),
+            ToolVersion::from_version_output("cmake version 3.10.2\n\nCMake suite maintained")
+        );
+        assert_eq!(
+            Some(ToolVersion {
+                major: 1,
+                minor: 8,
+                patch: 0
+            }),
+            ToolVersion::from_version_output("1.8\n")
+        );
+        assert_eq!(
+            Some(ToolVersion {
+                major: 7,
+                minor: 3,
+                patch: 0
+            }),
+            ToolVersion::from_version_output("gcc (Ubuntu 7.3.0-16ubuntu3) 7.3.0")
+        );
+        assert_eq!(None, ToolVersion::from_version_output("no numbers here"));
+    }
+
+    #[test]
+    fn versions_order_numerically() {
+        let older: ToolVersion = "3.7.2".parse().unwrap();
+        let newer: ToolVersion = "3.10.0".parse().unwrap();
+        assert!(older < newer);
+        assert_eq!(MINIMUM_CMAKE_VERSION, older);
+    }
+
+    #[test]
+    fn missing_tools_are_reported() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml")
+            .with_selection(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
+            .expect("Should be able to select the armv7 target");
+        let config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        assert_eq!(
+            vec![
+                ToolchainIssue::MissingExecutable("arm-linux-gnueabihf-gcc".into()),
+                ToolchainIssue::MissingExecutable("cmake".into()),
+                ToolchainIssue::MissingExecutable("ninja".into()),
+            ],
+            check_toolchain_in(&config, HostOs::Linux, &[])
+        );
+    }
+
+    #[test]
+    fn environment_names_tools_the_manifest_does_not() {
+        let env = |name: &str| match name {
+            "CMAKE" => Some("/nix/store/cmake/bin/cmake".to_string()),
+            "NINJA" => Some("/nix/store/ninja/bin/ninja".to_string()),
+            "FEL4_COMPILER_LAUNCHER" => Some("ccache".to_string()),
+            _ => None,
+        };
+        let toolchain = ToolchainSettings {
+            cmake: Some("/opt/cmake/bin/cmake".into()),
+            ..ToolchainSettings::default()
+        };
+        let filled = toolchain_with_defaults_from(&toolchain, env);
+        assert_eq!(Some("/opt/cmake/bin/cmake"), filled.cmake.as_deref());
+        assert_eq!(Some("/nix/store/ninja/bin/ninja"), filled.ninja.as_deref());
+        assert_eq!(Some("ccache"), filled.compiler_launcher.as_deref());
+        assert_eq!(
+            toolchain,
+            toolchain_with_defaults_from(&toolchain, |_| Some(String::new()))
+        );
+    }
+
+    #[test]
+    fn configured_tools_are_checked_in_place() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let mut config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        config.toolchain.compiler_prefix = Some("x86_64-elf-".into());
+        config.toolchain.cmake = Some("/nonexistent/cmake".into());
+        config.toolchain.compiler_launcher = Some("sccache".into());
+        assert_eq!(
+            vec![
+                ToolchainIssue::MissingExecutable("x86_64-elf-gcc".into()),
+                ToolchainIssue::MissingExecutable("/nonexistent/cmake".into()),
+                ToolchainIssue::MissingExecutable("ninja".into()),
+                ToolchainIssue::MissingExecutable("sccache".into()),
+            ],
+            check_toolchain_in(&config, HostOs::Linux, &[])
+        );
+    }
+
+    #[test]
+    fn windows_paths_are_given_to_cmake_with_forward_slashes() {
+        assert_eq!(
+            OsString::from("C:/work/app/deps/seL4_kernel/gcc.cmake"),
+            HostOs::Windows.cmake_path(Path::new(r"\\?\C:\work\app\deps\seL4_kernel\gcc.cmake"))
+        );
+        assert_eq!(
+            OsString::from("/work/app/deps/seL4_kernel"),
+            HostOs::Linux.cmake_path(Path::new("/work/app/deps/seL4_kernel"))
+        );
+    }
+
+    #[test]
+    fn windows_executables_may_carry_an_exe_extension() {
+        assert_eq!(
+            vec!["ninja.exe".to_string(), "ninja".to_string()],
+            HostOs::Windows.executable_file_names("ninja")
+        );
+        assert_eq!(
+            vec!["cmake.EXE".to_string()],
+            HostOs::Windows.executable_file_names("cmake.EXE")
+        );
+        assert_eq!(
+            vec!["ninja".to_string()],
+            HostOs::MacOs.executable_file_names("ninja")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_package_manager_dirs_include_chocolatey() {
+        assert!(HostOs::current()
+            .package_manager_dirs()
+            .iter()
+            .any(|d| d.ends_with("bin")));
+    }
+
+    #[test]
+    fn macos_hosts_use_homebrew_cross_toolchains() {
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        let issues = check_toolchain_in(&config, HostOs::MacOs, &[]);
+        assert_eq!(
+            ToolchainIssue::MissingExecutable("x86_64-elf-gcc".into()),
+            issues[0]
+        );
+        assert_eq!(
+            "The required x86_64-elf-gcc executable was not found on PATH. \
+             Try `brew install x86_64-elf-gcc`",
+            issues[0].describe_for_host(HostOs::MacOs)
+        );
+        assert_eq!(
+            Some("arm-none-eabi-"),
+            cross_compiler_prefix_for_host(SupportedTarget::Armv7Sel4Fel4, HostOs::MacOs)
+        );
+    }
+
+    #[test]
+    fn cross_compiler_prefixes_follow_the_platform_and_float_abi() {
+        let text = get_exemplar_default_toml().replacen(
+            "KernelAArch32FPUEnableContextSwitch = true",
+            "KernelAArch32FPUEnableContextSwitch = false",
+            1,
+        );
+        let full = parse_full_manifest(&text)
+            .expect("Should be able to get the default fel4.toml")
+            .with_selection(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
+            .expect("Should be able to select the armv7 target");
+        let soft = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        assert_eq!(Some(FloatAbi::Soft), soft.float_abi());
+        assert_eq!(
+            Some("arm-linux-gnueabi-"),
+            configured_compiler_prefix(&soft, HostOs::Linux)
+        );
+        assert_eq!(
+            Some("arm-none-eabi-"),
+            configured_compiler_prefix(&soft, HostOs::MacOs)
+        );
+
+        let overridden = parse_full_manifest(format!(
+            "{}\n[toolchain.armv7-sel4-fel4]\ncompiler-prefix = \"arm-a-\"\n\
+             [toolchain.armv7-sel4-fel4.sabre]\ncompiler-prefix = \"arm-sabre-\"\n",
+            text
+        )).unwrap()
+            .with_selection(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
+            .unwrap();
+        let config = resolve_fel4_config(&overridden, &BuildProfile::Debug).unwrap();
+        assert_eq!(Some("arm-sabre-"), configured_compiler_prefix(&config, HostOs::Linux));
+        assert!(config.toolchain.platform_compiler_prefixes.is_empty());
+        assert_eq!(
+            None,
+            default_cross_compiler_prefix(
+                HostOs::Linux,
+                SupportedTarget::X8664Sel4Fel4,
+                Some(SupportedPlatform::PC99),
+                None
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn outdated_tools_are_reported() {
+        use std::fs::{self, File};
+        use std::io::Write;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = ::tempfile::tempdir().unwrap();
+        for &(name, version) in &[
+            ("gcc", "gcc (GCC) 8.1.0"),
+            ("cmake", "cmake version 3.5.1"),
+            ("ninja", "1.8.2"),
+        ] {
+            let path = dir.path().join(name);
+            let mut f = File::create(&path).unwrap();
+            writeln!(f, "#!/bin/sh\necho '{}'", version).unwrap();
+            drop(f);
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let full = parse_full_manifest(get_exemplar_default_toml())
+            .expect("Should be able to get the default fel4.toml");
+        let config =
+            resolve_fel4_config(full, &BuildProfile::Debug).expect("Trouble in config resolution");
+        assert_eq!(
+            vec![ToolchainIssue::VersionTooOld {
+                executable: dir.path().join("cmake"),
+                found: "3.5.1".parse().unwrap(),
+                minimum: MINIMUM_CMAKE_VERSION,
+            }],
+            check_toolchain_in(&config, HostOs::Linux, &[dir.path().to_path_buf()])
+        );
+    }
+}
@@ -0,0 +1,109 @@
+/// Optional diagnostic tracing of parsing, resolution, and CMake configuration
+///
+/// With the `trace` feature enabled, each step the crate takes is reported
+/// as a `TraceEvent`: to a hook installed with `set_trace_hook`, or else to
+/// standard error when the `FEL4_CONFIG_TRACE` environment variable is set.
+/// Without the feature, tracing compiles away entirely.
+use std::env::{self, VarError};
+
+/// Emit a trace event for an area of the crate, using `format!` arguments.
+/// Does nothing unless the `trace` feature is enabled.
+#[cfg(feature = "trace")]
+macro_rules! fel4_trace {
+    ($area:expr, $($arg:tt)*) => {
+        ::trace::emit($area, &format!($($arg)*))
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! fel4_trace {
+    ($area:expr, $($arg:tt)*) => {
+        if false {
+            let _ = ($area, format!($($arg)*));
+        }
+    };
+}
+
+/// The environment variable that enables trace output on standard error
+pub const TRACE_ENV_VAR: &str = "FEL4_CONFIG_TRACE";
+
+/// A single step reported by the crate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceEvent<'a> {
+    /// The part of the crate reporting, e.g. `"manifest"`, `"resolve"`,
+    /// `"cmake"`, or `"env"`
+    pub area: &'static str,
+    pub message: &'a str,
+}
+
+/// A function that receives every trace event
+pub type TraceHook = fn(&TraceEvent);
+
+#[cfg(feature = "trace")]
+static TRACE_HOOK: ::std::sync::Mutex<Option<TraceHook>> = ::std::sync::Mutex::new(None);
+
+/// Route trace events to `hook` instead of standard error, or restore the
+/// default behavior with `None`. Has no effect without the `trace` feature.
+#[cfg_attr(not(feature = "trace"), allow(unused_variables))]
+pub fn set_trace_hook(hook: Option<TraceHook>) {
+    #[cfg(feature = "trace")]
+    {
+        *TRACE_HOOK.lock().unwrap_or_else(|e| e.into_inner()) = hook;
+    }
+}
+
+#[cfg(feature = "trace")]
+pub(crate) fn emit(area: &'static str, message: &str) {
+    let event = TraceEvent { area, message };
+    let hook = *TRACE_HOOK.lock().unwrap_or_else(|e| e.into_inner());
+    match hook {
+        Some(hook) => hook(&event),
+        None => {
+            if env::var_os(TRACE_ENV_VAR).is_some() {
+                eprintln!("fel4-config[{}]: {}", event.area, event.message);
+            }
+        }
+    }
+}
+
+/// Read an environment variable, tracing whether it was consulted and found
+pub(crate) fn var(name: &str) -> Result<String, VarError> {
+    let value = env::var(name);
+    match value {
+        Ok(ref v) => fel4_trace!("env", "read {}={}", name, v),
+        Err(_) => fel4_trace!("env", "{} is not set", name),
+    }
+    value
+}
+
+#[cfg(all(test, feature = "trace"))]
+mod tests {
+    use super::super::*;
+    use super::*;
+    use std::sync::Mutex;
+
+    static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    fn record(event: &TraceEvent) {
+        EVENTS
+            .lock()
+            .unwrap()
+            .push(format!("{}: {}", event.area, event.message));
+    }
+
+    #[test]
+    fn hook_receives_parsing_resolution_and_cmake_events() {
+        set_trace_hook(Some(record));
+        let full = parse_full_manifest(get_exemplar_default_toml()).unwrap();
+        let config = resolve_fel4_config(full, &BuildProfile::Debug).unwrap();
+        let mut recorded = RecordedDefinitions::default();
+        configure_cmake_build(&mut recorded, &config, "./some/repo", "x86_64-sel4-fel4").unwrap();
+        set_trace_hook(None);
+
+        let events = EVENTS.lock().unwrap();
+        let saw = |prefix: &str| events.iter().any(|e| e.starts_with(prefix));
+        assert!(saw("manifest: read [x86_64-sel4-fel4]"));
+        assert!(saw("resolve: resolving x86_64-sel4-fel4 pc99 debug"));
+        assert!(saw("cmake: define KernelPrinting:BOOL=ON"));
+    }
+}
@@ -1,5 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Error as FmtError, Formatter};
+use std::path::{Path, PathBuf};
+use std::slice;
 use std::str::FromStr;
 use toml;
 
@@ -8,13 +10,726 @@ use toml;
 #[derive(Clone, Debug, PartialEq)]
 pub struct Fel4Config {
     pub artifact_path: String,
+    /// How build outputs are arranged beneath the `artifact_path`
+    pub artifact_layout: ArtifactLayout,
     pub target_specs_path: String,
     pub target: SupportedTarget,
     pub platform: SupportedPlatform,
     pub build_profile: BuildProfile,
+    /// The `CMAKE_BUILD_TYPE` the kernel is built with
+    pub cmake_build_type: String,
     pub properties: HashMap<String, FlatTomlValue>,
+    /// The values the kernel's CMake build chose for known properties the
+    /// manifest left unset, from `augment_from_generated_headers`. Empty
+    /// until then.
+    pub effective_properties: HashMap<String, FlatTomlValue>,
+    pub toolchain: ToolchainSettings,
+    pub rootserver: RootserverSettings,
+    pub kernel_source: Option<KernelSource>,
+    /// The kernel release the project expects to be built against
+    pub kernel_version: Option<String>,
+    /// The kernel git commit, or a prefix of it, the project expects
+    pub kernel_commit: Option<String>,
+    /// The image base name, see `Fel4Config::image_file_name`
+    pub image_name: Option<String>,
+    /// The image naming pattern, see `Fel4Config::image_file_name`
+    pub image_name_pattern: Option<String>,
+    /// The timestamp reproducible kernel builds are pinned to, if any
+    pub source_date_epoch: Option<SourceDateEpoch>,
+    /// The manifest layer each of the `properties` was resolved from
+    pub provenance: HashMap<String, PropertyLayer>,
+    /// The names of the `properties` in the order the manifest declared them,
+    /// through the target table, then the build profile and platform
+    /// subtables, followed in name order by those implied during resolution
+    pub property_order: Vec<String>,
+    /// The targets the manifest has tables for, in `SupportedTarget::targets`
+    /// order
+    pub declared_targets: Vec<SupportedTarget>,
+    /// The manifest file the configuration was read from, if it was read
+    /// from a file
+    pub manifest_path: Option<PathBuf>,
+    /// The scopes the manifest's `[fel4.property-scopes]` table declares,
+    /// which replace `PropertyScope::default_for`
+    pub property_scopes: HashMap<String, PropertyScope>,
+    /// The properties the manifest's `locked-properties` array protects from
+    /// `Fel4Config::override_property`
+    pub locked_properties: Vec<String>,
 }
 
+/// The semantic facts about a configuration that consumers would otherwise
+/// each re-derive from the raw properties, see `Fel4Config::traits`
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ConfigTraits {
+    pub architecture: Architecture,
+    /// The kernel's word size in bits
+    pub word_size: u32,
+    /// Whether the kernel is a debug build, from `KernelDebugBuild`, or the
+    /// build profile where that is unset
+    pub debug: bool,
+    /// Whether the kernel supports more than one core, from
+    /// `KernelMaxNumNodes`
+    pub smp: bool,
+}
+
+impl Fel4Config {
+    /// The directory, relative to the project root, that build outputs for
+    /// this target and build profile are written to.
+    ///
+    /// With the default `ArtifactLayout::PerProfile` this is
+    /// `$ARTIFACT_PATH/$TARGET/$PROFILE`, so that switching targets or build
+    /// profiles never overwrites another selection's artifacts.
+    pub fn output_dir(&self) -> PathBuf {
+        let artifacts = Path::new(&self.artifact_path);
+        match self.artifact_layout {
+            ArtifactLayout::PerProfile => artifacts
+                .join(self.target.full_name())
+                .join(self.build_profile.full_name()),
+            ArtifactLayout::Flat => artifacts.to_path_buf(),
+        }
+    }
+
+    /// The target, platform, and build profile, for sorting collections of
+    /// configurations deterministically
+    pub fn selection_key(&self) -> (SupportedTarget, SupportedPlatform, BuildProfile) {
+        (self.target, self.platform, self.build_profile)
+    }
+
+    /// The resolved properties in `property_order`, followed in name order by
+    /// any properties it doesn't mention
+    pub fn ordered_properties(&self) -> impl Iterator<Item = (&str, &FlatTomlValue)> {
+        let mut ordered: Vec<(&str, &FlatTomlValue)> = Vec::with_capacity(self.properties.len());
+        let mut seen = HashSet::new();
+        for name in &self.property_order {
+            if let Some(value) = self.properties.get(name) {
+                if seen.insert(name.as_str()) {
+                    ordered.push((name.as_str(), value));
+                }
+            }
+        }
+        let mut rest: Vec<(&str, &FlatTomlValue)> = self
+            .properties
+            .iter()
+            .filter(|&(name, _)| !seen.contains(name.as_str()))
+            .map(|(name, value)| (name.as_str(), value))
+            .collect();
+        rest.sort_by(|a, b| a.0.cmp(b.0));
+        ordered.extend(rest);
+        ordered.into_iter()
+    }
+
+    /// The resolved properties belonging to a `PropertyGroup`, in the order
+    /// of `ordered_properties`
+    pub fn properties_in_group(
+        &self,
+        group: PropertyGroup,
+    ) -> impl Iterator<Item = (&str, &FlatTomlValue)> {
+        self.ordered_properties()
+            .filter(move |&(name, _)| PropertyGroup::of(name) == group)
+    }
+
+    /// A property's value as the kernel is built with it: the resolved
+    /// value, or else the default from `effective_properties`
+    pub fn effective_property(&self, property_name: &str) -> Option<&FlatTomlValue> {
+        self.properties
+            .get(property_name)
+            .or_else(|| self.effective_properties.get(property_name))
+    }
+
+    /// The declared or default scope of a property
+    pub fn property_scope(&self, property_name: &str) -> PropertyScope {
+        self.property_scopes
+            .get(property_name)
+            .cloned()
+            .unwrap_or_else(|| PropertyScope::default_for(property_name))
+    }
+
+    /// The resolved properties whose changes require reflashing the kernel,
+    /// in the order of `ordered_properties`
+    pub fn abi_affecting_properties(&self) -> impl Iterator<Item = (&str, &FlatTomlValue)> {
+        self.ordered_properties()
+            .filter(move |&(name, _)| self.property_scope(name) == PropertyScope::KernelAbi)
+    }
+
+    /// The resolved properties whose changes only affect the component, in
+    /// the order of `ordered_properties`
+    pub fn component_local_properties(&self) -> impl Iterator<Item = (&str, &FlatTomlValue)> {
+        self.ordered_properties()
+            .filter(move |&(name, _)| self.property_scope(name) == PropertyScope::ComponentLocal)
+    }
+
+    /// How a resolved property came by its value, from its `provenance`, or
+    /// None if the property isn't resolved
+    pub fn property_origin(&self, property_name: &str) -> Option<PropertyOrigin> {
+        if !self.properties.contains_key(property_name) {
+            return None;
+        }
+        Some(match self.provenance.get(property_name) {
+            Some(PropertyLayer::Rule) => PropertyOrigin::Defaulted,
+            Some(_) => PropertyOrigin::Explicit,
+            None => PropertyOrigin::Overridden,
+        })
+    }
+
+    /// The resolved properties in the order of `ordered_properties`, without
+    /// the user and linker script properties the kernel build doesn't know
+    /// about
+    pub fn kernel_properties(&self) -> impl Iterator<Item = (&str, &FlatTomlValue)> {
+        self.ordered_properties().filter(|&(name, _)| {
+            !matches!(
+                PropertyGroup::of(name),
+                PropertyGroup::User | PropertyGroup::Linker
+            )
+        })
+    }
+
+    /// The resolved properties whose names start with `prefix`, such as
+    /// `"KernelX86"` or `"LibSel4"`, in the order of `ordered_properties`
+    pub fn properties_with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a FlatTomlValue)> {
+        self.ordered_properties()
+            .filter(move |&(name, _)| name.starts_with(prefix))
+    }
+
+    /// The resolved properties the predicate accepts, given each name and
+    /// value, in the order of `ordered_properties`
+    pub fn find_property<F>(&self, predicate: F) -> impl Iterator<Item = (&str, &FlatTomlValue)>
+    where
+        F: Fn(&str, &FlatTomlValue) -> bool,
+    {
+        self.ordered_properties()
+            .filter(move |&(name, value)| predicate(name, value))
+    }
+}
+
+/// The prefix that marks a property as the application's own, exempt from
+/// the whitelist and left out of the kernel's CMake definitions
+pub const USER_PROPERTY_PREFIX: &str = "X-";
+
+/// Whether a property name is a user property, such as `X-AppLogLevel`
+pub fn is_user_property(property_name: &str) -> bool {
+    property_name.len() > USER_PROPERTY_PREFIX.len()
+        && property_name.starts_with(USER_PROPERTY_PREFIX)
+}
+
+/// The part of the system a property configures, ordered as
+/// `PropertyGroup::groups` lists them
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[non_exhaustive]
+pub enum PropertyGroup {
+    /// The seL4 kernel itself
+    Kernel,
+    /// The libsel4 userland library
+    LibSel4,
+    /// The elfloader that boots the kernel image
+    Elfloader,
+    /// The build of the userland image, including linking and platform
+    /// support libraries
+    Build,
+    /// The userland image's linker script, which fel4-config generates
+    /// itself, so the kernel build never sees these properties
+    Linker,
+    /// The application's own pass-through properties, named with the
+    /// `USER_PROPERTY_PREFIX`
+    User,
+}
+const PROPERTY_GROUP_KERNEL: &str = "kernel";
+const PROPERTY_GROUP_LIBSEL4: &str = "libsel4";
+const PROPERTY_GROUP_ELFLOADER: &str = "elfloader";
+const PROPERTY_GROUP_BUILD: &str = "build";
+const PROPERTY_GROUP_LINKER: &str = "linker";
+const PROPERTY_GROUP_USER: &str = "user";
+impl PropertyGroup {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            PropertyGroup::Kernel => PROPERTY_GROUP_KERNEL,
+            PropertyGroup::LibSel4 => PROPERTY_GROUP_LIBSEL4,
+            PropertyGroup::Elfloader => PROPERTY_GROUP_ELFLOADER,
+            PropertyGroup::Build => PROPERTY_GROUP_BUILD,
+            PropertyGroup::Linker => PROPERTY_GROUP_LINKER,
+            PropertyGroup::User => PROPERTY_GROUP_USER,
+        }
+    }
+
+    pub fn groups() -> Vec<PropertyGroup> {
+        vec![
+            PropertyGroup::Kernel,
+            PropertyGroup::LibSel4,
+            PropertyGroup::Elfloader,
+            PropertyGroup::Build,
+            PropertyGroup::Linker,
+            PropertyGroup::User,
+        ]
+    }
+
+    /// A stable key for sorting property names, by group in `groups` order
+    /// and then by name, as reports list them
+    pub fn sort_key(property_name: &str) -> (PropertyGroup, &str) {
+        (PropertyGroup::of(property_name), property_name)
+    }
+
+    /// The group a property belongs to. Properties other than the kernel's,
+    /// libsel4's, the elfloader's, the linker script's, and user properties
+    /// belong to `Build`.
+    pub fn of(property_name: &str) -> PropertyGroup {
+        match property_name {
+            n if is_user_property(n) => PropertyGroup::User,
+            "UserLinkerScript" | "UserLinkerEntryPoint" | "UserImageBase" => PropertyGroup::Linker,
+            // Configures the kernel despite its name
+            "HardwareDebugAPI" => PropertyGroup::Kernel,
+            n if n.starts_with("Kernel") => PropertyGroup::Kernel,
+            n if n.starts_with("LibSel4") => PropertyGroup::LibSel4,
+            n if n.starts_with("Elfloader") => PropertyGroup::Elfloader,
+            _ => PropertyGroup::Build,
+        }
+    }
+}
+
+impl Display for PropertyGroup {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for PropertyGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            PROPERTY_GROUP_KERNEL => Ok(PropertyGroup::Kernel),
+            PROPERTY_GROUP_LIBSEL4 => Ok(PropertyGroup::LibSel4),
+            PROPERTY_GROUP_ELFLOADER => Ok(PropertyGroup::Elfloader),
+            PROPERTY_GROUP_BUILD => Ok(PropertyGroup::Build),
+            PROPERTY_GROUP_LINKER => Ok(PropertyGroup::Linker),
+            PROPERTY_GROUP_USER => Ok(PropertyGroup::User),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// The arrangement of build outputs beneath the artifact-path, from the
+/// `artifact-layout` key of the `[fel4]` table
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ArtifactLayout {
+    /// Outputs are separated into `$TARGET/$PROFILE` subdirectories
+    #[default]
+    PerProfile,
+    /// Outputs are written directly to the artifact-path, as fel4-config
+    /// 0.3 did, and each build overwrites the last
+    Flat,
+}
+const ARTIFACT_LAYOUT_PER_PROFILE: &str = "per-profile";
+const ARTIFACT_LAYOUT_FLAT: &str = "flat";
+impl ArtifactLayout {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            ArtifactLayout::PerProfile => ARTIFACT_LAYOUT_PER_PROFILE,
+            ArtifactLayout::Flat => ARTIFACT_LAYOUT_FLAT,
+        }
+    }
+
+    pub fn layouts() -> Vec<ArtifactLayout> {
+        vec![ArtifactLayout::PerProfile, ArtifactLayout::Flat]
+    }
+
+    pub fn layout_names() -> Vec<String> {
+        ArtifactLayout::layouts()
+            .iter()
+            .map(|l| l.full_name().into())
+            .collect()
+    }
+}
+
+impl Display for ArtifactLayout {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for ArtifactLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            ARTIFACT_LAYOUT_PER_PROFILE => Ok(ArtifactLayout::PerProfile),
+            ARTIFACT_LAYOUT_FLAT => Ok(ArtifactLayout::Flat),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// The kind of machine a project's images run on, from the `deployment` key
+/// of the `[fel4]` table
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum Deployment {
+    /// Images are flashed to physical boards
+    Hardware,
+    /// Images only run in a simulator such as QEMU
+    Simulation,
+}
+const DEPLOYMENT_HARDWARE: &str = "hardware";
+const DEPLOYMENT_SIMULATION: &str = "simulation";
+impl Deployment {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            Deployment::Hardware => DEPLOYMENT_HARDWARE,
+            Deployment::Simulation => DEPLOYMENT_SIMULATION,
+        }
+    }
+
+    pub fn deployments() -> Vec<Deployment> {
+        vec![Deployment::Hardware, Deployment::Simulation]
+    }
+
+    pub fn deployment_names() -> Vec<String> {
+        Deployment::deployments()
+            .iter()
+            .map(|d| d.full_name().into())
+            .collect()
+    }
+}
+
+impl Display for Deployment {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for Deployment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            DEPLOYMENT_HARDWARE => Ok(Deployment::Hardware),
+            DEPLOYMENT_SIMULATION => Ok(Deployment::Simulation),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// The timestamp a reproducible kernel build is pinned to, exported to the
+/// build as `SOURCE_DATE_EPOCH`
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum SourceDateEpoch {
+    /// A fixed number of seconds since the Unix epoch
+    Fixed(i64),
+    /// The commit time of the seL4_kernel checkout's HEAD
+    KernelCommit,
+}
+const SOURCE_DATE_EPOCH_KERNEL_COMMIT: &str = "kernel-commit";
+
+impl Display for SourceDateEpoch {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            SourceDateEpoch::Fixed(seconds) => write!(f, "{}", seconds),
+            SourceDateEpoch::KernelCommit => f.write_str(SOURCE_DATE_EPOCH_KERNEL_COMMIT),
+        }
+    }
+}
+
+impl FromStr for SourceDateEpoch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            SOURCE_DATE_EPOCH_KERNEL_COMMIT => Ok(SourceDateEpoch::KernelCommit),
+            _ => s
+                .parse()
+                .ok()
+                .filter(|seconds| *seconds >= 0)
+                .map(SourceDateEpoch::Fixed)
+                .ok_or_else(|| s.to_string()),
+        }
+    }
+}
+
+/// Whether changing a property changes the kernel image and its ABI, or only
+/// the component being built
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum PropertyScope {
+    /// Changing the property requires rebuilding and reflashing the kernel
+    KernelAbi,
+    /// Changing the property only requires rebuilding the component
+    ComponentLocal,
+}
+const PROPERTY_SCOPE_KERNEL_ABI: &str = "kernel-abi";
+const PROPERTY_SCOPE_COMPONENT_LOCAL: &str = "component-local";
+impl PropertyScope {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            PropertyScope::KernelAbi => PROPERTY_SCOPE_KERNEL_ABI,
+            PropertyScope::ComponentLocal => PROPERTY_SCOPE_COMPONENT_LOCAL,
+        }
+    }
+
+    pub fn scopes() -> Vec<PropertyScope> {
+        vec![PropertyScope::KernelAbi, PropertyScope::ComponentLocal]
+    }
+
+    pub fn scope_names() -> Vec<String> {
+        PropertyScope::scopes()
+            .iter()
+            .map(|s| s.full_name().into())
+            .collect()
+    }
+
+    /// The scope of a property the manifest doesn't declare one for: the
+    /// kernel's, libsel4's, and the elfloader's properties affect the kernel
+    /// ABI, and the rest are local to the component
+    pub fn default_for(property_name: &str) -> PropertyScope {
+        match PropertyGroup::of(property_name) {
+            PropertyGroup::Kernel | PropertyGroup::LibSel4 | PropertyGroup::Elfloader => {
+                PropertyScope::KernelAbi
+            }
+            _ => PropertyScope::ComponentLocal,
+        }
+    }
+}
+
+impl Display for PropertyScope {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for PropertyScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            PROPERTY_SCOPE_KERNEL_ABI => Ok(PropertyScope::KernelAbi),
+            PROPERTY_SCOPE_COMPONENT_LOCAL => Ok(PropertyScope::ComponentLocal),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// The layer of a manifest that a resolved property's value came from
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum PropertyLayer {
+    /// The top-level `[$TARGET]` table
+    Target,
+    /// The `[$TARGET.$PROFILE]` subtable
+    BuildProfile,
+    /// The `[$TARGET.$PLATFORM]` subtable
+    Platform,
+    /// The `[$TARGET.test]` subtable, applied when resolving for tests
+    Test,
+    /// Not set by the manifest, but implied by a `PropertyRule`
+    Rule,
+}
+const PROPERTY_LAYER_TARGET: &str = "target";
+const PROPERTY_LAYER_BUILD_PROFILE: &str = "build-profile";
+const PROPERTY_LAYER_PLATFORM: &str = "platform";
+const PROPERTY_LAYER_TEST: &str = "test";
+const PROPERTY_LAYER_RULE: &str = "rule";
+impl PropertyLayer {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            PropertyLayer::Target => PROPERTY_LAYER_TARGET,
+            PropertyLayer::BuildProfile => PROPERTY_LAYER_BUILD_PROFILE,
+            PropertyLayer::Platform => PROPERTY_LAYER_PLATFORM,
+            PropertyLayer::Test => PROPERTY_LAYER_TEST,
+            PropertyLayer::Rule => PROPERTY_LAYER_RULE,
+        }
+    }
+
+    /// Where the layer is found for a configuration, as a toml table path
+    /// such as `x86_64-sel4-fel4.debug`, or `rules` for implied values
+    pub fn location(&self, fel4_config: &Fel4Config) -> String {
+        let target = fel4_config.target.full_name();
+        match *self {
+            PropertyLayer::Target => target.to_string(),
+            PropertyLayer::BuildProfile => {
+                format!("{}.{}", target, fel4_config.build_profile.full_name())
+            }
+            PropertyLayer::Platform => format!("{}.{}", target, fel4_config.platform.full_name()),
+            PropertyLayer::Test => format!("{}.test", target),
+            PropertyLayer::Rule => "rules".to_string(),
+        }
+    }
+}
+
+impl Display for PropertyLayer {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for PropertyLayer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            PROPERTY_LAYER_TARGET => Ok(PropertyLayer::Target),
+            PROPERTY_LAYER_BUILD_PROFILE => Ok(PropertyLayer::BuildProfile),
+            PROPERTY_LAYER_PLATFORM => Ok(PropertyLayer::Platform),
+            PROPERTY_LAYER_TEST => Ok(PropertyLayer::Test),
+            PROPERTY_LAYER_RULE => Ok(PropertyLayer::Rule),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// How a resolved property came to have its value
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum PropertyOrigin {
+    /// Set by one of the manifest's tables
+    Explicit,
+    /// Not set by the manifest, but filled in by a `PropertyRule` or a bundle
+    Defaulted,
+    /// Set on the configuration after it was resolved, so not traceable to
+    /// the manifest
+    Overridden,
+}
+const PROPERTY_ORIGIN_EXPLICIT: &str = "explicit";
+const PROPERTY_ORIGIN_DEFAULTED: &str = "defaulted";
+const PROPERTY_ORIGIN_OVERRIDDEN: &str = "overridden";
+impl PropertyOrigin {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            PropertyOrigin::Explicit => PROPERTY_ORIGIN_EXPLICIT,
+            PropertyOrigin::Defaulted => PROPERTY_ORIGIN_DEFAULTED,
+            PropertyOrigin::Overridden => PROPERTY_ORIGIN_OVERRIDDEN,
+        }
+    }
+}
+
+impl Display for PropertyOrigin {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for PropertyOrigin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            PROPERTY_ORIGIN_EXPLICIT => Ok(PropertyOrigin::Explicit),
+            PROPERTY_ORIGIN_DEFAULTED => Ok(PropertyOrigin::Defaulted),
+            PROPERTY_ORIGIN_OVERRIDDEN => Ok(PropertyOrigin::Overridden),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// Where to obtain the seL4 kernel source, from a `[fel4.kernel-source]` table
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum KernelSource {
+    /// A git repository, optionally checked out at a tag or revision
+    Git { url: String, rev: Option<String> },
+    /// A gzipped tarball, verified against its sha256 digest before use
+    Tarball { url: String, sha256: String },
+}
+
+/// The floating point ABI an ARM target's userland and cross compiler use,
+/// from the `float-abi` key of a `[toolchain.<target>]` table
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum FloatAbi {
+    /// Floating point arguments are passed in FPU registers
+    Hard,
+    /// Floating point is emulated, without touching the FPU
+    Soft,
+}
+const FLOAT_ABI_HARD: &str = "hf";
+const FLOAT_ABI_SOFT: &str = "sf";
+impl FloatAbi {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            FloatAbi::Hard => FLOAT_ABI_HARD,
+            FloatAbi::Soft => FLOAT_ABI_SOFT,
+        }
+    }
+
+    pub fn abis() -> Vec<FloatAbi> {
+        vec![FloatAbi::Hard, FloatAbi::Soft]
+    }
+
+    pub fn abi_names() -> Vec<String> {
+        FloatAbi::abis()
+            .iter()
+            .map(|a| a.full_name().into())
+            .collect()
+    }
+}
+
+impl Display for FloatAbi {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for FloatAbi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            FLOAT_ABI_HARD => Ok(FloatAbi::Hard),
+            FLOAT_ABI_SOFT => Ok(FloatAbi::Soft),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// Host toolchain settings for a target, from a `[toolchain.<target>]` table
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ToolchainSettings {
+    /// Replaces the default cross compiler prefix for the target
+    pub compiler_prefix: Option<String>,
+    /// Replaces the cross compiler prefix for particular platforms of the
+    /// target, from `[toolchain.<target>.<platform>]` tables. Resolution
+    /// moves the selected platform's prefix into `compiler_prefix`, so this
+    /// is always empty in a `Fel4Config`.
+    pub platform_compiler_prefixes: HashMap<SupportedPlatform, String>,
+    /// The floating point ABI of an ARM target, which the kernel's FPU
+    /// options and the cross compiler prefix must agree with
+    pub float_abi: Option<FloatAbi>,
+    /// The cmake executable to use instead of searching PATH
+    pub cmake: Option<String>,
+    /// The ninja executable to use instead of searching PATH
+    pub ninja: Option<String>,
+    /// A compiler cache, such as `ccache` or `sccache`, that the kernel's C
+    /// and C++ compilers are launched through
+    pub compiler_launcher: Option<String>,
+    /// Additional variables for the kernel's CMake toolchain file
+    pub variables: Vec<FlatTomlProperty>,
+    /// The CMake targets to build, such as `KERNEL_BUILD_TARGET`, rather than
+    /// every target of the kernel build when empty
+    pub build_targets: Vec<String>,
+    /// The environment variables removed from CMake's environment, or
+    /// `DEFAULT_CLEARED_ENV` when not given
+    pub cleared_env: Option<Vec<String>>,
+}
+
+/// Root task settings for a target, from a `[<target>.rootserver]` table.
+///
+/// These size the root task rather than the kernel, so they are exported to
+/// the Rust build, see `rootserver_env`, and never passed to CMake.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RootserverSettings {
+    /// The root task's stack size in bytes
+    pub stack_size: Option<i64>,
+    /// The root task's heap size in bytes
+    pub heap_size: Option<i64>,
+    /// A hint for the size of the root task's CNode, as a number of bits
+    pub cnode_size_bits: Option<i64>,
+}
+
+/// The CMake target of the seL4 kernel image
+pub const KERNEL_BUILD_TARGET: &str = "kernel.elf";
+/// The CMake target of the libsel4 library
+pub const LIBSEL4_BUILD_TARGET: &str = "sel4";
+
 /// A single toml key-value pair where the value only includes non-nestable
 /// structures
 #[derive(PartialEq, Clone, Debug)]
@@ -29,8 +744,141 @@ impl FlatTomlProperty {
     }
 }
 
+/// One of several definitions of the same property
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertyDefinition {
+    /// The toml table path the property was defined in
+    pub table: String,
+    /// The defined value, as a toml literal
+    pub value: String,
+}
+
+impl PropertyDefinition {
+    pub fn new(table: String, value: &FlatTomlValue) -> Self {
+        PropertyDefinition {
+            table,
+            value: value.to_string(),
+        }
+    }
+}
+
+impl Display for PropertyDefinition {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "[{}] = {}", self.table, self.value)
+    }
+}
+
+/// The properties of a single manifest table, in the order they were
+/// added, with at most one property of any name.
+///
+/// Two sets are equal when they hold the same properties, whatever order
+/// they were added in.
+#[derive(Clone, Debug, Default)]
+pub struct PropertySet {
+    properties: Vec<FlatTomlProperty>,
+    index: HashMap<String, usize>,
+}
+
+impl PropertySet {
+    pub fn new() -> Self {
+        PropertySet::default()
+    }
+
+    /// Collect properties into a set, failing with the first property whose
+    /// name was already collected
+    pub fn from_properties<I: IntoIterator<Item = FlatTomlProperty>>(
+        properties: I,
+    ) -> Result<Self, FlatTomlProperty> {
+        let mut set = PropertySet::new();
+        for p in properties {
+            set.insert(p)?;
+        }
+        Ok(set)
+    }
+
+    /// Add a property after those already in the set, unless one of the same
+    /// name is already present, in which case the property is handed back
+    pub fn insert(&mut self, property: FlatTomlProperty) -> Result<(), FlatTomlProperty> {
+        if self.index.contains_key(&property.name) {
+            return Err(property);
+        }
+        self.index
+            .insert(property.name.clone(), self.properties.len());
+        self.properties.push(property);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FlatTomlValue> {
+        self.index.get(name).map(|&i| &self.properties[i].value)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    /// Take a property out of the set, keeping the order of the rest
+    pub fn remove(&mut self, name: &str) -> Option<FlatTomlProperty> {
+        let i = self.index.remove(name)?;
+        let property = self.properties.remove(i);
+        for later in self.index.values_mut().filter(|later| **later > i) {
+            *later -= 1;
+        }
+        Some(property)
+    }
+
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// Reorder the properties, keeping the relative order of those with equal
+    /// keys
+    pub fn sort_by_key<K: Ord, F: FnMut(&FlatTomlProperty) -> K>(&mut self, f: F) {
+        self.properties.sort_by_key(f);
+        self.index = self
+            .properties
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.name.clone(), i))
+            .collect();
+    }
+
+    /// The properties in the order they were added
+    pub fn iter(&self) -> slice::Iter<'_, FlatTomlProperty> {
+        self.properties.iter()
+    }
+}
+
+impl PartialEq for PropertySet {
+    fn eq(&self, other: &PropertySet) -> bool {
+        self.len() == other.len() && self.iter().all(|p| other.get(&p.name) == Some(&p.value))
+    }
+}
+
+impl<'a> IntoIterator for &'a PropertySet {
+    type Item = &'a FlatTomlProperty;
+    type IntoIter = slice::Iter<'a, FlatTomlProperty>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.properties.iter()
+    }
+}
+
+impl IntoIterator for PropertySet {
+    type Item = FlatTomlProperty;
+    type IntoIter = ::std::vec::IntoIter<FlatTomlProperty>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.properties.into_iter()
+    }
+}
+
 /// A subset of `toml::Value` that only includes non-nestable structures
 #[derive(PartialEq, Clone, Debug)]
+#[non_exhaustive]
 pub enum FlatTomlValue {
     /// Represents a TOML string
     String(String),
@@ -44,7 +892,142 @@ pub enum FlatTomlValue {
     Datetime(toml::value::Datetime),
 }
 
+impl<'a> From<&'a FlatTomlValue> for toml::Value {
+    fn from(value: &'a FlatTomlValue) -> Self {
+        match *value {
+            FlatTomlValue::String(ref s) => toml::Value::String(s.clone()),
+            FlatTomlValue::Integer(i) => toml::Value::Integer(i),
+            FlatTomlValue::Float(f) => toml::Value::Float(f),
+            FlatTomlValue::Boolean(b) => toml::Value::Boolean(b),
+            FlatTomlValue::Datetime(ref d) => toml::Value::Datetime(d.clone()),
+        }
+    }
+}
+
+impl FlatTomlValue {
+    /// The non-nestable toml value as a `FlatTomlValue`, or None for arrays
+    /// and tables
+    pub fn from_toml(value: &toml::Value) -> Option<Self> {
+        match *value {
+            toml::Value::String(ref s) => Some(FlatTomlValue::String(s.clone())),
+            toml::Value::Integer(i) => Some(FlatTomlValue::Integer(i)),
+            toml::Value::Float(f) => Some(FlatTomlValue::Float(f)),
+            toml::Value::Boolean(b) => Some(FlatTomlValue::Boolean(b)),
+            toml::Value::Datetime(ref d) => Some(FlatTomlValue::Datetime(d.clone())),
+            toml::Value::Array(_) | toml::Value::Table(_) => None,
+        }
+    }
+
+    pub fn value_type(&self) -> FlatTomlType {
+        match *self {
+            FlatTomlValue::String(_) => FlatTomlType::String,
+            FlatTomlValue::Integer(_) => FlatTomlType::Integer,
+            FlatTomlValue::Float(_) => FlatTomlType::Float,
+            FlatTomlValue::Boolean(_) => FlatTomlType::Boolean,
+            FlatTomlValue::Datetime(_) => FlatTomlType::Datetime,
+        }
+    }
+
+    /// Parse a value that is expected to have a particular type.
+    ///
+    /// Toml literals of the expected type are accepted as `from_str` would
+    /// accept them, as are integer literals where a float is expected. Where
+    /// a string is expected, any text that isn't a quoted toml string is
+    /// taken as the string itself, so that e.g. `-O2` needn't be quoted.
+    pub fn parse_as(s: &str, expected: FlatTomlType) -> Result<Self, String> {
+        match (s.parse::<FlatTomlValue>(), expected) {
+            (Ok(ref v), t) if v.value_type() == t => Ok(v.clone()),
+            (Ok(FlatTomlValue::Integer(i)), FlatTomlType::Float) => {
+                Ok(FlatTomlValue::Float(i as f64))
+            }
+            (_, FlatTomlType::String) => Ok(FlatTomlValue::String(s.to_string())),
+            _ => Err(format!("{} is not a toml {}", s, expected)),
+        }
+    }
+}
+
+/// Rendered as a toml literal, which `from_str` parses back to the same
+/// value
+impl Display for FlatTomlValue {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            FlatTomlValue::String(ref s) => {
+                f.write_str("\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                f.write_str("\"")
+            }
+            FlatTomlValue::Integer(i) => write!(f, "{}", i),
+            FlatTomlValue::Float(x) if x.is_nan() => f.write_str("nan"),
+            FlatTomlValue::Float(x) if x.is_infinite() => {
+                f.write_str(if x > 0.0 { "inf" } else { "-inf" })
+            }
+            // Debug formatting always includes a fraction or an exponent, so
+            // the literal can't be mistaken for an integer
+            FlatTomlValue::Float(x) => write!(f, "{:?}", x),
+            FlatTomlValue::Boolean(b) => write!(f, "{}", b),
+            FlatTomlValue::Datetime(ref d) => write!(f, "{}", d),
+        }
+    }
+}
+
+/// Parses a single toml literal, of any type
+impl FromStr for FlatTomlValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        let not_a_literal = || format!("{} is not a toml literal", s);
+        if s.contains('\n') {
+            return Err(not_a_literal());
+        }
+        let document = format!("value = {}", s)
+            .parse::<toml::Value>()
+            .map_err(|_| not_a_literal())?;
+        match document.as_table() {
+            Some(t) if t.len() == 1 => t
+                .get("value")
+                .and_then(FlatTomlValue::from_toml)
+                .ok_or_else(not_a_literal),
+            _ => Err(not_a_literal()),
+        }
+    }
+}
+
+/// The type of a `FlatTomlValue`
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum FlatTomlType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Datetime,
+}
+
+impl Display for FlatTomlType {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(match *self {
+            FlatTomlType::String => "string",
+            FlatTomlType::Integer => "integer",
+            FlatTomlType::Float => "float",
+            FlatTomlType::Boolean => "boolean",
+            FlatTomlType::Datetime => "datetime",
+        })
+    }
+}
+
+/// Ordered as `SupportedTarget::targets` lists them
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[non_exhaustive]
 pub enum SupportedTarget {
     X8664Sel4Fel4,
     Armv7Sel4Fel4,
@@ -80,6 +1063,24 @@ impl SupportedTarget {
     }
 }
 
+impl SupportedTarget {
+    /// The kernel architecture the target builds for, matching `KernelArch`
+    pub fn architecture(&self) -> Architecture {
+        match *self {
+            SupportedTarget::X8664Sel4Fel4 => Architecture::X86,
+            SupportedTarget::Armv7Sel4Fel4 | SupportedTarget::Aarch64Sel4Fel4 => Architecture::Arm,
+        }
+    }
+
+    /// The target's word size in bits
+    pub fn word_size(&self) -> u32 {
+        match *self {
+            SupportedTarget::X8664Sel4Fel4 | SupportedTarget::Aarch64Sel4Fel4 => 64,
+            SupportedTarget::Armv7Sel4Fel4 => 32,
+        }
+    }
+}
+
 impl Display for SupportedTarget {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
         f.write_str(self.full_name())
@@ -99,16 +1100,69 @@ impl FromStr for SupportedTarget {
     }
 }
 
+/// The kernel architecture families, as named by `KernelArch`
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum Architecture {
+    X86,
+    Arm,
+}
+
+const ARCHITECTURE_X86: &str = "x86";
+const ARCHITECTURE_ARM: &str = "arm";
+
+impl Architecture {
+    pub fn full_name(&self) -> &'static str {
+        match *self {
+            Architecture::X86 => ARCHITECTURE_X86,
+            Architecture::Arm => ARCHITECTURE_ARM,
+        }
+    }
+
+    pub fn architectures() -> Vec<Architecture> {
+        vec![Architecture::X86, Architecture::Arm]
+    }
+
+    pub fn architecture_names() -> Vec<String> {
+        Architecture::architectures()
+            .iter()
+            .map(|a| a.full_name().into())
+            .collect()
+    }
+}
+
+impl Display for Architecture {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
+}
+
+impl FromStr for Architecture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        match s {
+            ARCHITECTURE_X86 => Ok(Architecture::X86),
+            ARCHITECTURE_ARM => Ok(Architecture::Arm),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// Ordered as `SupportedPlatform::platforms` lists them
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[non_exhaustive]
 pub enum SupportedPlatform {
     PC99,
     Sabre,
     Tx1,
+    Tx2,
 }
 
 const PLATFORM_PC99: &str = "pc99";
 const PLATFORM_SABRE: &str = "sabre";
 const PLATFORM_TX1: &str = "tx1";
+const PLATFORM_TX2: &str = "tx2";
 
 impl SupportedPlatform {
     pub fn full_name(&self) -> &'static str {
@@ -116,6 +1170,7 @@ impl SupportedPlatform {
             SupportedPlatform::PC99 => PLATFORM_PC99,
             SupportedPlatform::Sabre => PLATFORM_SABRE,
             SupportedPlatform::Tx1 => PLATFORM_TX1,
+            SupportedPlatform::Tx2 => PLATFORM_TX2,
         }
     }
 
@@ -124,6 +1179,7 @@ impl SupportedPlatform {
             SupportedPlatform::PC99,
             SupportedPlatform::Sabre,
             SupportedPlatform::Tx1,
+            SupportedPlatform::Tx2,
         ]
     }
 
@@ -149,12 +1205,15 @@ impl FromStr for SupportedPlatform {
             PLATFORM_PC99 => Ok(SupportedPlatform::PC99),
             PLATFORM_SABRE => Ok(SupportedPlatform::Sabre),
             PLATFORM_TX1 => Ok(SupportedPlatform::Tx1),
+            PLATFORM_TX2 => Ok(SupportedPlatform::Tx2),
             _ => Err(s.to_string()),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+/// Ordered as `BuildProfile::build_profiles` lists them
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[non_exhaustive]
 pub enum BuildProfile {
     Debug,
     Release,
@@ -179,6 +1238,21 @@ impl BuildProfile {
             .map(|t| t.full_name().into())
             .collect()
     }
+
+    /// The `CMAKE_BUILD_TYPE` matching the profile's cargo semantics, unless
+    /// the manifest overrides it
+    pub fn default_cmake_build_type(&self) -> &'static str {
+        match *self {
+            BuildProfile::Debug => "Debug",
+            BuildProfile::Release => "Release",
+        }
+    }
+}
+
+impl Display for BuildProfile {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.full_name())
+    }
 }
 
 impl FromStr for BuildProfile {
@@ -0,0 +1,333 @@
+/// Named `[variant.<name>]` selections of a target, platform, build profile,
+/// and `[preset.<name>]` property tables, so one manifest can describe
+/// several configurations of the same project
+use std::collections::BTreeMap;
+use toml;
+
+use super::{is_supported_target_platform_pair, resolve_fel4_config_for, ConfigError};
+use locking::{check_unlocked, LockPolicy};
+use manifest::{extract_flat_properties, FullFel4Manifest};
+use types::*;
+
+/// A `[variant.<name>]` table
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestVariant {
+    pub target: SupportedTarget,
+    pub platform: SupportedPlatform,
+    pub build_profile: BuildProfile,
+    /// The `[preset.<name>]` tables applied over the target's properties, in
+    /// the order they are applied
+    pub presets: Vec<String>,
+}
+
+/// Resolve and validate the configuration a `[variant.<name>]` table names.
+///
+/// Each of the variant's presets is applied in turn, its properties
+/// replacing any the target's tables set, conditional, derived, or neither,
+/// and a preset later in the list replacing those earlier. The resolved
+/// configuration then reports the presets' properties as set by the
+/// `[$TARGET]` table.
+///
+/// Presets may not set the manifest's locked properties, see
+/// `resolve_variant_with_policy`.
+pub fn resolve_variant(full: &FullFel4Manifest, name: &str) -> Result<Fel4Config, ConfigError> {
+    resolve_variant_with_policy(full, name, LockPolicy::Enforce)
+}
+
+/// Resolve a `[variant.<name>]` table like `resolve_variant`, with presets
+/// that set locked properties refused or allowed according to the policy
+pub fn resolve_variant_with_policy(
+    full: &FullFel4Manifest,
+    name: &str,
+    policy: LockPolicy,
+) -> Result<Fel4Config, ConfigError> {
+    let variant = full
+        .variants
+        .get(name)
+        .ok_or_else(|| ConfigError::UnknownVariant(name.to_string()))?;
+    fel4_trace!("resolve", "resolving variant {}", name);
+    let mut applied = full.clone();
+    if let Some(target) = applied.targets.get_mut(&variant.target) {
+        for preset_name in &variant.presets {
+            let preset = full.presets.get(preset_name).ok_or_else(|| {
+                ConfigError::InvalidVariant(
+                    format!("variant.{}", name),
+                    format!("there is no [preset.{}] table", preset_name),
+                )
+            })?;
+            for property in preset {
+                check_unlocked(
+                    &full.locked_properties,
+                    &property.name,
+                    &format!("preset.{}", preset_name),
+                    policy,
+                )?;
+                for properties in ::std::iter::once(&mut target.direct_properties)
+                    .chain(
+                        target
+                            .build_profile_properties
+                            .get_mut(&variant.build_profile),
+                    )
+                    .chain(target.platform_properties.get_mut(&variant.platform))
+                {
+                    properties.remove(&property.name);
+                }
+                target.conditional_properties.remove(&property.name);
+                target.derived_properties.remove(&property.name);
+                let _ = target.direct_properties.insert(property.clone());
+            }
+        }
+    }
+    resolve_fel4_config_for(
+        &applied,
+        variant.target,
+        variant.platform,
+        &variant.build_profile,
+    )
+}
+
+/// Parse the optional `[preset.<name>]` tables of flat properties
+pub(crate) fn parse_presets(
+    raw: &toml::Value,
+) -> Result<BTreeMap<String, PropertySet>, ConfigError> {
+    let mut presets = BTreeMap::new();
+    for (name, value) in named_tables(raw, "preset")? {
+        let table = value
+            .as_table()
+            .ok_or_else(|| ConfigError::UnexpectedStructure(format!("preset.{}", name)))?;
+        let properties = extract_flat_properties(table).map_err(|prop_name| {
+            ConfigError::UnexpectedStructure(format!("preset.{}.{}", name, prop_name))
+        })?;
+        fel4_trace!("manifest", "read [preset.{}]", name);
+        presets.insert(name.clone(), properties);
+    }
+    Ok(presets)
+}
+
+/// Parse the optional `[variant.<name>]` tables, each of which must select a
+/// supported target and platform pair and name only presets the manifest has
+pub(crate) fn parse_variants(
+    raw: &toml::Value,
+    presets: &BTreeMap<String, PropertySet>,
+) -> Result<BTreeMap<String, ManifestVariant>, ConfigError> {
+    let mut variants = BTreeMap::new();
+    for (name, value) in named_tables(raw, "variant")? {
+        let path = format!("variant.{}", name);
+        let table = value
+            .as_table()
+            .ok_or_else(|| ConfigError::UnexpectedStructure(path.clone()))?;
+        if let Some(k) = table
+            .keys()
+            .find(|k| !["target", "platform", "profile", "presets"].contains(&k.as_str()))
+        {
+            return Err(ConfigError::UnexpectedStructure(format!("{}.{}", path, k)));
+        }
+        let invalid = |reason: String| ConfigError::InvalidVariant(path.clone(), reason);
+        let required = |key: &str| {
+            table
+                .get(key)
+                .and_then(toml::Value::as_str)
+                .ok_or_else(|| invalid(format!("{} should be given as a string", key)))
+        };
+        let unknown = |key: &str, names: Vec<String>, name: String| {
+            invalid(format!("{} should be one of {:?}, not {}", key, names, name))
+        };
+        let target: SupportedTarget = required("target")?
+            .parse()
+            .map_err(|n| unknown("target", SupportedTarget::target_names(), n))?;
+        let platform: SupportedPlatform = required("platform")?
+            .parse()
+            .map_err(|n| unknown("platform", SupportedPlatform::platform_names(), n))?;
+        let build_profile: BuildProfile = required("profile")?
+            .parse()
+            .map_err(|n| unknown("profile", BuildProfile::build_profile_names(), n))?;
+        if !is_supported_target_platform_pair(target, platform) {
+            return Err(invalid(format!(
+                "the {} target is not a supported combination with the {} platform",
+                target, platform
+            )));
+        }
+        let preset_names = match table.get("presets") {
+            None => Vec::new(),
+            Some(v) => v
+                .as_array()
+                .and_then(|names| {
+                    names
+                        .iter()
+                        .map(|n| n.as_str().map(str::to_string))
+                        .collect()
+                })
+                .ok_or_else(|| invalid("presets should be an array of strings".to_string()))?,
+        };
+        if let Some(missing) = preset_names.iter().find(|n| !presets.contains_key(*n)) {
+            return Err(invalid(format!("there is no [preset.{}] table", missing)));
+        }
+        fel4_trace!("manifest", "read [{}]", path);
+        variants.insert(
+            name.clone(),
+            ManifestVariant {
+                target,
+                platform,
+                build_profile,
+                presets: preset_names,
+            },
+        );
+    }
+    Ok(variants)
+}
+
+/// A `[variant.<name>]` table as `parse_variants` reads it
+pub(crate) fn variant_to_toml(variant: &ManifestVariant) -> toml::Value {
+    let mut table = BTreeMap::new();
+    let strings = [
+        ("target", variant.target.full_name()),
+        ("platform", variant.platform.full_name()),
+        ("profile", variant.build_profile.full_name()),
+    ];
+    for &(name, value) in &strings {
+        table.insert(name.to_string(), toml::Value::String(value.to_string()));
+    }
+    if !variant.presets.is_empty() {
+        table.insert(
+            "presets".to_string(),
+            toml::Value::Array(
+                variant
+                    .presets
+                    .iter()
+                    .map(|p| toml::Value::String(p.clone()))
+                    .collect(),
+            ),
+        );
+    }
+    toml::Value::Table(table)
+}
+
+/// The subtables of a top-level table such as `[variant]`, if present
+fn named_tables<'a>(
+    raw: &'a toml::Value,
+    root_name: &str,
+) -> Result<Vec<(&'a String, &'a toml::Value)>, ConfigError> {
+    match raw.get(root_name) {
+        None => Ok(Vec::new()),
+        Some(toml::Value::Table(t)) => Ok(t.iter().collect()),
+        Some(_) => Err(ConfigError::UnexpectedStructure(root_name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    const VARIANTS: &str = r#"
+[preset.quiet]
+KernelPrinting = false
+KernelDebugBuild = false
+
+[preset.smp]
+KernelMaxNumNodes = 4
+
+[variant.developer]
+target = "x86_64-sel4-fel4"
+platform = "pc99"
+profile = "debug"
+
+[variant.hil-test]
+target = "x86_64-sel4-fel4"
+platform = "pc99"
+profile = "debug"
+presets = ["quiet", "smp"]
+
+[variant.production]
+target = "x86_64-sel4-fel4"
+platform = "pc99"
+profile = "release"
+presets = ["smp"]
+"#;
+
+    #[test]
+    fn variants_resolve_their_selection_with_presets_applied() {
+        let text = format!("{}{}", get_exemplar_default_toml(), VARIANTS);
+        let full = parse_full_manifest(&text).expect("Variant tables should parse");
+        assert_eq!(
+            Some(&ManifestVariant {
+                target: SupportedTarget::X8664Sel4Fel4,
+                platform: SupportedPlatform::PC99,
+                build_profile: BuildProfile::Debug,
+                presets: vec!["quiet".into(), "smp".into()],
+            }),
+            full.variants.get("hil-test")
+        );
+        let quiet: Vec<&str> = full.presets["quiet"].iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(vec!["KernelPrinting", "KernelDebugBuild"], quiet);
+        assert_eq!(
+            Ok(full.clone()),
+            parse_full_manifest(full_manifest_to_toml_string(&full))
+        );
+
+        assert_eq!(
+            resolve_fel4_config(&full, &BuildProfile::Debug),
+            resolve_variant(&full, "developer")
+        );
+        let hil = resolve_variant(&full, "hil-test").unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(false)),
+            hil.properties.get("KernelPrinting")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(4)),
+            hil.properties.get("KernelMaxNumNodes")
+        );
+        assert_eq!(
+            Some(PropertyLayer::Target),
+            hil.provenance.get("KernelMaxNumNodes").cloned()
+        );
+        let production = resolve_variant(&full, "production").unwrap();
+        assert_eq!(BuildProfile::Release, production.build_profile);
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(4)),
+            production.properties.get("KernelMaxNumNodes")
+        );
+
+        assert_eq!(
+            Err(ConfigError::UnknownVariant("staging".into())),
+            resolve_variant(&full, "staging")
+        );
+
+        let mut edited = full.clone();
+        let _ = edited.presets.remove("smp");
+        match resolve_variant(&edited, "hil-test") {
+            Err(ConfigError::InvalidVariant(ref path, _)) => assert_eq!("variant.hil-test", path),
+            other => panic!("Expected the missing preset to be rejected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_variants_are_rejected() {
+        let parse = |table: &str| {
+            parse_full_manifest(format!(
+                "{}\n[variant.broken]\n{}\n",
+                get_exemplar_default_toml(),
+                table
+            ))
+        };
+        let selection = "target = \"armv7-sel4-fel4\"\nplatform = \"sabre\"\n";
+        assert_eq!(
+            Err(ConfigError::UnexpectedStructure(
+                "variant.broken.features".into()
+            )),
+            parse(&format!("{}profile = \"debug\"\nfeatures = []", selection))
+        );
+        for table in &[
+            selection.to_string(),
+            format!("{}profile = \"fast\"", selection),
+            format!("{}profile = \"debug\"\npresets = [\"missing\"]", selection),
+            "target = \"armv7-sel4-fel4\"\nplatform = \"pc99\"\nprofile = \"debug\"".to_string(),
+        ] {
+            match parse(table) {
+                Err(ConfigError::InvalidVariant(ref path, _)) => assert_eq!("variant.broken", path),
+                other => panic!("Expected {} to be rejected, got {:?}", table, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+/// The `[vars]` table of named scalars, which the rest of the manifest refers
+/// to as `{{name}}` in property values, paths, and other strings
+use std::collections::BTreeMap;
+use toml;
+
+use super::ConfigError;
+use types::*;
+
+/// Expand every `{{name}}` reference in a parsed manifest using its `[vars]`
+/// table, which is then removed.
+///
+/// A string that is nothing but a reference takes the variable's value and
+/// type, so `KernelFoo = "{{base}}"` is an integer where `base` is. A
+/// reference within a longer string is replaced with the value's text. In
+/// the `image-name-pattern`, references to names that aren't variables are
+/// left as the pattern's own placeholders.
+pub(crate) fn expand_manifest_vars(raw: &toml::Value) -> Result<toml::Value, ConfigError> {
+    let root = match raw.as_table() {
+        Some(t) => t,
+        None => return Ok(raw.clone()),
+    };
+    let mut vars = BTreeMap::new();
+    match root.get("vars") {
+        None => (),
+        Some(toml::Value::Table(t)) => {
+            for (name, value) in t {
+                let value = FlatTomlValue::from_toml(value)
+                    .ok_or_else(|| ConfigError::UnexpectedStructure(format!("vars.{}", name)))?;
+                vars.insert(name.as_str(), value);
+            }
+        }
+        Some(_) => return Err(ConfigError::UnexpectedStructure("vars".into())),
+    }
+    let mut expanded = BTreeMap::new();
+    for (name, value) in root.iter().filter(|&(k, _)| k != "vars") {
+        expanded.insert(name.clone(), expand_value(value, name, &vars)?);
+    }
+    fel4_trace!("manifest", "expanded {} vars", vars.len());
+    Ok(toml::Value::Table(expanded))
+}
+
+/// Expand the references within the value at a dotted toml path
+fn expand_value(
+    value: &toml::Value,
+    path: &str,
+    vars: &BTreeMap<&str, FlatTomlValue>,
+) -> Result<toml::Value, ConfigError> {
+    Ok(match *value {
+        toml::Value::String(ref s) => expand_string(s, path, vars)?,
+        toml::Value::Array(ref items) => toml::Value::Array(
+            items
+                .iter()
+                .map(|v| expand_value(v, path, vars))
+                .collect::<Result<_, _>>()?,
+        ),
+        toml::Value::Table(ref t) => {
+            let mut expanded = BTreeMap::new();
+            for (k, v) in t {
+                expanded.insert(
+                    k.clone(),
+                    expand_value(v, &format!("{}.{}", path, k), vars)?,
+                );
+            }
+            toml::Value::Table(expanded)
+        }
+        ref other => other.clone(),
+    })
+}
+
+fn expand_string(
+    s: &str,
+    path: &str,
+    vars: &BTreeMap<&str, FlatTomlValue>,
+) -> Result<toml::Value, ConfigError> {
+    let keeps_placeholders = path == "fel4.image-name-pattern";
+    if s.starts_with("{{") && s.ends_with("}}") && s.len() > 4 && !s[2..].contains("{{") {
+        if let Some(value) = vars.get(s[2..s.len() - 2].trim()) {
+            return Ok(toml::Value::from(value));
+        }
+    }
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = rest[start + 2..end].trim();
+        expanded.push_str(&rest[..start]);
+        match vars.get(name) {
+            Some(FlatTomlValue::String(text)) => expanded.push_str(text),
+            Some(value) => expanded.push_str(&value.to_string()),
+            None if keeps_placeholders => expanded.push_str(&rest[start..end + 2]),
+            None => {
+                let (table, key) = match path.rfind('.') {
+                    Some(i) => (&path[..i], &path[i + 1..]),
+                    None => ("", path),
+                };
+                return Err(ConfigError::UndefinedVariable(
+                    name.to_string(),
+                    table.to_string(),
+                    key.to_string(),
+                ));
+            }
+        }
+        rest = &rest[end + 2..];
+    }
+    expanded.push_str(rest);
+    Ok(toml::Value::String(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn vars_are_expanded_where_referenced() {
+        let text = get_exemplar_default_toml()
+            .replacen(
+                "[fel4]\n",
+                concat!(
+                    "[vars]\nfan-out = 128\nname = \"hub\"\ncore_count = 2\n\n",
+                    "[fel4]\nimage-name-pattern = \"{{name}}-{{ profile }}\"\n",
+                ),
+                1,
+            )
+            .replacen(
+                "KernelRetypeFanOutLimit = 256",
+                "KernelRetypeFanOutLimit = \"{{fan-out}}\"\nX-Label = \"{{name}} x{{core_count}}\"",
+                1,
+            )
+            .replacen("artifact-path = \"", "artifact-path = \"{{ name }}/", 1);
+        let full = parse_full_manifest(&text).expect("Vars should be expanded");
+        assert_eq!(
+            Some("hub-{{ profile }}".to_string()),
+            full.image_name_pattern
+        );
+        assert!(full.artifact_path.starts_with("hub/"));
+        let config = resolve_fel4_config(&full, &BuildProfile::Release).unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(128)),
+            config.properties.get("KernelRetypeFanOutLimit")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::String("hub x2".into())),
+            config.properties.get("X-Label")
+        );
+        assert!(config.image_file_name().unwrap().starts_with("hub-release"));
+
+        assert_eq!(
+            Err(ConfigError::UndefinedVariable(
+                "fanout".into(),
+                "x86_64-sel4-fel4".into(),
+                "KernelRetypeFanOutLimit".into()
+            )),
+            parse_full_manifest(text.replacen("{{fan-out}}", "{{fanout}}", 1))
+        );
+        assert_eq!(
+            Err(ConfigError::UnexpectedStructure("vars.name".into())),
+            parse_full_manifest(text.replacen("name = \"hub\"", "name = [\"hub\"]", 1))
+        );
+    }
+}
@@ -0,0 +1,201 @@
+/// The upstream seL4 verified configurations, and comparing resolved
+/// configurations against them for assurance documentation
+use std::fmt::{Display, Error as FmtError, Formatter};
+use toml;
+
+use manifest::extract_flat_properties;
+use types::*;
+
+/// The machine-readable descriptions shipped in the `verified` directory
+const VERIFIED_CONFIGURATION_SOURCES: &[&str] = &[
+    include_str!("../verified/x86_64-pc99.toml"),
+    include_str!("../verified/armv7-sabre.toml"),
+];
+
+/// A configuration the seL4 proofs apply to
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedConfiguration {
+    pub target: SupportedTarget,
+    pub platform: SupportedPlatform,
+    /// The upstream file the configuration was transcribed from, relative to
+    /// the root of the seL4 kernel repository
+    pub source: String,
+    /// The whitelisted options the verified configuration sets
+    pub properties: PropertySet,
+}
+
+/// Every verified configuration, one per target and platform pair the seL4
+/// proofs cover
+pub fn verified_configurations() -> Vec<VerifiedConfiguration> {
+    VERIFIED_CONFIGURATION_SOURCES
+        .iter()
+        .map(|text| parse_verified_configuration(text))
+        .collect()
+}
+
+fn parse_verified_configuration(text: &str) -> VerifiedConfiguration {
+    let raw: toml::Value = text
+        .parse()
+        .expect("Verified configurations should be valid toml");
+    let string = |key: &str| {
+        raw.get(key)
+            .and_then(toml::Value::as_str)
+            .expect("Verified configurations should name their selection and source")
+    };
+    VerifiedConfiguration {
+        target: string("target").parse().expect("Verified targets exist"),
+        platform: string("platform").parse().expect("Verified platforms exist"),
+        source: string("source").to_string(),
+        properties: raw
+            .get("properties")
+            .and_then(toml::Value::as_table)
+            .and_then(|t| extract_flat_properties(t).ok())
+            .expect("Verified configurations should have flat properties"),
+    }
+}
+
+/// One way a resolved configuration differs from the verified configuration
+/// for its target and platform
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum VerifiedDeviation {
+    /// The seL4 proofs don't cover the target and platform at all
+    UnverifiedSelection(SupportedTarget, SupportedPlatform),
+    /// The configuration sets an option to a value other than the verified one
+    DifferentValue {
+        property: String,
+        verified: FlatTomlValue,
+        actual: FlatTomlValue,
+    },
+    /// The configuration leaves an option the verified configuration sets to
+    /// the kernel build's default
+    Unset {
+        property: String,
+        verified: FlatTomlValue,
+    },
+}
+
+impl Display for VerifiedDeviation {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            VerifiedDeviation::UnverifiedSelection(target, platform) => write!(
+                f,
+                "the {} target on the {} platform has no verified configuration",
+                target, platform
+            ),
+            VerifiedDeviation::DifferentValue {
+                ref property,
+                ref verified,
+                ref actual,
+            } => write!(f, "{} is {}, but verified as {}", property, actual, verified),
+            VerifiedDeviation::Unset {
+                ref property,
+                ref verified,
+            } => write!(f, "{} is unset, but verified as {}", property, verified),
+        }
+    }
+}
+
+/// Every deviation of a resolved configuration from the verified
+/// configuration for its target and platform, in the verified
+/// configuration's order.
+///
+/// Options the verified configuration doesn't set are left at the kernel
+/// build's defaults there, which fel4-config can't know, so they aren't
+/// compared.
+pub fn compare_to_verified(fel4_config: &Fel4Config) -> Vec<VerifiedDeviation> {
+    let verified = match verified_configurations()
+        .into_iter()
+        .find(|v| v.target == fel4_config.target && v.platform == fel4_config.platform)
+    {
+        Some(verified) => verified,
+        None => {
+            return vec![VerifiedDeviation::UnverifiedSelection(
+                fel4_config.target,
+                fel4_config.platform,
+            )]
+        }
+    };
+    verified
+        .properties
+        .into_iter()
+        .filter_map(
+            |FlatTomlProperty { name, value }| match fel4_config.properties.get(&name) {
+                None => Some(VerifiedDeviation::Unset {
+                    property: name,
+                    verified: value,
+                }),
+                Some(actual) if *actual != value => Some(VerifiedDeviation::DifferentValue {
+                    property: name,
+                    verified: value,
+                    actual: actual.clone(),
+                }),
+                Some(_) => None,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn verified_configurations_describe_supported_selections() {
+        for verified in verified_configurations() {
+            assert!(is_supported_target_platform_pair(
+                verified.target,
+                verified.platform
+            ));
+            for property in &verified.properties {
+                let known: KnownProperty = property.name.parse().expect("Verified names are known");
+                assert!(known.applies_to(verified.target), "{}", property.name);
+                assert_eq!(known.value_type(), property.value.value_type());
+            }
+        }
+    }
+
+    #[test]
+    fn deviations_from_the_verified_configuration_are_reported() {
+        let release = test_helpers::ManifestFixture::new()
+            .without_property("x86_64-sel4-fel4", "KernelSupportPCID")
+            .resolve(&BuildProfile::Release)
+            .unwrap();
+        let deviations = compare_to_verified(&release);
+        assert!(deviations.contains(&VerifiedDeviation::DifferentValue {
+            property: "KernelVerificationBuild".into(),
+            verified: FlatTomlValue::Boolean(true),
+            actual: FlatTomlValue::Boolean(false),
+        }));
+        assert!(deviations.contains(&VerifiedDeviation::Unset {
+            property: "KernelSupportPCID".into(),
+            verified: FlatTomlValue::Boolean(false),
+        }));
+        assert!(!deviations.iter().any(|d| match *d {
+            VerifiedDeviation::DifferentValue { ref property, .. } => property == "KernelFastpath",
+            _ => false,
+        }));
+        assert_eq!(
+            "KernelNumDomains is 1, but verified as 16",
+            deviations
+                .iter()
+                .find(|d| d.to_string().starts_with("KernelNumDomains"))
+                .unwrap()
+                .to_string()
+        );
+
+        let tx1 = test_helpers::fixture_config(
+            SupportedTarget::Aarch64Sel4Fel4,
+            SupportedPlatform::Tx1,
+            BuildProfile::Release,
+        );
+        assert_eq!(
+            vec![VerifiedDeviation::UnverifiedSelection(
+                SupportedTarget::Aarch64Sel4Fel4,
+                SupportedPlatform::Tx1
+            )],
+            compare_to_verified(&tx1)
+        );
+    }
+}
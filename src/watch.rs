@@ -0,0 +1,170 @@
+/// Re-validating and re-resolving a manifest as it is edited, for tools that
+/// give live feedback on fel4.toml
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use super::{resolve_fel4_config, ConfigError};
+use diagnostics::{validate_manifest_source, Diagnostic, Severity};
+use diff::{diff_configs, ConfigDiff};
+use manifest::{decode_manifest, parse_full_manifest};
+use types::*;
+
+/// How often `watch_manifest` looks for changes to the manifest
+pub const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The state of a watched manifest after it was read
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestUpdate {
+    /// Every problem `validate_manifest_source` found, empty when the
+    /// manifest is valid
+    pub diagnostics: Vec<Diagnostic>,
+    /// The configuration of each build profile, when the manifest is valid
+    pub profiles: Vec<ProfileUpdate>,
+}
+
+impl ManifestUpdate {
+    pub fn is_valid(&self) -> bool {
+        self.diagnostics.iter().all(|d| d.severity != Severity::Error)
+    }
+}
+
+/// One build profile's configuration of a watched manifest
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileUpdate {
+    pub config: Fel4Config,
+    /// The resolved properties that changed since the profile last resolved,
+    /// which is empty the first time
+    pub changes: ConfigDiff,
+}
+
+/// Whether `watch_manifest` should keep watching
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum WatchControl {
+    Continue,
+    Stop,
+}
+
+/// Watch a manifest file, calling `callback` with a `ManifestUpdate` once
+/// straight away and again whenever the file's contents change, until the
+/// callback returns `WatchControl::Stop`.
+///
+/// The file is polled every `WATCH_POLL_INTERVAL`, so no platform file
+/// notification support is needed. While it is missing or unreadable, as
+/// when an editor saves by replacing it, it is treated as unchanged. Only
+/// failing to read it in the first place is an error.
+pub fn watch_manifest<P, F>(fel4_manifest_path: P, mut callback: F) -> Result<(), ConfigError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&ManifestUpdate) -> WatchControl,
+{
+    let path = fel4_manifest_path.as_ref();
+    let mut contents = fs::read(path).map_err(|_| ConfigError::FileReadFailure)?;
+    let mut last_resolved = HashMap::new();
+    loop {
+        let update = manifest_update(&contents, &mut last_resolved);
+        if callback(&update) == WatchControl::Stop {
+            return Ok(());
+        }
+        loop {
+            thread::sleep(WATCH_POLL_INTERVAL);
+            match fs::read(path) {
+                Ok(current) if current != contents => {
+                    fel4_trace!("manifest", "{} changed", path.display());
+                    contents = current;
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn manifest_update(
+    contents: &[u8],
+    last_resolved: &mut HashMap<BuildProfile, Fel4Config>,
+) -> ManifestUpdate {
+    let text = match decode_manifest(contents) {
+        Ok(text) => text,
+        Err(e) => {
+            return ManifestUpdate {
+                diagnostics: vec![Diagnostic::from_config_error(&e, "")],
+                profiles: Vec::new(),
+            }
+        }
+    };
+    let diagnostics = validate_manifest_source(&text);
+    let mut update = ManifestUpdate {
+        diagnostics,
+        profiles: Vec::new(),
+    };
+    if !update.is_valid() {
+        return update;
+    }
+    let full = match parse_full_manifest(&text) {
+        Ok(full) => full,
+        Err(e) => {
+            update.diagnostics.push(Diagnostic::from_config_error(&e, &text));
+            return update;
+        }
+    };
+    for profile in BuildProfile::build_profiles() {
+        let config = match resolve_fel4_config(&full, &profile) {
+            Ok(config) => config,
+            Err(e) => {
+                update.diagnostics.push(Diagnostic::from_config_error(&e, &text));
+                update.profiles.clear();
+                return update;
+            }
+        };
+        let changes = last_resolved
+            .get(&profile)
+            .map(|last| diff_configs(last, &config))
+            .unwrap_or_default();
+        last_resolved.insert(profile, config.clone());
+        update.profiles.push(ProfileUpdate { config, changes });
+    }
+    update
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn edits_are_reported_as_property_changes() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let path = dir.path().join("fel4.toml");
+        let original = get_exemplar_default_toml();
+        fs::write(&path, original).unwrap();
+        let edits = [
+            original.replacen("KernelMaxNumNodes = 1", "KernelMaxNumNodes = 2", 1),
+            format!("{}\n[nonsense", original),
+        ];
+        let mut updates = Vec::new();
+        watch_manifest(&path, |update| {
+            updates.push(update.clone());
+            match edits.get(updates.len() - 1) {
+                Some(edit) => {
+                    fs::write(&path, edit).unwrap();
+                    WatchControl::Continue
+                }
+                None => WatchControl::Stop,
+            }
+        }).unwrap();
+
+        assert_eq!(3, updates.len());
+        assert!(updates[0].is_valid());
+        assert!(updates[0].profiles.iter().all(|p| p.changes.is_empty()));
+        for profile in &updates[1].profiles {
+            let changed: Vec<&str> = profile.changes.names().collect();
+            assert_eq!(vec!["KernelMaxNumNodes"], changed);
+        }
+        assert!(!updates[2].is_valid());
+        assert!(updates[2].profiles.is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+/// An interactive questionnaire that assembles a working fel4.toml, for the
+/// `fel4-config wizard` command
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use super::{exemplars, is_supported_target_platform_pair, resolve_fel4_config, ConfigError};
+use bundles::{performance_counter_export_property, BenchmarkMode};
+use exemplar::{write_new_manifest, ExemplarWriteError};
+use manifest::{full_manifest_to_toml_string, parse_full_manifest};
+use types::*;
+
+/// The choices the wizard asks for
+#[derive(Clone, Debug, PartialEq)]
+pub struct WizardAnswers {
+    pub target: SupportedTarget,
+    pub platform: SupportedPlatform,
+    /// `KernelPrinting` for the debug profile
+    pub debug_printing: bool,
+    /// `KernelPrinting` for the release profile
+    pub release_printing: bool,
+    /// `KernelBenchmarks` for the debug profile, or None to leave
+    /// benchmarking off
+    pub benchmarks: Option<BenchmarkMode>,
+    /// `KernelMaxNumNodes`, the number of cores the kernel runs on
+    pub max_num_nodes: i64,
+}
+
+impl Default for WizardAnswers {
+    /// The answers of the x86_64 exemplar
+    fn default() -> Self {
+        WizardAnswers {
+            target: SupportedTarget::X8664Sel4Fel4,
+            platform: SupportedPlatform::PC99,
+            debug_printing: true,
+            release_printing: false,
+            benchmarks: None,
+            max_num_nodes: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum WizardError {
+    #[fail(display = "Unable to read the answers or write the questions: {}", _0)]
+    Io(String),
+    #[fail(display = "The input ended before the {} question was answered", _0)]
+    EndOfInput(&'static str),
+    #[fail(display = "The answers don't make a valid manifest: {}", _0)]
+    InvalidAnswers(ConfigError),
+}
+
+/// The manifest for a set of answers, starting from the exemplar for the
+/// chosen target and platform with only that target's tables.
+///
+/// The manifest is checked to resolve for every build profile.
+pub fn wizard_manifest(answers: &WizardAnswers) -> Result<String, ConfigError> {
+    let (_, _, exemplar) = exemplars()
+        .find(|&(t, p, _)| t == answers.target && p == answers.platform)
+        .ok_or(ConfigError::TargetPlatformMismatch(
+            answers.target,
+            answers.platform,
+        ))?;
+    let mut full = parse_full_manifest(exemplar)?;
+    full.targets.retain(|&t, _| t == answers.target);
+    {
+        let target = full
+            .targets
+            .get_mut(&answers.target)
+            .ok_or(ConfigError::MissingTargetTable(answers.target))?;
+        // The kernel only benchmarks debug builds
+        target.direct_properties.remove("KernelBenchmarks");
+        set_property(
+            &mut target.direct_properties,
+            "KernelMaxNumNodes",
+            FlatTomlValue::Integer(answers.max_num_nodes),
+        );
+        if answers.benchmarks.is_some() {
+            // Benchmarking needs the performance counters exported, which
+            // the exemplars disable wherever they set it
+            let export = performance_counter_export_property(answers.target);
+            let tables = ::std::iter::once(&mut target.direct_properties)
+                .chain(target.platform_properties.values_mut())
+                .chain(target.build_profile_properties.values_mut());
+            for properties in tables.filter(|p| p.contains(export)) {
+                set_property(properties, export, FlatTomlValue::Boolean(true));
+            }
+        }
+        let profiles = [
+            (BuildProfile::Debug, answers.debug_printing, answers.benchmarks),
+            (BuildProfile::Release, answers.release_printing, None),
+        ];
+        for &(profile, printing, benchmarks) in &profiles {
+            let properties = target.build_profile_properties.entry(profile).or_default();
+            set_property(
+                properties,
+                "KernelPrinting",
+                FlatTomlValue::Boolean(printing),
+            );
+            set_property(
+                properties,
+                "KernelBenchmarks",
+                FlatTomlValue::String(benchmarks.map_or("none", |m| m.full_name()).into()),
+            );
+        }
+    }
+    for profile in BuildProfile::build_profiles() {
+        resolve_fel4_config(&full, &profile)?;
+    }
+    Ok(full_manifest_to_toml_string(&full))
+}
+
+/// Ask for each of the `WizardAnswers` on `output`, reading answers from
+/// `input` a line at a time, and return the `wizard_manifest`.
+///
+/// An empty answer takes the default shown in brackets, and an answer that
+/// isn't one of the choices asks again.
+pub fn run_wizard<R: BufRead, W: Write>(
+    mut input: R,
+    mut output: W,
+) -> Result<String, WizardError> {
+    let defaults = WizardAnswers::default();
+    let mut ask = |question: &'static str, choices: &[String], default: &str| loop {
+        write!(output, "{} ({}) [{}]: ", question, choices.join(", "), default)
+            .and_then(|_| output.flush())
+            .map_err(|e| WizardError::Io(e.to_string()))?;
+        let mut line = String::new();
+        let read = input
+            .read_line(&mut line)
+            .map_err(|e| WizardError::Io(e.to_string()))?;
+        if read == 0 {
+            return Err(WizardError::EndOfInput(question));
+        }
+        let answer = match line.trim() {
+            "" => default,
+            a => a,
+        };
+        if choices.iter().any(|c| c == answer) {
+            return Ok(answer.to_string());
+        }
+        writeln!(output, "Please answer one of: {}", choices.join(", "))
+            .map_err(|e| WizardError::Io(e.to_string()))?;
+    };
+    let yes_no = |answer: String| answer == "y";
+    let yes_no_choices = ["y".to_string(), "n".to_string()];
+    let yes_no_default = |b: bool| if b { "y" } else { "n" };
+
+    let target: SupportedTarget = ask(
+        "Target",
+        &SupportedTarget::target_names(),
+        defaults.target.full_name(),
+    )?.parse()
+        .expect("Answers are one of the target names");
+    let platforms: Vec<String> = SupportedPlatform::platforms()
+        .into_iter()
+        .filter(|&p| is_supported_target_platform_pair(target, p))
+        .map(|p| p.full_name().to_string())
+        .collect();
+    let platform: SupportedPlatform = ask("Platform", &platforms, &platforms[0])?
+        .parse()
+        .expect("Answers are one of the platform names");
+    let debug_printing = yes_no(ask(
+        "Enable kernel printing in debug builds",
+        &yes_no_choices,
+        yes_no_default(defaults.debug_printing),
+    )?);
+    let release_printing = yes_no(ask(
+        "Enable kernel printing in release builds",
+        &yes_no_choices,
+        yes_no_default(defaults.release_printing),
+    )?);
+    let mut benchmark_choices = vec!["none".to_string()];
+    benchmark_choices.extend(BenchmarkMode::modes().iter().map(|m| m.full_name().into()));
+    let benchmark = ask("Kernel benchmarks in debug builds", &benchmark_choices, "none")?;
+    let benchmarks = BenchmarkMode::modes()
+        .into_iter()
+        .find(|m| m.full_name() == benchmark);
+    let node_choices: Vec<String> = (1..9).map(|n| n.to_string()).collect();
+    let max_num_nodes = ask(
+        "Number of cores (KernelMaxNumNodes)",
+        &node_choices,
+        &defaults.max_num_nodes.to_string(),
+    )?.parse()
+        .expect("Answers are one of the core counts");
+
+    wizard_manifest(&WizardAnswers {
+        target,
+        platform,
+        debug_printing,
+        release_printing,
+        benchmarks,
+        max_num_nodes,
+    }).map_err(WizardError::InvalidAnswers)
+}
+
+/// Write a manifest the wizard produced as `write_exemplar_manifest` writes
+/// exemplars, refusing to replace an existing file unless `overwrite` is
+/// set and then backing it up
+pub fn write_wizard_manifest<P: AsRef<Path>>(
+    path: P,
+    manifest: &str,
+    overwrite: bool,
+) -> Result<Option<PathBuf>, ExemplarWriteError> {
+    write_new_manifest(path.as_ref(), manifest, overwrite)
+}
+
+/// Replace a property's value in place, or add it after the others
+fn set_property(properties: &mut PropertySet, name: &str, value: FlatTomlValue) {
+    if properties.contains(name) {
+        *properties = PropertySet::from_properties(properties.iter().map(|p| {
+            if p.name == name {
+                FlatTomlProperty::new(name.to_string(), value.clone())
+            } else {
+                p.clone()
+            }
+        })).expect("Replacing a value keeps the names unique");
+    } else {
+        let _ = properties.insert(FlatTomlProperty::new(name.to_string(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn wizard_answers_make_a_resolvable_manifest() {
+        let input = "armv7-sel4-fel4\n\nn\ny\nfeatures\ngeneric\n4\n";
+        let mut output = Vec::new();
+        let text = run_wizard(input.as_bytes(), &mut output).unwrap();
+        let prompts = String::from_utf8(output).unwrap();
+        assert!(prompts.contains("Platform (sabre) [sabre]: "));
+        assert!(prompts.contains("Please answer one of: none, generic"));
+
+        let full = parse_full_manifest(&text).unwrap();
+        assert_eq!(
+            vec![SupportedTarget::Armv7Sel4Fel4],
+            full.declared_targets()
+        );
+        let debug = resolve_fel4_config(&full, &BuildProfile::Debug).unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(false)),
+            debug.properties.get("KernelPrinting")
+        );
+        assert_eq!(
+            Some(&FlatTomlValue::Integer(4)),
+            debug.properties.get("KernelMaxNumNodes")
+        );
+        assert_eq!(
+            Some(BenchmarkMode::Generic),
+            debug.benchmarks().map(|b| b.mode)
+        );
+        let release = resolve_fel4_config(&full, &BuildProfile::Release).unwrap();
+        assert_eq!(
+            Some(&FlatTomlValue::Boolean(true)),
+            release.properties.get("KernelPrinting")
+        );
+
+        assert_eq!(
+            Err(WizardError::EndOfInput("Platform")),
+            run_wizard("x86_64-sel4-fel4\n".as_bytes(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn default_answers_match_the_exemplar() {
+        let text = wizard_manifest(&WizardAnswers::default()).unwrap();
+        let config =
+            resolve_fel4_config(parse_full_manifest(&text).unwrap(), &BuildProfile::Debug).unwrap();
+        let exemplar = test_helpers::fixture_config(
+            SupportedTarget::X8664Sel4Fel4,
+            SupportedPlatform::PC99,
+            BuildProfile::Debug,
+        );
+        assert_eq!(exemplar.properties, config.properties);
+    }
+}
@@ -0,0 +1,511 @@
+/// Systems built from several components, each with its own fel4 manifest,
+/// that share one kernel configuration
+use std::collections::BTreeSet;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::path::{Path, PathBuf};
+
+use super::{resolve_fel4_config_with_options, ConfigError, ResolutionOptions};
+use locking::{check_unlocked, LockPolicy};
+use manifest::{get_full_manifest, FullFel4Manifest};
+use types::*;
+
+/// One component of a `Fel4Workspace`
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkspaceComponent {
+    pub manifest_path: PathBuf,
+    pub manifest: FullFel4Manifest,
+}
+
+/// The fel4 manifests of every component of a system, with the kernel
+/// configuration they share
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fel4Workspace {
+    /// A manifest whose target tables hold the properties every component
+    /// shares. Its `[fel4]` table is not used.
+    pub common: Option<FullFel4Manifest>,
+    pub components: Vec<WorkspaceComponent>,
+}
+
+/// A kernel option the components of a workspace resolved differently
+#[derive(Clone, Debug, PartialEq)]
+pub struct KernelDivergence {
+    /// The property name, or `target` or `platform` for components that
+    /// select different ones
+    pub property: String,
+    /// Each component's manifest path and value, which is `None` where the
+    /// component doesn't set the property
+    pub values: Vec<(PathBuf, Option<FlatTomlValue>)>,
+}
+
+impl Display for KernelDivergence {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{} is", self.property)?;
+        for (i, (path, value)) in self.values.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            match value {
+                Some(v) => write!(f, "{} {} in {}", separator, v, path.display())?,
+                None => write!(f, "{} unset in {}", separator, path.display())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every kernel option the components of a workspace disagree on
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkspaceDivergence {
+    pub divergences: Vec<KernelDivergence>,
+}
+
+impl Display for WorkspaceDivergence {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        for (i, divergence) in self.divergences.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", divergence)?;
+        }
+        Ok(())
+    }
+}
+
+/// A component property that contradicts the common kernel configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommonConflict {
+    /// The component's manifest path
+    pub component: String,
+    pub property: String,
+    pub component_definition: PropertyDefinition,
+    pub common_definition: PropertyDefinition,
+}
+
+impl Display for CommonConflict {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(
+            f,
+            "The {} component sets {} in {}, but the common kernel configuration sets it in {}",
+            self.component, self.property, self.component_definition, self.common_definition
+        )
+    }
+}
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[non_exhaustive]
+pub enum WorkspaceError {
+    #[fail(display = "The {} component manifest is invalid: {}", _0, _1)]
+    Component(String, Box<ConfigError>),
+    #[fail(display = "{}", _0)]
+    CommonConflict(Box<CommonConflict>),
+    #[fail(
+        display = "The workspace components disagree on kernel options: {}",
+        _0
+    )]
+    Divergence(WorkspaceDivergence),
+    #[fail(
+        display = "The common manifest's [{}] table has {}, which components can't share",
+        _0, _1
+    )]
+    UnsharedCommonTable(String, &'static str),
+}
+
+impl Fel4Workspace {
+    /// Load the common kernel configuration and each component's manifest
+    pub fn load<P, I, Q>(
+        common_manifest_path: P,
+        component_manifest_paths: I,
+    ) -> Result<Self, WorkspaceError>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = Q>,
+        Q: AsRef<Path>,
+    {
+        let load = |path: &Path| {
+            get_full_manifest(path)
+                .map_err(|e| WorkspaceError::Component(path.display().to_string(), Box::new(e)))
+        };
+        let common = load(common_manifest_path.as_ref())?;
+        let components = component_manifest_paths
+            .into_iter()
+            .map(|path| {
+                Ok(WorkspaceComponent {
+                    manifest_path: path.as_ref().to_path_buf(),
+                    manifest: load(path.as_ref())?,
+                })
+            })
+            .collect::<Result<_, WorkspaceError>>()?;
+        Ok(Fel4Workspace {
+            common: Some(common),
+            components,
+        })
+    }
+
+    /// A component's manifest with the common kernel configuration added to
+    /// the tables of each target it declares, ahead of its own properties.
+    ///
+    /// A component may repeat a common property with the same value, but
+    /// setting it to another value is a `CommonConflict`, and setting one the
+    /// common manifest locks to another value is a `LockedProperty` error.
+    /// Only direct, build profile, and platform properties are shared, so a
+    /// common manifest with conditional, derived, test, rootserver, or
+    /// toolchain settings is an `UnsharedCommonTable` error.
+    pub fn component_manifest(
+        &self,
+        component: &WorkspaceComponent,
+    ) -> Result<FullFel4Manifest, WorkspaceError> {
+        self.component_manifest_with_policy(component, LockPolicy::Enforce)
+    }
+
+    /// A component's manifest merged like `component_manifest`, with
+    /// component properties that change the common manifest's locked
+    /// properties refused or allowed according to the policy
+    pub fn component_manifest_with_policy(
+        &self,
+        component: &WorkspaceComponent,
+        policy: LockPolicy,
+    ) -> Result<FullFel4Manifest, WorkspaceError> {
+        let mut merged = component.manifest.clone();
+        let common = match self.common {
+            Some(ref common) => common,
+            None => return Ok(merged),
+        };
+        check_shareable(common)?;
+        let name = component.manifest_path.display().to_string();
+        let merge_properties = |table: &str, shared: &PropertySet, own: &PropertySet| {
+            merge_properties(&name, table, shared, own, &common.locked_properties, policy)
+        };
+        for locked in &common.locked_properties {
+            if !merged.locked_properties.contains(locked) {
+                merged.locked_properties.push(locked.clone());
+            }
+        }
+        for (identity, shared) in &common.targets {
+            let target = match merged.targets.get_mut(identity) {
+                Some(target) => target,
+                None => continue,
+            };
+            let table = identity.full_name().to_string();
+            target.direct_properties =
+                merge_properties(&table, &shared.direct_properties, &target.direct_properties)?;
+            for (profile, properties) in &shared.build_profile_properties {
+                let own = target
+                    .build_profile_properties
+                    .remove(profile)
+                    .unwrap_or_default();
+                let table = format!("{}.{}", identity, profile);
+                target
+                    .build_profile_properties
+                    .insert(*profile, merge_properties(&table, properties, &own)?);
+            }
+            for (platform, properties) in &shared.platform_properties {
+                let own = target
+                    .platform_properties
+                    .remove(platform)
+                    .unwrap_or_default();
+                let table = format!("{}.{}", identity, platform);
+                target
+                    .platform_properties
+                    .insert(*platform, merge_properties(&table, properties, &own)?);
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Resolve every component for a build profile, in component order,
+    /// failing with a `Divergence` unless they agree on the kernel options
+    /// `kernel_divergences` compares
+    pub fn resolve(
+        &self,
+        build_profile: &BuildProfile,
+    ) -> Result<Vec<(PathBuf, Fel4Config)>, WorkspaceError> {
+        self.resolve_with_options(build_profile, &ResolutionOptions::new())
+    }
+
+    /// Resolve every component like `resolve`, merging and resolving each
+    /// with the options, whose `lock_policy` also applies to the merge
+    pub fn resolve_with_options(
+        &self,
+        build_profile: &BuildProfile,
+        options: &ResolutionOptions,
+    ) -> Result<Vec<(PathBuf, Fel4Config)>, WorkspaceError> {
+        let configs = self
+            .components
+            .iter()
+            .map(|component| {
+                let component_error = |e| {
+                    WorkspaceError::Component(
+                        component.manifest_path.display().to_string(),
+                        Box::new(e),
+                    )
+                };
+                let manifest =
+                    self.component_manifest_with_policy(component, options.lock_policy)?;
+                let (config, _warnings) =
+                    resolve_fel4_config_with_options(manifest, build_profile, options)
+                        .map_err(component_error)?;
+                Ok((component.manifest_path.clone(), config))
+            })
+            .collect::<Result<Vec<_>, WorkspaceError>>()?;
+        let divergences = kernel_divergences(&configs);
+        if divergences.is_empty() {
+            Ok(configs)
+        } else {
+            Err(WorkspaceError::Divergence(WorkspaceDivergence {
+                divergences,
+            }))
+        }
+    }
+}
+
+/// Refuse the common manifest settings `component_manifest` can't merge,
+/// rather than dropping them
+fn check_shareable(common: &FullFel4Manifest) -> Result<(), WorkspaceError> {
+    let unshared = |table: String, what| Err(WorkspaceError::UnsharedCommonTable(table, what));
+    for (identity, target) in &common.targets {
+        if !target.conditional_properties.is_empty() {
+            return unshared(identity.full_name().to_string(), "a conditional property");
+        }
+        if !target.derived_properties.is_empty() {
+            return unshared(identity.full_name().to_string(), "a derived property");
+        }
+        if !target.test_properties.is_empty() {
+            return unshared(format!("{}.test", identity), "test properties");
+        }
+        if target.rootserver != RootserverSettings::default() {
+            return unshared(format!("{}.rootserver", identity), "rootserver settings");
+        }
+    }
+    for (identity, toolchain) in &common.toolchains {
+        if *toolchain != ToolchainSettings::default() {
+            return unshared(format!("toolchain.{}", identity), "toolchain settings");
+        }
+    }
+    Ok(())
+}
+
+/// `own` properties already in `shared` must have the same value, and those
+/// that are `locked` may only repeat the `shared` value
+fn merge_properties(
+    component: &str,
+    table: &str,
+    shared: &PropertySet,
+    own: &PropertySet,
+    locked: &[String],
+    policy: LockPolicy,
+) -> Result<PropertySet, WorkspaceError> {
+    let mut merged = shared.clone();
+    for property in own.iter() {
+        if shared.get(&property.name) != Some(&property.value) {
+            check_unlocked(
+                locked,
+                &property.name,
+                &format!("the {} component", component),
+                policy,
+            ).map_err(|e| WorkspaceError::Component(component.to_string(), Box::new(e)))?;
+        }
+        match shared.get(&property.name) {
+            Some(value) if *value != property.value => {
+                return Err(WorkspaceError::CommonConflict(Box::new(CommonConflict {
+                    component: component.to_string(),
+                    property: property.name.clone(),
+                    component_definition: PropertyDefinition::new(
+                        table.to_string(),
+                        &property.value,
+                    ),
+                    common_definition: PropertyDefinition::new(table.to_string(), value),
+                })))
+            }
+            Some(_) => {}
+            None => {
+                let _ = merged.insert(property.clone());
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// The kernel options resolved configurations disagree on, in name order
+/// after any differences in target and platform.
+///
+/// Kernel options are the properties any of the configurations scope as
+/// `PropertyScope::KernelAbi`, which every component's kernel image and
+/// bindings must agree on.
+pub fn kernel_divergences(configs: &[(PathBuf, Fel4Config)]) -> Vec<KernelDivergence> {
+    let mut divergences = Vec::new();
+    let mut compare = |property: &str, value_of: &dyn Fn(&Fel4Config) -> Option<FlatTomlValue>| {
+        let values: Vec<(PathBuf, Option<FlatTomlValue>)> = configs
+            .iter()
+            .map(|(path, config)| (path.clone(), value_of(config)))
+            .collect();
+        if values.windows(2).any(|w| w[0].1 != w[1].1) {
+            divergences.push(KernelDivergence {
+                property: property.to_string(),
+                values,
+            });
+        }
+    };
+    compare("target", &|c| {
+        Some(FlatTomlValue::String(c.target.full_name().into()))
+    });
+    compare("platform", &|c| {
+        Some(FlatTomlValue::String(c.platform.full_name().into()))
+    });
+    let names: BTreeSet<&str> = configs
+        .iter()
+        .flat_map(|(_, config)| config.abi_affecting_properties())
+        .map(|(name, _)| name)
+        .collect();
+    for name in names {
+        compare(name, &|c| c.properties.get(name).cloned());
+    }
+    divergences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn components_share_the_common_kernel_configuration() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let common = dir.path().join("kernel.toml");
+        fs::write(
+            &common,
+            test_helpers::ManifestFixture::new()
+                .with_property("x86_64-sel4-fel4", "KernelNumPriorities", 128)
+                .to_toml_string(),
+        ).unwrap();
+        let write_component = |name: &str, fixture: test_helpers::ManifestFixture| {
+            let path = dir.path().join(name);
+            fs::write(&path, fixture.to_toml_string()).unwrap();
+            path
+        };
+        let app = write_component(
+            "app.toml",
+            test_helpers::ManifestFixture::new()
+                .without_property("x86_64-sel4-fel4", "KernelNumPriorities")
+                .with_property("x86_64-sel4-fel4", "X-AppLogLevel", 2),
+        );
+        let driver = write_component(
+            "driver.toml",
+            test_helpers::ManifestFixture::new()
+                .without_property("x86_64-sel4-fel4", "KernelNumPriorities"),
+        );
+
+        let workspace = Fel4Workspace::load(&common, vec![&app, &driver]).unwrap();
+        let configs = workspace.resolve(&BuildProfile::Debug).unwrap();
+        assert_eq!(2, configs.len());
+        for (_, config) in &configs {
+            assert_eq!(
+                Some(&FlatTomlValue::Integer(128)),
+                config.properties.get("KernelNumPriorities")
+            );
+        }
+
+        let conflicting = write_component(
+            "conflicting.toml",
+            test_helpers::ManifestFixture::new().with_property(
+                "x86_64-sel4-fel4",
+                "KernelNumPriorities",
+                64,
+            ),
+        );
+        match Fel4Workspace::load(&common, vec![&app, &conflicting])
+            .unwrap()
+            .resolve(&BuildProfile::Debug)
+        {
+            Err(WorkspaceError::CommonConflict(ref conflict)) => {
+                assert_eq!("KernelNumPriorities", conflict.property)
+            }
+            other => panic!("Expected a conflict with the common table, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn components_cannot_change_common_locked_properties() {
+        let common = test_helpers::ManifestFixture::new()
+            .with_property("fel4", "locked-properties", vec!["X-AppLogLevel"])
+            .parse()
+            .unwrap();
+        let component = |level: Option<i64>| {
+            let fixture = test_helpers::ManifestFixture::new();
+            let fixture = match level {
+                Some(level) => fixture.with_property("x86_64-sel4-fel4", "X-AppLogLevel", level),
+                None => fixture,
+            };
+            WorkspaceComponent {
+                manifest_path: PathBuf::from("app/fel4.toml"),
+                manifest: fixture.parse().unwrap(),
+            }
+        };
+        let workspace = Fel4Workspace {
+            common: Some(common),
+            components: vec![component(Some(2))],
+        };
+        match workspace.resolve(&BuildProfile::Debug) {
+            Err(WorkspaceError::Component(_, ref e)) => assert_eq!(
+                ConfigError::LockedProperty(
+                    "X-AppLogLevel".into(),
+                    "the app/fel4.toml component".into()
+                ),
+                **e
+            ),
+            other => panic!("Expected a locked property error, got {:?}", other),
+        }
+        let allowing = ResolutionOptions::new().lock_policy(LockPolicy::AllowLocked);
+        let configs = workspace
+            .resolve_with_options(&BuildProfile::Debug, &allowing)
+            .unwrap();
+        assert!(configs[0].1.is_locked("X-AppLogLevel"));
+        assert!(workspace.component_manifest(&component(None)).is_ok());
+    }
+
+    #[test]
+    fn common_conditional_properties_are_rejected() {
+        let toml = test_helpers::ManifestFixture::new()
+            .without_property("x86_64-sel4-fel4", "KernelMaxNumNodes")
+            .to_toml_string()
+            .replace(
+                "[x86_64-sel4-fel4]\n",
+                "[x86_64-sel4-fel4]\nKernelMaxNumNodes = { default = 1, release = 4 }\n",
+            );
+        let common = manifest::parse_full_manifest(&toml).unwrap();
+        let workspace = Fel4Workspace {
+            common: Some(common),
+            components: vec![WorkspaceComponent {
+                manifest_path: PathBuf::from("app/fel4.toml"),
+                manifest: test_helpers::ManifestFixture::new().parse().unwrap(),
+            }],
+        };
+        assert_eq!(
+            Err(WorkspaceError::UnsharedCommonTable(
+                "x86_64-sel4-fel4".into(),
+                "a conditional property"
+            )),
+            workspace.resolve(&BuildProfile::Debug)
+        );
+    }
+
+    #[test]
+    fn divergent_kernel_options_are_reported() {
+        let config = |printing: bool| {
+            test_helpers::ManifestFixture::new()
+                .with_property("x86_64-sel4-fel4.debug", "KernelPrinting", printing)
+                .with_property("x86_64-sel4-fel4", "X-AppLogLevel", printing)
+                .resolve(&BuildProfile::Debug)
+                .unwrap()
+        };
+        let configs = vec![
+            (PathBuf::from("a/fel4.toml"), config(true)),
+            (PathBuf::from("b/fel4.toml"), config(false)),
+        ];
+        let divergences = kernel_divergences(&configs);
+        assert_eq!(1, divergences.len());
+        assert_eq!(
+            "KernelPrinting is true in a/fel4.toml, false in b/fel4.toml",
+            divergences[0].to_string()
+        );
+        assert!(kernel_divergences(&configs[..1]).is_empty());
+    }
+}
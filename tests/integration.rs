@@ -19,79 +19,40 @@ fn get_full_manifest_happy_path() {
         .targets
         .get(&SupportedTarget::X8664Sel4Fel4)
         .unwrap();
-    assert!(
-        x86_target
-            .direct_properties
-            .iter()
-            .any(|p| p.name == "BuildWithCommonSimulationSettings")
-    );
+    assert!(x86_target
+        .direct_properties
+        .contains("BuildWithCommonSimulationSettings"));
     assert_eq!(
-        FlatTomlValue::String("x86".to_string()),
-        x86_target
-            .direct_properties
-            .iter()
-            .find(|p| p.name == "KernelArch")
-            .unwrap()
-            .value
+        Some(&FlatTomlValue::String("x86".to_string())),
+        x86_target.direct_properties.get("KernelArch")
     );
     assert_eq!(
-        FlatTomlValue::Boolean(true),
-        x86_target
-            .build_profile_properties
-            .get_vec(&BuildProfile::Debug)
-            .unwrap()
-            .iter()
-            .find(|p| p.name == "KernelDebugBuild")
-            .unwrap()
-            .value
+        Some(&FlatTomlValue::Boolean(true)),
+        x86_target.build_profile_properties[&BuildProfile::Debug].get("KernelDebugBuild")
     );
     assert_eq!(
-        FlatTomlValue::Boolean(false),
-        x86_target
-            .build_profile_properties
-            .get_vec(&BuildProfile::Release)
-            .unwrap()
-            .iter()
-            .find(|p| p.name == "KernelDebugBuild")
-            .unwrap()
-            .value
+        Some(&FlatTomlValue::Boolean(false)),
+        x86_target.build_profile_properties[&BuildProfile::Release].get("KernelDebugBuild")
     );
     assert_eq!(
-        FlatTomlValue::String("nehalem".to_string()),
-        x86_target
-            .platform_properties
-            .get_vec(&SupportedPlatform::PC99)
-            .unwrap()
-            .iter()
-            .find(|p| p.name == "KernelX86MicroArch")
-            .unwrap()
-            .value
+        Some(&FlatTomlValue::String("nehalem".to_string())),
+        x86_target.platform_properties[&SupportedPlatform::PC99].get("KernelX86MicroArch")
     );
     let armv7_target = manifest
         .targets
         .get(&SupportedTarget::Armv7Sel4Fel4)
         .unwrap();
     assert_eq!(
-        FlatTomlValue::String("aarch32".to_string()),
-        armv7_target
-            .direct_properties
-            .iter()
-            .find(|p| p.name == "KernelArmSel4Arch")
-            .unwrap()
-            .value
+        Some(&FlatTomlValue::String("aarch32".to_string())),
+        armv7_target.direct_properties.get("KernelArmSel4Arch")
     );
     let aarch64_target = manifest
         .targets
         .get(&SupportedTarget::Aarch64Sel4Fel4)
         .unwrap();
     assert_eq!(
-        FlatTomlValue::String("aarch64".to_string()),
-        aarch64_target
-            .direct_properties
-            .iter()
-            .find(|p| p.name == "KernelArmSel4Arch")
-            .unwrap()
-            .value
+        Some(&FlatTomlValue::String("aarch64".to_string())),
+        aarch64_target.direct_properties.get("KernelArmSel4Arch")
     );
 }
 
@@ -121,10 +82,10 @@ fn get_resolved_x86_64_manifest_happy_path() {
 #[test]
 fn get_resolved_armv7_manifest_happy_path() {
     let manifest_file = write_exemplar_toml_to_temp_file();
-    let mut full = get_full_manifest(manifest_file.path())
-        .expect("Should be able to read the default fel4.toml");
-    full.selected_target = SupportedTarget::Armv7Sel4Fel4;
-    full.selected_platform = SupportedPlatform::Sabre;
+    let full = get_full_manifest(manifest_file.path())
+        .expect("Should be able to read the default fel4.toml")
+        .with_selection(SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre)
+        .expect("Should be able to select an exemplar target");
     let config = resolve_fel4_config(full, &BuildProfile::Debug)
         .expect("Should have been able to resolve all this");
     assert_eq!(SupportedTarget::Armv7Sel4Fel4, config.target);
@@ -139,10 +100,10 @@ fn get_resolved_armv7_manifest_happy_path() {
 #[test]
 fn get_resolved_aarch64_manifest_happy_path() {
     let manifest_file = write_exemplar_toml_to_temp_file();
-    let mut full = get_full_manifest(manifest_file.path())
-        .expect("Should be able to read the default fel4.toml");
-    full.selected_target = SupportedTarget::Aarch64Sel4Fel4;
-    full.selected_platform = SupportedPlatform::Tx1;
+    let full = get_full_manifest(manifest_file.path())
+        .expect("Should be able to read the default fel4.toml")
+        .with_selection(SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1)
+        .expect("Should be able to select an exemplar target");
     let config = resolve_fel4_config(full, &BuildProfile::Debug)
         .expect("Should have been able to resolve all this");
     assert_eq!(SupportedTarget::Aarch64Sel4Fel4, config.target);
@@ -153,3 +114,33 @@ fn get_resolved_aarch64_manifest_happy_path() {
         config.properties.get("KernelArch").unwrap()
     );
 }
+
+#[test]
+fn every_declared_target_resolves_without_reselecting() {
+    let manifest_file = write_exemplar_toml_to_temp_file();
+    let full = get_full_manifest(manifest_file.path())
+        .expect("Should be able to read the default fel4.toml");
+    for &(target, platform) in &[
+        (SupportedTarget::X8664Sel4Fel4, SupportedPlatform::PC99),
+        (SupportedTarget::Armv7Sel4Fel4, SupportedPlatform::Sabre),
+        (SupportedTarget::Aarch64Sel4Fel4, SupportedPlatform::Tx1),
+    ] {
+        let config = resolve_fel4_config_for(&full, target, platform, &BuildProfile::Release)
+            .expect("Should resolve every exemplar target");
+        assert_eq!(target, config.target);
+        assert_eq!(platform, config.platform);
+    }
+    assert_eq!(SupportedTarget::X8664Sel4Fel4, full.selected_target);
+    assert_eq!(
+        Err(ConfigError::TargetPlatformMismatch(
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::PC99
+        )),
+        resolve_fel4_config_for(
+            &full,
+            SupportedTarget::Armv7Sel4Fel4,
+            SupportedPlatform::PC99,
+            &BuildProfile::Debug
+        )
+    );
+}